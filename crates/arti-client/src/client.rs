@@ -476,6 +476,35 @@ impl InertTorClient {
             None => Ok(None),
         }
     }
+
+    /// Return the onion address of the onion service with the specified `nickname`,
+    /// as derived from its identity key.
+    ///
+    /// If `selector` is `Some`, only the selected keystore is searched.
+    /// Otherwise, the identity key is looked up in all the configured keystores.
+    ///
+    /// Returns `Ok(None)` if the identity key of the service could not be found.
+    ///
+    /// Unlike [`TorClient::create_onion_service`], this does not require
+    /// the service to be created.
+    #[cfg(feature = "onion-service-service")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "onion-service-service")))]
+    pub fn onion_service_hsid(
+        &self,
+        nickname: &tor_hsservice::HsNickname,
+        selector: Option<tor_keymgr::KeystoreSelector>,
+    ) -> crate::Result<Option<tor_hsservice::HsId>> {
+        let keymgr = self.keymgr.as_ref().ok_or(ErrorDetail::KeystoreRequired {
+            action: "look up onion service identity key",
+        })?;
+        let spec = tor_hsservice::HsIdPublicKeySpecifier::new(nickname.clone());
+        let key: Option<tor_hscrypto::pk::HsIdKey> = match selector {
+            Some(selector) => keymgr.get_from(&spec, selector)?,
+            None => keymgr.get(&spec)?,
+        };
+
+        Ok(key.map(|key| key.id()))
+    }
 }
 
 /// Preferences for whether a [`TorClient`] should bootstrap on its own or not.
@@ -1693,6 +1722,21 @@ impl<R: Runtime> TorClient<R> {
                 action: "launch onion service",
             })?
             .clone();
+
+        self.launch_onion_service_with_keymgr(config, keymgr)
+    }
+
+    /// Try to launch an onion service with a given configuration,
+    /// using `keymgr` to look up (and store) its keys.
+    #[cfg(feature = "onion-service-service")]
+    fn launch_onion_service_with_keymgr(
+        &self,
+        config: tor_hsservice::OnionServiceConfig,
+        keymgr: Arc<KeyMgr>,
+    ) -> crate::Result<(
+        Arc<tor_hsservice::RunningOnionService>,
+        impl futures::Stream<Item = tor_hsservice::RendRequest>,
+    )> {
         let state_dir = self::StateDirectory::new(&self.state_dir, &self.storage_mistrust)
             .map_err(ErrorDetail::StateAccess)?;
 
@@ -1764,6 +1808,73 @@ impl<R: Runtime> TorClient<R> {
         self.launch_onion_service(config)
     }
 
+    /// Try to launch an onion service with a given configuration and provided
+    /// [`HsIdKeypair`], without ever writing the `HsIdKeypair` to disk.
+    ///
+    /// Unlike [`launch_onion_service_with_hsid`](TorClient::launch_onion_service_with_hsid),
+    /// this does not use this `TorClient`'s `KeyMgr` at all.
+    /// Instead, the service gets a key manager of its own,
+    /// backed by an in-memory [ephemeral](ArtiKeystoreKind::Ephemeral) keystore
+    /// that initially contains only `id_keypair`.
+    /// This is intended for deployments where the identity key is provisioned at startup
+    /// (for example, from a secret passed in through the environment),
+    /// and should never be stored on the host.
+    /// If you have the secret key as bytes, you can construct the `HsIdKeypair` using
+    /// [`ExpandedKeypair::from_secret_key_bytes`](tor_llcrypto::pk::ed25519::ExpandedKeypair::from_secret_key_bytes).
+    ///
+    /// **Important**: this has some tradeoffs you should be aware of:
+    ///
+    ///   * all the other keys of the service (such as its introduction point keys
+    ///     and descriptor signing keys) are also kept only in memory,
+    ///     and are lost when the service stops.
+    ///     When it is restarted, the service will need to create new introduction points,
+    ///     so clients holding one of its old descriptors may fail to connect to it
+    ///     until they fetch the new one.
+    ///   * the rest of the service's state (for example, its replay logs)
+    ///     is still stored in the state directory, as usual.
+    ///   * keeping the key off the disk does not mean it cannot leak:
+    ///     it is still present in the memory of this process,
+    ///     and in whatever you used to provision it
+    ///     (environment variables are often visible to other processes
+    ///     running as the same user, and may end up in crash reports or logs).
+    ///   * since the key is not stored anywhere, you are responsible for backing it up.
+    ///     If you lose it, you lose the onion address of the service.
+    ///
+    /// See [`launch_onion_service`](TorClient::launch_onion_service) for details
+    /// about how to handle the requests received by the service.
+    #[cfg(all(
+        feature = "onion-service-service",
+        feature = "experimental-api",
+        feature = "ephemeral-keystore"
+    ))]
+    pub fn launch_onion_service_with_ephemeral_hsid(
+        &self,
+        config: tor_hsservice::OnionServiceConfig,
+        id_keypair: HsIdKeypair,
+    ) -> crate::Result<(
+        Arc<tor_hsservice::RunningOnionService>,
+        impl futures::Stream<Item = tor_hsservice::RendRequest>,
+    )> {
+        let nickname = config.nickname();
+        let hsid_spec = HsIdKeypairSpecifier::new(nickname.clone());
+
+        let ephemeral_store = ArtiEphemeralKeystore::new(format!("ephemeral-{nickname}"));
+        let keymgr = KeyMgrBuilder::default()
+            .primary_store(Box::new(ephemeral_store))
+            .build()
+            .map_err(|_| internal!("failed to build keymgr"))
+            .map_err(ErrorDetail::from)?;
+
+        let _kp = keymgr.insert::<HsIdKeypair>(
+            id_keypair,
+            &hsid_spec,
+            KeystoreSelector::Primary,
+            false,
+        )?;
+
+        self.launch_onion_service_with_keymgr(config, Arc::new(keymgr))
+    }
+
     /// Generate a service discovery keypair for connecting to a hidden service running in
     /// "restricted discovery" mode.
     ///
@@ -1990,6 +2101,32 @@ impl<R: Runtime> TorClient<R> {
         self.status_receiver.clone()
     }
 
+    /// Return true if this client is currently configured to connect to onion services.
+    #[cfg(feature = "rpc")]
+    pub(crate) fn allows_onion_addrs(&self) -> bool {
+        #[cfg(feature = "onion-service-client")]
+        {
+            self.addrcfg.get().allow_onion_addrs
+        }
+        #[cfg(not(feature = "onion-service-client"))]
+        {
+            false
+        }
+    }
+
+    /// Return true if this client is currently configured to use bridges.
+    #[cfg(feature = "rpc")]
+    pub(crate) fn uses_bridges(&self) -> bool {
+        #[cfg(feature = "bridge-client")]
+        {
+            self.guardmgr.bridges_enabled()
+        }
+        #[cfg(not(feature = "bridge-client"))]
+        {
+            false
+        }
+    }
+
     /// Change the client's current dormant mode, putting background tasks to sleep
     /// or waking them up as appropriate.
     ///
@@ -2217,4 +2354,71 @@ mod test {
                 .unwrap();
         });
     }
+
+    #[cfg(all(
+        feature = "onion-service-service",
+        feature = "experimental-api",
+        feature = "ephemeral-keystore"
+    ))]
+    #[test]
+    fn ephemeral_hsid_not_stored() {
+        use std::path::Path;
+        use tor_hscrypto::pk::HsIdKey;
+        use tor_llcrypto::pk::ed25519;
+
+        /// Return every file under `dir`, recursively.
+        fn files_under(dir: &Path) -> Vec<PathBuf> {
+            let mut files = vec![];
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    files.extend(files_under(&path));
+                } else {
+                    files.push(path);
+                }
+            }
+            files
+        }
+
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let state_dir = tempfile::tempdir().unwrap();
+            let cache_dir = tempfile::tempdir().unwrap();
+            let cfg = TorClientConfigBuilder::from_directories(&state_dir, &cache_dir)
+                .build()
+                .unwrap();
+            let client = TorClient::with_runtime(rt)
+                .config(cfg)
+                .bootstrap_behavior(BootstrapBehavior::Manual)
+                .create_unbootstrapped()
+                .unwrap();
+
+            let keypair = ed25519::Keypair::generate(&mut rand::thread_rng());
+            let id_keypair = HsIdKeypair::from(ed25519::ExpandedKeypair::from(&keypair));
+            let hsid = HsIdKey::from(&id_keypair).id();
+            let nickname: tor_hsservice::HsNickname = "allium-cepa".parse().unwrap();
+            let svc_config = tor_hsservice::config::OnionServiceConfigBuilder::default()
+                .nickname(nickname.clone())
+                .build()
+                .unwrap();
+
+            let (service, _requests) = client
+                .launch_onion_service_with_ephemeral_hsid(svc_config, id_keypair)
+                .unwrap();
+
+            // The service uses the identity key we gave it...
+            assert_eq!(service.onion_name(), Some(hsid));
+            // ...but the client's keystore doesn't know about it...
+            assert_eq!(
+                client
+                    .inert_client
+                    .onion_service_hsid(&nickname, None)
+                    .unwrap(),
+                None
+            );
+            // ...and it was never written to the state directory.
+            assert!(files_under(state_dir.path())
+                .iter()
+                .all(|path| !path.to_string_lossy().contains("hs_id")));
+        });
+    }
 }
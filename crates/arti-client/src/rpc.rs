@@ -84,6 +84,40 @@ impl From<crate::status::BootstrapStatus> for ClientStatusInfo {
     }
 }
 
+/// The optional features that a `TorClient` supports.
+///
+/// This depends both on how `arti-client` was built,
+/// and on how the client is currently configured.
+/// It is reported over RPC so that applications can adapt to what Arti can do.
+/// New fields may be added in the future;
+/// applications should ignore fields they don't recognize,
+/// and treat a missing field as `false`.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ClientCapabilities {
+    /// True if we can connect to onion services.
+    pub onion_service_client: bool,
+    /// True if we can run onion services.
+    pub onion_service_service: bool,
+    /// True if we connect to the Tor network via bridges.
+    pub bridges: bool,
+    /// True if we can use pluggable transports to reach our bridges.
+    pub pluggable_transports: bool,
+}
+
+impl ClientCapabilities {
+    /// Return the capabilities of `client`, given its current configuration.
+    pub fn of_client<R: Runtime>(client: &TorClient<R>) -> Self {
+        let bridges = client.uses_bridges();
+        Self {
+            onion_service_client: client.allows_onion_addrs(),
+            onion_service_service: cfg!(feature = "onion-service-service"),
+            bridges,
+            pluggable_transports: bridges && cfg!(feature = "pt-client"),
+        }
+    }
+}
+
 // NOTE: These functions could be defined as methods on TorClient<R>.
 // I'm defining them like this to make it more clear that they are never
 // invoked as client.method(), but only via the RPC system.
@@ -29,6 +29,14 @@ impl<R: Runtime> TorRelayBuilder<R> {
         }
     }
 
+    /// Set the configuration for the `TorRelay` under construction.
+    ///
+    /// If not called, then a compiled-in default configuration will be used.
+    pub(crate) fn config(mut self, config: TorRelayConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Return a newly created TorRelay object.
     pub(crate) fn create(&self) -> Result<TorRelay<R>, Error> {
         TorRelay::create_inner(self.runtime.clone(), &self.config).map_err(Into::into)
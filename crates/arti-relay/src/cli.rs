@@ -1,6 +1,12 @@
 use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use fs_mistrust::Mistrust;
+use tor_config::mistrust::BuilderExt as _;
+use tor_config::{ConfigurationSource, ConfigurationSources};
+use tor_config_path::{CfgPath, CfgPathError, CfgPathResolver};
 
 /// A Rust Tor relay implementation.
 #[derive(Clone, Debug, Parser)]
@@ -13,8 +19,7 @@ pub(crate) struct Cli {
     /// Override the log level from the configuration.
     #[arg(long, short, global = true)]
     #[arg(value_name = "LEVEL")]
-    #[clap(default_value_t = LogLevel::Info)]
-    pub(crate) log_level: LogLevel,
+    pub(crate) log_level: Option<LogLevel>,
 
     /// Don't check permissions on the files we use.
     #[arg(long, global = true)]
@@ -26,12 +31,51 @@ pub(crate) struct Cli {
     pub(crate) options: Vec<String>,
 
     /// Config file(s) to read.
+    ///
+    /// May be specified more than once, in which case later files override earlier ones.
+    /// Every file specified must exist.
+    ///
+    /// If not specified, `~/.config/arti-relay/arti-relay.toml` and the `.toml` files in
+    /// `~/.config/arti-relay/arti-relay.d/` are read, if they exist.
     #[arg(long, short, global = true)]
     #[arg(value_name = "FILE")]
-    #[clap(default_values_t = default_config_files().into_iter().map(CliOsString))]
     pub(crate) config: Vec<CliOsString>,
 }
 
+impl Cli {
+    /// Return the [`ConfigurationSources`] specified by the command line.
+    ///
+    /// The files given with `--config` are read in order,
+    /// and are all required to exist.
+    /// If there are none, the default config files are read instead (if they exist).
+    /// The `--option` overrides are applied last.
+    pub(crate) fn config_sources(&self) -> Result<ConfigurationSources, CfgPathError> {
+        let mut options = self.options.clone();
+        if self.disable_fs_permission_checks {
+            options.push("storage.permissions.dangerously_trust_everyone=true".to_owned());
+        }
+
+        let mut sources = ConfigurationSources::try_from_cmdline(
+            default_config_files,
+            self.config.iter().map(|f| f.0.clone()),
+            options,
+        )?;
+
+        // The config files are checked using the default permissions,
+        // since we haven't read the configured ones yet.
+        let mistrust = if self.disable_fs_permission_checks {
+            Mistrust::new_dangerously_trust_everyone()
+        } else {
+            fs_mistrust::MistrustBuilder::default()
+                .build_for_arti()
+                .expect("Could not construct default fs-mistrust")
+        };
+        sources.set_mistrust(mistrust);
+
+        Ok(sources)
+    }
+}
+
 /// Main subcommands.
 #[derive(Clone, Debug, Subcommand)]
 pub(crate) enum Commands {
@@ -39,19 +83,56 @@ pub(crate) enum Commands {
     Run(RunArgs),
     /// Print build information.
     BuildInfo,
+    /// Check whether the relay's ORPort can be reached.
+    ///
+    /// This connects to the ORPort directly from this host,
+    /// so it can't tell whether the ORPort is reachable from outside
+    /// (for example, through a firewall or NAT).
+    CheckReachability(CheckReachabilityArgs),
 }
 
 /// Arguments when running an Arti relay.
 #[derive(Clone, Debug, Args)]
 pub(crate) struct RunArgs {}
 
-/// Paths used for default configuration files.
-fn default_config_files() -> Vec<OsString> {
+/// Arguments when checking whether the relay's ORPort can be reached.
+#[derive(Clone, Debug, Args)]
+pub(crate) struct CheckReachabilityArgs {
+    /// The externally visible address and port of the ORPort to check.
+    // TODO RELAY: default to the address in the configuration, once there is one.
+    #[arg(long)]
+    #[arg(value_name = "ADDR:PORT")]
+    pub(crate) address: SocketAddr,
+
+    /// How long to wait for a connection before giving up, in seconds.
+    #[arg(long, default_value_t = 10)]
+    #[arg(value_name = "SECONDS")]
+    timeout: u64,
+}
+
+impl CheckReachabilityArgs {
+    /// Return how long to wait for a connection before giving up.
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+}
+
+/// Return the default configuration files.
+fn default_config_files() -> Result<Vec<ConfigurationSource>, CfgPathError> {
+    // TODO RELAY: resolve using arti-relay-specific variables
+    let path_resolver = CfgPathResolver::default();
+
     // TODO: these are temporary default paths
-    vec![
-        "~/.config/arti-relay/arti-relay.toml".into(),
-        "~/.config/arti-relay/arti-relay.d/".into(),
+    [
+        "~/.config/arti-relay/arti-relay.toml",
+        "~/.config/arti-relay/arti-relay.d/",
     ]
+    .into_iter()
+    .map(|f| {
+        let path = CfgPath::new(f.into()).path(&path_resolver)?;
+        Ok(ConfigurationSource::from_path(path))
+    })
+    .collect()
 }
 
 /// Log levels allowed by the cli.
@@ -96,10 +177,12 @@ mod test {
         Cli::parse_from(["arti-relay", "build-info"]);
         Cli::parse_from(["arti-relay", "run"]);
 
+        let cli = Cli::parse_from(["arti-relay", "run"]);
+        assert_eq!(cli.log_level, None);
         let cli = Cli::parse_from(["arti-relay", "--log-level", "warn", "run"]);
-        assert_eq!(cli.log_level, LogLevel::Warn);
+        assert_eq!(cli.log_level, Some(LogLevel::Warn));
         let cli = Cli::parse_from(["arti-relay", "run", "--log-level", "warn"]);
-        assert_eq!(cli.log_level, LogLevel::Warn);
+        assert_eq!(cli.log_level, Some(LogLevel::Warn));
 
         let cli = Cli::parse_from(["arti-relay", "--disable-fs-permission-checks", "run"]);
         assert!(cli.disable_fs_permission_checks);
@@ -107,6 +190,52 @@ mod test {
         assert!(cli.disable_fs_permission_checks);
     }
 
+    #[test]
+    fn config_files() {
+        let cli = Cli::parse_from(["arti-relay", "run"]);
+        assert!(cli.config.is_empty());
+
+        let cli = Cli::parse_from(["arti-relay", "-c", "a.toml", "--config", "b.toml", "run"]);
+        assert_eq!(
+            cli.config,
+            vec![
+                CliOsString(OsString::from("a.toml")),
+                CliOsString(OsString::from("b.toml")),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_reachability() {
+        let cli = Cli::parse_from([
+            "arti-relay",
+            "check-reachability",
+            "--address",
+            "192.0.2.1:9001",
+        ]);
+        let Commands::CheckReachability(args) = cli.command else {
+            panic!("wrong command");
+        };
+        assert_eq!(args.address, "192.0.2.1:9001".parse().unwrap());
+        assert_eq!(args.timeout(), Duration::from_secs(10));
+
+        let cli = Cli::parse_from([
+            "arti-relay",
+            "check-reachability",
+            "--address",
+            "[2001:db8::1]:443",
+            "--timeout",
+            "3",
+        ]);
+        let Commands::CheckReachability(args) = cli.command else {
+            panic!("wrong command");
+        };
+        assert_eq!(args.timeout(), Duration::from_secs(3));
+
+        // The address is required.
+        assert!(Cli::try_parse_from(["arti-relay", "check-reachability"]).is_err());
+    }
+
     #[test]
     fn clap_bug() {
         let cli = Cli::parse_from(["arti-relay", "-o", "foo=1", "run"]);
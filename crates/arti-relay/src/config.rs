@@ -3,6 +3,7 @@
 //! NOTE: At the moment, only StorageConfig is implemented but as we ramp up arti relay
 //! implementation, more configurations will show up.
 
+use std::num::NonZeroU64;
 use std::path::Path;
 use std::{collections::HashMap, path::PathBuf};
 
@@ -11,11 +12,13 @@ use derive_more::AsRef;
 
 use fs_mistrust::{Mistrust, MistrustBuilder};
 use serde::{Deserialize, Serialize};
-use tor_chanmgr::{ChannelConfig, ChannelConfigBuilder};
+use tor_chanmgr::{BandwidthLimit, ChannelConfig, ChannelConfigBuilder};
 use tor_config::{impl_standard_builder, mistrust::BuilderExt, ConfigBuildError};
 use tor_config_path::CfgPath;
 use tor_keymgr::config::{ArtiKeystoreConfig, ArtiKeystoreConfigBuilder};
 
+use crate::logging::{LoggingConfig, LoggingConfigBuilder};
+
 /// A configuration used by a TorRelay.
 ///
 /// Most users will create a TorRelayConfig by running
@@ -32,6 +35,11 @@ use tor_keymgr::config::{ArtiKeystoreConfig, ArtiKeystoreConfigBuilder};
 #[builder(derive(Serialize, Deserialize, Debug))]
 #[non_exhaustive]
 pub(crate) struct TorRelayConfig {
+    /// How the relay identifies itself to the network and its users.
+    #[builder(sub_builder)]
+    #[builder_field_attr(serde(default))]
+    pub(crate) relay: RelayConfig,
+
     /// Directories for storing information on disk
     #[builder(sub_builder)]
     #[builder_field_attr(serde(default))]
@@ -53,9 +61,23 @@ pub(crate) struct TorRelayConfig {
     #[builder(sub_builder)]
     #[builder_field_attr(serde(default))]
     pub(crate) channel: ChannelConfig,
+
+    /// How much bandwidth the relay may use.
+    #[builder(sub_builder)]
+    #[builder_field_attr(serde(default))]
+    pub(crate) bandwidth: BandwidthConfig,
+
+    /// Logging configuration
+    #[builder(sub_builder)]
+    #[builder_field_attr(serde(default))]
+    pub(crate) logging: LoggingConfig,
 }
 impl_standard_builder! { TorRelayConfig }
 
+impl tor_config::load::TopLevel for TorRelayConfig {
+    type Builder = TorRelayConfigBuilder;
+}
+
 #[allow(unused)] // TODO RELAY remove
 impl TorRelayConfigBuilder {
     /// Returns a `TorRelayConfigBuilder` using the specified state and cache directories.
@@ -85,6 +107,145 @@ fn default_extend<T: Default + Extend<X>, X>(to_add: impl IntoIterator<Item = X>
     collection
 }
 
+/// The longest nickname that a relay may have.
+///
+/// See the definition of `nickname` in dir-spec.
+const MAX_NICKNAME_LEN: usize = 19;
+
+/// The longest contact info, in bytes, that we accept for a relay.
+///
+/// The spec doesn't set a limit, but the contact info ends up in every
+/// descriptor we publish, so there's no reason to allow anything huge.
+const MAX_CONTACT_INFO_LEN: usize = 1024;
+
+/// Configuration for how the relay presents itself to the network.
+///
+/// These values will end up in the relay's descriptor.
+#[derive(Debug, Clone, Builder, Eq, PartialEq)]
+#[builder(build_fn(error = "ConfigBuildError", validate = "Self::validate"))]
+#[builder(derive(Debug, Serialize, Deserialize))]
+#[non_exhaustive]
+#[allow(unused)] // TODO RELAY: use these when we publish our descriptor
+pub(crate) struct RelayConfig {
+    /// A short name for the relay, to help people recognize it.
+    ///
+    /// This must be between 1 and 19 characters long, and may only contain
+    /// ASCII letters and digits.
+    /// Nicknames are not unique, and must never be used to identify a relay.
+    #[builder(default, setter(into, strip_option))]
+    pub(crate) nickname: Option<String>,
+
+    /// How to reach the operator of this relay.
+    ///
+    /// This is free-form text, usually an email address, but it must fit on a single line.
+    #[builder(default, setter(into, strip_option))]
+    pub(crate) contact_info: Option<String>,
+}
+impl_standard_builder! { RelayConfig }
+
+impl RelayConfigBuilder {
+    /// Return an error if this builder isn't valid.
+    fn validate(&self) -> Result<(), ConfigBuildError> {
+        if let Some(Some(nickname)) = &self.nickname {
+            check_nickname(nickname).map_err(|problem| ConfigBuildError::Invalid {
+                field: "nickname".into(),
+                problem: problem.into(),
+            })?;
+        }
+        if let Some(Some(contact_info)) = &self.contact_info {
+            check_contact_info(contact_info).map_err(|problem| ConfigBuildError::Invalid {
+                field: "contact_info".into(),
+                problem: problem.into(),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether `nickname` is a valid relay nickname, and if not, say why.
+fn check_nickname(nickname: &str) -> Result<(), &'static str> {
+    if nickname.is_empty() {
+        Err("must not be empty")
+    } else if nickname.len() > MAX_NICKNAME_LEN {
+        Err("must be at most 19 characters long")
+    } else if !nickname.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Err("must only contain the characters A-Z, a-z and 0-9")
+    } else {
+        Ok(())
+    }
+}
+
+/// Check whether `contact_info` is acceptable as relay contact info, and if not, say why.
+fn check_contact_info(contact_info: &str) -> Result<(), &'static str> {
+    if contact_info.trim().is_empty() {
+        Err("must not be empty")
+    } else if contact_info.len() > MAX_CONTACT_INFO_LEN {
+        Err("must be at most 1024 bytes long")
+    } else if contact_info.chars().any(char::is_control) {
+        Err("must not contain newlines or other control characters")
+    } else {
+        Ok(())
+    }
+}
+
+/// Configuration for limiting the bandwidth that the relay uses.
+///
+/// These are like C Tor's `RelayBandwidthRate` and `RelayBandwidthBurst` options.
+/// The limits apply separately to the bytes that the relay reads
+/// and to the bytes that it writes, on all of its channels together.
+///
+/// By default, there is no limit.
+#[derive(Debug, Clone, Builder, Eq, PartialEq)]
+#[builder(build_fn(error = "ConfigBuildError", validate = "Self::validate"))]
+#[builder(derive(Debug, Serialize, Deserialize))]
+#[non_exhaustive]
+pub(crate) struct BandwidthConfig {
+    /// The average number of bytes per second that the relay may read, and may write.
+    #[builder(default, setter(strip_option))]
+    rate: Option<u64>,
+
+    /// The largest number of bytes that the relay may read, or write, in a single burst.
+    ///
+    /// This must be at least `rate`. If it is not set, it is the same as `rate`.
+    #[builder(default, setter(strip_option))]
+    burst: Option<u64>,
+}
+impl_standard_builder! { BandwidthConfig }
+
+impl BandwidthConfigBuilder {
+    /// Return an error if this builder isn't valid.
+    fn validate(&self) -> Result<(), ConfigBuildError> {
+        let rate = self.rate.flatten();
+        let burst = self.burst.flatten();
+        if rate == Some(0) {
+            return Err(ConfigBuildError::Invalid {
+                field: "rate".into(),
+                problem: "must be greater than zero".into(),
+            });
+        }
+        match (rate, burst) {
+            (None, Some(_)) => Err(ConfigBuildError::Inconsistent {
+                fields: vec!["rate".into(), "burst".into()],
+                problem: "burst is set, but rate is not".into(),
+            }),
+            (Some(rate), Some(burst)) if burst < rate => Err(ConfigBuildError::Inconsistent {
+                fields: vec!["rate".into(), "burst".into()],
+                problem: "burst must be at least as large as rate".into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl BandwidthConfig {
+    /// Return the bandwidth limit that this configuration asks for, if any.
+    pub(crate) fn limit(&self) -> Option<BandwidthLimit> {
+        let rate = NonZeroU64::new(self.rate?)?;
+        let burst = self.burst.and_then(NonZeroU64::new).unwrap_or(rate);
+        Some(BandwidthLimit::new(rate, burst))
+    }
+}
+
 /// Configuration for where information should be stored on disk.
 ///
 /// By default, cache information will be stored in `${ARTI_RELAY_CACHE}`, and
@@ -134,6 +295,15 @@ pub(crate) struct StorageConfig {
     keystore: ArtiKeystoreConfig,
 
     /// Configuration about which permissions we want to enforce on our files.
+    ///
+    /// On startup, the relay refuses to run if its state or cache directories
+    /// (or the keystore within the state directory) can be read or modified by
+    /// untrusted users.
+    ///
+    /// For unusual setups in which this check gets in the way, it can be disabled by setting
+    /// `storage.permissions.dangerously_trust_everyone = true`, or by setting the
+    /// `ARTI_FS_DISABLE_PERMISSION_CHECKS` environment variable.
+    /// Doing so is not recommended: anyone who can read the relay's keys can impersonate it.
     #[builder(sub_builder(fn_name = "build_for_arti"))]
     #[builder_field_attr(serde(default))]
     permissions: Mistrust,
@@ -146,21 +316,32 @@ impl StorageConfig {
         &self.permissions
     }
 
+    /// Return the fully expanded path of the state directory.
+    pub(crate) fn state_dir(&self) -> Result<PathBuf, ConfigBuildError> {
+        expand_dir(&self.state_dir, "state_dir")
+    }
+
+    /// Return the fully expanded path of the cache directory.
+    pub(crate) fn cache_dir(&self) -> Result<PathBuf, ConfigBuildError> {
+        expand_dir(&self.cache_dir, "cache_dir")
+    }
+
     /// Return the fully expanded path of the keystore directory.
     pub(crate) fn keystore_dir(&self) -> Result<PathBuf, ConfigBuildError> {
-        // TODO RELAY: resolve using arti-relay-specific variables
-        let r = tor_config_path::CfgPathResolver::default();
-        Ok(self
-            .state_dir
-            .path(&r)
-            .map_err(|e| ConfigBuildError::Invalid {
-                field: "state_dir".to_owned(),
-                problem: e.to_string(),
-            })?
-            .join("keystore"))
+        Ok(self.state_dir()?.join("keystore"))
     }
 }
 
+/// Try to expand `dir`, the value of the `field` option, to be a path buffer.
+fn expand_dir(dir: &CfgPath, field: &str) -> Result<PathBuf, ConfigBuildError> {
+    // TODO RELAY: resolve using arti-relay-specific variables
+    let r = tor_config_path::CfgPathResolver::default();
+    dir.path(&r).map_err(|e| ConfigBuildError::Invalid {
+        field: field.to_owned(),
+        problem: e.to_string(),
+    })
+}
+
 /// Return the default cache directory.
 fn default_cache_dir() -> CfgPath {
     CfgPath::new("${ARTI_RELAY_CACHE}".to_owned())
@@ -194,4 +375,121 @@ mod test {
 
         assert_ne!(val, TorRelayConfig::default());
     }
+
+    #[test]
+    fn relay_info() {
+        let mut bld = TorRelayConfigBuilder::default();
+        bld.relay()
+            .nickname("Unnamed0")
+            .contact_info("Jo Operator <jo@example.com>");
+        let val = bld.build().unwrap();
+        assert_eq!(val.relay.nickname.as_deref(), Some("Unnamed0"));
+        assert_eq!(
+            val.relay.contact_info.as_deref(),
+            Some("Jo Operator <jo@example.com>")
+        );
+
+        // Both are optional.
+        let dflt = TorRelayConfig::default();
+        assert_eq!(dflt.relay.nickname, None);
+        assert_eq!(dflt.relay.contact_info, None);
+    }
+
+    #[test]
+    fn bad_nickname() {
+        let long = "a".repeat(20);
+        for nickname in ["", "has space", "dash-dash", "ünicode", &long] {
+            let mut bld = TorRelayConfigBuilder::default();
+            bld.relay().nickname(nickname);
+            let err = bld.build().unwrap_err();
+            assert!(
+                matches!(&err, ConfigBuildError::Invalid { field, .. } if field == "relay.nickname"),
+                "{nickname:?}: {err:?}"
+            );
+        }
+
+        let mut bld = TorRelayConfigBuilder::default();
+        bld.relay().nickname("a".repeat(19));
+        bld.build().unwrap();
+    }
+
+    #[test]
+    fn bad_contact_info() {
+        let long = "a".repeat(1025);
+        for contact_info in ["", "   ", "two\nlines", "nul\0", &long] {
+            let mut bld = TorRelayConfigBuilder::default();
+            bld.relay().contact_info(contact_info);
+            let err = bld.build().unwrap_err();
+            assert!(
+                matches!(&err, ConfigBuildError::Invalid { field, .. } if field == "relay.contact_info"),
+                "{contact_info:?}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn bandwidth() {
+        let limit = |rate, burst| {
+            BandwidthLimit::new(
+                NonZeroU64::new(rate).unwrap(),
+                NonZeroU64::new(burst).unwrap(),
+            )
+        };
+
+        assert_eq!(TorRelayConfig::default().bandwidth.limit(), None);
+
+        let mut bld = TorRelayConfigBuilder::default();
+        bld.bandwidth().rate(1000);
+        let val = bld.build().unwrap();
+        assert_eq!(val.bandwidth.limit(), Some(limit(1000, 1000)));
+
+        bld.bandwidth().burst(5000);
+        let val = bld.build().unwrap();
+        assert_eq!(val.bandwidth.limit(), Some(limit(1000, 5000)));
+    }
+
+    #[test]
+    fn bad_bandwidth() {
+        let check_err = |rate: Option<u64>, burst: Option<u64>| {
+            let mut bld = TorRelayConfigBuilder::default();
+            if let Some(rate) = rate {
+                bld.bandwidth().rate(rate);
+            }
+            if let Some(burst) = burst {
+                bld.bandwidth().burst(burst);
+            }
+            let err = bld.build().unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    ConfigBuildError::Invalid { .. } | ConfigBuildError::Inconsistent { .. }
+                ),
+                "{rate:?} {burst:?}: {err:?}"
+            );
+        };
+
+        check_err(Some(0), None);
+        check_err(None, Some(1000));
+        check_err(Some(1000), Some(999));
+    }
+
+    #[test]
+    fn expand_dirs() {
+        let val = TorRelayConfigBuilder::from_directories("/var/tmp/state", "/var/tmp/cache")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            val.storage.state_dir().unwrap(),
+            PathBuf::from("/var/tmp/state")
+        );
+        assert_eq!(
+            val.storage.cache_dir().unwrap(),
+            PathBuf::from("/var/tmp/cache")
+        );
+        assert_eq!(
+            val.storage.keystore_dir().unwrap(),
+            PathBuf::from("/var/tmp/state/keystore")
+        );
+    }
 }
@@ -51,9 +51,69 @@ pub(crate) enum ErrorDetail {
     /// Error from the KeyMgr crate.
     #[error("KeyMgr error")]
     KeyMgr(#[from] tor_keymgr::Error),
+    /// Unable to load or generate one of the relay's keys.
+    #[error("Unable to load or generate the relay {which} key")]
+    RelayKey {
+        /// Which key we were trying to get.
+        which: &'static str,
+        /// The underlying error.
+        #[source]
+        cause: tor_keymgr::Error,
+    },
+    /// A directory we need was inaccessible, or had unsafe permissions.
+    #[error("Problem accessing {which} directory")]
+    FsMistrust {
+        /// Which directory we were trying to access.
+        which: &'static str,
+        /// The underlying error.
+        #[source]
+        cause: fs_mistrust::Error,
+    },
+}
+
+/// A broad classification of an [`Error`].
+///
+/// This is coarser than [`ErrorKind`]: it is meant for deciding what to do
+/// when the relay stops because of an error, for example whether a process
+/// supervisor should try restarting it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub(crate) enum ErrorCategory {
+    /// The configuration, or the environment it refers to, is unusable.
+    ///
+    /// Retrying won't help until the operator fixes something.
+    Config,
+    /// We couldn't load, generate or store one of the relay's keys.
+    Key,
+    /// A network operation failed.
+    ///
+    /// This may well be transient, so retrying later is reasonable.
+    Network,
+    /// A bug in Arti.
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Return the exit status that the relay process should use
+    /// when it stops because of an error in this category.
+    ///
+    /// These follow the conventions of `sysexits.h`.
+    pub(crate) fn exit_code(self) -> u8 {
+        match self {
+            ErrorCategory::Config => 78,   // EX_CONFIG
+            ErrorCategory::Key => 74,      // EX_IOERR
+            ErrorCategory::Network => 75,  // EX_TEMPFAIL
+            ErrorCategory::Internal => 70, // EX_SOFTWARE
+        }
+    }
 }
 
 impl Error {
+    /// Return the broad category of this error.
+    pub(crate) fn category(&self) -> ErrorCategory {
+        self.detail.category()
+    }
+
     /// Consume this error and return the underlying error detail object.
     // TODO RELAY: remove
     #[allow(unused)]
@@ -80,6 +140,26 @@ impl tor_error::HasKind for ErrorDetail {
             ErrorDetail::Bug(e) => e.kind(),
             ErrorDetail::Configuration(e) => e.kind(),
             ErrorDetail::KeyMgr(e) => e.kind(),
+            ErrorDetail::RelayKey { cause, .. } => cause.kind(),
+            ErrorDetail::FsMistrust { .. } => ErrorKind::FsPermissions,
+        }
+    }
+}
+
+impl ErrorDetail {
+    /// Return the broad category of this error.
+    fn category(&self) -> ErrorCategory {
+        use ErrorCategory as C;
+        use ErrorDetail as E;
+
+        // Whatever the error, a bug underneath it is still a bug.
+        if self.kind() == ErrorKind::Internal {
+            return C::Internal;
+        }
+        match self {
+            E::Bug(_) => C::Internal,
+            E::Configuration(_) | E::FsMistrust { .. } => C::Config,
+            E::KeyMgr(_) | E::RelayKey { .. } => C::Key,
         }
     }
 }
@@ -116,4 +196,33 @@ mod test {
         }
         check(); // doesn't do anything, but avoids "unused function" warnings.
     }
+
+    #[test]
+    fn categories() {
+        let bug: Error = ErrorDetail::from(tor_error::internal!("oops")).into();
+        assert_eq!(bug.category(), ErrorCategory::Internal);
+
+        let config: Error = ErrorDetail::from(tor_config::ConfigBuildError::MissingField {
+            field: "storage".into(),
+        })
+        .into();
+        assert_eq!(config.category(), ErrorCategory::Config);
+
+        let key: Error = ErrorDetail::RelayKey {
+            which: "identity",
+            cause: tor_keymgr::Error::Corruption(
+                tor_keymgr::KeystoreCorruptionError::MissingCertificate,
+            ),
+        }
+        .into();
+        assert_eq!(key.category(), ErrorCategory::Key);
+
+        // A bug inside the key manager is still a bug.
+        let key_bug: Error = ErrorDetail::RelayKey {
+            which: "signing",
+            cause: tor_error::internal!("oops").into(),
+        }
+        .into();
+        assert_eq!(key_bug.category(), ErrorCategory::Internal);
+    }
 }
@@ -0,0 +1,197 @@
+//! Configure tracing subscribers for the relay.
+
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use derive_builder::Builder;
+use fs_mistrust::Mistrust;
+use serde::{Deserialize, Serialize};
+use tor_basic_utils::PathExt as _;
+use tor_config::{impl_standard_builder, ConfigBuildError};
+use tor_config_path::{CfgPath, CfgPathResolver};
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{filter::Targets, fmt, registry, Layer};
+
+/// The environment variable that can be used to override `logging.console`.
+///
+/// Its value uses the same syntax as `logging.console`, which is similar to that of `RUST_LOG`.
+pub(crate) const LOG_FILTER_ENV_VAR: &str = "ARTI_RELAY_LOG";
+
+/// Configuration for how the relay logs.
+#[derive(Debug, Clone, Builder, Eq, PartialEq)]
+#[builder(build_fn(error = "ConfigBuildError"))]
+#[builder(derive(Debug, Serialize, Deserialize))]
+#[non_exhaustive]
+pub(crate) struct LoggingConfig {
+    /// Filtering directives that determine tracing levels as described at
+    /// <https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/targets/struct.Targets.html#impl-FromStr>
+    ///
+    /// You can override this setting with the `ARTI_RELAY_LOG` environment variable,
+    /// or with the -l, --log-level command line parameter.
+    ///
+    /// Example: "info,tor_proto::channel=trace"
+    #[builder(default = "default_console_filter()", setter(into))]
+    console: String,
+
+    /// The format in which to write log messages.
+    #[builder(default)]
+    format: LogFormat,
+
+    /// A file to write log messages to, in addition to the console.
+    ///
+    /// The file is appended to, and is never rotated.
+    ///
+    /// The default is not to log to any file.
+    #[builder(default, setter(into, strip_option))]
+    file: Option<CfgPath>,
+}
+impl_standard_builder! { LoggingConfig }
+
+/// Return a default tracing filter value for `logging.console`.
+fn default_console_filter() -> String {
+    "info".to_owned()
+}
+
+/// The format in which log messages are written.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    /// Human-readable text, one line per message.
+    #[default]
+    Pretty,
+    /// Newline-delimited JSON objects, one per message.
+    Json,
+}
+
+/// As [`Targets::from_str`], but wrapped in an [`anyhow::Result`].
+fn filt_from_str_verbose(s: &str, source: &str) -> Result<Targets> {
+    Targets::from_str(s).with_context(|| format!("in {}", source))
+}
+
+/// Return the filter to apply to our log messages.
+///
+/// The command line parameter `cli` takes precedence over the environment,
+/// which takes precedence over the configuration.
+fn log_filter(config: &LoggingConfig, cli: Option<&str>) -> Result<Targets> {
+    if let Some(cli) = cli {
+        return filt_from_str_verbose(cli, "--log-level command line parameter");
+    }
+
+    match std::env::var(LOG_FILTER_ENV_VAR) {
+        Ok(env) if !env.is_empty() => filt_from_str_verbose(&env, LOG_FILTER_ENV_VAR),
+        _ => filt_from_str_verbose(&config.console, "logging.console"),
+    }
+}
+
+/// Construct a tracing [`Layer`] that formats messages according to `format`,
+/// and writes them using `writer`.
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_ansi(ansi).with_writer(writer);
+    match format {
+        LogFormat::Pretty => Box::new(layer),
+        LogFormat::Json => Box::new(layer.json()),
+    }
+}
+
+/// Try to construct a tracing [`Layer`] for writing to the configured log file, if any.
+fn logfile_layer<S>(
+    config: &LoggingConfig,
+    filter: Targets,
+    mistrust: &Mistrust,
+) -> Result<Option<impl Layer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let Some(path) = &config.file else {
+        // Fortunately, Option<Layer> implements Layer, so we can just return None here.
+        return Ok(None);
+    };
+
+    // TODO RELAY: resolve using arti-relay-specific variables
+    let path = path.path(&CfgPathResolver::default())?;
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+    mistrust.make_directory(directory)?;
+    if path.file_name().is_none() {
+        return Err(anyhow!(
+            "No file name for log file {}",
+            path.display_lossy()
+        ));
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("open log file {}", path.display_lossy()))?;
+
+    Ok(Some(
+        fmt_layer(config.format, Mutex::new(file), false).with_filter(filter),
+    ))
+}
+
+/// Set up logging, according to `config`.
+///
+/// `cli` is the log level specified on the command line, if any.
+///
+/// `mistrust` is used to check the permissions of the directory containing the log file.
+pub(crate) fn setup_logging(
+    config: &LoggingConfig,
+    mistrust: &Mistrust,
+    cli: Option<&str>,
+) -> Result<()> {
+    let filter = log_filter(config, cli)?;
+
+    let registry = registry()
+        .with(fmt_layer(config.format, std::io::stdout, true).with_filter(filter.clone()));
+    let registry = registry.with(logfile_layer(config, filter, mistrust)?);
+
+    registry.init();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let cfg = LoggingConfig::default();
+        assert_eq!(cfg.console, "info");
+        assert_eq!(cfg.format, LogFormat::Pretty);
+        assert!(cfg.file.is_none());
+    }
+
+    #[test]
+    fn cli_overrides_config() {
+        let mut bld = LoggingConfigBuilder::default();
+        bld.console("not a valid filter=!!");
+        let cfg = bld.build().unwrap();
+
+        assert!(log_filter(&cfg, Some("debug")).is_ok());
+        assert!(log_filter(&cfg, Some("not a valid filter=!!")).is_err());
+    }
+}
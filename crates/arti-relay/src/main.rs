@@ -7,13 +7,52 @@ mod builder;
 mod cli;
 mod config;
 mod err;
+mod logging;
+mod reachability;
 mod relay;
 
+use std::process::ExitCode;
+
+use anyhow::Context;
 use clap::Parser;
+use tor_error::ErrorReport as _;
+use tor_rtcompat::BlockOn as _;
 
+use crate::config::TorRelayConfig;
+use crate::err::ErrorCategory;
 use crate::relay::TorRelay;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // This is the same format that anyhow uses when `main` returns an error.
+            eprintln!("Error: {e:?}");
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Return the exit status to use when we stop because of `e`.
+///
+/// Errors that we know how to classify get the exit status of their
+/// [`ErrorCategory`], so that a process supervisor can tell them apart.
+/// Anything else gets the generic failure status 1.
+fn exit_code(e: &anyhow::Error) -> u8 {
+    let category = if let Some(e) = e.downcast_ref::<err::Error>() {
+        Some(e.category())
+    } else if e.is::<reachability::ReachabilityError>() {
+        Some(ErrorCategory::Network)
+    } else if e.is::<tor_config::ConfigError>() || e.is::<tor_config::load::ConfigResolveError>() {
+        Some(ErrorCategory::Config)
+    } else {
+        None
+    };
+    category.map_or(1, ErrorCategory::exit_code)
+}
+
+/// Run the command given on the command line.
+fn run() -> anyhow::Result<()> {
     let cli = cli::Cli::parse();
 
     // use the tokio runtime from tor_rtcompat unless we later find a reason to use tokio directly;
@@ -33,9 +72,48 @@ fn main() -> anyhow::Result<()> {
             println!("Host triple: {}", env!("BUILD_HOST"));
         }
         cli::Commands::Run(_args) => {
-            let _relay = TorRelay::with_runtime(runtime).create()?;
+            let config = load_config(&cli)?;
+            let log_level = cli.log_level.as_ref().map(ToString::to_string);
+            logging::setup_logging(
+                &config.logging,
+                config.storage.permissions(),
+                log_level.as_deref(),
+            )
+            .context("set up logging")?;
+
+            let _relay = TorRelay::with_runtime(runtime).config(config).create()?;
+        }
+        cli::Commands::CheckReachability(args) => {
+            let addr = args.address;
+            println!("Checking whether the ORPort at {addr} is reachable...");
+            let outcome =
+                runtime.block_on(reachability::check_orport(&runtime, addr, args.timeout()));
+            match outcome {
+                Ok(elapsed) => {
+                    println!("PASS: connected to {addr} in {}ms", elapsed.as_millis());
+                    println!(
+                        "(We connected from this host, so this doesn't prove that {addr} \
+                         is reachable from outside your network.)"
+                    );
+                }
+                Err(e) => {
+                    println!("FAIL: {}", e.report());
+                    return Err(e).with_context(|| format!("ORPort at {addr} is not reachable"));
+                }
+            }
         }
     }
 
     Ok(())
 }
+
+/// Load the relay configuration from the sources specified on the command line `cli`.
+fn load_config(cli: &cli::Cli) -> anyhow::Result<TorRelayConfig> {
+    let sources = cli
+        .config_sources()
+        .context("identify default config file locations")?;
+    let cfg = sources.load().context("load configuration files")?;
+    let config = tor_config::resolve::<TorRelayConfig>(cfg).context("read configuration")?;
+
+    Ok(config)
+}
@@ -0,0 +1,111 @@
+//! A self-test to check whether our ORPort can be reached.
+//!
+//! This is roughly analogous to C Tor's reachability self-test,
+//! but it only checks that we can open a TCP connection to the ORPort:
+//! it does not (yet) check that there is a working relay behind it.
+//!
+//! Note that C Tor's self-test connects to its ORPort _through the Tor network_,
+//! so that the connection comes from outside.
+//! We connect directly, from this host,
+//! so a successful check does **not** prove that the rest of the network can reach us:
+//! the connection may never leave our own host or local network,
+//! and so it can succeed even when a firewall or NAT
+//! blocks connections from outside.
+//! A failed check, on the other hand, does suggest that something is wrong.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+use tor_rtcompat::{NetStreamProvider, SleepProvider, SleepProviderExt as _};
+
+/// A reason why our ORPort is not reachable.
+#[derive(Error, Clone, Debug)]
+#[non_exhaustive]
+pub(crate) enum ReachabilityError {
+    /// The connection attempt failed.
+    #[error("Unable to connect to {addr}")]
+    Connect {
+        /// The address we tried to connect to.
+        addr: SocketAddr,
+        /// The underlying error.
+        #[source]
+        cause: Arc<io::Error>,
+    },
+    /// The connection attempt didn't finish in time.
+    #[error("Timed out after {}s while connecting to {addr}", timeout.as_secs_f64())]
+    Timeout {
+        /// The address we tried to connect to.
+        addr: SocketAddr,
+        /// How long we waited.
+        timeout: Duration,
+    },
+}
+
+/// Check whether we can connect to the ORPort at `addr`, giving up after `timeout`.
+///
+/// On success, returns how long it took to connect.
+///
+/// This only checks that _we_ can connect to `addr`;
+/// see the [module documentation](self) for why that doesn't mean
+/// that anyone else can.
+pub(crate) async fn check_orport<R>(
+    runtime: &R,
+    addr: SocketAddr,
+    timeout: Duration,
+) -> Result<Duration, ReachabilityError>
+where
+    R: NetStreamProvider + SleepProvider,
+{
+    let started = runtime.now();
+    let stream = runtime
+        .timeout(timeout, runtime.connect(&addr))
+        .await
+        .map_err(|_| ReachabilityError::Timeout { addr, timeout })?
+        .map_err(|cause| ReachabilityError::Connect {
+            addr,
+            cause: Arc::new(cause),
+        })?;
+    let elapsed = runtime.now().saturating_duration_since(started);
+    drop(stream);
+
+    Ok(elapsed)
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use super::*;
+    use tor_rtcompat::NetStreamListener as _;
+
+    #[test]
+    fn reachable_and_not() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let timeout = Duration::from_secs(5);
+            let listener = rt.listen(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            check_orport(&rt, addr, timeout).await.unwrap();
+
+            // Once nobody is listening, we should get an error.
+            drop(listener);
+            let err = check_orport(&rt, addr, timeout).await.unwrap_err();
+            assert!(matches!(err, ReachabilityError::Connect { .. }));
+        });
+    }
+}
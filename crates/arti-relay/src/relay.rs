@@ -1,21 +1,34 @@
 //! Entry point of a Tor relay that is the [`TorRelay`] objects
 
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use tor_chanmgr::Dormancy;
 use tor_error::internal;
 use tor_keymgr::{
-    ArtiEphemeralKeystore, ArtiNativeKeystore, KeyMgr, KeyMgrBuilder, KeystoreSelector,
+    ArtiEphemeralKeystore, ArtiNativeKeystore, KeyMgr, KeyMgrBuilder, KeySpecifierPattern as _,
+    KeygenRng, KeystoreSelector,
 };
+use tor_llcrypto::pk::ed25519::Ed25519Identity;
 use tor_memquota::ArcMemoryQuotaTrackerExt as _;
 use tor_netdir::params::NetParameters;
+use tor_proto::channel::TrafficSnapshot;
 use tor_proto::memquota::ToplevelAccount;
-use tor_relay_crypto::pk::{RelayIdentityKeypair, RelayIdentityKeypairSpecifier};
+use tor_relay_crypto::pk::{
+    RelayIdentityKeypair, RelayIdentityKeypairSpecifier, RelaySigningKeypair,
+    RelaySigningKeypairSpecifier, RelaySigningKeypairSpecifierPattern,
+};
 use tor_rtcompat::{PreferredRuntime, Runtime};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{builder::TorRelayBuilder, config::TorRelayConfig, err::ErrorDetail};
 
+/// How long a newly generated relay signing key (`KP_relaysign_ed`) stays valid.
+///
+/// This is the same as the default `SigningKeyLifetime` in C Tor.
+// TODO RELAY: make this configurable, and rotate the key before it expires.
+const SIGNING_KEY_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
 /// Represent an active Relay on the Tor network.
 #[derive(Clone)]
 pub struct TorRelay<R: Runtime> {
@@ -28,6 +41,8 @@ pub struct TorRelay<R: Runtime> {
     /// Key manager holding all relay keys and certificates.
     #[allow(unused)] // TODO RELAY remove
     keymgr: Arc<KeyMgr>,
+    /// Our long-term Ed25519 identity (`KP_relayid_ed`).
+    identity: Ed25519Identity,
 }
 
 #[allow(unused)] // TODO: Remove me when used.
@@ -62,7 +77,9 @@ impl<R: Runtime> TorRelay<R> {
 
     /// Return a TorRelay object.
     pub(crate) fn create_inner(runtime: R, config: &TorRelayConfig) -> Result<Self, ErrorDetail> {
-        let keymgr = Self::create_keymgr(config)?;
+        Self::check_directories(config)?;
+        let (keymgr, identity) = Self::create_keymgr(config, runtime.wallclock())?;
+        info!("Our Ed25519 identity is {identity}");
         let chanmgr = Arc::new(tor_chanmgr::ChanMgr::new(
             runtime.clone(),
             &config.channel,
@@ -70,14 +87,60 @@ impl<R: Runtime> TorRelay<R> {
             &NetParameters::from_map(&config.override_net_params),
             ToplevelAccount::new_noop(), // TODO RELAY get mq from TorRelay
         ));
+        chanmgr.set_bandwidth_limit(config.bandwidth.limit());
         Ok(Self {
             runtime,
             chanmgr,
             keymgr,
+            identity,
         })
     }
 
-    fn create_keymgr(config: &TorRelayConfig) -> Result<Arc<KeyMgr>, ErrorDetail> {
+    /// Return the fingerprint of this relay's long-term Ed25519 identity key.
+    ///
+    /// This displays in the standard unpadded base64 format,
+    /// as found in the `fingerprint-ed25519` file of a C Tor relay
+    /// and in the `id ed25519` lines of the consensus.
+    //
+    // TODO RELAY: also return the RSA identity fingerprint, once we have an RSA identity key.
+    pub fn identity_fingerprint(&self) -> Ed25519Identity {
+        self.identity
+    }
+
+    /// Return the total number of bytes that this relay has read and written on its channels.
+    ///
+    /// The counts start at zero when the relay is created, and only ever increase.
+    pub fn traffic(&self) -> TrafficSnapshot {
+        self.chanmgr.traffic()
+    }
+
+    /// Create our state and cache directories if they are missing,
+    /// and make sure that their permissions are acceptable
+    /// according to `storage.permissions`.
+    fn check_directories(config: &TorRelayConfig) -> Result<(), ErrorDetail> {
+        let permissions = config.storage.permissions();
+        let dirs = [
+            ("state", config.storage.state_dir()?),
+            ("cache", config.storage.cache_dir()?),
+        ];
+
+        for (which, dir) in dirs {
+            permissions
+                .make_directory(&dir)
+                .map_err(|cause| ErrorDetail::FsMistrust { which, cause })?;
+        }
+
+        Ok(())
+    }
+
+    /// Create the key manager for the keystore in `storage.keystore_dir`,
+    /// and make sure that it holds all of the keys we need at `now`.
+    ///
+    /// Returns the key manager and our Ed25519 identity.
+    fn create_keymgr(
+        config: &TorRelayConfig,
+        now: SystemTime,
+    ) -> Result<(Arc<KeyMgr>, Ed25519Identity), ErrorDetail> {
         let key_store_dir = config.storage.keystore_dir()?;
         let permissions = config.storage.permissions();
 
@@ -97,31 +160,179 @@ impl<R: Runtime> TorRelay<R> {
         );
 
         // Attempt to generate any missing keys/cert from the KeyMgr.
-        Self::try_generate_keys(&keymgr)?;
+        let identity = Self::try_generate_keys(&keymgr, now)?;
 
-        Ok(keymgr)
+        Ok((keymgr, identity))
     }
 
-    fn try_generate_keys(keymgr: &KeyMgr) -> Result<(), ErrorDetail> {
+    /// Load the relay's keys from `keymgr`, generating any that are missing.
+    ///
+    /// Existing keys are never overwritten.
+    ///
+    /// Returns our Ed25519 identity.
+    fn try_generate_keys(keymgr: &KeyMgr, now: SystemTime) -> Result<Ed25519Identity, ErrorDetail> {
         let mut rng = rand::thread_rng();
 
         // Attempt to get the relay long-term identity key from the key manager. If not present,
         // generate it. We need this key to sign the signing certificates.
-        let _kp_relay_id = keymgr.get_or_generate::<RelayIdentityKeypair>(
-            &RelayIdentityKeypairSpecifier::new(),
-            KeystoreSelector::default(),
-            &mut rng,
-        )?;
+        let kp_relay_id = keymgr
+            .get_or_generate::<RelayIdentityKeypair>(
+                &RelayIdentityKeypairSpecifier::new(),
+                KeystoreSelector::default(),
+                &mut rng,
+            )
+            .map_err(|cause| ErrorDetail::RelayKey {
+                which: "identity",
+                cause,
+            })?;
+
+        let _kp_relaysign =
+            Self::get_or_generate_signing_key(keymgr, now, &mut rng).map_err(|cause| {
+                ErrorDetail::RelayKey {
+                    which: "signing",
+                    cause,
+                }
+            })?;
 
         // TODO: Once certificate supports is added to the KeyMgr, we need to get/gen the
         // RelaySigning (KP_relaysign_ed) certs from the native persistent store.
-        //
-        // If present, rotate it if expired. Else, generate it. Rotation or creation require the
-        // relay identity keypair (above) in order to sign the RelaySigning.
+        // Creating them requires the relay identity keypair (above).
         //
         // We then need to generate the RelayLink (KP_link_ed) certificate which is in turn signed
         // by the RelaySigning cert.
 
-        Ok(())
+        Ok(kp_relay_id.to_ed25519_id())
+    }
+
+    /// Return the relay signing keypair that expires last, among those in `keymgr`
+    /// that have not expired at `now`.
+    ///
+    /// If there is no such keypair, generate one that expires [`SIGNING_KEY_LIFETIME`]
+    /// after `now`, and store it in the default keystore.
+    fn get_or_generate_signing_key(
+        keymgr: &KeyMgr,
+        now: SystemTime,
+        rng: &mut dyn KeygenRng,
+    ) -> tor_keymgr::Result<RelaySigningKeypair> {
+        let pattern = RelaySigningKeypairSpecifierPattern::new_any().arti_pattern()?;
+        let entries = keymgr.list_matching(&pattern)?;
+        let newest = entries
+            .iter()
+            .filter_map(
+                |entry| match RelaySigningKeypairSpecifier::try_from(entry.key_path()) {
+                    Ok(spec) => Some((spec.valid_until(), entry)),
+                    Err(e) => {
+                        warn!("Ignoring unrecognized relay signing key: {e}");
+                        None
+                    }
+                },
+            )
+            .filter(|(valid_until, _)| *valid_until > now)
+            .max_by_key(|(valid_until, _)| *valid_until);
+
+        if let Some((_, entry)) = newest {
+            // If the key vanished since we listed it, we'll just make a new one.
+            if let Some(key) = keymgr.get_entry::<RelaySigningKeypair>(entry)? {
+                return Ok(key);
+            }
+        }
+
+        let spec = RelaySigningKeypairSpecifier::new((now + SIGNING_KEY_LIFETIME).into());
+        info!("Generating a new relay signing key");
+        keymgr.generate::<RelaySigningKeypair>(&spec, KeystoreSelector::default(), rng, false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use super::*;
+
+    /// Return a `KeyMgr` that only has an ephemeral keystore.
+    fn ephemeral_keymgr() -> KeyMgr {
+        KeyMgrBuilder::default()
+            .primary_store(Box::new(ArtiEphemeralKeystore::new("test".into())))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn generate_then_load() {
+        let keymgr = ephemeral_keymgr();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let identity = TorRelay::<PreferredRuntime>::try_generate_keys(&keymgr, now).unwrap();
+        let get_id = || {
+            keymgr
+                .get::<RelayIdentityKeypair>(&RelayIdentityKeypairSpecifier::new())
+                .unwrap()
+                .unwrap()
+                .to_ed25519_id()
+        };
+        let get_signing = |now| {
+            TorRelay::<PreferredRuntime>::get_or_generate_signing_key(
+                &keymgr,
+                now,
+                &mut rand::thread_rng(),
+            )
+            .unwrap()
+            .to_ed25519_id()
+        };
+        let id = get_id();
+        assert_eq!(id, identity);
+        let signing = get_signing(now);
+
+        // Running again must load the same keys, not replace them.
+        TorRelay::<PreferredRuntime>::try_generate_keys(&keymgr, now).unwrap();
+        assert_eq!(get_id(), id);
+        assert_eq!(get_signing(now + Duration::from_secs(86400)), signing);
+
+        // Once the signing key has expired, we get a new one, but keep the identity.
+        let later = now + SIGNING_KEY_LIFETIME + Duration::from_secs(1);
+        let new_signing = get_signing(later);
+        assert_ne!(new_signing, signing);
+        assert_eq!(get_signing(later), new_signing);
+        assert_eq!(get_id(), id);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuse_bad_permissions() {
+        use crate::config::TorRelayConfigBuilder;
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        use tor_config_path::CfgPath;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state_dir = dir.path().join("state");
+        let mut bld = TorRelayConfigBuilder::default();
+        bld.storage()
+            .cache_dir(CfgPath::new_literal(dir.path().join("cache")))
+            .state_dir(CfgPath::new_literal(&state_dir));
+        let config = bld.build().unwrap();
+
+        // The directories get created, with acceptable permissions.
+        TorRelay::<PreferredRuntime>::check_directories(&config).unwrap();
+
+        // Anybody can read and write the state directory: refuse to use it.
+        fs::set_permissions(&state_dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let err = TorRelay::<PreferredRuntime>::check_directories(&config).unwrap_err();
+        assert!(matches!(
+            err,
+            ErrorDetail::FsMistrust { which: "state", .. }
+        ));
     }
 }
@@ -9,7 +9,7 @@ use std::{
 };
 
 use crate::msgs::{
-    request::InvalidRequestError,
+    request::{InvalidRequestError, Request},
     response::{ResponseKind, RpcError, ValidatedResponse},
     AnyRequestId, ObjectId,
 };
@@ -187,6 +187,24 @@ impl RpcConn {
         self.session.as_ref()
     }
 
+    /// Check that Arti is still answering on this connection, by sending it an `rpc:ping`.
+    ///
+    /// You can use this to keep the connection from looking idle
+    /// if there is a proxy or firewall between you and Arti
+    /// that closes idle connections.
+    /// We recommend calling this after 30 seconds with no other requests.
+    ///
+    /// This works whether or not the connection has been authenticated.
+    pub fn ping(&self) -> Result<(), ProtoError> {
+        let request = Request::new(
+            ObjectId::connection_id(),
+            "rpc:ping",
+            stream::NoParameters {},
+        );
+        let _pong: stream::EmptyResponse = self.execute_internal_ok(&request.encode()?)?;
+        Ok(())
+    }
+
     /// Run a command, and wait for success or failure.
     ///
     /// Note that this function will return `Err(.)` only if sending the command or getting a
@@ -427,6 +445,11 @@ pub enum ConnectError {
     /// The connect point uses an RPC authentication type we don't support.
     #[error("Authentication type is not supported")]
     AuthenticationNotSupported,
+    /// Arti could not prove that it knows our authentication secret.
+    ///
+    /// This might mean that we are talking to something other than the Arti we expected.
+    #[error("Arti did not prove that it knows the authentication cookie")]
+    ServerAuthenticationFailed,
     /// We couldn't decode one of the responses we got.
     #[error("Message not in expected format: {0:?}")]
     BadMessage(#[source] Arc<serde_json::Error>),
@@ -453,6 +476,7 @@ impl HasClientErrorAction for ConnectError {
             E::ProtoError(e) => e.client_action(),
             E::AllAttemptsDeclined => A::Abort,
             E::AuthenticationNotSupported => A::Decline,
+            E::ServerAuthenticationFailed => A::Abort,
         }
     }
 }
@@ -581,6 +605,128 @@ mod test {
         assert_eq!(map.get("xyz"), Some(&serde_json::Value::Number(3.into())));
     }
 
+    #[test]
+    fn ping() {
+        let (conn, sock) = dummy_connected();
+
+        let user_thread = thread::spawn(move || {
+            conn.ping().unwrap();
+            conn
+        });
+
+        let fake_arti_thread = thread::spawn(move || {
+            let mut sock = BufReader::new(sock);
+            let mut s = String::new();
+            let _len = sock.read_line(&mut s).unwrap();
+            let request: serde_json::Value = serde_json::from_str(&s).unwrap();
+            assert_eq!(request["obj"], "connection");
+            assert_eq!(request["method"], "rpc:ping");
+            let response = serde_json::json!({
+                "id": request["id"].clone(),
+                "result": {}
+            });
+            write_val(sock.get_mut(), &response);
+            sock // prevent close
+        });
+
+        let _sock = fake_arti_thread.join().unwrap();
+        let _conn = user_thread.join().unwrap();
+    }
+
+    /// Helper: Run cookie authentication against a fake Arti that knows `server_cookie`.
+    ///
+    /// Return the client's result.
+    fn cookie_auth_with(
+        client_cookie: tor_rpc_connect::auth::Cookie,
+        server_cookie: tor_rpc_connect::auth::Cookie,
+    ) -> Result<ObjectId, ConnectError> {
+        use tor_rpc_connect::auth::{CookieAuthMac, CookieAuthNonce};
+        const ADDR: &str = "inet:127.0.0.1:9180";
+
+        let (conn, sock) = dummy_connected();
+
+        let user_thread = thread::spawn(move || {
+            let result = conn.authenticate_cookie(&client_cookie, ADDR);
+            (result, conn)
+        });
+
+        let fake_arti_thread = thread::spawn(move || {
+            let mut sock = BufReader::new(sock);
+            let mut s = String::new();
+            let _len = sock.read_line(&mut s).unwrap();
+            let request: serde_json::Value = serde_json::from_str(&s).unwrap();
+            assert_eq!(request["obj"], "connection");
+            assert_eq!(request["method"], "auth:cookie_begin");
+            let client_nonce: CookieAuthNonce =
+                serde_json::from_value(request["params"]["client_nonce"].clone()).unwrap();
+            let server_nonce = CookieAuthNonce::new(&mut testing_rng());
+            let response = serde_json::json!({
+                "id": request["id"].clone(),
+                "result": {
+                    "cookie_auth": "cookie-auth-1",
+                    "server_addr": ADDR,
+                    "server_mac": server_cookie.server_mac(ADDR, &client_nonce),
+                    "server_nonce": server_nonce,
+                }
+            });
+            write_val(sock.get_mut(), &response);
+
+            s.clear();
+            if sock.read_line(&mut s).unwrap() == 0 {
+                // The client gave up.
+                return sock;
+            }
+            let request: serde_json::Value = serde_json::from_str(&s).unwrap();
+            assert_eq!(request["obj"], "cookie-auth-1");
+            assert_eq!(request["method"], "auth:cookie_continue");
+            let client_mac: CookieAuthMac =
+                serde_json::from_value(request["params"]["client_mac"].clone()).unwrap();
+            let response = if client_mac == server_cookie.client_mac(ADDR, &server_nonce) {
+                serde_json::json!({
+                    "id": request["id"].clone(),
+                    "result": { "session": "session-1" }
+                })
+            } else {
+                serde_json::json!({
+                    "id": request["id"].clone(),
+                    "error": {
+                        "message": "Incorrect authentication value",
+                        "code": 1,
+                        "kinds": ["arti:LocalProtocolViolation"],
+                    }
+                })
+            };
+            write_val(sock.get_mut(), &response);
+            sock // prevent close
+        });
+
+        let (result, conn) = user_thread.join().unwrap();
+        drop(conn);
+        let _sock = fake_arti_thread.join().unwrap();
+        result
+    }
+
+    /// Helper: Create a new cookie file called `name` in `dir`.
+    fn new_cookie(dir: &tempfile::TempDir, name: &str) -> tor_rpc_connect::auth::Cookie {
+        let mistrust = fs_mistrust::Mistrust::new_dangerously_trust_everyone();
+        tor_rpc_connect::auth::Cookie::create(&dir.path().join(name), &mut testing_rng(), &mistrust)
+            .unwrap()
+    }
+
+    #[test]
+    fn cookie_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let cookie = new_cookie(&dir, "cookie");
+        let other_cookie = new_cookie(&dir, "other_cookie");
+
+        let session = cookie_auth_with(cookie.clone(), cookie.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&session).unwrap(), "session-1");
+
+        // If Arti can't prove that it knows our cookie, we don't send a proof of our own.
+        let err = cookie_auth_with(cookie, other_cookie).unwrap_err();
+        assert!(matches!(err, ConnectError::ServerAuthenticationFailed));
+    }
+
     #[test]
     fn complex() {
         use std::sync::atomic::Ordering::SeqCst;
@@ -1,6 +1,7 @@
 //! Authentication for RpcConn.
 
 use serde::{Deserialize, Serialize};
+use tor_rpc_connect::auth::{Cookie, CookieAuthMac, CookieAuthNonce};
 
 use crate::msgs::{request::Request, ObjectId};
 
@@ -19,6 +20,30 @@ struct Authenticated {
     session: ObjectId,
 }
 
+/// Arguments to an `auth:cookie_begin` request.
+#[derive(Serialize, Debug)]
+struct CookieBeginParams<'a> {
+    /// A random nonce that we chose.
+    client_nonce: &'a CookieAuthNonce,
+}
+/// Response to an `auth:cookie_begin` request.
+#[derive(Deserialize, Debug)]
+struct CookieBeginReply {
+    /// An object that we use for the `auth:cookie_continue` request.
+    cookie_auth: ObjectId,
+    /// Arti's proof that it knows the cookie.
+    server_mac: CookieAuthMac,
+    /// A random nonce that Arti chose.
+    server_nonce: CookieAuthNonce,
+}
+
+/// Arguments to an `auth:cookie_continue` request.
+#[derive(Serialize, Debug)]
+struct CookieContinueParams<'a> {
+    /// Our proof that we know the cookie.
+    client_mac: &'a CookieAuthMac,
+}
+
 impl RpcConn {
     /// Try to negotiate "inherent" authentication, using the provided scheme name.
     ///
@@ -40,4 +65,50 @@ impl RpcConn {
 
         Ok(authenticated.session)
     }
+
+    /// Try to negotiate "cookie" authentication, using the provided cookie and server address.
+    ///
+    /// (Cookie authentication is available whenever the client proves that they
+    /// are authorized by showing that they can read a secret cookie file.
+    /// Arti proves that it can read the same cookie file in return.)
+    ///
+    /// The `server_addr` must be the address that Arti thinks it is listening on,
+    /// as given in the connect point.
+    pub(crate) fn authenticate_cookie(
+        &self,
+        cookie: &Cookie,
+        server_addr: &str,
+    ) -> Result<ObjectId, ConnectError> {
+        let client_nonce = CookieAuthNonce::new(&mut rand::thread_rng());
+        let r: Request<CookieBeginParams> = Request::new(
+            ObjectId::connection_id(),
+            "auth:cookie_begin",
+            CookieBeginParams {
+                client_nonce: &client_nonce,
+            },
+        );
+        let begin: CookieBeginReply = self
+            .execute_internal(&r.encode()?)?
+            .map_err(ConnectError::AuthenticationRejected)?;
+
+        // Make sure that we're talking to somebody who can read the cookie
+        // before we prove that we can read it too.
+        if begin.server_mac != cookie.server_mac(server_addr, &client_nonce) {
+            return Err(ConnectError::ServerAuthenticationFailed);
+        }
+
+        let client_mac = cookie.client_mac(server_addr, &begin.server_nonce);
+        let r: Request<CookieContinueParams> = Request::new(
+            begin.cookie_auth,
+            "auth:cookie_continue",
+            CookieContinueParams {
+                client_mac: &client_mac,
+            },
+        );
+        let authenticated: Authenticated = self
+            .execute_internal(&r.encode()?)?
+            .map_err(ConnectError::AuthenticationRejected)?;
+
+        Ok(authenticated.session)
+    }
 }
@@ -174,19 +174,20 @@ fn try_connect(
         auth,
         ..
     } = parsed.resolve(resolver)?.connect(mistrust)?;
-    match auth {
-        RpcAuth::None => {}
-        // TODO RPC: Implement cookie auth.
-        _ => return Err(ConnectError::AuthenticationNotSupported),
-    }
     let mut conn = RpcConn::new(
         llconn::Reader::new(io::BufReader::new(reader)),
         llconn::Writer::new(writer),
     );
 
-    // TODO RPC: remove this "scheme name" from the protocol?
-    // This will get refactored when we do cookie auth.
-    let session_id = conn.authenticate_inherent("inherent:unix_path")?;
+    let session_id = match auth {
+        // TODO RPC: remove this "scheme name" from the protocol?
+        RpcAuth::None => conn.authenticate_inherent("inherent:unix_path")?,
+        RpcAuth::Cookie {
+            secret,
+            server_address,
+        } => conn.authenticate_cookie(&secret, &server_address)?,
+        _ => return Err(ConnectError::AuthenticationNotSupported),
+    };
     conn.session = Some(session_id);
 
     Ok(conn)
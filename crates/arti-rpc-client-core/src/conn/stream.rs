@@ -79,7 +79,7 @@ impl From<IoError> for StreamError {
 
 /// Arguments to a request that takes no parameters.
 #[derive(Serialize, Debug)]
-struct NoParameters {}
+pub(super) struct NoParameters {}
 
 /// A response with a single ID.
 #[derive(Deserialize, Debug)]
@@ -90,7 +90,7 @@ struct SingleIdResponse {
 
 /// A response with no data.
 #[derive(Deserialize, Debug)]
-struct EmptyResponse {}
+pub(super) struct EmptyResponse {}
 
 /// Representation of a single proxy, as delivered by the RPC API.
 // TODO RPC: This is duplicated from proxyinfo.rs; decide on our strategy for this stuff.
@@ -0,0 +1,99 @@
+//! Objects that wrap a [`ClientCirc`](tor_proto::circuit::ClientCirc).
+
+use derive_deftly::Deftly;
+use std::sync::{Arc, Weak};
+use tor_proto::circuit::ClientCirc;
+use tor_rpcbase::{self as rpc, templates::*};
+
+/// An RPC object representing a circuit.
+///
+/// This object is returned by the `arti:x_get_stream_circuit` method.
+///
+/// Holding this object does not keep the circuit open:
+/// once the circuit is closed, methods on this object will fail.
+#[derive(Deftly)]
+#[derive_deftly(Object)]
+pub(crate) struct RpcCircuit {
+    /// The circuit itself.
+    circ: Weak<ClientCirc>,
+}
+
+/// Error returned by an operation on an RpcCircuit.
+#[derive(Debug, Clone, thiserror::Error)]
+enum CircuitError {
+    /// The circuit has been closed and dropped.
+    #[error("Circuit is no longer available")]
+    Gone,
+
+    /// We couldn't close the streams on a circuit.
+    #[error("Unable to close streams on circuit")]
+    CloseStreams(#[source] tor_proto::Error),
+}
+
+impl tor_error::HasKind for CircuitError {
+    fn kind(&self) -> tor_error::ErrorKind {
+        use tor_error::ErrorKind as EK;
+        use CircuitError as E;
+        match self {
+            E::Gone => EK::BadApiUsage,
+            E::CloseStreams(e) => tor_error::HasKind::kind(e),
+        }
+    }
+}
+
+impl RpcCircuit {
+    /// Construct a new RpcCircuit referring to `circ`.
+    pub(crate) fn new(circ: &Arc<ClientCirc>) -> Self {
+        Self {
+            circ: Arc::downgrade(circ),
+        }
+    }
+
+    /// Return the circuit, if it is still around.
+    fn circ(&self) -> Result<Arc<ClientCirc>, CircuitError> {
+        self.circ.upgrade().ok_or(CircuitError::Gone)
+    }
+}
+
+/// Close every stream on a circuit, without closing the circuit itself.
+///
+/// The target must be a circuit handle, as returned by `arti:x_get_stream_circuit`.
+/// Each stream on the circuit is closed gracefully, by sending an END message.
+///
+/// Returns the number of streams that were closed.
+///
+/// **This is an experimental method.**
+#[derive(Debug, serde::Deserialize, serde::Serialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "arti:x_close_circuit_streams"))]
+struct CloseCircuitStreams {}
+
+impl rpc::RpcMethod for CloseCircuitStreams {
+    type Output = ClosedStreams;
+    type Update = rpc::NoUpdates;
+}
+
+/// The result of a [`CloseCircuitStreams`] request.
+#[derive(Debug, serde::Serialize)]
+struct ClosedStreams {
+    /// The number of streams that were closed.
+    n_closed: usize,
+}
+
+/// Implement CloseCircuitStreams for RpcCircuit.
+async fn rpccircuit_close_circuit_streams(
+    rpc_circuit: Arc<RpcCircuit>,
+    _method: Box<CloseCircuitStreams>,
+    _ctx: Arc<dyn rpc::Context>,
+) -> Result<ClosedStreams, CircuitError> {
+    let n_closed = rpc_circuit
+        .circ()?
+        .close_all_streams()
+        .await
+        .map_err(CircuitError::CloseStreams)?;
+    Ok(ClosedStreams { n_closed })
+}
+
+rpc::static_rpc_invoke_fn! {
+    rpccircuit_close_circuit_streams;
+}
@@ -1,12 +1,14 @@
 //! RPC connection support, mainloop, and protocol implementation.
 
 pub(crate) mod auth;
+mod ping;
 
 use std::{
     collections::HashMap,
+    future::Future,
     io::Error as IoError,
     pin::Pin,
-    sync::{Arc, Mutex, RwLock, Weak},
+    sync::{atomic::AtomicBool, Arc, Mutex, RwLock, Weak},
 };
 
 use asynchronous_codec::JsonCodecError;
@@ -78,6 +80,16 @@ pub struct Connection {
 
     /// A reference to the manager associated with this session.
     mgr: Weak<RpcMgr>,
+
+    /// The authentication that a client must perform on this connection
+    /// before it can get an RPC session.
+    require_auth: tor_rpc_connect::auth::RpcAuth,
+
+    /// True if a client has already begun cookie authentication on this connection.
+    ///
+    /// We only allow one attempt per connection,
+    /// so that an unauthenticated client can't make us hold an unbounded number of them.
+    cookie_auth_begun: AtomicBool,
 }
 
 /// The inner, lock-protected part of an RPC connection.
@@ -152,6 +164,7 @@ impl Connection {
         dispatch_table: Arc<RwLock<rpc::DispatchTable>>,
         global_id_mac_key: MacKey,
         mgr: Weak<RpcMgr>,
+        require_auth: tor_rpc_connect::auth::RpcAuth,
     ) -> Arc<Self> {
         Arc::new_cyclic(|this_connection| Self {
             inner: Mutex::new(Inner {
@@ -163,6 +176,8 @@ impl Connection {
             connection_id,
             global_id_mac_key,
             mgr,
+            require_auth,
+            cookie_auth_begun: AtomicBool::new(false),
         })
     }
 
@@ -253,6 +268,31 @@ impl Connection {
     where
         IN: futures::AsyncRead + Send + Sync + Unpin + 'static,
         OUT: futures::AsyncWrite + Send + Sync + Unpin + 'static,
+    {
+        self.run_until_shutdown(input, output, futures::future::pending())
+            .await
+    }
+
+    /// As [`run`](Connection::run), but shut down the connection gracefully
+    /// once `shutdown` resolves.
+    ///
+    /// When `shutdown` resolves, we stop reading new requests,
+    /// and wait for every request that is already running to finish.
+    /// Then we send the client an error response with no request ID,
+    /// to tell it that we are shutting down, and return.
+    ///
+    /// Note that some requests (like those that watch for updates) never finish on their own;
+    /// the caller may need to give up on the connection if it takes too long to drain.
+    pub async fn run_until_shutdown<IN, OUT, SHUT>(
+        self: Arc<Self>,
+        input: IN,
+        output: OUT,
+        shutdown: SHUT,
+    ) -> Result<(), ConnectionError>
+    where
+        IN: futures::AsyncRead + Send + Sync + Unpin + 'static,
+        OUT: futures::AsyncWrite + Send + Sync + Unpin + 'static,
+        SHUT: Future<Output = ()> + Send,
     {
         let write = Box::pin(asynchronous_codec::FramedWrite::new(
             output,
@@ -267,17 +307,21 @@ impl Connection {
             .fuse(),
         );
 
-        self.run_loop(read, write).await
+        self.run_loop(read, write, shutdown).await
     }
 
     /// Run in a loop, handling requests from `request_stream` and writing
     /// responses onto `response_stream`.
     ///
     /// After this returns, even if it returns `Ok(())`, the connection must no longer be used.
+    ///
+    /// Once `shutdown` resolves, we stop reading requests, and return
+    /// once every request that was already running has finished.
     pub(crate) async fn run_loop(
         self: Arc<Self>,
         mut request_stream: BoxedRequestStream,
         mut response_sink: BoxedResponseSink,
+        shutdown: impl Future<Output = ()>,
     ) -> Result<(), ConnectionError> {
         // This function will multiplex on three streams:
         // * `request_stream` -- a stream of incoming requests from the client.
@@ -300,11 +344,19 @@ impl Connection {
         /// Helper: enforce an explicit "continue".
         struct Continue;
 
+        let shutdown = shutdown.fuse();
+        futures::pin_mut!(shutdown);
+
         // We create a separate async block here and immediately await it,
         // so that any internal `returns` and `?`s do not escape the function.
         let outcome = async {
             loop {
                 let _: Continue = futures::select! {
+                    () = shutdown.as_mut() => {
+                        // Stop reading requests, and drain the ones we have.
+                        break;
+                    }
+
                     r = finished_requests.next() => {
                         // A task is done, so we can forget about it.
                         let () = r.expect("Somehow, future::pending() terminated.");
@@ -364,6 +416,37 @@ impl Connection {
                     }
                 };
             }
+
+            // We're shutting down: wait for the requests that are still running.
+            // (One future in `finished_requests` is our `pending()` placeholder.)
+            while finished_requests.len() > 1 {
+                let _: Continue = futures::select! {
+                    r = finished_requests.next() => {
+                        let () = r.expect("Somehow, future::pending() terminated.");
+                        Continue
+                    }
+                    r = rx_response.next() => {
+                        let update = r.expect("Somehow, tx_update got closed.");
+                        response_sink.send(update).await.map_err(ConnectionError::writing)?;
+                        Continue
+                    }
+                };
+            }
+            // Every finished request has queued its final response by now.
+            while let Ok(Some(response)) = rx_response.try_next() {
+                response_sink
+                    .send(response)
+                    .await
+                    .map_err(ConnectionError::writing)?;
+            }
+
+            // Tell the client why we're closing.
+            let response = BoxedResponse::from_error(None, ShuttingDown);
+            response_sink
+                .send(response)
+                .await
+                .map_err(ConnectionError::writing)?;
+            Ok(())
         }
         .await;
 
@@ -650,6 +733,16 @@ impl rpc::Context for Connection {
     }
 }
 
+/// An error sent to the client when we close its connection because we are shutting down.
+#[derive(thiserror::Error, Clone, Debug)]
+#[error("Arti is shutting down")]
+struct ShuttingDown;
+impl tor_error::HasKind for ShuttingDown {
+    fn kind(&self) -> tor_error::ErrorKind {
+        tor_error::ErrorKind::ArtiShuttingDown
+    }
+}
+
 /// An error given when an RPC request is cancelled.
 ///
 /// This is a separate type from [`crate::cancel::Cancelled`] since eventually
@@ -664,3 +757,89 @@ impl tor_error::HasKind for RequestCancelled {
         tor_error::ErrorKind::Other
     }
 }
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use std::task::Poll;
+
+    use futures::channel::oneshot;
+    use futures::SinkExt as _;
+    use futures_await_test::async_test;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Parse `json` as a request.
+    fn request(json: serde_json::Value) -> Result<FlexibleRequest, JsonCodecError> {
+        Ok(serde_json::from_str(&json.to_string()).unwrap())
+    }
+
+    #[async_test]
+    async fn run_until_shutdown_drains() {
+        let mgr = RpcMgr::new(|_| -> Arc<dyn rpc::Object> { unimplemented!() }).unwrap();
+        let conn = mgr.new_connection(tor_rpc_connect::auth::RpcAuth::None);
+
+        let release_tx = crate::test_utils::hold_next_wait();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let (request_tx, request_rx) = mpsc::unbounded();
+        let (response_tx, mut response_rx) = mpsc::unbounded::<BoxedResponse>();
+        let response_sink =
+            response_tx.sink_map_err(|_| JsonCodecError::Io(std::io::ErrorKind::BrokenPipe.into()));
+        let shutdown = async move {
+            let _ = shutdown_rx.await;
+        };
+        let mut run =
+            Box::pin(conn.run_loop(Box::pin(request_rx), Box::pin(response_sink), shutdown));
+        let mut next_response = || {
+            response_rx
+                .try_next()
+                .ok()
+                .flatten()
+                .map(|r| serde_json::to_value(r).unwrap())
+        };
+
+        // Start a request that won't finish yet, and one that finishes right away.
+        request_tx
+            .unbounded_send(request(json!({
+                "id": 1, "obj": "connection", "method": "x-test:wait", "params": {}
+            })))
+            .unwrap();
+        request_tx
+            .unbounded_send(request(json!({
+                "id": 2, "obj": "connection", "method": "rpc:ping", "params": {}
+            })))
+            .unwrap();
+        assert!(futures::poll!(&mut run).is_pending());
+        assert_eq!(next_response(), Some(json!({ "id": 2, "result": {} })));
+        assert_eq!(next_response(), None);
+
+        // Once we're told to shut down, we wait for the running request.
+        shutdown_tx.send(()).unwrap();
+        assert!(futures::poll!(&mut run).is_pending());
+        assert_eq!(next_response(), None);
+
+        // When it finishes, we send its response, tell the client why we're closing,
+        // and stop.
+        release_tx.send(()).unwrap();
+        assert!(matches!(futures::poll!(&mut run), Poll::Ready(Ok(()))));
+        assert_eq!(next_response(), Some(json!({ "id": 1, "result": {} })));
+        let closing = next_response().unwrap();
+        assert_eq!(closing.get("id"), None);
+        assert_eq!(closing["error"]["kinds"], json!(["arti:ArtiShuttingDown"]));
+        assert_eq!(next_response(), None);
+    }
+}
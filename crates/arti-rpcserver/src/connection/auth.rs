@@ -4,10 +4,13 @@
 //! on the special "connection" object, which gives you an RPC _session_ as a
 //! result.  The RPC session is the root for all other capabilities.
 
+mod cookie;
+
 use std::sync::Arc;
 
 use super::Connection;
 use derive_deftly::Deftly;
+use tor_rpc_connect::auth::RpcAuth;
 use tor_rpcbase as rpc;
 use tor_rpcbase::templates::*;
 
@@ -70,9 +73,9 @@ mod get_rpc_protocol {
 
 /// Information about how an RPC session has been authenticated.
 ///
-/// Currently, this isn't actually used for anything, since there's only one way
-/// to authenticate a connection.  It exists so that later we can pass
-/// information to the session-creator function.
+/// Currently, this isn't actually used for anything, since every way
+/// to authenticate a connection grants the same access.  It exists so that
+/// later we can pass information to the session-creator function.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct RpcAuthentication {}
@@ -82,12 +85,31 @@ pub struct RpcAuthentication {}
 /// Conceptually, an authentication scheme answers the question "How can the
 /// Arti process know you have permissions to use or administer it?"
 ///
-/// TODO RPC: The only supported one for now is "inherent:unix_path"
+/// Which schemes a connection accepts depends on the connect point
+/// at which the client connected.
 #[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 enum AuthenticationScheme {
     /// Inherent authority based on the ability to access an AF_UNIX address.
     #[serde(rename = "inherent:unix_path")]
     InherentUnixPath,
+    /// Authority based on the ability to read a cookie file.
+    ///
+    /// This scheme uses the `auth:cookie_begin` and `auth:cookie_continue` methods,
+    /// not `auth:authenticate`.
+    #[serde(rename = "fs:cookie")]
+    Cookie,
+}
+
+impl Connection {
+    /// Return the authentication scheme that clients must use on this connection,
+    /// if we support any.
+    fn auth_scheme(&self) -> Option<AuthenticationScheme> {
+        match &self.require_auth {
+            RpcAuth::None => Some(AuthenticationScheme::InherentUnixPath),
+            RpcAuth::Cookie { .. } => Some(AuthenticationScheme::Cookie),
+            _ => None,
+        }
+    }
 }
 
 /// Ask which authentication methods are supported.
@@ -120,13 +142,13 @@ impl rpc::RpcMethod for AuthQuery {
 }
 /// Implement `auth:AuthQuery` on a connection.
 async fn conn_authquery(
-    _conn: Arc<Connection>,
+    conn: Arc<Connection>,
     _query: Box<AuthQuery>,
     _ctx: Arc<dyn rpc::Context>,
 ) -> Result<SupportedAuth, rpc::RpcError> {
-    // Right now, every connection supports the same scheme.
+    // Right now, every connection supports at most one scheme.
     Ok(SupportedAuth {
-        schemes: vec![AuthenticationScheme::InherentUnixPath],
+        schemes: conn.auth_scheme().into_iter().collect(),
     })
 }
 rpc::static_rpc_invoke_fn! {
@@ -138,8 +160,9 @@ rpc::static_rpc_invoke_fn! {
 /// After connecting to Arti, clients use this method to create a Session,
 /// which they then use to access other functionality.
 ///
-/// For now, only the `inherent:unix_path` method is supported;
-/// other methods will be implemented in the future.
+/// For now, only the `inherent:unix_path` method is supported here.
+/// To authenticate with `fs:cookie`,
+/// use `auth:cookie_begin` and `auth:cookie_continue` instead.
 ///
 /// You typically won't need to invoke this method yourself;
 /// instead, your RPC library (such as `arti-rpc-client-core`)
@@ -150,7 +173,7 @@ rpc::static_rpc_invoke_fn! {
 struct Authenticate {
     /// The authentication scheme as enumerated in the spec.
     ///
-    /// TODO RPC: The only supported one for now is "inherent:unix_path"
+    /// The only one supported here is "inherent:unix_path".
     scheme: AuthenticationScheme,
 }
 
@@ -168,8 +191,21 @@ impl rpc::RpcMethod for Authenticate {
 
 /// An error during authentication.
 #[derive(Debug, Clone, thiserror::Error, serde::Serialize)]
-#[allow(dead_code)] // TODO RPC
-enum AuthenticationFailure {}
+enum AuthenticationFailure {
+    /// The client tried to use an authentication scheme
+    /// that this connection doesn't accept.
+    #[error("Tried to use unexpected authentication method")]
+    IncorrectMethod,
+    /// The client's proof of authentication was wrong.
+    #[error("Incorrect authentication value")]
+    IncorrectAuthentication,
+    /// The client tried to reuse a single-use authentication object.
+    #[error("Authentication object was already used")]
+    AlreadyUsed,
+    /// The client tried to begin authenticating more than once on a single connection.
+    #[error("Too many authentication attempts on this connection")]
+    TooManyAttempts,
+}
 
 impl tor_error::HasKind for AuthenticationFailure {
     fn kind(&self) -> tor_error::ErrorKind {
@@ -187,11 +223,14 @@ async fn authenticate_connection(
     method: Box<Authenticate>,
     ctx: Arc<dyn rpc::Context>,
 ) -> Result<AuthenticateReply, rpc::RpcError> {
-    match method.scheme {
-        // For now, we only support AF_UNIX connections, and we assume that if
+    match (method.scheme, unauth.auth_scheme()) {
+        // If a connect point requires no authentication, we assume that if
         // you have permission to open such a connection to us, you have
         // permission to use Arti. We will refine this later on!
-        AuthenticationScheme::InherentUnixPath => {}
+        (AuthenticationScheme::InherentUnixPath, Some(AuthenticationScheme::InherentUnixPath)) => {}
+        // Cookie authentication has its own methods;
+        // and we don't accept any scheme other than the one the connect point requires.
+        (_, _) => return Err(AuthenticationFailure::IncorrectMethod.into()),
     }
 
     let auth = RpcAuthentication {};
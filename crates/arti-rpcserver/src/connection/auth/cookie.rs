@@ -0,0 +1,352 @@
+//! Cookie authentication for RPC connections.
+//!
+//! See `rpc-cookie-sketch.md` for the protocol that we implement here.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock, Weak,
+};
+
+use derive_deftly::Deftly;
+use tor_rpc_connect::auth::{Cookie, CookieAuthMac, CookieAuthNonce, RpcAuth};
+use tor_rpcbase as rpc;
+use tor_rpcbase::templates::*;
+
+use super::{AuthenticateReply, AuthenticationFailure, RpcAuthentication};
+use crate::{connection::MgrDisappearedError, Connection, RpcMgr};
+
+/// Begin cookie authentication on an RPC Connection.
+///
+/// The client sends a random nonce;
+/// Arti replies with a proof that it knows the cookie,
+/// and with an object to use for a single `auth:cookie_continue` request.
+///
+/// This method is only available on connections
+/// whose connect point requires cookie authentication.
+/// It can only be invoked once per connection.
+///
+/// You typically won't need to invoke this method yourself;
+/// instead, your RPC library (such as `arti-rpc-client-core`)
+/// should handle it for you.
+#[derive(Debug, serde::Deserialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "auth:cookie_begin"))]
+struct CookieBegin {
+    /// A random nonce chosen by the client.
+    client_nonce: CookieAuthNonce,
+}
+
+/// A reply from the `auth:cookie_begin` method.
+#[derive(Debug, serde::Serialize)]
+struct CookieBeginReply {
+    /// An object to use for a single `auth:cookie_continue` request.
+    cookie_auth: rpc::ObjectId,
+    /// The address at which Arti is listening for this connection.
+    server_addr: String,
+    /// Arti's proof that it knows the cookie.
+    server_mac: CookieAuthMac,
+    /// A random nonce chosen by Arti.
+    server_nonce: CookieAuthNonce,
+}
+
+impl rpc::RpcMethod for CookieBegin {
+    type Output = CookieBeginReply;
+    type Update = rpc::NoUpdates;
+}
+
+/// An in-progress attempt to authenticate with a cookie.
+///
+/// # In the Arti RPC System
+///
+/// An in-progress attempt to authenticate with a cookie.
+///
+/// This object is returned by `auth:cookie_begin`.
+/// It can be used for a single `auth:cookie_continue` request,
+/// whether that request succeeds or not;
+/// after that request, it is released.
+#[derive(Deftly)]
+#[derive_deftly(Object)]
+struct CookieAuthInProgress {
+    /// The cookie that the client must prove that it knows.
+    cookie: Cookie,
+    /// The address at which we're listening for this connection.
+    server_addr: String,
+    /// The nonce that we sent to the client.
+    server_nonce: CookieAuthNonce,
+    /// The manager that will create the session, if authentication succeeds.
+    mgr: Weak<RpcMgr>,
+    /// True if this object has already been used for `auth:cookie_continue`.
+    used: AtomicBool,
+    /// The handle under which this object is registered on its connection.
+    ///
+    /// We release it once this object has been used.
+    handle: OnceLock<rpc::ObjectId>,
+}
+
+/// Finish cookie authentication, returning a new Session.
+///
+/// The client sends a proof that it knows the cookie,
+/// computed from the nonce that Arti sent in reply to `auth:cookie_begin`.
+#[derive(Debug, serde::Deserialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "auth:cookie_continue"))]
+struct CookieContinue {
+    /// The client's proof that it knows the cookie.
+    client_mac: CookieAuthMac,
+}
+
+impl rpc::RpcMethod for CookieContinue {
+    type Output = AuthenticateReply;
+    type Update = rpc::NoUpdates;
+}
+
+/// Invoke the "auth:cookie_begin" method on a connection.
+async fn cookie_begin(
+    unauth: Arc<Connection>,
+    method: Box<CookieBegin>,
+    ctx: Arc<dyn rpc::Context>,
+) -> Result<CookieBeginReply, rpc::RpcError> {
+    let RpcAuth::Cookie {
+        secret,
+        server_address,
+    } = &unauth.require_auth
+    else {
+        return Err(AuthenticationFailure::IncorrectMethod.into());
+    };
+    if unauth.cookie_auth_begun.swap(true, Ordering::SeqCst) {
+        return Err(AuthenticationFailure::TooManyAttempts.into());
+    }
+
+    let server_nonce = CookieAuthNonce::new(&mut rand::thread_rng());
+    let server_mac = secret.server_mac(server_address, &method.client_nonce);
+    let in_progress = Arc::new(CookieAuthInProgress {
+        cookie: secret.clone(),
+        server_addr: server_address.clone(),
+        server_nonce: server_nonce.clone(),
+        mgr: Weak::clone(&unauth.mgr),
+        used: AtomicBool::new(false),
+        handle: OnceLock::new(),
+    });
+    let cookie_auth = ctx.register_owned(Arc::clone(&in_progress) as _);
+    let _ = in_progress.handle.set(cookie_auth.clone());
+
+    Ok(CookieBeginReply {
+        cookie_auth,
+        server_addr: server_address.clone(),
+        server_mac,
+        server_nonce,
+    })
+}
+
+/// Invoke the "auth:cookie_continue" method on an in-progress cookie authentication.
+async fn cookie_continue(
+    in_progress: Arc<CookieAuthInProgress>,
+    method: Box<CookieContinue>,
+    ctx: Arc<dyn rpc::Context>,
+) -> Result<AuthenticateReply, rpc::RpcError> {
+    // Only allow one guess per server nonce.
+    if in_progress.used.swap(true, Ordering::SeqCst) {
+        return Err(AuthenticationFailure::AlreadyUsed.into());
+    }
+    // This object is no good for anything else now, so we don't keep it around.
+    if let Some(handle) = in_progress.handle.get() {
+        let _ = ctx.release_owned(handle);
+    }
+    let expected_mac = in_progress
+        .cookie
+        .client_mac(&in_progress.server_addr, &in_progress.server_nonce);
+    if method.client_mac != expected_mac {
+        return Err(AuthenticationFailure::IncorrectAuthentication.into());
+    }
+
+    let auth = RpcAuthentication {};
+    let session = {
+        let mgr = in_progress
+            .mgr
+            .upgrade()
+            .ok_or(MgrDisappearedError::RpcMgrDisappeared)?;
+        mgr.create_session(&auth)
+    };
+    let session = ctx.register_owned(session);
+    Ok(AuthenticateReply { session })
+}
+rpc::static_rpc_invoke_fn! {
+    cookie_begin;
+    cookie_continue;
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use futures_await_test::async_test;
+    use tor_rpcbase::ContextExt as _;
+
+    use super::*;
+
+    /// The address that our fake connections claim to be listening on.
+    const SERVER_ADDR: &str = "inet:127.0.0.1:9180";
+
+    /// A session object, as returned by our fake `RpcMgr`.
+    #[derive(Deftly)]
+    #[derive_deftly(Object)]
+    struct FakeSession {}
+
+    /// Return a new cookie, along with the directory that holds its file.
+    fn make_cookie() -> (tempfile::TempDir, Cookie) {
+        let dir = tempfile::tempdir().unwrap();
+        let mistrust = fs_mistrust::Mistrust::new_dangerously_trust_everyone();
+        let cookie = Cookie::create(
+            &dir.path().join("rpc.cookie"),
+            &mut rand::thread_rng(),
+            &mistrust,
+        )
+        .unwrap();
+        (dir, cookie)
+    }
+
+    /// Return a new `RpcMgr` and a connection from it that requires `require_auth`.
+    fn make_connection(require_auth: RpcAuth) -> (Arc<RpcMgr>, Arc<Connection>) {
+        let mgr = RpcMgr::new(|_| Arc::new(FakeSession {}) as Arc<dyn rpc::Object>).unwrap();
+        let conn = mgr.new_connection(require_auth);
+        (mgr, conn)
+    }
+
+    /// Invoke `auth:cookie_begin` on `conn`.
+    async fn begin(
+        conn: &Arc<Connection>,
+        client_nonce: &CookieAuthNonce,
+    ) -> Result<CookieBeginReply, rpc::RpcError> {
+        let method = Box::new(CookieBegin {
+            client_nonce: client_nonce.clone(),
+        });
+        cookie_begin(Arc::clone(conn), method, Arc::clone(conn) as _).await
+    }
+
+    /// Invoke `auth:cookie_continue` on the in-progress authentication `cookie_auth`.
+    async fn continue_with(
+        conn: &Arc<Connection>,
+        cookie_auth: &rpc::ObjectId,
+        client_mac: CookieAuthMac,
+    ) -> Result<AuthenticateReply, rpc::RpcError> {
+        let in_progress = conn.lookup::<CookieAuthInProgress>(cookie_auth).unwrap();
+        let method = Box::new(CookieContinue { client_mac });
+        cookie_continue(in_progress, method, Arc::clone(conn) as _).await
+    }
+
+    /// Return the error message that a client would get for `err`.
+    fn message(err: impl Into<rpc::RpcError>) -> serde_json::Value {
+        serde_json::to_value(err.into()).unwrap()["message"].clone()
+    }
+
+    #[async_test]
+    async fn round_trip() {
+        let (_dir, cookie) = make_cookie();
+        let (_mgr, conn) = make_connection(RpcAuth::Cookie {
+            secret: cookie.clone(),
+            server_address: SERVER_ADDR.into(),
+        });
+
+        let client_nonce = CookieAuthNonce::new(&mut rand::thread_rng());
+        let reply = begin(&conn, &client_nonce).await.unwrap();
+        assert_eq!(reply.server_addr, SERVER_ADDR);
+        // The server proved that it knows the cookie.
+        assert_eq!(
+            reply.server_mac,
+            cookie.server_mac(SERVER_ADDR, &client_nonce)
+        );
+
+        let client_mac = cookie.client_mac(SERVER_ADDR, &reply.server_nonce);
+        let auth = continue_with(&conn, &reply.cookie_auth, client_mac.clone())
+            .await
+            .unwrap();
+        let _session: Arc<FakeSession> = conn.lookup(&auth.session).unwrap();
+
+        // The in-progress object is only good for one request, even a successful one,
+        // so we've released it.
+        assert!(conn
+            .lookup::<CookieAuthInProgress>(&reply.cookie_auth)
+            .is_err());
+    }
+
+    #[async_test]
+    async fn wrong_mac() {
+        let (_dir, cookie) = make_cookie();
+        let (_dir2, other_cookie) = make_cookie();
+        let (_mgr, conn) = make_connection(RpcAuth::Cookie {
+            secret: cookie.clone(),
+            server_address: SERVER_ADDR.into(),
+        });
+
+        let client_nonce = CookieAuthNonce::new(&mut rand::thread_rng());
+        let reply = begin(&conn, &client_nonce).await.unwrap();
+        // Keep our own reference, as a request already in flight would.
+        let in_progress = conn
+            .lookup::<CookieAuthInProgress>(&reply.cookie_auth)
+            .unwrap();
+        let wrong_mac = other_cookie.client_mac(SERVER_ADDR, &reply.server_nonce);
+        let err = continue_with(&conn, &reply.cookie_auth, wrong_mac)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            message(err),
+            message(AuthenticationFailure::IncorrectAuthentication)
+        );
+
+        // We've released the object after a wrong guess, too.
+        assert!(conn
+            .lookup::<CookieAuthInProgress>(&reply.cookie_auth)
+            .is_err());
+
+        // And even with the right MAC, the object is refused.
+        let right_mac = cookie.client_mac(SERVER_ADDR, &reply.server_nonce);
+        let method = Box::new(CookieContinue {
+            client_mac: right_mac,
+        });
+        let err = cookie_continue(in_progress, method, Arc::clone(&conn) as _)
+            .await
+            .unwrap_err();
+        assert_eq!(message(err), message(AuthenticationFailure::AlreadyUsed));
+    }
+
+    #[async_test]
+    async fn one_attempt_per_connection() {
+        let (_dir, cookie) = make_cookie();
+        let (_mgr, conn) = make_connection(RpcAuth::Cookie {
+            secret: cookie,
+            server_address: SERVER_ADDR.into(),
+        });
+
+        let client_nonce = CookieAuthNonce::new(&mut rand::thread_rng());
+        let _reply = begin(&conn, &client_nonce).await.unwrap();
+        let err = begin(&conn, &client_nonce).await.unwrap_err();
+        assert_eq!(
+            message(err),
+            message(AuthenticationFailure::TooManyAttempts)
+        );
+    }
+
+    #[async_test]
+    async fn not_a_cookie_connection() {
+        let (_mgr, conn) = make_connection(RpcAuth::None);
+
+        let client_nonce = CookieAuthNonce::new(&mut rand::thread_rng());
+        let err = begin(&conn, &client_nonce).await.unwrap_err();
+        assert_eq!(
+            message(err),
+            message(AuthenticationFailure::IncorrectMethod)
+        );
+    }
+}
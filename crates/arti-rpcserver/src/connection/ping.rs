@@ -0,0 +1,45 @@
+//! The `rpc:ping` method, which clients can use to keep a connection alive.
+
+use std::sync::Arc;
+
+use super::Connection;
+use derive_deftly::Deftly;
+use tor_rpcbase as rpc;
+use tor_rpcbase::templates::*;
+
+/// Check that the RPC connection is still working.
+///
+/// This method must be invoked on the connection object (`"connection"`).
+/// It does nothing, and returns an empty result (the "pong")
+/// as soon as Arti reads it.
+/// It works whether or not the connection has been authenticated.
+///
+/// Clients can use this method to keep a long-lived connection from looking idle,
+/// in case something between the client and Arti closes idle connections.
+/// We recommend sending a ping after 30 seconds with no other traffic on the connection:
+/// that is well below the idle timeout of most proxies and firewalls,
+/// and it costs almost nothing.
+///
+/// Arti never sends pings of its own,
+/// since it cannot send anything but responses to the client's requests.
+#[derive(Debug, serde::Deserialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "rpc:ping"))]
+struct Ping {}
+
+impl rpc::RpcMethod for Ping {
+    type Output = rpc::Nil;
+    type Update = rpc::NoUpdates;
+}
+
+/// Implement `rpc:ping` on a connection.
+async fn conn_ping(
+    _conn: Arc<Connection>,
+    _method: Box<Ping>,
+    _ctx: Arc<dyn rpc::Context>,
+) -> Result<rpc::Nil, rpc::RpcError> {
+    Ok(rpc::NIL)
+}
+rpc::static_rpc_invoke_fn! {
+    conn_ping;
+}
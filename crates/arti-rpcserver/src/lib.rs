@@ -42,6 +42,7 @@
 //! <!-- @@ end lint list maintained by maint/add_warning @@ -->
 
 mod cancel;
+mod circuit;
 mod codecs;
 mod connection;
 mod err;
@@ -51,6 +52,8 @@ mod msgs;
 mod objmap;
 mod session;
 mod stream;
+#[cfg(any(test, feature = "testing"))]
+pub mod test_utils;
 
 pub use connection::{auth::RpcAuthentication, Connection, ConnectionError};
 pub use mgr::RpcMgr;
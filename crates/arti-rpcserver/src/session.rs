@@ -4,10 +4,15 @@
 //! is the root for all other RPC capabilities.
 
 use arti_client::{
-    rpc::{ClientConnectionResult, ConnectWithPrefs, ResolvePtrWithPrefs, ResolveWithPrefs},
+    rpc::{
+        ClientCapabilities, ClientConnectionResult, ConnectWithPrefs, ResolvePtrWithPrefs,
+        ResolveWithPrefs,
+    },
+    status::{BootstrapEvents, BootstrapStatus},
     TorClient,
 };
 use derive_deftly::Deftly;
+use futures::{SinkExt as _, StreamExt as _};
 use std::{net::IpAddr, sync::Arc};
 use tor_error::into_internal;
 use tor_rtcompat::Runtime;
@@ -51,6 +56,15 @@ trait Client: rpc::Object {
     /// Return a new isolated TorClient.
     fn isolated_client(&self) -> Arc<dyn rpc::Object>;
 
+    /// Return the features that the client supports, as currently configured.
+    fn capabilities(&self) -> ClientCapabilities;
+
+    /// Return the client's current bootstrap status.
+    fn bootstrap_status(&self) -> BootstrapStatus;
+
+    /// Return a stream of changes to the client's bootstrap status.
+    fn bootstrap_events(&self) -> BootstrapEvents;
+
     /// Upcast `self` to an rpc::Object.
     fn upcast_arc(self: Arc<Self>) -> Arc<dyn rpc::Object>;
 }
@@ -60,6 +74,18 @@ impl<R: Runtime> Client for TorClient<R> {
         Arc::new(TorClient::isolated_client(self))
     }
 
+    fn capabilities(&self) -> ClientCapabilities {
+        ClientCapabilities::of_client(self)
+    }
+
+    fn bootstrap_status(&self) -> BootstrapStatus {
+        TorClient::bootstrap_status(self)
+    }
+
+    fn bootstrap_events(&self) -> BootstrapEvents {
+        TorClient::bootstrap_events(self)
+    }
+
     fn upcast_arc(self: Arc<Self>) -> Arc<dyn rpc::Object> {
         self
     }
@@ -116,6 +142,124 @@ async fn isolated_client_on_session(
     Ok(rpc::SingleIdResponse::from(ctx.register_owned(new_client)))
 }
 
+/// Describe the optional features that are available through this session.
+///
+/// Applications can use this to decide which functionality to offer.
+///
+/// The result is an object of booleans.
+/// It describes the session's client as currently configured:
+/// for example, `onion_service_client` is false
+/// if the client is configured not to connect to onion services.
+/// New fields may be added in the future;
+/// applications should ignore fields they don't recognize,
+/// and treat a missing field as `false`.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "arti:session_capabilities"))]
+struct GetSessionCapabilities {}
+
+impl rpc::RpcMethod for GetSessionCapabilities {
+    type Output = SessionCapabilities;
+    type Update = rpc::NoUpdates;
+}
+
+/// The optional features available through an RPC session.
+#[derive(Clone, Debug, serde::Serialize)]
+struct SessionCapabilities {
+    /// The features supported by the session's client.
+    #[serde(flatten)]
+    client: ClientCapabilities,
+    /// True if the `arti:x_list_all_rpc_methods` method is available.
+    list_all_rpc_methods: bool,
+}
+
+/// Implement GetSessionCapabilities on an RpcSession.
+async fn session_capabilities_on_session(
+    session: Arc<RpcSession>,
+    _method: Box<GetSessionCapabilities>,
+    _ctx: Arc<dyn rpc::Context>,
+) -> Result<SessionCapabilities, rpc::RpcError> {
+    Ok(SessionCapabilities {
+        client: session.client.capabilities(),
+        list_all_rpc_methods: cfg!(feature = "describe-methods"),
+    })
+}
+
+/// Report how far the session's client has come in bootstrapping.
+///
+/// If `watch` is false, returns the current status immediately.
+///
+/// If `watch` is true, sends the current status as an update,
+/// followed by another update whenever the status changes,
+/// and returns the final status once the client is ready for traffic.
+/// (Remember to request updates for this to be useful!)
+#[derive(Debug, serde::Deserialize, serde::Serialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "arti:bootstrap_status"))]
+struct GetBootstrapStatus {
+    /// If true, keep reporting changes until bootstrapping is complete.
+    #[serde(default)]
+    watch: bool,
+}
+
+impl rpc::RpcMethod for GetBootstrapStatus {
+    type Output = BootstrapStatusInfo;
+    type Update = BootstrapStatusInfo;
+}
+
+/// A client's bootstrap progress, as reported over RPC.
+#[derive(Clone, Debug, serde::Serialize)]
+struct BootstrapStatusInfo {
+    /// True if the client is ready for traffic.
+    ready: bool,
+    /// Approximate estimate of how close the client is to being ready for traffic,
+    /// from 0.0 to 1.0.
+    ///
+    /// This value is a rough approximation; it is not guaranteed to be monotonic.
+    fraction: f32,
+    /// A human-readable description of the current bootstrap phase.
+    ///
+    /// This is designed for display to users, not for machine parsing.
+    phase: String,
+}
+
+impl From<BootstrapStatus> for BootstrapStatusInfo {
+    fn from(s: BootstrapStatus) -> Self {
+        Self {
+            ready: s.ready_for_traffic(),
+            fraction: s.as_frac(),
+            phase: s.to_string(),
+        }
+    }
+}
+
+/// Implement GetBootstrapStatus on an RpcSession.
+async fn bootstrap_status_on_session(
+    session: Arc<RpcSession>,
+    method: Box<GetBootstrapStatus>,
+    _ctx: Arc<dyn rpc::Context>,
+    mut updates: rpc::UpdateSink<BootstrapStatusInfo>,
+) -> Result<BootstrapStatusInfo, rpc::RpcError> {
+    // Subscribe before looking at the current status, so that we can't miss a change.
+    let mut events = session.client.bootstrap_events();
+    let mut status: BootstrapStatusInfo = session.client.bootstrap_status().into();
+    if !method.watch {
+        return Ok(status);
+    }
+
+    updates.send(status.clone()).await?;
+    while !status.ready {
+        let Some(new_status) = events.next().await else {
+            // The client has shut down; report what we last saw.
+            break;
+        };
+        status = new_status.into();
+        updates.send(status.clone()).await?;
+    }
+
+    Ok(status)
+}
+
 /// Implement ConnectWithPrefs on an RpcSession
 ///
 /// (Delegates to TorClient.)
@@ -157,6 +301,8 @@ async fn session_resolve_ptr_with_prefs(
 static_rpc_invoke_fn! {
     get_client_on_session;
     isolated_client_on_session;
+    session_capabilities_on_session;
+    bootstrap_status_on_session;
     @special session_connect_with_prefs;
     @special session_resolve_with_prefs;
     @special session_resolve_ptr_with_prefs;
@@ -203,3 +349,167 @@ mod list_all_methods {
 
     static_rpc_invoke_fn! { session_list_all_rpc_methods; }
 }
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use std::path::Path;
+    use std::sync::RwLock;
+
+    use arti_client::config::{ClientAddrConfigBuilder, Reconfigure, TorClientConfigBuilder};
+    use arti_client::{BootstrapBehavior, TorClientConfig};
+    use futures::SinkExt as _;
+    use serde_json::json;
+
+    use super::*;
+
+    /// A context that can only dispatch methods.
+    struct Ctx {
+        /// The dispatch table to use.
+        table: Arc<RwLock<rpc::DispatchTable>>,
+    }
+
+    impl rpc::Context for Ctx {
+        fn lookup_object(
+            &self,
+            _id: &rpc::ObjectId,
+        ) -> Result<Arc<dyn rpc::Object>, rpc::LookupError> {
+            unimplemented!()
+        }
+        fn register_owned(&self, _object: Arc<dyn rpc::Object>) -> rpc::ObjectId {
+            unimplemented!()
+        }
+        fn register_weak(&self, _object: Arc<dyn rpc::Object>) -> rpc::ObjectId {
+            unimplemented!()
+        }
+        fn release_owned(&self, _object: &rpc::ObjectId) -> Result<(), rpc::LookupError> {
+            unimplemented!()
+        }
+        fn dispatch_table(&self) -> &Arc<RwLock<rpc::DispatchTable>> {
+            &self.table
+        }
+    }
+
+    /// Return a client configuration that keeps its state and cache in `dir`.
+    fn client_config(dir: &Path) -> TorClientConfigBuilder {
+        TorClientConfigBuilder::from_directories(dir.join("state"), dir.join("cache"))
+    }
+
+    /// Return a session for a new, unbootstrapped client with the configuration `config`.
+    ///
+    /// Also return the client, so that the test can reconfigure it.
+    fn make_session<R: Runtime>(
+        rt: R,
+        config: TorClientConfig,
+    ) -> (Arc<RpcSession>, Arc<TorClient<R>>) {
+        let client = TorClient::with_runtime(rt)
+            .config(config)
+            .bootstrap_behavior(BootstrapBehavior::Manual)
+            .create_unbootstrapped()
+            .unwrap();
+        let client = Arc::new(client);
+        (RpcSession::new_with_client(client.clone()), client)
+    }
+
+    /// Start invoking the method described by the JSON `method` on `session`,
+    /// sending any updates to `updates`.
+    fn start_invoke(
+        session: &Arc<RpcSession>,
+        method: serde_json::Value,
+        updates: rpc::dispatch::BoxedUpdateSink,
+    ) -> rpc::dispatch::RpcResultFuture {
+        let ctx = Arc::new(Ctx {
+            table: Arc::new(RwLock::new(rpc::DispatchTable::from_inventory())),
+        });
+        let method: Box<dyn rpc::DynMethod> = serde_json::from_value(method).unwrap();
+        rpc::invoke_rpc_method(
+            ctx,
+            &rpc::ObjectId::from("session"),
+            session.clone(),
+            method,
+            updates,
+        )
+        .unwrap()
+    }
+
+    /// Invoke the method described by the JSON `method` on `session`,
+    /// and return the JSON reply.
+    async fn invoke(session: &Arc<RpcSession>, method: serde_json::Value) -> serde_json::Value {
+        let discard = Box::pin(futures::sink::drain().sink_err_into());
+        let reply = start_invoke(session, method, discard).await.unwrap();
+        serde_json::to_value(reply).unwrap()
+    }
+
+    #[test]
+    fn session_capabilities() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let (session, client) = make_session(rt, client_config(dir.path()).build().unwrap());
+            let method = json!({ "method": "arti:session_capabilities", "params": {} });
+
+            let caps = invoke(&session, method.clone()).await;
+            // We haven't configured any bridges.
+            assert_eq!(caps["bridges"], false);
+            assert_eq!(caps["pluggable_transports"], false);
+            assert_eq!(
+                caps["list_all_rpc_methods"],
+                cfg!(feature = "describe-methods")
+            );
+
+            // Once the client is told not to connect to onion services,
+            // the session doesn't claim that it can.
+            let mut config = client_config(dir.path());
+            *config.address_filter() = serde_json::from_value::<ClientAddrConfigBuilder>(json!({
+                "allow_onion_addrs": false,
+            }))
+            .unwrap();
+            client
+                .reconfigure(&config.build().unwrap(), Reconfigure::AllOrNothing)
+                .unwrap();
+            let caps = invoke(&session, method).await;
+            assert_eq!(caps["onion_service_client"], false);
+        });
+    }
+
+    #[test]
+    fn bootstrap_status() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let (session, client) = make_session(rt, client_config(dir.path()).build().unwrap());
+            let expected = client.bootstrap_status();
+
+            // Without `watch`, we're told the current status right away.
+            let method = json!({ "method": "arti:bootstrap_status", "params": {} });
+            let status = invoke(&session, method).await;
+            assert_eq!(status["ready"], false);
+            assert_eq!(status["phase"], expected.to_string());
+            let fraction = status["fraction"].as_f64().unwrap();
+            assert!((0.0..1.0).contains(&fraction));
+
+            // With `watch`, we're sent the current status as an update,
+            // and we keep waiting, since the client never finishes bootstrapping.
+            let (update_tx, mut update_rx) =
+                futures::channel::mpsc::unbounded::<rpc::dispatch::RpcValue>();
+            let updates =
+                Box::pin(update_tx.sink_map_err(|_| rpc::SendUpdateError::ConnectionClosed));
+            let method = json!({ "method": "arti:bootstrap_status", "params": { "watch": true } });
+            let mut reply = start_invoke(&session, method, updates);
+            assert!(futures::poll!(&mut reply).is_pending());
+            let update = update_rx.try_next().unwrap().unwrap();
+            assert_eq!(serde_json::to_value(update).unwrap(), status);
+        });
+    }
+}
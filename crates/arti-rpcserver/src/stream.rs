@@ -9,10 +9,12 @@ use std::{
     sync::{Arc, Mutex},
 };
 use tor_error::into_internal;
-use tor_proto::stream::DataStreamCtrl;
+use tor_linkspec::HasRelayIds as _;
+use tor_proto::circuit::{Path, PathEntry};
+use tor_proto::stream::{ClientStreamCtrl as _, DataStreamCtrl};
 use tor_rpcbase::{self as rpc, templates::*};
 
-use crate::RpcSession;
+use crate::{circuit::RpcCircuit, RpcSession};
 
 /// An RPC object representing a (possibly unconstructed) DataStream.
 ///
@@ -86,6 +88,13 @@ enum DataStreamError {
     /// but that RpcDataStream had already been used previously.
     #[error("Data stream object already used")]
     AlreadyUsed,
+
+    /// Application asked about the circuit of an RpcDataStream,
+    /// but that RpcDataStream has no circuit.
+    ///
+    /// Either it was never used to open a stream, or its circuit has closed.
+    #[error("Data stream object has no circuit")]
+    NoCircuit,
 }
 
 impl tor_error::HasKind for DataStreamError {
@@ -94,6 +103,7 @@ impl tor_error::HasKind for DataStreamError {
         use DataStreamError as E;
         match self {
             E::AlreadyUsed => EK::BadApiUsage, // TODO RPC: is this the correct ErrorKind?
+            E::NoCircuit => EK::BadApiUsage,
         }
     }
 }
@@ -125,7 +135,6 @@ impl RpcDataStream {
     }
 
     /// Return the `DataStreamCtrl` for this stream, if it has one.
-    #[allow(dead_code)]
     fn get_ctrl(&self) -> Option<Arc<DataStreamCtrl>> {
         let inner = self.inner.lock().expect("poisoned lock");
         if let Inner::Stream(s) = &*inner {
@@ -271,9 +280,205 @@ async fn new_stream_handle_on_session(
 ) -> Result<rpc::SingleIdResponse, rpc::RpcError> {
     Ok(new_stream_handle_impl(session, ctx.as_ref()).into())
 }
+/// Return a handle for the circuit that carries a given stream.
+///
+/// The target must be a stream handle that was used to open a stream.
+///
+/// The resulting ObjectID will be a handle to the circuit,
+/// which can be used as the target of circuit methods such as `arti:x_close_circuit_streams`.
+///
+/// **This is an experimental method.**
+#[derive(Debug, serde::Deserialize, serde::Serialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "arti:x_get_stream_circuit"))]
+struct GetStreamCircuit {}
+
+impl rpc::RpcMethod for GetStreamCircuit {
+    type Output = rpc::SingleIdResponse;
+    type Update = rpc::NoUpdates;
+}
+
+/// Implement GetStreamCircuit for RpcDataStream.
+async fn rpcdatastream_get_stream_circuit(
+    rpc_data_stream: Arc<RpcDataStream>,
+    _method: Box<GetStreamCircuit>,
+    ctx: Arc<dyn rpc::Context>,
+) -> Result<rpc::SingleIdResponse, DataStreamError> {
+    let circ = rpc_data_stream
+        .get_ctrl()
+        .and_then(|ctrl| ctrl.circuit())
+        .ok_or(DataStreamError::NoCircuit)?;
+    let rpc_circ = Arc::new(RpcCircuit::new(&circ));
+    Ok(ctx.register_owned(rpc_circ as _).into())
+}
+
+/// Return the path of the circuit that carries a given stream.
+///
+/// The target must be a stream handle that was used to open a stream.
+///
+/// Returns a list of the circuit's hops, starting with the first hop.
+/// For each relay, we give its identities, but not its addresses:
+/// if the circuit goes through a bridge, the bridge's address stays private.
+/// A hop that isn't a relay (such as the onion service at the end of a
+/// rendezvous circuit) is reported as "virtual".
+///
+/// Note that the path can change if the circuit is extended after you ask.
+///
+/// **This is an experimental method.**
+#[derive(Debug, serde::Deserialize, serde::Serialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "arti:x_get_circuit_path"))]
+struct GetCircuitPath {}
+
+impl rpc::RpcMethod for GetCircuitPath {
+    type Output = CircuitPath;
+    type Update = rpc::NoUpdates;
+}
+
+/// The result of a [`GetCircuitPath`] request.
+#[derive(Debug, serde::Serialize)]
+struct CircuitPath {
+    /// The hops of the circuit, starting with the first hop.
+    hops: Vec<CircuitHop>,
+}
+
+/// A single hop of a circuit, as reported by [`GetCircuitPath`].
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CircuitHop {
+    /// A relay (or bridge) on the Tor network.
+    Relay {
+        /// The relay's Ed25519 identity, if we know it.
+        ed25519_id: Option<String>,
+        /// The relay's RSA identity fingerprint, if we know it.
+        rsa_id: Option<String>,
+    },
+    /// A hop that isn't a relay.
+    Virtual,
+}
+
+impl From<&Path> for CircuitPath {
+    fn from(path: &Path) -> Self {
+        let hops = path.iter().map(CircuitHop::from).collect();
+        CircuitPath { hops }
+    }
+}
+
+impl From<&PathEntry> for CircuitHop {
+    fn from(entry: &PathEntry) -> Self {
+        match entry.as_chan_target() {
+            Some(relay) => CircuitHop::Relay {
+                ed25519_id: relay.ed_identity().map(ToString::to_string),
+                rsa_id: relay.rsa_identity().map(ToString::to_string),
+            },
+            None => CircuitHop::Virtual,
+        }
+    }
+}
+
+/// Implement GetCircuitPath for RpcDataStream.
+async fn rpcdatastream_get_circuit_path(
+    rpc_data_stream: Arc<RpcDataStream>,
+    _method: Box<GetCircuitPath>,
+    _ctx: Arc<dyn rpc::Context>,
+) -> Result<CircuitPath, DataStreamError> {
+    let circ = rpc_data_stream
+        .get_ctrl()
+        .and_then(|ctrl| ctrl.circuit())
+        .ok_or(DataStreamError::NoCircuit)?;
+    Ok(CircuitPath::from(circ.path_ref().as_ref()))
+}
+
 rpc::static_rpc_invoke_fn! {
     new_stream_handle_on_session;
+    rpcdatastream_get_stream_circuit;
+    rpcdatastream_get_circuit_path;
     @special rpcdatastream_connect_with_prefs;
     @special rpcdatastream_resolve_with_prefs;
     @special rpcdatastream_resolve_ptr_with_prefs;
 }
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use serde_json::json;
+    use tor_linkspec::{HasRelayIds as _, OwnedChanTarget};
+
+    use super::*;
+
+    /// Return a relay with the given identities.
+    fn relay(ed: Option<[u8; 32]>, rsa: Option<[u8; 20]>) -> PathEntry {
+        let mut builder = OwnedChanTarget::builder();
+        if let Some(ed) = ed {
+            builder.ed_identity(ed.into());
+        }
+        if let Some(rsa) = rsa {
+            builder.rsa_identity(rsa.into());
+        }
+        PathEntry::new_fake_relay(builder.build().unwrap())
+    }
+
+    #[test]
+    fn hop_from_entry() {
+        let entry = relay(Some([7; 32]), Some([9; 20]));
+        let target = entry.as_chan_target().unwrap();
+        match CircuitHop::from(&entry) {
+            CircuitHop::Relay { ed25519_id, rsa_id } => {
+                assert_eq!(ed25519_id, Some(target.ed_identity().unwrap().to_string()));
+                assert_eq!(rsa_id, Some(target.rsa_identity().unwrap().to_string()));
+            }
+            CircuitHop::Virtual => panic!("relay reported as virtual"),
+        }
+
+        // A relay whose Ed25519 identity we don't know.
+        let entry = relay(None, Some([9; 20]));
+        assert!(matches!(
+            CircuitHop::from(&entry),
+            CircuitHop::Relay {
+                ed25519_id: None,
+                rsa_id: Some(_),
+            }
+        ));
+
+        let entry = PathEntry::new_fake_virtual();
+        assert!(matches!(CircuitHop::from(&entry), CircuitHop::Virtual));
+    }
+
+    #[test]
+    fn path_from_fake_circuit() {
+        let guard = relay(Some([1; 32]), Some([2; 20]));
+        let middle = relay(Some([3; 32]), Some([4; 20]));
+        let hop_json = |entry: &PathEntry| {
+            let target = entry.as_chan_target().unwrap();
+            json!({
+                "relay": {
+                    "ed25519_id": target.ed_identity().unwrap().to_string(),
+                    "rsa_id": target.rsa_identity().unwrap().to_string(),
+                }
+            })
+        };
+        let expected = json!({
+            "hops": [hop_json(&guard), hop_json(&middle), "virtual"],
+        });
+        let path = Path::new_fake(vec![guard, middle, PathEntry::new_fake_virtual()]);
+
+        let reply = serde_json::to_value(CircuitPath::from(&path)).unwrap();
+        assert_eq!(reply, expected);
+
+        let reply = serde_json::to_value(CircuitPath::from(&Path::default())).unwrap();
+        assert_eq!(reply, json!({ "hops": [] }));
+    }
+}
@@ -0,0 +1,72 @@
+//! Helpers for testing code that runs an RPC server.
+//!
+//! These register extra RPC methods, whose names start with `x-test:`.
+//! Don't enable them outside of tests.
+
+// @@ begin test lint list maintained by maint/add_warning @@
+#![allow(clippy::bool_assert_comparison)]
+#![allow(clippy::clone_on_copy)]
+#![allow(clippy::dbg_macro)]
+#![allow(clippy::mixed_attributes_style)]
+#![allow(clippy::print_stderr)]
+#![allow(clippy::print_stdout)]
+#![allow(clippy::single_char_pattern)]
+#![allow(clippy::unwrap_used)]
+#![allow(clippy::unchecked_duration_subtraction)]
+#![allow(clippy::useless_vec)]
+#![allow(clippy::needless_pass_by_value)]
+//! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+use std::sync::{Arc, Mutex};
+
+use derive_deftly::Deftly;
+use futures::channel::oneshot;
+use tor_rpcbase::{self as rpc, templates::*};
+
+use crate::Connection;
+
+/// The receiver that the next `x-test:wait` request waits on.
+static RELEASE: Mutex<Option<oneshot::Receiver<()>>> = Mutex::new(None);
+
+/// Arrange for the next `x-test:wait` request to wait until the returned sender
+/// is used or dropped.
+///
+/// `x-test:wait` is invoked on a connection, and returns an empty object.
+/// Use it to have a request that is still running when the test wants it to be.
+///
+/// # Panics
+///
+/// An `x-test:wait` request panics if this wasn't called before it.
+pub fn hold_next_wait() -> oneshot::Sender<()> {
+    let (release_tx, release_rx) = oneshot::channel();
+    *RELEASE.lock().unwrap() = Some(release_rx);
+    release_tx
+}
+
+/// A method that doesn't finish until the test lets it.
+///
+/// See [`hold_next_wait`].
+#[derive(Debug, serde::Deserialize, Deftly)]
+#[derive_deftly(DynMethod)]
+#[deftly(rpc(method_name = "x-test:wait"))]
+struct Wait {}
+
+impl rpc::RpcMethod for Wait {
+    type Output = rpc::Nil;
+    type Update = rpc::NoUpdates;
+}
+
+/// Implement `x-test:wait` on a connection.
+async fn conn_wait(
+    _conn: Arc<Connection>,
+    _method: Box<Wait>,
+    _ctx: Arc<dyn rpc::Context>,
+) -> Result<rpc::Nil, rpc::RpcError> {
+    let release = RELEASE.lock().unwrap().take().unwrap();
+    let _ = release.await;
+    Ok(rpc::NIL)
+}
+
+rpc::static_rpc_invoke_fn! {
+    conn_wait;
+}
@@ -9,7 +9,8 @@ use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "onion-service-service")]
 use crate::onion_proxy::{
-    OnionServiceProxyConfigBuilder, OnionServiceProxyConfigMap, OnionServiceProxyConfigMapBuilder,
+    OnionServiceProxyConfig, OnionServiceProxyConfigBuilder, OnionServiceProxyConfigMap,
+    OnionServiceProxyConfigMapBuilder,
 };
 #[cfg(not(feature = "onion-service-service"))]
 use crate::onion_proxy_disabled::{OnionServiceProxyConfigMap, OnionServiceProxyConfigMapBuilder};
@@ -21,6 +22,8 @@ use arti_client::TorClientConfig;
 use tor_config::define_list_builder_accessors;
 use tor_config::resolve_alternative_specs;
 pub(crate) use tor_config::{impl_standard_builder, ConfigBuildError, Listen};
+#[cfg(feature = "onion-service-service")]
+use tor_hsservice::HsNickname;
 
 use crate::{LoggingConfig, LoggingConfigBuilder};
 
@@ -309,6 +312,14 @@ impl ArtiConfig {
     pub fn rpc(&self) -> &RpcConfig {
         &self.rpc
     }
+
+    /// Return an iterator over the configured onion services, ordered by nickname.
+    #[cfg(feature = "onion-service-service")]
+    pub(crate) fn onion_services(
+        &self,
+    ) -> impl Iterator<Item = (&HsNickname, &OnionServiceProxyConfig)> + '_ {
+        self.onion_services.iter()
+    }
 }
 
 #[cfg(test)]
@@ -522,6 +533,16 @@ mod test {
             ],
         );
 
+        declare_exceptions(
+            None,
+            None, // there's no default value to put in an example
+            Recognized,
+            &[
+                // Unset by default
+                "download_schedule.user_agent",
+            ],
+        );
+
         declare_exceptions(
             Some(InOld),
             Some(InNew),
@@ -1281,6 +1302,11 @@ example config file {which:?}, uncommented={uncommented:?}
                     ProxyPattern::all_ports(),
                     ProxyAction::DestroyCircuit,
                 ));
+                b.proxy()
+                    .forward_source_address("127.0.0.1".parse().unwrap());
+                b.service()
+                    .intro_point_selection()
+                    .prefer_fastest_percent(Some(50));
 
                 #[cfg(feature = "restricted-discovery")]
                 {
@@ -4,22 +4,27 @@ use anyhow::Result;
 use arti_rpcserver::RpcMgr;
 use derive_builder::Builder;
 use fs_mistrust::Mistrust;
-use futures::{stream::StreamExt, task::SpawnExt, AsyncReadExt};
+use futures::{
+    channel::mpsc, future::RemoteHandle, select_biased, stream::StreamExt, task::SpawnExt,
+    AsyncReadExt, FutureExt as _,
+};
 use listener::{RpcListenerMap, RpcListenerMapBuilder};
 use serde::{Deserialize, Serialize};
 use session::ArtiRpcSession;
-use std::{io::Result as IoResult, sync::Arc};
-use tor_config::{define_list_builder_helper, impl_standard_builder, ConfigBuildError};
+use std::{convert::Infallible, io::Result as IoResult, sync::Arc, time::Duration};
+use tor_config::{
+    define_list_builder_helper, impl_standard_builder, ConfigBuildError, ConfigurationSources,
+};
 use tor_config_path::CfgPathResolver;
-use tor_rpc_connect::auth::RpcAuth;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use arti_client::TorClient;
-use tor_rtcompat::{general, NetStreamListener as _, Runtime};
+use tor_rtcompat::{general, NetStreamListener as _, Runtime, SleepProviderExt as _};
 
 pub(crate) mod conntarget;
 pub(crate) mod listener;
 mod proxyinfo;
+mod reconfigure;
 mod session;
 
 pub(crate) use session::{RpcStateSender, RpcVisibleArtiState};
@@ -28,7 +33,7 @@ pub(crate) use session::{RpcStateSender, RpcVisibleArtiState};
 ///
 /// You cannot change this section on a running Arti client.
 #[derive(Debug, Clone, Builder, Eq, PartialEq)]
-#[builder(build_fn(error = "ConfigBuildError"))]
+#[builder(build_fn(error = "ConfigBuildError", validate = "Self::validate"))]
 #[builder(derive(Debug, Serialize, Deserialize))]
 #[builder_struct_attr(non_exhaustive)]
 #[non_exhaustive]
@@ -47,9 +52,98 @@ pub struct RpcConfig {
     #[builder(sub_builder)]
     #[builder_field_attr(serde(default))]
     listen_default: ListenDefaults,
+
+    /// If false, never bind the connect points in `listen_default`,
+    /// even if no enabled connect points are found under `listen`.
+    ///
+    /// Set this to false to make sure that we only bind
+    /// the connect points that are named explicitly under `listen`.
+    #[builder(default = "true")]
+    use_listen_default: bool,
+
+    /// The permissions to give every RPC socket that we bind in the filesystem,
+    /// as a Unix file mode (for example, `0o600`).
+    ///
+    /// Anybody who can connect to one of these sockets
+    /// can ask for the access that its connect point grants,
+    /// so by default only the user running Arti may connect.
+    /// Use `0o660` to let members of the socket's group connect as well.
+    ///
+    /// We refuse to bind a socket whose directory would let anybody reach it
+    /// whom these permissions are meant to keep out,
+    /// so that nobody else can connect before we've set them.
+    ///
+    /// This option has no effect on platforms without Unix file permissions.
+    #[builder(default = "DEFAULT_SOCKET_MODE")]
+    socket_mode: u32,
 }
 impl_standard_builder! { RpcConfig }
 
+/// Default value for `RpcConfig.socket_mode`.
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+impl RpcConfigBuilder {
+    /// Return an error if this builder isn't valid.
+    fn validate(&self) -> Result<(), ConfigBuildError> {
+        let enable = self.enable.unwrap_or(false);
+        let use_listen_default = self.use_listen_default.unwrap_or(true);
+        if enable && !use_listen_default {
+            let listen = self.listen.build()?;
+            if !listen.values().any(|set| set.is_enabled()) {
+                let problem = "RPC is enabled, but no connect points in 'listen' are enabled, \
+                               and 'use_listen_default' is false";
+                return Err(ConfigBuildError::Inconsistent {
+                    fields: vec![
+                        "enable".into(),
+                        "listen".into(),
+                        "use_listen_default".into(),
+                    ],
+                    problem: problem.into(),
+                });
+            }
+        }
+        if let Some(mode) = self.socket_mode {
+            let problem = if mode & !0o777 != 0 {
+                Some("must contain only permission bits (at most 0o777)")
+            } else if mode & 0o600 != 0o600 {
+                Some("must let the owner read and write the socket")
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                return Err(ConfigBuildError::Invalid {
+                    field: "socket_mode".into(),
+                    problem: problem.into(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&RpcConfig> for RpcConfigBuilder {
+    /// Return a builder that will build a copy of `cfg`.
+    ///
+    /// This lets you change a few options of an existing `RpcConfig`
+    /// and build it again.
+    fn from(cfg: &RpcConfig) -> Self {
+        let mut builder = RpcConfigBuilder::default();
+        builder.enable(cfg.enable);
+        // Replace the default connect points, rather than adding to them.
+        let listen = builder.listen();
+        listen.clear();
+        listen.extend(
+            cfg.listen
+                .iter()
+                .map(|(name, set)| (name.clone(), set.into())),
+        );
+        *builder.listen_default().access_opt_mut() = Some(cfg.listen_default.clone());
+        builder.use_listen_default(cfg.use_listen_default);
+        builder.socket_mode(cfg.socket_mode);
+        builder
+    }
+}
+
 /// Type alias to enable sub_builder to work.
 type ListenDefaults = Vec<String>;
 
@@ -92,7 +186,7 @@ async fn launch_all_listeners<R: Runtime>(
     let mut guards = Vec::new();
     for (name, listener_cfg) in cfg.listen.iter() {
         for (lis, info, guard) in listener_cfg
-            .bind(runtime, name.as_str(), resolver, mistrust)
+            .bind(runtime, name.as_str(), resolver, mistrust, cfg.socket_mode)
             .await?
         {
             // (Note that `bind` only returns enabled listeners, so we don't need to check here.
@@ -107,11 +201,18 @@ async fn launch_all_listeners<R: Runtime>(
             guards.push(guard);
         }
     }
-    if listeners.is_empty() {
+    if listeners.is_empty() && cfg.use_listen_default {
         for (idx, connpt) in cfg.listen_default.iter().enumerate() {
             let display_index = idx + 1; // One-indexed values are more human-readable.
-            let (lis, info, guard) =
-                listener::bind_string(connpt, display_index, runtime, resolver, mistrust).await?;
+            let (lis, info, guard) = listener::bind_string(
+                connpt,
+                display_index,
+                runtime,
+                resolver,
+                mistrust,
+                cfg.socket_mode,
+            )
+            .await?;
             debug!(
                 "Listening at {} for {}",
                 lis.local_addr()
@@ -141,17 +242,20 @@ async fn launch_all_listeners<R: Runtime>(
 
 /// Create an RPC manager, bind to connect points, and open a listener task to accept incoming
 /// RPC connections.
+///
+/// `client_config_sources` are the sources from which `client`'s configuration was loaded.
 pub(crate) async fn launch_rpc_mgr<R: Runtime>(
     runtime: &R,
     cfg: &RpcConfig,
     resolver: &CfgPathResolver,
     mistrust: &Mistrust,
     client: TorClient<R>,
-) -> Result<Option<(Arc<RpcMgr>, RpcStateSender)>> {
+    client_config_sources: ConfigurationSources,
+) -> Result<Option<(Arc<RpcMgr>, RpcStateSender, RpcShutdownHandle)>> {
     if !cfg.enable {
         return Ok(None);
     }
-    let (rpc_state, rpc_state_sender) = RpcVisibleArtiState::new();
+    let (rpc_state, rpc_state_sender) = RpcVisibleArtiState::new(client_config_sources);
 
     // TODO RPC: there should be an error return instead.
     let rpc_mgr = RpcMgr::new(move |auth| ArtiRpcSession::new(auth, &client, &rpc_state))?;
@@ -165,52 +269,97 @@ pub(crate) async fn launch_rpc_mgr<R: Runtime>(
     let rpc_mgr_clone = rpc_mgr.clone();
 
     let (incoming, guards) = launch_all_listeners(runtime, cfg, resolver, mistrust).await?;
+    let (shutdown_tx, shutdown_rx) = postage::broadcast::channel(1);
 
     // TODO: Using spawn in this way makes it hard to report whether we
     // succeeded or not. This is something we should fix when we refactor
     // our service-launching code.
-    runtime.spawn(async move {
-        let result = run_rpc_listener(rt_clone, incoming, rpc_mgr_clone).await;
+    let finished = runtime.spawn_with_handle(async move {
+        let result = run_rpc_listener(rt_clone, incoming, rpc_mgr_clone, shutdown_rx).await;
         if let Err(e) = result {
             tracing::warn!("RPC manager quit with an error: {}", e);
         }
         drop(guards);
     })?;
-    Ok(Some((rpc_mgr, rpc_state_sender)))
+    let shutdown = RpcShutdownHandle {
+        shutdown_tx,
+        finished,
+    };
+    Ok(Some((rpc_mgr, rpc_state_sender, shutdown)))
+}
+
+/// A handle to shut down our RPC listener, along with the connections it accepted.
+///
+/// If this handle is dropped without calling [`shutdown`](RpcShutdownHandle::shutdown),
+/// the listener and its connections stop abruptly.
+pub(crate) struct RpcShutdownHandle {
+    /// A sender that we drop to tell the listener and its connections to shut down.
+    shutdown_tx: postage::broadcast::Sender<Infallible>,
+    /// A future for the listener task,
+    /// which finishes once the listener and all of its connections are done.
+    finished: RemoteHandle<()>,
+}
+
+impl RpcShutdownHandle {
+    /// Stop accepting RPC connections, and tell every open connection to shut down.
+    ///
+    /// Each connection finishes the requests that it is already running,
+    /// and then tells its client that we are shutting down.
+    /// We wait up to `timeout` for this to happen.
+    pub(crate) async fn shutdown<R: Runtime>(self, runtime: &R, timeout: Duration) {
+        drop(self.shutdown_tx);
+        if runtime.timeout(timeout, self.finished).await.is_err() {
+            warn!("Timed out waiting for RPC connections to finish; closing them anyway.");
+        }
+    }
 }
 
 /// Backend function to implement an RPC listener: runs in a loop.
+///
+/// Once `shutdown` is closed, we stop accepting connections,
+/// and return after all of our connections have shut down.
 async fn run_rpc_listener<R: Runtime>(
     runtime: R,
     mut incoming: impl futures::Stream<Item = IoResult<IncomingConn>> + Unpin,
     rpc_mgr: Arc<RpcMgr>,
+    mut shutdown: postage::broadcast::Receiver<Infallible>,
 ) -> Result<()> {
-    while let Some((stream, _addr, info)) = incoming.next().await.transpose()? {
+    // Each connection task holds a clone of `drained_tx`,
+    // so `drained_rx` is closed once they have all finished.
+    let (drained_tx, mut drained_rx) = mpsc::channel::<Infallible>(0);
+
+    loop {
+        let accepted = select_biased! {
+            _ = shutdown.next().fuse() => break,
+            accepted = incoming.next().fuse() => accepted,
+        };
+        let Some((stream, _addr, info)) = accepted.transpose()? else {
+            break;
+        };
         // TODO RPC: Perhaps we should have rpcmgr hold the client reference?
-        // TODO RPC: We'll need to pass info (or part of it?) to rpc_mgr.
+        // TODO RPC: We may need to pass more of info to rpc_mgr.
         debug!("Received incoming RPC connection from {}", &info.name);
 
-        match info.auth {
-            RpcAuth::None => {
-                // "None" auth works trivially; there's nothing to do.
-            }
-            _ => {
-                // TODO RPC: implement cookie auth, and reject other auth types earlier.
-                debug!("Dropping RPC connection; auth type is not supported");
-                continue;
-            }
-        }
-
-        let connection = rpc_mgr.new_connection();
+        let connection = rpc_mgr.new_connection(info.auth.clone());
         let (input, output) = stream.split();
-
-        runtime.spawn(async {
-            let result = connection.run(input, output).await;
+        let mut shutdown = shutdown.clone();
+        let drained_tx = drained_tx.clone();
+
+        runtime.spawn(async move {
+            let shutdown = async move {
+                let _: Option<Infallible> = shutdown.next().await;
+            };
+            let result = connection.run_until_shutdown(input, output, shutdown).await;
             if let Err(e) = result {
                 tracing::warn!("RPC session ended with an error: {}", e);
             }
+            drop(drained_tx);
         })?;
     }
+
+    debug!("RPC listener stopped; waiting for connections to finish.");
+    drop(drained_tx);
+    let _: Option<Infallible> = drained_rx.next().await;
     Ok(())
 }
 
@@ -230,9 +379,13 @@ mod test {
     #![allow(clippy::needless_pass_by_value)]
     //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
 
+    use futures::{AsyncBufReadExt as _, AsyncWriteExt as _};
     use listener::{ConnectPointOptionsBuilder, RpcListenerSetConfigBuilder};
     use tor_config_path::CfgPath;
+    use tor_rpc_connect::auth::RpcAuth;
     use tor_rpc_connect::ParsedConnectPoint;
+    use tor_rtcompat::NetStreamProvider as _;
+    use tor_rtmock::MockRuntime;
 
     use super::*;
 
@@ -255,6 +408,104 @@ mod test {
         }
     }
 
+    #[test]
+    fn use_listen_default() {
+        fn build(s: &str) -> Result<RpcConfig, anyhow::Error> {
+            let b: RpcConfigBuilder = toml::from_str(s)?;
+            Ok(b.build()?)
+        }
+
+        // With only the default connect points disabled, we'd bind nothing.
+        let no_listeners = r#"
+enable = true
+use_listen_default = false
+[listen."user-default"]
+enable = false
+"#;
+        let err = build(no_listeners).unwrap_err();
+        assert!(err.to_string().contains("use_listen_default"), "{}", err);
+
+        // That's fine if RPC isn't enabled.
+        let disabled = build(&no_listeners.replace("enable = true", "enable = false")).unwrap();
+        assert!(!disabled.use_listen_default);
+
+        // It's also fine if we have some other enabled connect point.
+        let cfg = build(
+            r#"
+enable = true
+use_listen_default = false
+[listen."user-default"]
+enable = false
+[listen."my-connpt"]
+file = "/home/dante/.paradiso/connpt.toml"
+"#,
+        )
+        .unwrap();
+        assert!(!cfg.use_listen_default);
+
+        // The "user-default" entry under `listen` is enabled unless we disable it,
+        // so it counts as an enabled connect point.
+        let cfg = build("enable = true\nuse_listen_default = false").unwrap();
+        assert!(!cfg.use_listen_default);
+
+        // And we use the default connect points unless told otherwise.
+        assert!(RpcConfig::default().use_listen_default);
+    }
+
+    #[test]
+    fn socket_mode() {
+        fn build(s: &str) -> Result<RpcConfig, anyhow::Error> {
+            let b: RpcConfigBuilder = toml::from_str(s)?;
+            Ok(b.build()?)
+        }
+
+        assert_eq!(RpcConfig::default().socket_mode, 0o600);
+        assert_eq!(build("socket_mode = 0o660").unwrap().socket_mode, 0o660);
+
+        for bad in ["socket_mode = 0o4700", "socket_mode = 0o077"] {
+            let err = build(bad).unwrap_err();
+            assert!(err.to_string().contains("socket_mode"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn builder_round_trip() {
+        let cfg: RpcConfigBuilder = toml::from_str(
+            r#"
+enable = true
+listen_default = ["unix:/tmp/arti-rpc.sock"]
+socket_mode = 0o660
+[listen."user-default"]
+enable = false
+[listen."system-default"]
+dir = "/usr/local/etc/arti-rpc/connect.d"
+file_options = { "tmp.toml" = { "enable" = false } }
+[listen."my-connpt"]
+file = "/home/dante/.paradiso/connpt.toml"
+"#,
+        )
+        .unwrap();
+        let cfg = cfg.build().unwrap();
+
+        for cfg in [RpcConfig::default(), cfg] {
+            let rebuilt = RpcConfigBuilder::from(&cfg).build().unwrap();
+            assert_eq!(rebuilt, cfg);
+        }
+
+        // Connect points that we removed from the defaults stay removed.
+        let mut cfg = RpcConfig::default();
+        cfg.listen.remove("system-default");
+        let rebuilt = RpcConfigBuilder::from(&cfg).build().unwrap();
+        assert_eq!(rebuilt, cfg);
+
+        // We can change an option and build again.
+        let mut builder = RpcConfigBuilder::from(&cfg);
+        builder.use_listen_default(false);
+        let changed = builder.build().unwrap();
+        assert!(!changed.use_listen_default);
+        assert_eq!(changed.listen, cfg.listen);
+    }
+
     #[test]
     fn parsing_and_building() {
         fn build(s: &str) -> Result<RpcConfig, anyhow::Error> {
@@ -287,7 +538,8 @@ mod test {
                 ]
                 .into_iter()
                 .collect(),
-                listen_default: listen_defaults_defaults()
+                listen_default: listen_defaults_defaults(),
+                use_listen_default: true,
             }
         );
 
@@ -337,8 +589,110 @@ file = "/home/dante/.paradiso/connpt.toml"
                 ]
                 .into_iter()
                 .collect(),
-                listen_default: listen_defaults_defaults()
+                listen_default: listen_defaults_defaults(),
+                use_listen_default: true,
             }
         );
     }
+
+    /// Return a new `RpcMgr` that can't make sessions.
+    fn rpc_mgr() -> Arc<RpcMgr> {
+        RpcMgr::new(|_| -> Arc<dyn tor_rpcbase::Object> { unimplemented!() }).unwrap()
+    }
+
+    /// Read the next response from `responses`, or `None` at the end of the stream.
+    async fn next_response<R: futures::AsyncBufRead + Unpin>(
+        responses: &mut futures::io::Lines<R>,
+    ) -> Option<serde_json::Value> {
+        let line = responses.next().await?.unwrap();
+        Some(serde_json::from_str(&line).unwrap())
+    }
+
+    #[test]
+    fn listener_stops_on_shutdown() {
+        MockRuntime::test_with_various(|rt| async move {
+            let (shutdown_tx, shutdown_rx) = postage::broadcast::channel(1);
+            let incoming = futures::stream::pending();
+            let listener = run_rpc_listener(rt.clone(), incoming, rpc_mgr(), shutdown_rx);
+            futures::pin_mut!(listener);
+
+            // Without a shutdown, the accept loop keeps waiting for connections.
+            assert!(futures::poll!(&mut listener).is_pending());
+
+            drop(shutdown_tx);
+            listener.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn shutdown_handle_drains_connections() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let release_tx = arti_rpcserver::test_utils::hold_next_wait();
+
+            let addr = general::SocketAddr::from(std::net::SocketAddr::from(([127, 0, 0, 1], 0)));
+            let lis = rt.listen(&addr).await.unwrap();
+            let addr = lis.local_addr().unwrap();
+            let info = Arc::new(listener::RpcConnInfo {
+                name: "test".into(),
+                auth: RpcAuth::None,
+                options: ConnectPointOptionsBuilder::default().build().unwrap(),
+            });
+            let incoming = lis
+                .incoming()
+                .map(move |r| r.map(|(stream, addr)| (stream, addr, Arc::clone(&info))));
+            let (shutdown_tx, shutdown_rx) = postage::broadcast::channel(1);
+            let rt_clone = rt.clone();
+            let finished = rt
+                .spawn_with_handle(async move {
+                    run_rpc_listener(rt_clone, incoming, rpc_mgr(), shutdown_rx)
+                        .await
+                        .unwrap();
+                })
+                .unwrap();
+            let handle = RpcShutdownHandle {
+                shutdown_tx,
+                finished,
+            };
+
+            let stream = rt.connect(&addr).await.unwrap();
+            let (input, mut output) = stream.split();
+            let mut responses = futures::io::BufReader::new(input).lines();
+
+            // Start a request that won't finish yet.  Once the ping after it is answered,
+            // we know that it is running.
+            output
+                .write_all(
+                    concat!(
+                        r#"{"id": 1, "obj": "connection", "method": "x-test:wait", "params": {}}"#,
+                        "\n",
+                        r#"{"id": 2, "obj": "connection", "method": "rpc:ping", "params": {}}"#,
+                        "\n",
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            output.flush().await.unwrap();
+            assert_eq!(
+                next_response(&mut responses).await,
+                Some(serde_json::json!({ "id": 2, "result": {} }))
+            );
+
+            // Shutting down lets the running request finish.
+            let ((), ()) = futures::join!(handle.shutdown(&rt, Duration::from_secs(60)), async {
+                release_tx.send(()).unwrap();
+            });
+            assert_eq!(
+                next_response(&mut responses).await,
+                Some(serde_json::json!({ "id": 1, "result": {} }))
+            );
+            let closing = next_response(&mut responses).await.unwrap();
+            assert_eq!(closing.get("id"), None);
+            assert_eq!(
+                closing["error"]["kinds"],
+                serde_json::json!(["arti:ArtiShuttingDown"])
+            );
+            assert_eq!(next_response(&mut responses).await, None);
+        });
+    }
 }
@@ -109,6 +109,30 @@ pub(crate) struct RpcListenerSetConfig {
 }
 impl_standard_builder! { RpcListenerSetConfig: !Deserialize !Default }
 
+impl RpcListenerSetConfig {
+    /// Return true if this set of connect points is enabled.
+    ///
+    /// (Individual connect points within a directory may still be disabled.)
+    pub(super) fn is_enabled(&self) -> bool {
+        self.listener_options.is_enabled()
+    }
+}
+
+impl From<&RpcListenerSetConfig> for RpcListenerSetConfigBuilder {
+    fn from(cfg: &RpcListenerSetConfig) -> Self {
+        let mut builder = RpcListenerSetConfigBuilder::default();
+        *builder.listener_options() = cfg.listener_options.clone();
+        if let Some(file) = &cfg.file {
+            builder.file(file.clone());
+        }
+        if let Some(dir) = &cfg.dir {
+            builder.dir(dir.clone());
+        }
+        *builder.file_options() = cfg.file_options.clone();
+        builder
+    }
+}
+
 impl RpcListenerSetConfigBuilder {
     /// Return an error if this builder isn't valid.
     fn validate(&self) -> Result<(), ConfigBuildError> {
@@ -222,6 +246,8 @@ impl RpcListenerSetConfig {
     /// Load every enabled connect point from this file or directory,
     /// and bind to them.
     ///
+    /// Every socket that we bind in the filesystem gets the permissions `socket_mode`.
+    ///
     /// On success, returns a list of bound sockets,
     /// along with information about how to treat incoming connections on those sockets,
     /// and a guard object that must not be dropped until we are no longer listening on the socket.
@@ -231,6 +257,7 @@ impl RpcListenerSetConfig {
         config_key: &str,
         resolver: &CfgPathResolver,
         mistrust: &Mistrust,
+        socket_mode: u32,
     ) -> anyhow::Result<Vec<(general::Listener, Arc<RpcConnInfo>, ListenerGuard)>> {
         if !self.listener_options.is_enabled() {
             // We stop immediately if we're disabled at the RpcListenerSetConfig level,
@@ -266,7 +293,7 @@ impl RpcListenerSetConfig {
                 guard,
                 ..
             } = conn_pt
-                .bind(runtime, mistrust)
+                .bind_with_socket_mode(runtime, mistrust, socket_mode)
                 .await
                 .with_context(|| ctx("bind to"))?;
             return Ok(vec![(
@@ -335,7 +362,7 @@ impl RpcListenerSetConfig {
                     guard,
                     ..
                 } = conn_pt
-                    .bind(runtime, mistrust)
+                    .bind_with_socket_mode(runtime, mistrust, socket_mode)
                     .await
                     .with_context(|| ctx("bind to"))?;
                 listeners.push((
@@ -366,6 +393,7 @@ pub(super) async fn bind_string<R: Runtime>(
     runtime: &R,
     resolver: &CfgPathResolver,
     mistrust: &Mistrust,
+    socket_mode: u32,
 ) -> anyhow::Result<(general::Listener, Arc<RpcConnInfo>, ListenerGuard)> {
     let ctx = |action| format!("Can't {action} RPC connect point from rpc.listen_default.#{index}");
 
@@ -379,7 +407,7 @@ pub(super) async fn bind_string<R: Runtime>(
         guard,
         ..
     } = conn_pt
-        .bind(runtime, mistrust)
+        .bind_with_socket_mode(runtime, mistrust, socket_mode)
         .await
         .with_context(|| ctx("bind to"))?;
     Ok((
@@ -417,4 +445,43 @@ mod test {
         let m = listener_map_defaults();
         assert_eq!(m.len(), 2);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_mode_on_bind() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let dir = tempfile::tempdir().unwrap();
+            let sock_dir = dir.path().join("rpc");
+            let resolver = CfgPathResolver::default();
+            let mistrust = Mistrust::new_dangerously_trust_everyone();
+            let bind = |name: &str, mode| {
+                let connpt = format!(
+                    "[connect]\nsocket = \"unix:{}\"\nauth = \"none\"\n",
+                    sock_dir.join(name).display()
+                );
+                let rt = rt.clone();
+                let resolver = resolver.clone();
+                let mistrust = mistrust.clone();
+                async move { bind_string(&connpt, 1, &rt, &resolver, &mistrust, mode).await }
+            };
+            let mode = |name: &str| {
+                let meta = std::fs::metadata(sock_dir.join(name)).unwrap();
+                meta.permissions().mode() & 0o777
+            };
+
+            let _a = bind("a.sock", 0o660).await.unwrap();
+            assert_eq!(mode("a.sock"), 0o660);
+            let _b = bind("b.sock", 0o600).await.unwrap();
+            assert_eq!(mode("b.sock"), 0o600);
+
+            // If the group can reach the socket, the socket has to let the group in:
+            // otherwise it would be open to the group until we set its permissions.
+            std::fs::set_permissions(&sock_dir, std::fs::Permissions::from_mode(0o750)).unwrap();
+            assert!(bind("c.sock", 0o600).await.is_err());
+            let _d = bind("d.sock", 0o660).await.unwrap();
+            assert_eq!(mode("d.sock"), 0o660);
+        });
+    }
 }
@@ -0,0 +1,362 @@
+//! Implement RPC functionality for changing the configuration of a running client.
+
+use std::sync::{Arc, Mutex};
+
+use arti_client::{TorClient, TorClientConfig};
+use tor_config::load::ConfigResolveError;
+use tor_config::sources::MustRead;
+use tor_config::{ConfigurationSource, ConfigurationSources, Reconfigure};
+use tor_error::{into_internal, ErrorKind, HasKind};
+use tor_rpcbase::{self as rpc};
+use tor_rtcompat::Runtime;
+
+use super::session::ArtiRpcSession;
+
+/// Change the configuration of the client behind this session.
+///
+/// The `config` has the same format as the client options in Arti's configuration file.
+/// It is merged into the current configuration:
+/// any option that it leaves out keeps its current value.
+/// (Options given on Arti's command line still take precedence,
+/// just as they do over the configuration file.)
+///
+/// If the new configuration is invalid,
+/// we return an error that says which option is the problem,
+/// and we change nothing.
+///
+/// Otherwise, every change that can take effect on a running client does so immediately.
+/// If the new configuration also changes options that can't be changed on a running client,
+/// those options keep their old values until Arti restarts,
+/// and the reply lists them in `restart_required`.
+///
+/// The new configuration applies to every session on this Arti instance,
+/// not just this one.
+#[derive(Debug, serde::Deserialize, derive_deftly::Deftly)]
+#[derive_deftly(rpc::DynMethod)]
+#[deftly(rpc(method_name = "arti:reconfigure"))]
+struct ReconfigureClient {
+    /// The options to change.
+    config: toml::Table,
+}
+
+impl rpc::RpcMethod for ReconfigureClient {
+    type Output = ReconfigureReply;
+    type Update = rpc::NoUpdates;
+}
+
+/// The reply to a successful `arti:reconfigure` request.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+struct ReconfigureReply {
+    /// Descriptions of the changes that won't take effect until Arti restarts.
+    ///
+    /// Every other change has already taken effect.
+    /// If this is empty, the whole new configuration is in effect.
+    ///
+    /// (Right now, the client stops looking after the first such change it finds,
+    /// so this lists at most one change.)
+    restart_required: Vec<String>,
+}
+
+/// A client whose configuration we can change.
+///
+/// We use this trait so that an `ArtiRpcSession` doesn't need to know its runtime type.
+pub(super) trait ReconfigurableClient: Send + Sync {
+    /// Try to change this client's configuration to `config`,
+    /// as with [`TorClient::reconfigure`].
+    fn reconfigure(&self, config: &TorClientConfig, how: Reconfigure) -> arti_client::Result<()>;
+}
+
+impl<R: Runtime> ReconfigurableClient for TorClient<R> {
+    fn reconfigure(&self, config: &TorClientConfig, how: Reconfigure) -> arti_client::Result<()> {
+        TorClient::reconfigure(self, config, how)
+    }
+}
+
+/// An error encountered while changing the configuration of a client.
+#[derive(Clone, Debug, thiserror::Error)]
+enum ReconfigureError {
+    /// The new configuration was not valid.
+    #[error("Invalid configuration")]
+    InvalidConfig(#[source] Arc<ConfigResolveError>),
+    /// We couldn't apply the new configuration for some other reason.
+    #[error("Unable to reconfigure client")]
+    Failed(#[source] arti_client::Error),
+    /// An internal error occurred.
+    #[error("Internal error")]
+    Bug(#[from] tor_error::Bug),
+}
+
+impl HasKind for ReconfigureError {
+    fn kind(&self) -> ErrorKind {
+        use ReconfigureError as E;
+        match self {
+            E::InvalidConfig(_) => ErrorKind::InvalidConfig,
+            E::Failed(e) => e.kind(),
+            E::Bug(e) => e.kind(),
+        }
+    }
+}
+
+/// Merge `changes` into the configuration from `sources`,
+/// and apply as much of the result as we can to `client`.
+///
+/// On success, we add `changes` to `sources`, so that later changes are merged with it.
+/// We don't change anything if the merged configuration is invalid.
+fn reconfigure_client(
+    client: &dyn ReconfigurableClient,
+    sources: &Mutex<ConfigurationSources>,
+    changes: &toml::Table,
+) -> Result<ReconfigureReply, ReconfigureError> {
+    // We hold this lock until we're done, so that concurrent changes can't interleave.
+    let mut sources = sources.lock().expect("lock poisoned");
+    let changes = toml::to_string(changes).map_err(into_internal!("Couldn't encode changes"))?;
+    let mut new_sources = sources.clone();
+    new_sources.push_source(
+        ConfigurationSource::from_verbatim(changes),
+        MustRead::MustRead,
+    );
+    let config: TorClientConfig = new_sources
+        .load()
+        .map_err(ConfigResolveError::from)
+        .and_then(tor_config::resolve_ignore_warnings)
+        .map_err(|e| ReconfigureError::InvalidConfig(Arc::new(e)))?;
+    let restart_required = match client.reconfigure(&config, Reconfigure::CheckAllOrNothing) {
+        Ok(()) => vec![],
+        Err(e) if e.kind() == ErrorKind::InvalidConfigTransition => vec![innermost_message(&e)],
+        Err(e) => return Err(ReconfigureError::Failed(e)),
+    };
+    let how = if restart_required.is_empty() {
+        Reconfigure::AllOrNothing
+    } else {
+        // Apply everything else, and leave the options we can't change as they were.
+        Reconfigure::WarnOnFailures
+    };
+    client
+        .reconfigure(&config, how)
+        .map_err(ReconfigureError::Failed)?;
+    *sources = new_sources;
+    Ok(ReconfigureReply { restart_required })
+}
+
+/// Return the message of the innermost source of `err`.
+///
+/// For a refused configuration change, this is the message that names the option.
+fn innermost_message(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut err = err;
+    while let Some(source) = err.source() {
+        err = source;
+    }
+    err.to_string()
+}
+
+/// Implementation for ReconfigureClient on ArtiRpcSession.
+async fn rpc_session_reconfigure(
+    session: Arc<ArtiRpcSession>,
+    method: Box<ReconfigureClient>,
+    _ctx: Arc<dyn rpc::Context>,
+) -> Result<ReconfigureReply, ReconfigureError> {
+    reconfigure_client(
+        session.client.as_ref(),
+        &session.arti_state.client_config_sources,
+        &method.config,
+    )
+}
+rpc::static_rpc_invoke_fn! {rpc_session_reconfigure;}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use std::path::Path;
+    use std::sync::RwLock;
+
+    use arti_client::BootstrapBehavior;
+    use futures::SinkExt as _;
+    use serde_json::json;
+
+    use super::*;
+    use crate::rpc::session::RpcVisibleArtiState;
+
+    /// A context that can only dispatch methods.
+    struct Ctx {
+        /// The dispatch table to use.
+        table: Arc<RwLock<rpc::DispatchTable>>,
+    }
+
+    impl rpc::Context for Ctx {
+        fn lookup_object(
+            &self,
+            _id: &rpc::ObjectId,
+        ) -> Result<Arc<dyn rpc::Object>, rpc::LookupError> {
+            unimplemented!()
+        }
+        fn register_owned(&self, _object: Arc<dyn rpc::Object>) -> rpc::ObjectId {
+            unimplemented!()
+        }
+        fn register_weak(&self, _object: Arc<dyn rpc::Object>) -> rpc::ObjectId {
+            unimplemented!()
+        }
+        fn release_owned(&self, _object: &rpc::ObjectId) -> Result<(), rpc::LookupError> {
+            unimplemented!()
+        }
+        fn dispatch_table(&self) -> &Arc<RwLock<rpc::DispatchTable>> {
+            &self.table
+        }
+    }
+
+    /// Return the JSON for a client configuration that uses `state_dir` and `cache_dir`,
+    /// and sets `allow_local_addrs`.
+    fn config_json(
+        state_dir: &Path,
+        cache_dir: &Path,
+        allow_local_addrs: bool,
+    ) -> serde_json::Value {
+        json!({
+            "storage": { "state_dir": state_dir, "cache_dir": cache_dir },
+            "address_filter": { "allow_local_addrs": allow_local_addrs },
+        })
+    }
+
+    /// Return configuration sources that contain only the JSON `config`.
+    fn sources_for(config: serde_json::Value) -> ConfigurationSources {
+        let config: toml::Table = serde_json::from_value(config).unwrap();
+        let mut sources = ConfigurationSources::new_empty();
+        sources.push_source(
+            ConfigurationSource::from_verbatim(toml::to_string(&config).unwrap()),
+            MustRead::MustRead,
+        );
+        sources
+    }
+
+    /// Load a client configuration from `sources`.
+    fn load_config(sources: &ConfigurationSources) -> TorClientConfig {
+        tor_config::resolve_ignore_warnings(sources.load().unwrap()).unwrap()
+    }
+
+    /// Return a session whose client is configured from the JSON `config`.
+    fn make_session<R: Runtime>(rt: R, config: serde_json::Value) -> Arc<ArtiRpcSession> {
+        let sources = sources_for(config);
+        let cfg = load_config(&sources);
+        let client = TorClient::with_runtime(rt)
+            .config(cfg)
+            .bootstrap_behavior(BootstrapBehavior::Manual)
+            .create_unbootstrapped()
+            .unwrap();
+        let (arti_state, _sender) = RpcVisibleArtiState::new(sources);
+        ArtiRpcSession::with_client(client, &arti_state)
+    }
+
+    /// Invoke `arti:reconfigure` on `session`, asking for the changes in `config`.
+    ///
+    /// Return the JSON reply, or the JSON error.
+    async fn invoke_reconfigure(
+        session: &Arc<ArtiRpcSession>,
+        config: serde_json::Value,
+    ) -> Result<serde_json::Value, serde_json::Value> {
+        let ctx = Arc::new(Ctx {
+            table: Arc::new(RwLock::new(rpc::DispatchTable::from_inventory())),
+        });
+        let method: Box<ReconfigureClient> =
+            Box::new(serde_json::from_value(json!({ "config": config })).unwrap());
+        let discard = Box::pin(futures::sink::drain().sink_err_into());
+        let reply = rpc::invoke_rpc_method(
+            ctx,
+            &rpc::ObjectId::from("session"),
+            session.clone(),
+            method,
+            discard,
+        )
+        .unwrap()
+        .await;
+        match reply {
+            Ok(v) => Ok(serde_json::to_value(v).unwrap()),
+            Err(e) => Err(serde_json::to_value(e).unwrap()),
+        }
+    }
+
+    #[test]
+    fn live_change() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let state_dir = tempfile::tempdir().unwrap();
+            let cache_dir = tempfile::tempdir().unwrap();
+            let session = make_session(rt, config_json(state_dir.path(), cache_dir.path(), false));
+            let config = config_json(state_dir.path(), cache_dir.path(), true);
+
+            let reply = invoke_reconfigure(&session, config).await.unwrap();
+            assert_eq!(reply, json!({ "restart_required": [] }));
+        });
+    }
+
+    #[test]
+    fn change_needs_restart() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let state_dir = tempfile::tempdir().unwrap();
+            let cache_dir = tempfile::tempdir().unwrap();
+            let other_state_dir = tempfile::tempdir().unwrap();
+            let session = make_session(rt, config_json(state_dir.path(), cache_dir.path(), false));
+            let config = config_json(other_state_dir.path(), cache_dir.path(), true);
+
+            let reply = invoke_reconfigure(&session, config).await.unwrap();
+            assert_eq!(
+                reply,
+                json!({
+                    "restart_required": ["Cannot change storage.state_dir on a running client."]
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn merge_with_current() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let state_dir = tempfile::tempdir().unwrap();
+            let cache_dir = tempfile::tempdir().unwrap();
+            let other_state_dir = tempfile::tempdir().unwrap();
+            let session = make_session(rt, config_json(state_dir.path(), cache_dir.path(), false));
+
+            // Leaving out the storage options keeps them as they are,
+            // rather than asking to change them to their defaults.
+            let config = json!({ "address_filter": { "allow_local_addrs": true } });
+            let reply = invoke_reconfigure(&session, config).await.unwrap();
+            assert_eq!(reply, json!({ "restart_required": [] }));
+
+            // Later changes are merged with earlier ones.
+            let config = json!({ "storage": { "state_dir": other_state_dir.path() } });
+            let reply = invoke_reconfigure(&session, config).await.unwrap();
+            assert_eq!(
+                reply,
+                json!({
+                    "restart_required": ["Cannot change storage.state_dir on a running client."]
+                })
+            );
+            let sources = session.arti_state.client_config_sources.lock().unwrap();
+            let expected = sources_for(config_json(other_state_dir.path(), cache_dir.path(), true));
+            assert_eq!(load_config(&sources), load_config(&expected));
+        });
+    }
+
+    #[test]
+    fn invalid_config() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let state_dir = tempfile::tempdir().unwrap();
+            let cache_dir = tempfile::tempdir().unwrap();
+            let session = make_session(rt, config_json(state_dir.path(), cache_dir.path(), false));
+            let config = json!({ "bridges": { "enabled": true } });
+
+            let err = invoke_reconfigure(&session, config).await.unwrap_err();
+            assert_eq!(err["kinds"], json!(["arti:InvalidConfig"]));
+        });
+    }
+}
@@ -4,12 +4,17 @@ use arti_client::TorClient;
 use arti_rpcserver::RpcAuthentication;
 use derive_deftly::Deftly;
 use futures::stream::StreamExt as _;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 use tor_async_utils::{DropNotifyEofSignallable, DropNotifyWatchSender};
+use tor_config::ConfigurationSources;
 use tor_rpcbase::{self as rpc};
 use tor_rtcompat::Runtime;
 
 use super::proxyinfo::{self, ProxyInfo};
+use super::reconfigure::ReconfigurableClient;
 
 /// A top-level RPC session object.
 ///
@@ -37,6 +42,8 @@ pub(super) struct ArtiRpcSession {
     pub(super) arti_state: Arc<RpcVisibleArtiState>,
     /// The underlying RpcSession object that we delegate to.
     session: Arc<arti_rpcserver::RpcSession>,
+    /// The client of our underlying RpcSession, for use when reconfiguring it.
+    pub(super) client: Arc<dyn ReconfigurableClient>,
 }
 
 /// Information about the current global top-level Arti state,
@@ -54,6 +61,11 @@ pub(crate) struct RpcVisibleArtiState {
     ///
     /// Right now it only lists Socks; in the future it may list more.
     proxy_info: postage::watch::Receiver<ProxyInfoState>,
+    /// The sources of the client's current configuration.
+    ///
+    /// Every change made with `arti:reconfigure` is added here,
+    /// so that later changes are merged into it.
+    pub(super) client_config_sources: Mutex<ConfigurationSources>,
 }
 
 /// Handle to set RPC state across RPC sessions.  (See `RpcVisibleArtiState`.)
@@ -77,12 +89,21 @@ impl ArtiRpcSession {
         arti_state: &Arc<RpcVisibleArtiState>,
     ) -> Arc<Self> {
         let _ = auth; // This is currently unused; any authentication gives the same result.
-        let client = client_root.isolated_client();
-        let session = arti_rpcserver::RpcSession::new_with_client(Arc::new(client));
+        Self::with_client(client_root.isolated_client(), arti_state)
+    }
+
+    /// Construct a new `ArtiRpcSession` whose client is `client`.
+    pub(super) fn with_client<R: Runtime>(
+        client: TorClient<R>,
+        arti_state: &Arc<RpcVisibleArtiState>,
+    ) -> Arc<Self> {
+        let client = Arc::new(client);
+        let session = arti_rpcserver::RpcSession::new_with_client(Arc::clone(&client));
         let arti_state = Arc::clone(arti_state);
         Arc::new(ArtiRpcSession {
             session,
             arti_state,
+            client,
         })
     }
 }
@@ -106,11 +127,16 @@ impl DropNotifyEofSignallable for ProxyInfoState {
 
 impl RpcVisibleArtiState {
     /// Construct a new `RpcVisibleArtiState`.
-    pub(crate) fn new() -> (Arc<Self>, RpcStateSender) {
+    ///
+    /// `client_config_sources` are the sources of the client's configuration.
+    pub(crate) fn new(client_config_sources: ConfigurationSources) -> (Arc<Self>, RpcStateSender) {
         let (proxy_info_sender, proxy_info) = postage::watch::channel_with(ProxyInfoState::Unset);
         let proxy_info_sender = DropNotifyWatchSender::new(proxy_info_sender);
         (
-            Arc::new(Self { proxy_info }),
+            Arc::new(Self {
+                proxy_info,
+                client_config_sources: Mutex::new(client_config_sources),
+            }),
             RpcStateSender { proxy_info_sender },
         )
     }
@@ -176,7 +202,7 @@ mod test {
     #[test]
     fn set_proxy_info() {
         MockRuntime::test_with_various(|rt| async move {
-            let (state, mut sender) = RpcVisibleArtiState::new();
+            let (state, mut sender) = RpcVisibleArtiState::new(ConfigurationSources::new_empty());
             let _task = rt.clone().spawn_with_handle(async move {
                 sender.set_socks_listeners(&["8.8.4.4:99".parse().unwrap()]);
                 sender // keep sender alive
@@ -1,10 +1,14 @@
 //! The `hss` subcommand.
 
-use anyhow::anyhow;
-use arti_client::TorClientConfig;
+use anyhow::{anyhow, Context};
+use arti_client::{InertTorClient, TorClientConfig};
 use clap::{ArgMatches, Args, FromArgMatches, Parser, Subcommand, ValueEnum};
-use tor_hsservice::{HsId, HsNickname, OnionService};
+use tor_hsservice::{
+    HsId, HsNickname, KeystoreId, KeystoreSelector, OnionService, OnionServiceConfig, VanityPrefix,
+};
+use tor_rtcompat::Runtime;
 
+use crate::onion_proxy::OnionServiceProxyConfig;
 use crate::{ArtiConfig, Result, TorClient};
 
 /// The hss subcommands the arti CLI will be augmented with.
@@ -29,6 +33,15 @@ pub(crate) struct Hss {
 pub(crate) enum HssSubcommand {
     /// Print the .onion address of a hidden service
     OnionName(OnionNameArgs),
+
+    /// Generate the identity key of a hidden service, and print its .onion address
+    CreateIdentity(CreateIdentityArgs),
+
+    /// Check the configuration and keys of a hidden service, without launching it
+    Check,
+
+    /// List the configured hidden services
+    List,
 }
 
 /// The arguments of the [`OnionName`](HssSubcommand::OnionName) subcommand.
@@ -43,6 +56,27 @@ pub(crate) struct OnionNameArgs {
     generate: GenerateKey,
 }
 
+/// The arguments of the [`CreateIdentity`](HssSubcommand::CreateIdentity) subcommand.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct CreateIdentityArgs {
+    /// Replace the identity key of the service if it already has one
+    ///
+    /// This changes the .onion address of the service!
+    #[arg(long)]
+    force: bool,
+
+    /// Search for an identity key whose .onion address starts with this prefix
+    ///
+    /// Each character of the prefix makes the search about 32 times longer:
+    /// a prefix of n characters takes about 32^n attempts on average.
+    #[arg(long)]
+    prefix: Option<VanityPrefix>,
+
+    /// Give up on finding an address with the --prefix after this many attempts
+    #[arg(long, default_value_t = 1_000_000, requires = "prefix")]
+    max_attempts: u64,
+}
+
 /// Whether to generate the key if missing.
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
 enum GenerateKey {
@@ -64,12 +98,50 @@ enum KeyType {
 #[derive(Debug, Clone, Args)]
 pub(crate) struct CommonArgs {
     /// The nickname of the service
+    ///
+    /// Required by all the subcommands except `list`.
     #[arg(short, long)]
-    nickname: HsNickname,
+    nickname: Option<HsNickname>,
+
+    /// The ID of the keystore to look up (or generate) the keys of the service in
+    //
+    // If not specified, all the configured keystores are searched,
+    // and any new keys are generated into the primary keystore.
+    #[arg(long)]
+    keystore: Option<KeystoreId>,
+}
+
+impl CommonArgs {
+    /// Return the nickname specified by the `--nickname` argument.
+    ///
+    /// Returns an error if no nickname was specified.
+    fn nickname(&self) -> Result<&HsNickname> {
+        self.nickname
+            .as_ref()
+            .ok_or_else(|| anyhow!("--nickname is required by this subcommand"))
+    }
+
+    /// Return a description of the keystores that we search for keys,
+    /// as specified by the `--keystore` argument.
+    fn keystore_description(&self) -> String {
+        match &self.keystore {
+            Some(id) => format!("keystore {id}"),
+            None => "any of the configured keystores".to_string(),
+        }
+    }
+
+    /// Return the [`KeystoreSelector`] specified by the `--keystore` argument.
+    fn keystore_selector(&self) -> KeystoreSelector {
+        self.keystore
+            .as_ref()
+            .map(KeystoreSelector::Id)
+            .unwrap_or_default()
+    }
 }
 
 /// Run the `hss` subcommand.
-pub(crate) fn run(
+pub(crate) fn run<R: Runtime>(
+    runtime: R,
     hss_matches: &ArgMatches,
     config: &ArtiConfig,
     client_config: &TorClientConfig,
@@ -78,23 +150,38 @@ pub(crate) fn run(
 
     match hss.command {
         HssSubcommand::OnionName(args) => run_onion_name(&hss.common, &args, config, client_config),
+        HssSubcommand::CreateIdentity(args) => {
+            run_create_identity(&hss.common, &args, config, client_config)
+        }
+        HssSubcommand::Check => run_check(runtime, &hss.common, config, client_config),
+        HssSubcommand::List => run_list(config),
     }
 }
 
+/// Find the configuration of the service with the specified `nickname`.
+fn find_svc_config(nickname: &HsNickname, config: &ArtiConfig) -> Result<OnionServiceConfig> {
+    find_svc_proxy_config(nickname, config).map(|cfg| cfg.svc_cfg.clone())
+}
+
+/// Find the configuration of the service `nickname`, along with that of its reverse proxy.
+fn find_svc_proxy_config<'c>(
+    nickname: &HsNickname,
+    config: &'c ArtiConfig,
+) -> Result<&'c OnionServiceProxyConfig> {
+    config
+        .onion_services()
+        .find(|(n, _)| *n == nickname)
+        .map(|(_, cfg)| cfg)
+        .ok_or_else(|| anyhow!("Service {nickname} is not configured"))
+}
+
 /// Create the OnionService configured with `nickname`.
 fn create_svc(
     nickname: &HsNickname,
     config: &ArtiConfig,
     client_config: &TorClientConfig,
 ) -> Result<OnionService> {
-    let Some(svc_config) = config
-        .onion_services
-        .iter()
-        .find(|(n, _)| *n == nickname)
-        .map(|(_, cfg)| cfg.svc_cfg.clone())
-    else {
-        return Err(anyhow!("Service {nickname} is not configured"));
-    };
+    let svc_config = find_svc_config(nickname, config)?;
 
     // TODO: PreferredRuntime was arbitrarily chosen and is entirely unused
     // (we have to specify a concrete type for the runtime when calling
@@ -110,19 +197,41 @@ fn create_svc(
     )
 }
 
-/// Display the onion address, if any, of the specified service.
-fn display_onion_name(nickname: &HsNickname, hsid: Option<HsId>) -> Result<()> {
+/// Display the onion address of the specified service.
+fn display_onion_name(hsid: HsId) {
     // TODO: instead of the printlns here, we should have a formatter type that
     // decides how to display the output
-    if let Some(onion) = hsid {
-        println!("{onion}");
-    } else {
-        return Err(anyhow!(
-            "Service {nickname} does not exist, or does not have an K_hsid yet"
-        ));
-    }
+    println!("{hsid}");
+}
 
-    Ok(())
+/// Look up the onion address of `svc`.
+///
+/// Returns `Ok(None)` if the identity key of the service doesn't exist yet,
+/// and an error if it exists but couldn't be read.
+///
+/// If a `--keystore` was specified, only that keystore is searched,
+/// and it is an error for it not to exist.
+fn read_onion_name(
+    nickname: &HsNickname,
+    args: &CommonArgs,
+    svc: &OnionService,
+) -> Result<Option<HsId>> {
+    let Some(keystore) = &args.keystore else {
+        let hsid = svc
+            .try_onion_name()
+            .with_context(|| format!("Could not read the identity key of service {nickname}"))?;
+        return Ok(hsid);
+    };
+
+    let hsid = svc
+        .onion_name_from_keystore(KeystoreSelector::Id(keystore))
+        .with_context(|| {
+            format!(
+                "Could not read the identity key of service {nickname} from keystore {keystore} (is the keystore configured?)"
+            )
+        })?;
+
+    Ok(hsid)
 }
 
 /// Run the `hss onion-name` subcommand.
@@ -131,9 +240,16 @@ fn onion_name(
     config: &ArtiConfig,
     client_config: &TorClientConfig,
 ) -> Result<()> {
-    let onion_svc = create_svc(&args.nickname, config, client_config)?;
-    let hsid = onion_svc.onion_name();
-    display_onion_name(&args.nickname, hsid)?;
+    let nickname = args.nickname()?;
+    let onion_svc = create_svc(nickname, config, client_config)?;
+    let Some(hsid) = read_onion_name(nickname, args, &onion_svc)? else {
+        let location = args.keystore_description();
+        return Err(anyhow!(
+            "Service {nickname} does not have an identity key (K_hsid) in {location} yet \
+             (use --generate=if-needed to generate one)"
+        ));
+    };
+    display_onion_name(hsid);
 
     Ok(())
 }
@@ -144,16 +260,18 @@ fn get_or_generate_onion_name(
     config: &ArtiConfig,
     client_config: &TorClientConfig,
 ) -> Result<()> {
-    let svc = create_svc(&args.nickname, config, client_config)?;
-    let hsid = svc.onion_name();
-    match hsid {
-        Some(hsid) => display_onion_name(&args.nickname, Some(hsid)),
+    let nickname = args.nickname()?;
+    let svc = create_svc(nickname, config, client_config)?;
+    let hsid = match read_onion_name(nickname, args, &svc)? {
+        Some(hsid) => hsid,
         None => {
-            let selector = Default::default();
-            let hsid = svc.generate_identity_key(selector)?;
-            display_onion_name(&args.nickname, Some(hsid))
+            let selector = args.keystore_selector();
+            svc.generate_identity_key(selector)?
         }
-    }
+    };
+    display_onion_name(hsid);
+
+    Ok(())
 }
 
 /// Run the `hss onion-name` subcommand.
@@ -168,3 +286,150 @@ fn run_onion_name(
         GenerateKey::IfNeeded => get_or_generate_onion_name(args, config, client_config),
     }
 }
+
+/// Run the `hss create-identity` subcommand.
+///
+/// Unless `--force` was given, refuses to replace an existing identity key.
+fn run_create_identity(
+    args: &CommonArgs,
+    create_args: &CreateIdentityArgs,
+    config: &ArtiConfig,
+    client_config: &TorClientConfig,
+) -> Result<()> {
+    let nickname = args.nickname()?;
+    let svc = create_svc(nickname, config, client_config)?;
+    let selector = args.keystore_selector();
+
+    // (With --force, we don't look for an existing key,
+    // so that we can also replace a key that we can't read.)
+    if !create_args.force {
+        if let Some(hsid) = read_onion_name(nickname, args, &svc)? {
+            return Err(anyhow!(
+                "Service {nickname} already has an identity key (K_hsid) for {hsid} \
+                 (use --force to replace it)"
+            ));
+        }
+    }
+
+    let hsid = match (&create_args.prefix, create_args.force) {
+        (None, true) => svc.regenerate_identity_key(selector)?,
+        (None, false) => svc.generate_identity_key(selector)?,
+        (Some(prefix), force) => {
+            let max_attempts = create_args.max_attempts;
+            if let Some(expected) = prefix.expected_attempts() {
+                eprintln!(
+                    "Searching for an address starting with '{prefix}': \
+                     this takes about {expected} attempts on average."
+                );
+            }
+            svc.generate_vanity_identity_key(prefix, max_attempts, selector, force)?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Found no address starting with '{prefix}' after {max_attempts} attempts"
+                    )
+                })?
+        }
+    };
+    display_onion_name(hsid);
+
+    Ok(())
+}
+
+/// Run the `hss check` subcommand.
+///
+/// Unlike the other subcommands, this does not create the service:
+/// it only looks up its configuration, checks that it can actually be used,
+/// and checks that the keys it needs are present in the keystore.
+///
+/// Note that by the time we get here, the configuration has already passed
+/// the builders' validation (the whole `ArtiConfig` is validated when it is loaded),
+/// so [`check_svc_config`] only looks for problems that the builders allow.
+fn run_check<R: Runtime>(
+    runtime: R,
+    args: &CommonArgs,
+    config: &ArtiConfig,
+    client_config: &TorClientConfig,
+) -> Result<()> {
+    let nickname = args.nickname()?;
+    let svc_config = find_svc_proxy_config(nickname, config)?;
+
+    let client = TorClient::with_runtime(runtime)
+        .config(client_config.clone())
+        .create_inert()?;
+
+    let mut problems = check_svc_config(svc_config);
+    problems.extend(check_svc_keys(nickname, args, &client));
+    if problems.is_empty() {
+        println!("Service {nickname}: OK");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        eprintln!("Service {nickname}: {problem}");
+    }
+
+    Err(anyhow!(
+        "Found {} problem(s) with the configuration of service {nickname}",
+        problems.len()
+    ))
+}
+
+/// Check the configuration of a service for problems that its builders don't reject.
+///
+/// Returns a description of each problem found.
+fn check_svc_config(config: &OnionServiceProxyConfig) -> Vec<String> {
+    let mut problems = vec![];
+
+    if config.proxy_cfg.proxy_ports().is_empty() {
+        problems.push(
+            "no proxy_ports are configured, so every incoming stream would be rejected".into(),
+        );
+    }
+
+    problems
+}
+
+/// Check that the keys needed by the service `nickname` are present.
+///
+/// Returns a description of each problem found.
+fn check_svc_keys(
+    nickname: &HsNickname,
+    args: &CommonArgs,
+    client: &InertTorClient,
+) -> Vec<String> {
+    let mut problems = vec![];
+    let selector = args.keystore.as_ref().map(KeystoreSelector::Id);
+    let location = args.keystore_description();
+
+    match client.onion_service_hsid(nickname, selector) {
+        Ok(Some(_)) => {}
+        Ok(None) => problems.push(format!("identity key (K_hsid) not found in {location}")),
+        Err(e) => problems.push(format!(
+            "could not read identity key (K_hsid) from {location}: {}",
+            tor_error::Report(e)
+        )),
+    }
+
+    problems
+}
+
+/// Run the `hss list` subcommand.
+///
+/// Prints the nickname of each configured service, along with a short summary
+/// of its configuration.
+///
+/// Like `hss check`, this only looks at the configuration:
+/// it does not create any of the services, or look at their keys.
+fn run_list(config: &ArtiConfig) -> Result<()> {
+    // TODO: once we support single onion services (#727),
+    // this should also say whether each service is anonymous.
+    for (nickname, cfg) in config.onion_services() {
+        println!(
+            "{nickname}: {} port rule(s), {} intro point(s)",
+            cfg.proxy_cfg.proxy_ports().len(),
+            cfg.svc_cfg.num_intro_points(),
+        );
+    }
+
+    Ok(())
+}
@@ -25,6 +25,10 @@ use crate::onion_proxy;
 /// Shorthand for a boxed and pinned Future.
 type PinnedFuture<T> = std::pin::Pin<Box<dyn futures::Future<Output = T>>>;
 
+/// How long to wait for open RPC connections to finish when we shut down.
+#[cfg(feature = "rpc")]
+const RPC_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 /// Run the `proxy` subcommand.
 pub(crate) fn run<R: Runtime>(
     runtime: R,
@@ -122,6 +126,8 @@ async fn run_proxy<R: Runtime>(
     // NOTE: reconfigurable_modules stores the only strong references to these modules,
     // so we must keep the variable alive until the end of the function
     let weak_modules = reconfigurable_modules.iter().map(Arc::downgrade).collect();
+    #[cfg(feature = "rpc")]
+    let rpc_config_sources = config_sources.clone();
     reload_cfg::watch_for_config_changes(
         client.runtime(),
         config_sources,
@@ -130,15 +136,22 @@ async fn run_proxy<R: Runtime>(
     )?;
 
     #[cfg(feature = "rpc")]
-    let rpc_data = {
-        rpc::launch_rpc_mgr(
+    let (rpc_data, rpc_shutdown) = {
+        match rpc::launch_rpc_mgr(
             &runtime,
             &arti_config.rpc,
             &path_resolver,
             &fs_mistrust,
             client.clone(),
+            rpc_config_sources,
         )
         .await?
+        {
+            Some((rpc_mgr, rpc_state_sender, shutdown)) => {
+                (Some((rpc_mgr, rpc_state_sender)), Some(shutdown))
+            }
+            None => (None, None),
+        }
     };
 
     let mut proxy: Vec<PinnedFuture<(Result<()>, &str)>> = Vec::new();
@@ -206,6 +219,11 @@ async fn run_proxy<R: Runtime>(
             => r.context("bootstrap"),
     )?;
 
+    #[cfg(feature = "rpc")]
+    if let Some(rpc_shutdown) = rpc_shutdown {
+        rpc_shutdown.shutdown(&runtime, RPC_SHUTDOWN_TIMEOUT).await;
+    }
+
     // The modules can be dropped now, because we are exiting.
     drop(reconfigurable_modules);
 
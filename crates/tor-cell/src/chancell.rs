@@ -10,6 +10,7 @@
 pub mod codec;
 pub mod msg;
 use std::num::NonZeroU32;
+use std::str::FromStr;
 
 use caret::caret_int;
 use derive_deftly::Deftly;
@@ -141,6 +142,13 @@ impl ChanCmd {
         // a command value >= 128 are variable-length.
         self == ChanCmd::VERSIONS || self.0 >= 128_u8
     }
+    /// Return the length of the body of a cell with this command,
+    /// if this is a recognized command that uses the fixed-length format.
+    ///
+    /// Returns `None` for variable-length and unrecognized commands.
+    pub fn fixed_body_len(self) -> Option<usize> {
+        (self.is_recognized() && !self.is_var_cell()).then_some(CELL_DATA_LEN)
+    }
     /// Return what kind of circuit ID this command expects.
     fn allows_circid(self) -> CircIdReq {
         match self {
@@ -175,6 +183,32 @@ impl ChanCmd {
     }
 }
 
+/// An error from parsing a [`ChanCmd`] from a string.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Unrecognized channel command {0:?}")]
+#[non_exhaustive]
+pub struct ParseChanCmdError(String);
+
+impl FromStr for ChanCmd {
+    type Err = ParseChanCmdError;
+
+    /// Parse a `ChanCmd` from its name (as in `"CREATE2"`),
+    /// or from its numeric value (as in `"10"` or `"Unknown(10)"`).
+    ///
+    /// This accepts anything produced by `ChanCmd`'s `Display` implementation.
+    /// That implementation comes from `caret_int!`, and (like every other
+    /// `caret_int!` type) formats unrecognized commands as a bare number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numeric = s
+            .strip_prefix("Unknown(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap_or(s);
+        ChanCmd::from_name(s)
+            .or_else(|| numeric.parse::<u8>().ok().map(ChanCmd::from))
+            .ok_or_else(|| ParseChanCmdError(s.to_owned()))
+    }
+}
+
 /// A decoded and parsed channel cell of unrestricted type.
 pub type AnyChanCell = ChanCell<msg::AnyChanMsg>;
 
@@ -196,6 +230,13 @@ pub trait ChanMsg {
 }
 
 /// A decoded channel cell, to be sent or received on a channel.
+///
+/// A `ChanCell` holds a parsed message, not a raw body,
+/// so there is no unparsed body whose length would need checking here.
+/// When decoding, [`ChannelCodec`](codec::ChannelCodec) bounds the body of a
+/// fixed-length cell to [`CELL_DATA_LEN`] bytes,
+/// and the body of a variable-length cell to the `u16::MAX` bytes
+/// that its length field can express.
 #[derive(Debug, Deftly)]
 #[derive_deftly(HasMemoryCost)]
 #[deftly(has_memory_cost(bounds = "M: HasMemoryCostStructural"))]
@@ -184,3 +184,68 @@ fn versions() {
     let cc: AnyChanCell = v.into();
     assert_eq!(cc.circid(), None);
 }
+
+#[test]
+fn fixed_body_len() {
+    use tor_cell::chancell::CELL_DATA_LEN;
+
+    assert_eq!(ChanCmd::PADDING.fixed_body_len(), Some(CELL_DATA_LEN));
+    assert_eq!(ChanCmd::RELAY.fixed_body_len(), Some(CELL_DATA_LEN));
+    assert_eq!(ChanCmd::CREATE2.fixed_body_len(), Some(CELL_DATA_LEN));
+    assert_eq!(ChanCmd::NETINFO.fixed_body_len(), Some(CELL_DATA_LEN));
+
+    // Variable-length, despite its low number.
+    assert_eq!(ChanCmd::VERSIONS.fixed_body_len(), None);
+    assert_eq!(ChanCmd::VPADDING.fixed_body_len(), None);
+    assert_eq!(ChanCmd::CERTS.fixed_body_len(), None);
+
+    // Unrecognized commands, both in the fixed-length and variable-length ranges.
+    assert_eq!(ChanCmd::from(77).fixed_body_len(), None);
+    assert_eq!(ChanCmd::from(200).fixed_body_len(), None);
+}
+
+#[test]
+fn chancmd_display_and_parse() {
+    assert_eq!(ChanCmd::CREATE2.to_string(), "CREATE2");
+    assert_eq!(ChanCmd::from(200).to_string(), "200");
+
+    assert_eq!("RELAY".parse::<ChanCmd>().unwrap(), ChanCmd::RELAY);
+    assert_eq!("3".parse::<ChanCmd>().unwrap(), ChanCmd::RELAY);
+    assert_eq!("200".parse::<ChanCmd>().unwrap(), ChanCmd::from(200));
+    assert_eq!(
+        "Unknown(200)".parse::<ChanCmd>().unwrap(),
+        ChanCmd::from(200)
+    );
+
+    // Display and FromStr round-trip, whether or not the command is recognized.
+    for cmd in [
+        ChanCmd::VERSIONS,
+        ChanCmd::PADDING_NEGOTIATE,
+        ChanCmd::from(77),
+    ] {
+        assert_eq!(cmd.to_string().parse::<ChanCmd>().unwrap(), cmd);
+    }
+
+    assert!("relay".parse::<ChanCmd>().is_err());
+    assert!("256".parse::<ChanCmd>().is_err());
+    assert!("".parse::<ChanCmd>().is_err());
+    assert!("Unknown(256)".parse::<ChanCmd>().is_err());
+    assert!("Unknown(RELAY)".parse::<ChanCmd>().is_err());
+    assert!("Unknown(3".parse::<ChanCmd>().is_err());
+}
+
+#[test]
+fn max_length_varcell() {
+    // The largest possible variable-length cell round-trips...
+    let mut codec = codec::ChannelCodec::new(4);
+    let mut b = BytesMut::new();
+    let cell: AnyChanCell = msg::Vpadding::new(u16::MAX).into();
+    codec.write_cell(cell, &mut b).unwrap();
+    assert_eq!(b.len(), 7 + u16::MAX as usize);
+
+    // ...and decoding it consumes exactly that cell, no matter what follows.
+    b.extend_from_slice(&[0; 1000]);
+    let decoded = codec.decode_cell::<AnyChanMsg>(&mut b).unwrap().unwrap();
+    assert_eq!(decoded.msg().cmd(), ChanCmd::VPADDING);
+    assert_eq!(b.len(), 1000);
+}
@@ -4,6 +4,7 @@ use std::io;
 use std::sync::{Arc, Mutex};
 
 use crate::factory::{BootstrapReporter, ChannelFactory, IncomingChannelFactory};
+use crate::ratelimit::{BandwidthLimiter, RateLimitedStream};
 use crate::transport::TransportImplHelper;
 use crate::{event::ChanMgrEventSender, Error};
 
@@ -11,6 +12,7 @@ use std::time::Duration;
 use tor_error::internal;
 use tor_linkspec::{BridgeAddr, HasChanMethod, IntoOwnedChanTarget, OwnedChanTarget};
 use tor_proto::channel::params::ChannelPaddingInstructionsUpdates;
+use tor_proto::channel::TrafficCounter;
 use tor_proto::memquota::ChannelAccount;
 use tor_rtcompat::{tls::TlsConnector, Runtime, TlsProvider};
 
@@ -39,6 +41,10 @@ where
     transport: H,
     /// Object to build TLS connections.
     tls_connector: <R as TlsProvider<H::Stream>>::Connector,
+    /// If present, a counter for the traffic on every channel we build.
+    traffic: Option<Arc<TrafficCounter>>,
+    /// The limit on the bandwidth of all the channels we build, taken together.
+    limiter: Arc<BandwidthLimiter>,
 }
 
 impl<R: Runtime, H: TransportImplHelper> ChanBuilder<R, H>
@@ -52,8 +58,20 @@ where
             runtime,
             transport,
             tls_connector,
+            traffic: None,
+            limiter: Default::default(),
         }
     }
+
+    /// Count the traffic of every channel that this builder builds in `counter`.
+    pub(crate) fn set_traffic_counter(&mut self, counter: Arc<TrafficCounter>) {
+        self.traffic = Some(counter);
+    }
+
+    /// Make every channel that this builder builds obey `limiter`.
+    pub(crate) fn set_bandwidth_limiter(&mut self, limiter: Arc<BandwidthLimiter>) {
+        self.limiter = limiter;
+    }
 }
 #[async_trait]
 impl<R: Runtime, H: TransportImplHelper> ChannelFactory for ChanBuilder<R, H>
@@ -107,12 +125,16 @@ where
             source: ioe,
         };
 
-        let _tls = self
+        let tls = self
             .tls_connector
             .negotiate_unvalidated(stream, "ignored")
             .await
             .map_err(|e| map_ioe(e.into(), "TLS negotiation"))?;
 
+        // Channels that other relays open to us carry the traffic we relay for them,
+        // so they must obey our bandwidth limit too.
+        let _tls = RateLimitedStream::new(tls, Arc::clone(&self.limiter), self.runtime.clone());
+
         // TODO RELAY: do handshake and build channel
         todo!();
     }
@@ -190,6 +212,13 @@ where
         // 2. Set up the channel.
         let mut builder = ChannelBuilder::new();
         builder.set_declared_method(using_method);
+        if let Some(traffic) = &self.traffic {
+            builder.set_traffic_counter(Arc::clone(traffic));
+        }
+        // We do this even if there is no limit right now,
+        // so that any limit we set later will apply to this channel too.
+        // (Without a limit, the limiter doesn't take any locks.)
+        let tls = RateLimitedStream::new(tls, Arc::clone(&self.limiter), self.runtime.clone());
         let chan = builder
             .launch(
                 tls,
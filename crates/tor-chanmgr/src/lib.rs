@@ -48,6 +48,7 @@ mod err;
 mod event;
 pub mod factory;
 mod mgr;
+mod ratelimit;
 #[cfg(test)]
 mod testing;
 pub mod transport;
@@ -63,7 +64,7 @@ use tor_config::ReconfigureError;
 use tor_error::error_report;
 use tor_linkspec::{ChanTarget, OwnedChanTarget};
 use tor_netdir::{params::NetParameters, NetDirProvider};
-use tor_proto::channel::Channel;
+use tor_proto::channel::{Channel, TrafficCounter, TrafficSnapshot};
 #[cfg(feature = "experimental-api")]
 use tor_proto::memquota::ChannelAccount;
 use tor_proto::memquota::ToplevelAccount;
@@ -73,6 +74,7 @@ use void::{ResultVoidErrExt, Void};
 pub use err::Error;
 
 pub use config::{ChannelConfig, ChannelConfigBuilder};
+pub use ratelimit::BandwidthLimit;
 
 use tor_rtcompat::Runtime;
 
@@ -127,6 +129,12 @@ pub struct ChanMgr<R: Runtime> {
     /// Stream of [`ConnStatus`] events.
     bootstrap_status: event::ConnStatusEvents,
 
+    /// The number of bytes read and written on the channels we have built.
+    traffic: Arc<TrafficCounter>,
+
+    /// The limit on the bandwidth of the channels we build.
+    limiter: Arc<ratelimit::BandwidthLimiter>,
+
     /// This currently isn't actually used, but we're keeping a PhantomData here
     /// since probably we'll want it again, sooner or later.
     runtime: std::marker::PhantomData<fn(R) -> R>,
@@ -224,7 +232,11 @@ impl<R: Runtime> ChanMgr<R> {
         let sender = Arc::new(std::sync::Mutex::new(sender));
         let reporter = BootstrapReporter(sender);
         let transport = transport::DefaultTransport::new(runtime.clone());
-        let builder = builder::ChanBuilder::new(runtime, transport);
+        let traffic = Arc::new(TrafficCounter::new());
+        let mut builder = builder::ChanBuilder::new(runtime, transport);
+        builder.set_traffic_counter(Arc::clone(&traffic));
+        let limiter = Arc::new(ratelimit::BandwidthLimiter::default());
+        builder.set_bandwidth_limiter(Arc::clone(&limiter));
         let factory = factory::CompoundFactory::new(
             Arc::new(builder),
             #[cfg(feature = "pt-client")]
@@ -235,6 +247,8 @@ impl<R: Runtime> ChanMgr<R> {
         ChanMgr {
             mgr,
             bootstrap_status: receiver,
+            traffic,
+            limiter,
             runtime: std::marker::PhantomData,
         }
     }
@@ -311,6 +325,29 @@ impl<R: Runtime> ChanMgr<R> {
         self.bootstrap_status.clone()
     }
 
+    /// Return the total number of bytes that we have read and written
+    /// on the channels that this manager has built.
+    ///
+    /// This counts the traffic of every channel,
+    /// including channels that have since closed.
+    /// It does not (currently) include channels built through pluggable transports.
+    pub fn traffic(&self) -> TrafficSnapshot {
+        self.traffic.snapshot()
+    }
+
+    /// Limit the bandwidth of the channels that this manager builds to `limit`.
+    ///
+    /// The limit applies to all of the channels together, including the ones
+    /// that we already have, and (with the `relay` feature)
+    /// the ones that other relays open to us.
+    /// If `limit` is `None`, remove any existing limit.
+    ///
+    /// Like [`traffic`](ChanMgr::traffic), this does not (currently) apply to
+    /// channels built through pluggable transports.
+    pub fn set_bandwidth_limit(&self, limit: Option<BandwidthLimit>) {
+        self.limiter.set_limit(limit);
+    }
+
     /// Expire all channels that have been unused for too long.
     ///
     /// Return the duration from now until next channel expires.
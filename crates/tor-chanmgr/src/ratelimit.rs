@@ -0,0 +1,427 @@
+//! Limit the rate at which we read and write bytes on our channels.
+//!
+//! This is a token bucket in the style of C Tor's `BandwidthRate` and
+//! `BandwidthBurst` options: each direction has a bucket that holds at most
+//! `burst` bytes, and that refills at `rate` bytes per second.
+//! We only read or write as many bytes as there are in the bucket.
+//!
+//! The limit is shared between all the channels that a
+//! [`ChanMgr`](crate::ChanMgr) builds.
+
+use std::future::Future;
+use std::io;
+use std::num::NonZeroU64;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::ready;
+use tor_rtcompat::SleepProvider;
+
+/// The shortest time that we'll wait for a bucket to refill.
+///
+/// Without this, a slow rate would make us wake up for every single byte.
+const MIN_WAIT: Duration = Duration::from_millis(10);
+
+/// A limit on the number of bytes that we read, and on the number of bytes
+/// that we write, on all of our channels together.
+///
+/// The two directions are limited separately: a limit of 1000 bytes per second
+/// lets us read 1000 bytes per second _and_ write 1000 bytes per second.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct BandwidthLimit {
+    /// The average number of bytes per second that we allow.
+    pub rate: NonZeroU64,
+    /// The largest number of bytes that we allow in a single burst.
+    pub burst: NonZeroU64,
+}
+
+impl BandwidthLimit {
+    /// Return a new `BandwidthLimit` allowing `rate` bytes per second on average,
+    /// in bursts of up to `burst` bytes.
+    pub fn new(rate: NonZeroU64, burst: NonZeroU64) -> Self {
+        Self { rate, burst }
+    }
+}
+
+/// A token bucket for one direction of traffic.
+#[derive(Debug)]
+struct TokenBucket {
+    /// The number of bytes that we may transfer right now.
+    level: u64,
+    /// The last time at which we added bytes to `level`.
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Return a new, full, `TokenBucket` for `limit`.
+    fn new(limit: &BandwidthLimit, now: Instant) -> Self {
+        Self {
+            level: limit.burst.get(),
+            last_refill: now,
+        }
+    }
+
+    /// Add the bytes that `limit` allows us for the time since the last refill.
+    fn refill(&mut self, limit: &BandwidthLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let added = u128::from(limit.rate.get()) * elapsed.as_nanos() / 1_000_000_000;
+        // If `added` is zero, we leave `last_refill` alone, so that we don't
+        // lose the partial bytes when we're called very often.
+        if added > 0 {
+            let added = u64::try_from(added).unwrap_or(u64::MAX);
+            self.level = self.level.saturating_add(added).min(limit.burst.get());
+            self.last_refill = now;
+        }
+    }
+
+    /// Take up to `want` bytes out of this bucket.
+    ///
+    /// Returns the number of bytes taken, or, if the bucket is empty,
+    /// how long to wait before trying again.
+    fn claim(
+        &mut self,
+        limit: &BandwidthLimit,
+        now: Instant,
+        want: usize,
+    ) -> Result<usize, Duration> {
+        self.refill(limit, now);
+        if self.level == 0 {
+            let nanos = 1_000_000_000_u64.div_ceil(limit.rate.get());
+            return Err(Duration::from_nanos(nanos).max(MIN_WAIT));
+        }
+        let n = usize::try_from(self.level).unwrap_or(usize::MAX).min(want);
+        self.level -= n as u64;
+        Ok(n)
+    }
+
+    /// Put `n` bytes that we claimed but didn't use back into this bucket.
+    fn refund(&mut self, limit: &BandwidthLimit, n: usize) {
+        self.level = self.level.saturating_add(n as u64).min(limit.burst.get());
+    }
+}
+
+/// A direction of traffic.
+#[derive(Clone, Copy, Debug)]
+enum Direction {
+    /// Bytes that we read.
+    Read,
+    /// Bytes that we write.
+    Write,
+}
+
+/// The state of a [`BandwidthLimiter`] that has a limit.
+#[derive(Debug)]
+struct Buckets {
+    /// The limit that we're enforcing.
+    limit: BandwidthLimit,
+    /// The bucket for the bytes we read.
+    ///
+    /// This is `None` until we first read.
+    read: Option<TokenBucket>,
+    /// The bucket for the bytes we write.
+    ///
+    /// This is `None` until we first write.
+    write: Option<TokenBucket>,
+}
+
+/// A [`BandwidthLimit`], and the state that we need to enforce it.
+///
+/// This is shared between all the channels that we want to limit together.
+///
+/// Every channel obeys one of these, even when there is no limit,
+/// so that a limit set later applies to existing channels too.
+/// When there is no limit, reading and writing only need to check `limited`,
+/// and never take the lock.
+#[derive(Debug, Default)]
+pub(crate) struct BandwidthLimiter {
+    /// True if `buckets` is `Some`.
+    ///
+    /// Only changed while holding the lock on `buckets`.
+    limited: AtomicBool,
+    /// The current limit and buckets, or `None` if there is no limit.
+    buckets: Mutex<Option<Buckets>>,
+}
+
+impl BandwidthLimiter {
+    /// Start enforcing `limit` instead of the current limit.
+    ///
+    /// If `limit` is `None`, stop limiting the bandwidth.
+    pub(crate) fn set_limit(&self, limit: Option<BandwidthLimit>) {
+        let mut buckets = self.buckets.lock().expect("lock poisoned");
+        match (buckets.as_mut(), limit) {
+            (Some(buckets), Some(limit)) => {
+                // Keep the buckets, but make sure they respect the new burst.
+                buckets.limit = limit;
+                for bucket in [&mut buckets.read, &mut buckets.write]
+                    .into_iter()
+                    .flatten()
+                {
+                    bucket.level = bucket.level.min(limit.burst.get());
+                }
+            }
+            (_, limit) => {
+                *buckets = limit.map(|limit| Buckets {
+                    limit,
+                    read: None,
+                    write: None,
+                });
+            }
+        }
+        self.limited.store(buckets.is_some(), Ordering::Relaxed);
+    }
+
+    /// Take up to `want` bytes from the bucket for `dir`.
+    ///
+    /// Returns the number of bytes taken, or how long to wait before trying again.
+    fn claim(&self, dir: Direction, now: Instant, want: usize) -> Result<usize, Duration> {
+        if !self.limited.load(Ordering::Relaxed) {
+            return Ok(want);
+        }
+        let mut buckets = self.buckets.lock().expect("lock poisoned");
+        let Some(Buckets { limit, read, write }) = buckets.as_mut() else {
+            return Ok(want);
+        };
+        let bucket = match dir {
+            Direction::Read => read,
+            Direction::Write => write,
+        };
+        bucket
+            .get_or_insert_with(|| TokenBucket::new(limit, now))
+            .claim(limit, now, want)
+    }
+
+    /// Put `n` bytes that we claimed from the bucket for `dir`, but didn't use, back.
+    fn refund(&self, dir: Direction, n: usize) {
+        if n == 0 || !self.limited.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut buckets = self.buckets.lock().expect("lock poisoned");
+        let Some(Buckets { limit, read, write }) = buckets.as_mut() else {
+            return;
+        };
+        let bucket = match dir {
+            Direction::Read => read,
+            Direction::Write => write,
+        };
+        if let Some(bucket) = bucket {
+            bucket.refund(limit, n);
+        }
+    }
+}
+
+/// A stream whose reads and writes are limited by a [`BandwidthLimiter`].
+pub(crate) struct RateLimitedStream<S, R: SleepProvider> {
+    /// The underlying stream.
+    inner: S,
+    /// The limiter that we obey.
+    limiter: Arc<BandwidthLimiter>,
+    /// The runtime that we use to tell the time, and to wait.
+    runtime: R,
+    /// If we ran out of bytes to read, a timer for when we can try again.
+    read_wait: Option<Pin<Box<R::SleepFuture>>>,
+    /// If we ran out of bytes to write, a timer for when we can try again.
+    write_wait: Option<Pin<Box<R::SleepFuture>>>,
+}
+
+// We never pin-project to `runtime`, so we're `Unpin` whenever the stream is.
+impl<S: Unpin, R: SleepProvider> Unpin for RateLimitedStream<S, R> {}
+
+impl<S, R: SleepProvider> RateLimitedStream<S, R> {
+    /// Wrap `inner` so that its reads and writes obey `limiter`.
+    pub(crate) fn new(inner: S, limiter: Arc<BandwidthLimiter>, runtime: R) -> Self {
+        Self {
+            inner,
+            limiter,
+            runtime,
+            read_wait: None,
+            write_wait: None,
+        }
+    }
+}
+
+impl<S: Unpin, R: SleepProvider> RateLimitedStream<S, R> {
+    /// Wait until the bucket for `dir` has some bytes in it, then call `op` with the
+    /// underlying stream and the number of bytes that it may transfer.
+    ///
+    /// `op` must return the number of bytes that it actually transferred.
+    fn poll_limited(
+        &mut self,
+        cx: &mut Context<'_>,
+        dir: Direction,
+        want: usize,
+        op: impl FnOnce(Pin<&mut S>, &mut Context<'_>, usize) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        if want == 0 {
+            return op(Pin::new(&mut self.inner), cx, 0);
+        }
+        let wait = match dir {
+            Direction::Read => &mut self.read_wait,
+            Direction::Write => &mut self.write_wait,
+        };
+        let allowed = loop {
+            if let Some(sleep) = wait.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                *wait = None;
+            }
+            match self.limiter.claim(dir, self.runtime.now(), want) {
+                Ok(n) => break n,
+                Err(delay) => *wait = Some(Box::pin(self.runtime.sleep(delay))),
+            }
+        };
+        let result = op(Pin::new(&mut self.inner), cx, allowed);
+        let used = match &result {
+            Poll::Ready(Ok(n)) => *n,
+            _ => 0,
+        };
+        self.limiter.refund(dir, allowed.saturating_sub(used));
+        result
+    }
+}
+
+impl<S: AsyncRead + Unpin, R: SleepProvider> AsyncRead for RateLimitedStream<S, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_limited(cx, Direction::Read, buf.len(), |inner, cx, n| {
+                inner.poll_read(cx, &mut buf[..n])
+            })
+    }
+}
+
+impl<S: AsyncWrite + Unpin, R: SleepProvider> AsyncWrite for RateLimitedStream<S, R> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_limited(cx, Direction::Write, buf.len(), |inner, cx, n| {
+                inner.poll_write(cx, &buf[..n])
+            })
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    use futures::io::AsyncWriteExt as _;
+    use futures::task::SpawnExt as _;
+    use tor_rtmock::MockRuntime;
+
+    /// Return a limit of `rate` bytes per second, in bursts of up to `burst`.
+    fn limit(rate: u64, burst: u64) -> BandwidthLimit {
+        BandwidthLimit::new(rate.try_into().unwrap(), burst.try_into().unwrap())
+    }
+
+    #[test]
+    fn bucket() {
+        let fast = limit(1000, 1500);
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(&fast, start);
+
+        // We start out full.
+        assert_eq!(bucket.claim(&fast, start, 1000), Ok(1000));
+        assert_eq!(bucket.claim(&fast, start, 1000), Ok(500));
+        assert_eq!(bucket.claim(&fast, start, 1000), Err(MIN_WAIT));
+
+        // We refill at `rate`.
+        let later = start + Duration::from_millis(100);
+        assert_eq!(bucket.claim(&fast, later, 1000), Ok(100));
+
+        // Refunds go back in the bucket, but never above `burst`.
+        bucket.refund(&fast, 40);
+        assert_eq!(bucket.claim(&fast, later, 1000), Ok(40));
+        bucket.refund(&fast, 10_000);
+        assert_eq!(bucket.claim(&fast, later, 10_000), Ok(1500));
+
+        // A slow rate means a longer wait.
+        let slow = limit(10, 10);
+        let mut bucket = TokenBucket::new(&slow, start);
+        assert_eq!(bucket.claim(&slow, start, 100), Ok(10));
+        assert_eq!(
+            bucket.claim(&slow, start, 100),
+            Err(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn limiter() {
+        let limiter = BandwidthLimiter::default();
+        let now = Instant::now();
+
+        // With no limit, we can do anything, without even looking at the buckets.
+        assert!(!limiter.limited.load(Ordering::Relaxed));
+        assert_eq!(limiter.claim(Direction::Read, now, 1 << 20), Ok(1 << 20));
+
+        // The two directions are limited separately.
+        limiter.set_limit(Some(limit(100, 100)));
+        assert_eq!(limiter.claim(Direction::Read, now, 1000), Ok(100));
+        assert_eq!(limiter.claim(Direction::Write, now, 1000), Ok(100));
+        assert!(limiter.claim(Direction::Read, now, 1000).is_err());
+
+        // Changing the limit doesn't refill the buckets.
+        limiter.set_limit(Some(limit(1000, 1000)));
+        assert!(limiter.claim(Direction::Write, now, 1000).is_err());
+
+        // Removing the limit lets everything through again.
+        limiter.set_limit(None);
+        assert!(!limiter.limited.load(Ordering::Relaxed));
+        assert_eq!(limiter.claim(Direction::Write, now, 1000), Ok(1000));
+    }
+
+    #[test]
+    fn stream() {
+        MockRuntime::test_with_various(|rt| async move {
+            let limiter = Arc::new(BandwidthLimiter::default());
+            limiter.set_limit(Some(limit(1000, 1000)));
+            let mut stream =
+                RateLimitedStream::new(futures::io::sink(), Arc::clone(&limiter), rt.clone());
+
+            // The first 1000 bytes go out straight away.
+            stream.write_all(&[0; 1000]).await.unwrap();
+
+            // The rest have to wait for the bucket to refill.
+            let start = rt.now();
+            let write = rt
+                .spawn_with_handle(async move {
+                    stream.write_all(&[0; 500]).await.unwrap();
+                })
+                .unwrap();
+            rt.advance_until_stalled().await;
+            write.await;
+            let elapsed = rt.now() - start;
+            assert!(elapsed >= Duration::from_millis(500), "{elapsed:?}");
+            assert!(elapsed < Duration::from_millis(600), "{elapsed:?}");
+        });
+    }
+}
@@ -172,7 +172,7 @@ impl<R: Runtime> crate::mgr::AbstractCircBuilder<R> for crate::build::CircuitBui
 
     fn launch_parallelism(&self, spec: &TargetCircUsage) -> usize {
         match spec {
-            TargetCircUsage::Dir => 3,
+            TargetCircUsage::Dir { .. } => 3,
             _ => 1,
         }
     }
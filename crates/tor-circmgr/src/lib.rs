@@ -61,6 +61,7 @@ use tor_linkspec::IntoOwnedChanTarget;
 
 use futures::task::SpawnExt;
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 use tracing::{debug, info, trace, warn};
@@ -163,6 +164,32 @@ impl<'a> DirInfo<'a> {
     }
 }
 
+/// Whether to prefer or require a directory cache that we can reach over IPv6,
+/// when building a one-hop directory circuit.
+///
+/// Whenever we pick a directory cache for its IPv6 address,
+/// we only connect to it over IPv6.
+/// (If we already have a channel open to that cache, we may reuse it
+/// no matter which address it was opened to.)
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum DirIpv6Preference {
+    /// Use any directory cache, whatever its addresses.
+    #[default]
+    Any,
+    /// Use a directory cache that has an IPv6 address, if we can find one;
+    /// otherwise, use any directory cache.
+    ///
+    /// We'll still reuse an existing directory circuit,
+    /// even if we didn't build it over IPv6.
+    Prefer,
+    /// Only use directory caches that we can reach over IPv6.
+    ///
+    /// If we can't find any, building a directory circuit fails.
+    Require,
+}
+
 /// A Circuit Manager (CircMgr) manages a set of circuits, returning them
 /// when they're suitable, and launching them if they don't already exist.
 ///
@@ -197,8 +224,15 @@ impl<R: Runtime> CircMgr<R> {
 
     /// Return a circuit suitable for sending one-hop BEGINDIR streams,
     /// launching it if necessary.
-    pub async fn get_or_launch_dir(&self, netdir: DirInfo<'_>) -> Result<Arc<ClientCirc>> {
-        self.0.get_or_launch_dir(netdir).await
+    ///
+    /// The `ipv6` argument says whether we prefer or require
+    /// a directory cache that we can reach over IPv6.
+    pub async fn get_or_launch_dir(
+        &self,
+        netdir: DirInfo<'_>,
+        ipv6: DirIpv6Preference,
+    ) -> Result<Arc<ClientCirc>> {
+        self.0.get_or_launch_dir(netdir, ipv6).await
     }
 
     /// Return a circuit suitable for exiting to all of the provided
@@ -511,9 +545,13 @@ impl<B: AbstractCircBuilder<R> + 'static, R: Runtime> CircMgrInner<B, R> {
 
     /// Return a circuit suitable for sending one-hop BEGINDIR streams,
     /// launching it if necessary.
-    pub(crate) async fn get_or_launch_dir(&self, netdir: DirInfo<'_>) -> Result<Arc<B::Circ>> {
+    pub(crate) async fn get_or_launch_dir(
+        &self,
+        netdir: DirInfo<'_>,
+        ipv6: DirIpv6Preference,
+    ) -> Result<Arc<B::Circ>> {
         self.expire_circuits();
-        let usage = TargetCircUsage::Dir;
+        let usage = TargetCircUsage::Dir { ipv6 };
         self.mgr.get_or_launch(&usage, netdir).await.map(|(c, _)| c)
     }
 
@@ -1,12 +1,16 @@
 //! Code to construct paths to a directory for non-anonymous downloads
 use super::TorPath;
-use crate::Result;
-use tor_guardmgr::{GuardMgr, GuardMonitor, GuardUsable};
+use crate::{DirIpv6Preference, Error, Result};
+use tor_guardmgr::{GuardMgr, GuardMonitor, GuardRestriction, GuardUsable};
+use tor_linkspec::HasChanMethod;
 use tor_rtcompat::Runtime;
 
 /// A PathBuilder that can connect to a directory.
 #[non_exhaustive]
-pub(crate) struct DirPathBuilder {}
+pub(crate) struct DirPathBuilder {
+    /// Whether we prefer or require a directory cache that we can reach over IPv6.
+    ipv6: DirIpv6Preference,
+}
 
 impl Default for DirPathBuilder {
     fn default() -> Self {
@@ -17,21 +21,54 @@ impl Default for DirPathBuilder {
 impl DirPathBuilder {
     /// Create a new DirPathBuilder.
     pub(crate) fn new() -> Self {
-        DirPathBuilder {}
+        DirPathBuilder {
+            ipv6: DirIpv6Preference::default(),
+        }
+    }
+
+    /// Set whether we prefer or require a directory cache that we can reach over IPv6.
+    pub(crate) fn ipv6(&mut self, ipv6: DirIpv6Preference) -> &mut Self {
+        self.ipv6 = ipv6;
+        self
     }
 
     /// Try to create and return a path corresponding to the requirements of
     /// this builder.
+    ///
+    /// Along with the path, return true if we will only connect to its
+    /// directory cache over IPv6.
     pub(crate) fn pick_path<'a, RT: Runtime>(
         &self,
         guards: &GuardMgr<RT>,
-    ) -> Result<(TorPath<'a>, GuardMonitor, GuardUsable)> {
-        let guard_usage = tor_guardmgr::GuardUsageBuilder::default()
-            .kind(tor_guardmgr::GuardUsageKind::OneHopDirectory)
-            .build()
-            .expect("Unable to build directory guard usage");
-        let (guard, mon, usable) = guards.select_guard(guard_usage)?;
-        Ok((TorPath::new_one_hop_owned(&guard), mon, usable))
+    ) -> Result<(TorPath<'a>, bool, GuardMonitor, GuardUsable)> {
+        let select = |require_ipv6: bool| {
+            let mut guard_usage = tor_guardmgr::GuardUsageBuilder::default();
+            guard_usage.kind(tor_guardmgr::GuardUsageKind::OneHopDirectory);
+            if require_ipv6 {
+                guard_usage
+                    .restrictions()
+                    .push(GuardRestriction::RequireIpv6);
+            }
+            let guard_usage = guard_usage
+                .build()
+                .expect("Unable to build directory guard usage");
+            guards.select_guard(guard_usage)
+        };
+
+        let (guard, mon, usable) = match self.ipv6 {
+            DirIpv6Preference::Any => select(false)?,
+            DirIpv6Preference::Prefer => select(true).or_else(|_| select(false))?,
+            DirIpv6Preference::Require => select(true).map_err(|e| Error::NoRelay {
+                path_kind: "directory",
+                role: "IPv6-reachable directory cache",
+                problem: e.to_string(),
+            })?,
+        };
+        let ipv6_only = guard
+            .chan_method()
+            .socket_addrs()
+            .is_some_and(|addrs| !addrs.is_empty() && addrs.iter().all(|addr| addr.is_ipv6()));
+        Ok((TorPath::new_one_hop_owned(&guard), ipv6_only, mon, usable))
     }
 }
 
@@ -72,7 +109,7 @@ mod test {
             // This is a nice easy case, since we tested the harder cases
             // in guard-spec.  We'll just have every path succeed.
             for _ in 0..40 {
-                let (path, mon, usable) = DirPathBuilder::new().pick_path(&guards).unwrap();
+                let (path, _, mon, usable) = DirPathBuilder::new().pick_path(&guards).unwrap();
                 if let crate::path::TorPathInner::OwnedOneHop(relay) = path.inner {
                     distinct_guards.insert(RelayIds::from_relay_ids(&relay));
                     mon.succeeded();
@@ -87,4 +124,27 @@ mod test {
             );
         });
     }
+    #[test]
+    fn dirpath_ipv6() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            // None of the relays in this network has an IPv6 address.
+            let netdir = testnet::construct_netdir().unwrap_if_sufficient().unwrap();
+            let statemgr = TestingStateMgr::new();
+            let guards =
+                tor_guardmgr::GuardMgr::new(rt.clone(), statemgr, &TestConfig::default()).unwrap();
+            guards.install_test_netdir(&netdir);
+
+            let (_, ipv6_only, mon, _) = DirPathBuilder::new()
+                .ipv6(DirIpv6Preference::Prefer)
+                .pick_path(&guards)
+                .unwrap();
+            assert!(!ipv6_only);
+            mon.succeeded();
+
+            let outcome = DirPathBuilder::new()
+                .ipv6(DirIpv6Preference::Require)
+                .pick_path(&guards);
+            assert!(matches!(outcome, Err(Error::NoRelay { .. })));
+        });
+    }
 }
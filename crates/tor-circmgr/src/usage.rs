@@ -9,6 +9,7 @@ use tracing::trace;
 use void::Void;
 
 use crate::path::{dirpath::DirPathBuilder, exitpath::ExitPathBuilder, TorPath};
+use crate::DirIpv6Preference;
 use tor_chanmgr::ChannelUsage;
 #[cfg(feature = "geoip")]
 use tor_error::internal;
@@ -134,7 +135,10 @@ impl ExitPolicy {
 #[derive(Clone, Debug)]
 pub(crate) enum TargetCircUsage {
     /// Use for BEGINDIR-based non-anonymous directory connections
-    Dir,
+    Dir {
+        /// Whether we prefer or require a directory cache that we can reach over IPv6.
+        ipv6: DirIpv6Preference,
+    },
     /// Use to exit to one or more ports.
     Exit {
         /// List of ports the circuit has to allow.
@@ -199,7 +203,10 @@ pub(crate) enum TargetCircUsage {
 #[derive(Clone, Debug)]
 pub(crate) enum SupportedCircUsage {
     /// Usable for BEGINDIR-based non-anonymous directory connections
-    Dir,
+    Dir {
+        /// True if we only connect to this circuit's directory cache over IPv6.
+        ipv6_only: bool,
+    },
     /// Usable to exit to a set of ports.
     Exit {
         /// Exit policy of the circuit
@@ -245,9 +252,15 @@ impl TargetCircUsage {
         Option<GuardUsable>,
     )> {
         match self {
-            TargetCircUsage::Dir => {
-                let (path, mon, usable) = DirPathBuilder::new().pick_path(guards)?;
-                Ok((path, SupportedCircUsage::Dir, Some(mon), Some(usable)))
+            TargetCircUsage::Dir { ipv6 } => {
+                let (path, ipv6_only, mon, usable) =
+                    DirPathBuilder::new().ipv6(*ipv6).pick_path(guards)?;
+                Ok((
+                    path,
+                    SupportedCircUsage::Dir { ipv6_only },
+                    Some(mon),
+                    Some(usable),
+                ))
             }
             TargetCircUsage::Preemptive {
                 port,
@@ -405,7 +418,9 @@ impl SupportedCircUsage {
     pub(crate) fn supports(&self, target: &TargetCircUsage) -> bool {
         use SupportedCircUsage::*;
         match (self, target) {
-            (Dir, TargetCircUsage::Dir) => true,
+            (Dir { ipv6_only }, TargetCircUsage::Dir { ipv6 }) => {
+                *ipv6_only || *ipv6 != DirIpv6Preference::Require
+            }
             (
                 Exit {
                     policy: p1,
@@ -481,7 +496,13 @@ impl SupportedCircUsage {
     ) -> std::result::Result<(), RestrictionFailed> {
         use SupportedCircUsage::*;
         match (self, usage) {
-            (Dir, TargetCircUsage::Dir) => Ok(()),
+            (Dir { ipv6_only }, TargetCircUsage::Dir { ipv6 }) => {
+                if *ipv6_only || *ipv6 != DirIpv6Preference::Require {
+                    Ok(())
+                } else {
+                    Err(RestrictionFailed::NotSupported)
+                }
+            }
             // This usage is only used to create circuits preemptively, and doesn't actually
             // correspond to any streams; accordingly, we don't need to modify the circuit's
             // acceptable usage at all.
@@ -558,7 +579,7 @@ impl SupportedCircUsage {
         use ChannelUsage as CU;
         use SupportedCircUsage as SCU;
         match self {
-            SCU::Dir => CU::Dir,
+            SCU::Dir { .. } => CU::Dir,
             #[cfg(feature = "specific-relay")]
             SCU::DirSpecificTarget(_) => CU::Dir,
             SCU::Exit { .. } => CU::UserTraffic,
@@ -586,7 +607,7 @@ pub(crate) mod test {
         fn isol_eq(&self, other: &Self) -> bool {
             use TargetCircUsage::*;
             match (self, other) {
-                (Dir, Dir) => true,
+                (Dir { ipv6: i1 }, Dir { ipv6: i2 }) => i1 == i2,
                 (
                     Exit {
                         ports: p1,
@@ -623,7 +644,7 @@ pub(crate) mod test {
         fn isol_eq(&self, other: &Self) -> bool {
             use SupportedCircUsage::*;
             match (self, other) {
-                (Dir, Dir) => true,
+                (Dir { ipv6_only: i1 }, Dir { ipv6_only: i2 }) => i1 == i2,
                 (
                     Exit {
                         policy: p1,
@@ -721,8 +742,10 @@ pub(crate) mod test {
             .build()
             .unwrap();
 
-        let supp_dir = SupportedCircUsage::Dir;
-        let targ_dir = TargetCircUsage::Dir;
+        let supp_dir = SupportedCircUsage::Dir { ipv6_only: false };
+        let targ_dir = TargetCircUsage::Dir {
+            ipv6: DirIpv6Preference::Any,
+        };
         let supp_exit = SupportedCircUsage::Exit {
             policy: policy.clone(),
             isolation: Some(isolation.clone()),
@@ -817,8 +840,10 @@ pub(crate) mod test {
             .build()
             .unwrap();
 
-        let supp_dir = SupportedCircUsage::Dir;
-        let targ_dir = TargetCircUsage::Dir;
+        let supp_dir = SupportedCircUsage::Dir { ipv6_only: false };
+        let targ_dir = TargetCircUsage::Dir {
+            ipv6: DirIpv6Preference::Any,
+        };
         let supp_exit = SupportedCircUsage::Exit {
             policy: policy.clone(),
             isolation: Some(isolation.clone()),
@@ -905,6 +930,30 @@ pub(crate) mod test {
         assert!(supp_exit_no_iso_c.supports(&targ_exit_iso2));
     }
 
+    #[test]
+    fn dir_ipv6() {
+        let supp_any = SupportedCircUsage::Dir { ipv6_only: false };
+        let supp_v6 = SupportedCircUsage::Dir { ipv6_only: true };
+        let targ_prefer = TargetCircUsage::Dir {
+            ipv6: DirIpv6Preference::Prefer,
+        };
+        let targ_require = TargetCircUsage::Dir {
+            ipv6: DirIpv6Preference::Require,
+        };
+
+        assert!(supp_any.supports(&targ_prefer));
+        assert!(!supp_any.supports(&targ_require));
+        assert!(supp_v6.supports(&targ_prefer));
+        assert!(supp_v6.supports(&targ_require));
+
+        let mut supp_any_c = supp_any.clone();
+        assert!(supp_any_c.restrict_mut(&targ_require).is_err());
+        assert_isoleq!(supp_any, supp_any_c);
+        let mut supp_v6_c = supp_v6.clone();
+        supp_v6_c.restrict_mut(&targ_require).unwrap();
+        assert_isoleq!(supp_v6, supp_v6_c);
+    }
+
     #[test]
     fn buildpath() {
         tor_rtcompat::test_with_all_runtimes!(|rt| async move {
@@ -928,18 +977,20 @@ pub(crate) mod test {
                 VanguardMgr::new(&Default::default(), rt.clone(), statemgr, false).unwrap();
 
             // First, a one-hop directory circuit
-            let (p_dir, u_dir, _, _) = TargetCircUsage::Dir
-                .build_path(
-                    &mut rng,
-                    di,
-                    &guards,
-                    #[cfg(all(feature = "vanguards", feature = "hs-common"))]
-                    &vanguards,
-                    &config,
-                    now,
-                )
-                .unwrap();
-            assert!(matches!(u_dir, SupportedCircUsage::Dir));
+            let (p_dir, u_dir, _, _) = TargetCircUsage::Dir {
+                ipv6: DirIpv6Preference::Any,
+            }
+            .build_path(
+                &mut rng,
+                di,
+                &guards,
+                #[cfg(all(feature = "vanguards", feature = "hs-common"))]
+                &vanguards,
+                &config,
+                now,
+            )
+            .unwrap();
+            assert!(matches!(u_dir, SupportedCircUsage::Dir { .. }));
             assert_eq!(p_dir.len(), 1);
 
             // Now an exit circuit, to port 995.
@@ -114,6 +114,10 @@ pub enum RequestError {
     /// HTTP status code indicates a not completely successful request
     #[error("HTTP status code {0}: {1:?}")]
     HttpStatus(u16, String),
+
+    /// We got a partial response without a usable Content-Range header.
+    #[error("Missing or invalid Content-Range header on a partial response")]
+    BadContentRange,
 }
 
 impl From<TimeoutError> for RequestError {
@@ -193,6 +197,7 @@ impl HasKind for RequestError {
             E::TooMuchClockSkew => EK::TorDirectoryError,
             E::EmptyRequest => EK::Internal,
             E::HttpStatus(_, _) => EK::TorDirectoryError,
+            E::BadContentRange => EK::TorProtocolViolation,
         }
     }
 }
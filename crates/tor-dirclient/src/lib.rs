@@ -53,7 +53,7 @@ pub mod request;
 mod response;
 mod util;
 
-use tor_circmgr::{CircMgr, DirInfo};
+use tor_circmgr::{CircMgr, DirInfo, DirIpv6Preference};
 use tor_error::bad_api_usage;
 use tor_rtcompat::{Runtime, SleepProvider, SleepProviderExt};
 
@@ -74,7 +74,7 @@ use std::time::Duration;
 use tracing::info;
 
 pub use err::{Error, RequestError, RequestFailedError};
-pub use response::{DirResponse, SourceInfo};
+pub use response::{ContentRange, DirResponse, SourceInfo};
 
 /// Type for results returned in this crate.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -100,6 +100,8 @@ pub enum AnonymizedRequest {
 ///
 /// Circuits are built or found using `circ_mgr`, using paths
 /// constructed using `dirinfo`.
+/// The `ipv6` argument says whether to prefer or require
+/// a directory cache that we can reach over IPv6.
 ///
 /// For more fine-grained control over the circuit and stream used,
 /// construct them yourself, and then call [`send_request`] instead.
@@ -111,6 +113,7 @@ pub enum AnonymizedRequest {
 pub async fn get_resource<CR, R, SP>(
     req: &CR,
     dirinfo: DirInfo<'_>,
+    ipv6: DirIpv6Preference,
     runtime: &SP,
     circ_mgr: Arc<CircMgr<R>>,
 ) -> Result<DirResponse>
@@ -119,7 +122,7 @@ where
     R: Runtime,
     SP: SleepProvider,
 {
-    let circuit = circ_mgr.get_or_launch_dir(dirinfo).await?;
+    let circuit = circ_mgr.get_or_launch_dir(dirinfo, ipv6).await?;
 
     if req.anonymized() == AnonymizedRequest::Anonymized {
         return Err(bad_api_usage!("Tried to use get_resource for an anonymized request").into());
@@ -242,15 +245,18 @@ where
     // Handle the response
     // TODO: should there be a separate timeout here?
     let header = read_headers(&mut buffered).await.map_err(wrap_err)?;
-    if header.status != Some(200) {
-        return Ok(DirResponse::new(
-            header.status.unwrap_or(0),
-            header.status_message,
-            None,
-            vec![],
-            source,
-        ));
-    }
+    let status = match header.status {
+        Some(status @ (200 | 206)) => status,
+        other => {
+            return Ok(DirResponse::new(
+                other.unwrap_or(0),
+                header.status_message,
+                None,
+                vec![],
+                source,
+            ));
+        }
+    };
 
     let mut decoder =
         get_decoder(buffered, header.encoding.as_deref(), anonymized).map_err(wrap_err)?;
@@ -269,7 +275,8 @@ where
         (_, Ok(()), _) => Ok(()),
     };
 
-    Ok(DirResponse::new(200, None, ok.err(), result, source))
+    Ok(DirResponse::new(status, None, ok.err(), result, source)
+        .with_content_range(header.content_range))
 }
 
 /// Read and parse HTTP/1 headers from `stream`.
@@ -304,11 +311,12 @@ where
                 }
             }
             httparse::Status::Complete(n_parsed) => {
-                if response.code != Some(200) {
+                if !matches!(response.code, Some(200 | 206)) {
                     return Ok(HeaderStatus {
                         status: response.code,
                         status_message: response.reason.map(str::to_owned),
                         encoding: None,
+                        content_range: None,
                     });
                 }
                 let encoding = if let Some(enc) = response
@@ -326,11 +334,24 @@ where
                     length = Some(clen.parse()?);
                 }
                  */
+                // A 206 response holds only part of the document we asked for:
+                // find out which part.
+                let content_range = if response.code == Some(206) {
+                    let range = response
+                        .headers
+                        .iter()
+                        .find(|h| h.name.eq_ignore_ascii_case("Content-Range"))
+                        .ok_or(RequestError::BadContentRange)?;
+                    Some(parse_content_range(range.value)?)
+                } else {
+                    None
+                };
                 assert!(n_parsed == buf.len());
                 return Ok(HeaderStatus {
-                    status: Some(200),
+                    status: response.code,
                     status_message: None,
                     encoding,
+                    content_range,
                 });
             }
         }
@@ -349,6 +370,20 @@ struct HeaderStatus {
     status_message: Option<String>,
     /// The Content-Encoding header, if any.
     encoding: Option<String>,
+    /// For a 206 response, which part of the document we asked for its body holds.
+    content_range: Option<ContentRange>,
+}
+
+/// Parse the value of a Content-Range header of the form `bytes START-END/LENGTH`.
+fn parse_content_range(value: &[u8]) -> RequestResult<ContentRange> {
+    let parse = |v: &str| v.trim().parse().ok();
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.strip_prefix("bytes "))
+        .and_then(|v| v.split_once('/'))
+        .and_then(|(range, total)| Some((range.split_once('-')?, parse(total)?)))
+        .and_then(|((start, end), total)| ContentRange::new(parse(start)?, parse(end)?, total))
+        .ok_or(RequestError::BadContentRange)
 }
 
 /// Helper: download directory information from `stream` and
@@ -685,6 +720,32 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn headers_partial_content() -> RequestResult<()> {
+        let text = b"HTTP/1.0 206 Partial Content\r\nContent-Range: bytes 1000-1999/2000\r\n\r\n";
+        let mut s = &text[..];
+        let h = read_headers(&mut s).await?;
+
+        assert_eq!(h.status, Some(206));
+        assert_eq!(h.content_range, ContentRange::new(1000, 1999, 2000));
+
+        // A partial response has to say which part it holds.
+        for text in [
+            &b"HTTP/1.0 206 Partial Content\r\n\r\n"[..],
+            &b"HTTP/1.0 206 Partial Content\r\nContent-Range: lines 10-20/30\r\n\r\n"[..],
+            &b"HTTP/1.0 206 Partial Content\r\nContent-Range: bytes 10-20\r\n\r\n"[..],
+            &b"HTTP/1.0 206 Partial Content\r\nContent-Range: bytes 10-20/*\r\n\r\n"[..],
+            &b"HTTP/1.0 206 Partial Content\r\nContent-Range: bytes 20-10/30\r\n\r\n"[..],
+            &b"HTTP/1.0 206 Partial Content\r\nContent-Range: bytes 10-30/30\r\n\r\n"[..],
+        ] {
+            let mut s = text;
+            let h = read_headers(&mut s).await;
+            assert!(matches!(h, Err(RequestError::BadContentRange)));
+        }
+
+        Ok(())
+    }
+
     #[async_test]
     async fn headers_bogus() -> Result<()> {
         let text = b"HTTP/999.0 WHAT EVEN\r\n\r\n";
@@ -817,6 +878,27 @@ mod test {
         assert!(response.output_unchecked().starts_with(b"One fish"));
     }
 
+    #[test]
+    fn test_download_resumed() {
+        let mut req = request::ConsensusRequest::default();
+        req.set_resumable(true);
+        req.set_resume_offset(9);
+
+        let (response, request) = run_download_test(
+            req,
+            b"HTTP/1.0 206 Partial Content\r\nContent-Range: bytes 9-16/17\r\n\r\ntwo fish",
+        );
+        assert!(request
+            .unwrap()
+            .starts_with(b"GET /tor/status-vote/current/consensus-microdesc HTTP/1.0\r\n"));
+
+        let response = response.unwrap();
+        assert_eq!(response.status_code(), 206);
+        assert_eq!(response.content_range(), ContentRange::new(9, 16, 17));
+        assert!(!response.is_partial());
+        assert_eq!(response.output_unchecked(), b"two fish");
+    }
+
     #[test]
     fn test_404() {
         let req: request::MicrodescRequest = vec![[9; 32]].into_iter().collect();
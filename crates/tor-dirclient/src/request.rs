@@ -134,6 +134,12 @@ pub struct ConsensusRequest {
     last_consensus_sha3_256: Vec<[u8; 32]>,
     /// If present, the largest amount of clock skew to allow between ourself and a directory cache.
     skew_limit: Option<SkewLimit>,
+    /// If true, we want to keep whatever part of the consensus we receive,
+    /// even if the download is interrupted.
+    resumable: bool,
+    /// If present, the number of bytes of the consensus that we already have,
+    /// so that we only want the rest of it.
+    resume_offset: Option<usize>,
 }
 
 impl ConsensusRequest {
@@ -145,9 +151,16 @@ impl ConsensusRequest {
             last_consensus_published: None,
             last_consensus_sha3_256: Vec::new(),
             skew_limit: None,
+            resumable: false,
+            resume_offset: None,
         }
     }
 
+    /// Return the flavor of consensus that this request asks for.
+    pub fn flavor(&self) -> ConsensusFlavor {
+        self.flavor
+    }
+
     /// Add `id` to the list of authorities that this request should
     /// say we believe in.
     pub fn push_authority_id(&mut self, id: RsaIdentity) {
@@ -196,6 +209,37 @@ impl ConsensusRequest {
     pub fn set_skew_limit(&mut self, max_fast: Duration, max_slow: Duration) {
         self.skew_limit = Some(SkewLimit { max_fast, max_slow });
     }
+
+    /// Set whether we want to keep a partial response to this request.
+    ///
+    /// If this is true, and the download is interrupted,
+    /// the response holds whatever part of the consensus we received,
+    /// so that the caller can later resume the download
+    /// with [`set_resume_offset`](ConsensusRequest::set_resume_offset).
+    pub fn set_resumable(&mut self, resumable: bool) {
+        self.resumable = resumable;
+    }
+
+    /// Ask the directory cache to send only the part of the consensus
+    /// that starts `offset` bytes in, since we already have everything before that.
+    ///
+    /// A cache that supports this answers with status 206,
+    /// and the response reports where its body starts
+    /// (see [`DirResponse::content_range`](crate::DirResponse::content_range)).
+    /// A cache that doesn't support this sends the whole consensus, as usual.
+    ///
+    /// Since `offset` counts bytes of the uncompressed consensus,
+    /// we ask for the rest of the consensus uncompressed,
+    /// and we don't ask for a consensus diff.
+    pub fn set_resume_offset(&mut self, offset: usize) {
+        self.resume_offset = Some(offset);
+    }
+
+    /// Return the number of bytes of the consensus we're saying we already have,
+    /// if we're resuming a download.
+    pub fn resume_offset(&self) -> Option<usize> {
+        self.resume_offset
+    }
 }
 
 /// Convert a list of digests in some format to a string, for use in a request
@@ -249,10 +293,20 @@ impl sealed::RequestableInner for ConsensusRequest {
             uri.push_str(&ids);
         }
         // Without authorities, "../consensus-microdesc.z"
-        uri.push_str(".z");
+        //
+        // (If we're resuming, we leave off the ".z": a resumed download has to
+        // use the same offsets as the uncompressed consensus.)
+        if self.resume_offset.is_none() {
+            uri.push_str(".z");
+        }
 
         let mut req = http::Request::builder().method("GET").uri(uri);
-        req = add_common_headers(req, self.anonymized());
+        req = match self.resume_offset {
+            Some(offset) => req
+                .header(http::header::ACCEPT_ENCODING, "identity")
+                .header(http::header::RANGE, format!("bytes={}-", offset)),
+            None => add_common_headers(req, self.anonymized()),
+        };
 
         // Possibly, add an if-modified-since header.
         if let Some(when) = self.last_consensus_date() {
@@ -263,15 +317,22 @@ impl sealed::RequestableInner for ConsensusRequest {
         }
 
         // Possibly, add an X-Or-Diff-From-Consensus header.
-        if let Some(ids) = digest_list_stringify(&self.last_consensus_sha3_256, hex::encode, ", ") {
-            req = req.header("X-Or-Diff-From-Consensus", &ids);
+        //
+        // (We can't resume a download of a diff, so we don't ask for one when
+        // we're resuming.)
+        if self.resume_offset.is_none() {
+            if let Some(ids) =
+                digest_list_stringify(&self.last_consensus_sha3_256, hex::encode, ", ")
+            {
+                req = req.header("X-Or-Diff-From-Consensus", &ids);
+            }
         }
 
         Ok(req.body(String::new())?)
     }
 
     fn partial_response_body_ok(&self) -> bool {
-        false
+        self.resumable
     }
 
     fn check_circuit(&self, circ: &ClientCirc) -> Result<()> {
@@ -661,6 +722,57 @@ impl sealed::RequestableInner for HsDescUploadRequest {
     }
 }
 
+/// A wrapper around another [`Requestable`] that identifies us to the
+/// directory cache with a `User-Agent` header.
+///
+/// The header is only added to requests that aren't
+/// [anonymized](AnonymizedRequest::Anonymized):
+/// an anonymized request should never say anything about who we are.
+#[derive(Debug, Clone)]
+pub struct WithUserAgent<'a, R: ?Sized> {
+    /// The request to wrap.
+    inner: &'a R,
+    /// The value to use for the `User-Agent` header.
+    user_agent: String,
+}
+
+impl<'a, R: Requestable + ?Sized> WithUserAgent<'a, R> {
+    /// Wrap `inner` so that it identifies us as `user_agent`.
+    pub fn new(inner: &'a R, user_agent: impl Into<String>) -> Self {
+        WithUserAgent {
+            inner,
+            user_agent: user_agent.into(),
+        }
+    }
+}
+
+impl<'a, R: Requestable + ?Sized> sealed::RequestableInner for WithUserAgent<'a, R> {
+    fn make_request(&self) -> Result<http::Request<String>> {
+        let mut req = self.inner.make_request()?;
+        if self.anonymized() == AnonymizedRequest::Direct {
+            let value = http::HeaderValue::from_str(&self.user_agent).map_err(http::Error::from)?;
+            req.headers_mut().insert(http::header::USER_AGENT, value);
+        }
+        Ok(req)
+    }
+
+    fn partial_response_body_ok(&self) -> bool {
+        self.inner.partial_response_body_ok()
+    }
+
+    fn max_response_len(&self) -> usize {
+        self.inner.max_response_len()
+    }
+
+    fn check_circuit(&self, circ: &ClientCirc) -> Result<()> {
+        self.inner.check_circuit(circ)
+    }
+
+    fn anonymized(&self) -> AnonymizedRequest {
+        self.inner.anonymized()
+    }
+}
+
 /// Encodings that all Tor clients support.
 const UNIVERSAL_ENCODINGS: &str = "deflate, identity";
 
@@ -743,6 +855,40 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_user_agent() -> Result<()> {
+        let d1 = b"This is a testing digest. it isn";
+        let mut req = MicrodescRequest::default();
+        req.push(*d1);
+
+        let ua = WithUserAgent::new(&req, "private-net-client/1.0");
+        assert_eq!(ua.max_response_len(), req.max_response_len());
+        let encoded = crate::util::encode_request(&ua.make_request()?);
+        assert_eq!(encoded,
+                   format!("GET /tor/micro/d/VGhpcyBpcyBhIHRlc3RpbmcgZGlnZXN0LiBpdCBpc24.z HTTP/1.0\r\naccept-encoding: {}\r\nuser-agent: private-net-client/1.0\r\n\r\n", all_encodings()));
+
+        // A header value with a newline in it is rejected.
+        let ua = WithUserAgent::new(&req, "bad\r\nX-Injected: 1");
+        assert!(ua.make_request().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "hs-client")]
+    fn test_user_agent_anonymized() -> Result<()> {
+        use tor_llcrypto::pk::ed25519::Ed25519Identity;
+        let hsid = HsBlindId::from(Ed25519Identity::new([1; 32]));
+        let req = HsDescDownloadRequest::new(hsid);
+        let ua = WithUserAgent::new(&req, "private-net-client/1.0");
+        // We never identify ourselves on an anonymized request.
+        assert_eq!(
+            crate::util::encode_request(&ua.make_request()?),
+            crate::util::encode_request(&req.make_request()?)
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_cert_request() -> Result<()> {
         let d1 = b"This is a testing dn";
@@ -814,6 +960,18 @@ mod test {
         assert_eq!(req,
                    format!("GET /tor/status-vote/current/consensus-microdesc.z HTTP/1.0\r\naccept-encoding: {}\r\n\r\n", all_encodings()));
 
+        // Resumed request: no compression, and no diffs.
+        let mut req = ConsensusRequest::default();
+        req.push_old_consensus_digest(*d2);
+        req.set_last_consensus_date(d3);
+        req.set_resumable(true);
+        req.set_resume_offset(1234);
+        assert!(req.partial_response_body_ok());
+        assert_eq!(req.resume_offset(), Some(1234));
+        let req = crate::util::encode_request(&req.make_request()?);
+        assert_eq!(req,
+                   format!("GET /tor/status-vote/current/consensus-microdesc HTTP/1.0\r\naccept-encoding: identity\r\nrange: bytes=1234-\r\nif-modified-since: {}\r\n\r\n", when));
+
         Ok(())
     }
 
@@ -22,6 +22,46 @@ pub struct DirResponse {
     error: Option<RequestError>,
     /// Information about the directory cache we used.
     source: Option<SourceInfo>,
+    /// If this response holds only part of the document we asked for,
+    /// which part that is.
+    content_range: Option<ContentRange>,
+}
+
+/// The part of a document that a partial (HTTP 206) response holds,
+/// as given in its `Content-Range` header.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ContentRange {
+    /// The offset of the first byte of the part.
+    start: usize,
+    /// The offset of the last byte of the part.
+    end: usize,
+    /// The length of the whole document.
+    total: usize,
+}
+
+impl ContentRange {
+    /// Construct a new `ContentRange` for the bytes from `start` through `end` (inclusive)
+    /// of a document that is `total` bytes long.
+    ///
+    /// Return None if those bytes aren't all within the document.
+    pub fn new(start: usize, end: usize, total: usize) -> Option<Self> {
+        (start <= end && end < total).then_some(ContentRange { start, end, total })
+    }
+
+    /// Return the offset of the first byte of the part.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Return the offset of the last byte of the part.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Return the length of the whole document.
+    pub fn total(&self) -> usize {
+        self.total
+    }
 }
 
 /// Information about the source of a directory response.
@@ -52,19 +92,51 @@ impl DirResponse {
             output,
             error,
             source,
+            content_range: None,
         }
     }
 
+    /// Record that this response holds only the part of the document in `content_range`.
+    pub(crate) fn with_content_range(mut self, content_range: Option<ContentRange>) -> Self {
+        self.content_range = content_range;
+        self
+    }
+
     /// Construct a new successful DirResponse from its body.
     pub fn from_body(body: impl AsRef<[u8]>) -> Self {
         Self::new(200, None, None, body.as_ref().to_vec(), None)
     }
 
+    /// Construct a new DirResponse with the HTTP status code `status`, from its body.
+    #[cfg(feature = "testing")]
+    pub fn new_for_testing(status: u16, body: impl AsRef<[u8]>) -> Self {
+        Self::new(status, None, None, body.as_ref().to_vec(), None)
+    }
+
+    /// Record that this response was cut short by `error`.
+    #[cfg(feature = "testing")]
+    pub fn with_error_for_testing(mut self, error: RequestError) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Record that this response holds only the part of the document in `content_range`.
+    #[cfg(feature = "testing")]
+    pub fn with_content_range_for_testing(self, content_range: ContentRange) -> Self {
+        self.with_content_range(Some(content_range))
+    }
+
     /// Return the HTTP status code for this response.
     pub fn status_code(&self) -> u16 {
         self.status
     }
 
+    /// If this response holds only part of the document we asked for
+    /// (with HTTP status 206), return which part of that document it holds.
+    pub fn content_range(&self) -> Option<ContentRange> {
+        self.content_range
+    }
+
     /// Return true if this is in incomplete response.
     pub fn is_partial(&self) -> bool {
         self.error.is_some()
@@ -164,6 +236,15 @@ impl SourceInfo {
         }
     }
 
+    /// Construct a SourceInfo for an answer from `cache_id`, received on `circuit`.
+    #[cfg(feature = "testing")]
+    pub fn new_for_testing(circuit: UniqId, cache_id: OwnedChanTarget) -> Self {
+        SourceInfo {
+            circuit,
+            cache_id: cache_id.into(),
+        }
+    }
+
     /// Return the unique circuit identifier for the circuit on which
     /// we received this info.
     pub fn unique_circ_id(&self) -> &UniqId {
@@ -11,6 +11,7 @@ use std::{
 
 use crate::err::BootstrapAction;
 use crate::state::{DirState, PoisonedState};
+use crate::stats::CacheStatsTracker;
 use crate::DirMgrConfig;
 use crate::DocSource;
 use crate::{
@@ -21,6 +22,7 @@ use crate::{
 use futures::FutureExt;
 use futures::StreamExt;
 use oneshot_fused_workaround as oneshot;
+use tor_dirclient::request::WithUserAgent;
 use tor_dirclient::DirResponse;
 use tor_error::{info_report, warn_report};
 use tor_rtcompat::scheduler::TaskSchedule;
@@ -32,7 +34,7 @@ use crate::storage::Store;
 use once_cell::sync::Lazy;
 #[cfg(test)]
 use std::sync::Mutex;
-use tor_circmgr::{CircMgr, DirInfo};
+use tor_circmgr::{CircMgr, DirInfo, DirIpv6Preference};
 use tor_netdir::{NetDir, NetDirProvider as _};
 use tor_netdoc::doc::netstatus::ConsensusFlavor;
 
@@ -85,6 +87,7 @@ impl AttemptId {
 /// (if any) as having failed.
 fn note_request_outcome<R: Runtime>(
     circmgr: &CircMgr<R>,
+    stats: &CacheStatsTracker,
     outcome: &tor_dirclient::Result<tor_dirclient::DirResponse>,
 ) {
     use tor_dirclient::{Error::RequestFailed, RequestFailedError};
@@ -116,19 +119,38 @@ fn note_request_outcome<R: Runtime>(
         _ => return,
     };
 
-    note_cache_error(circmgr, source, &err.into());
+    note_cache_error(circmgr, stats, source, &err.into());
 }
 
 /// Record that a problem has occurred because of a failure in an answer from `source`.
 fn note_cache_error<R: Runtime>(
     circmgr: &CircMgr<R>,
+    stats: &CacheStatsTracker,
     source: &tor_dirclient::SourceInfo,
     problem: &Error,
 ) {
     use tor_circmgr::ExternalActivity;
 
+    let real_source = match blame_cache(stats, source, problem) {
+        Some(real_source) => real_source,
+        None => return,
+    };
+
+    circmgr.note_external_failure(real_source.cache_id(), ExternalActivity::DirCache);
+    circmgr.retire_circ(source.unique_circ_id());
+}
+
+/// Helper for [`note_cache_error`]: if `problem` is a cache's fault, record
+/// a failure in `stats` for the cache to blame, and return its `SourceInfo`.
+///
+/// `source` is the source of the answer in which we noticed `problem`.
+pub(crate) fn blame_cache<'a>(
+    stats: &CacheStatsTracker,
+    source: &'a tor_dirclient::SourceInfo,
+    problem: &'a Error,
+) -> Option<&'a tor_dirclient::SourceInfo> {
     if !problem.indicates_cache_failure() {
-        return;
+        return None;
     }
 
     // Does the error here tell us whom to really blame?  If so, blame them
@@ -145,18 +167,70 @@ fn note_cache_error<R: Runtime>(
     };
 
     info_report!(problem, "Marking {:?} as failed", real_source);
-    circmgr.note_external_failure(real_source.cache_id(), ExternalActivity::DirCache);
-    circmgr.retire_circ(source.unique_circ_id());
+    stats.note_failure(real_source.cache_id());
+    Some(real_source)
 }
 
 /// Record that `source` has successfully given us some directory info.
-fn note_cache_success<R: Runtime>(circmgr: &CircMgr<R>, source: &tor_dirclient::SourceInfo) {
+fn note_cache_success<R: Runtime>(
+    circmgr: &CircMgr<R>,
+    stats: &CacheStatsTracker,
+    source: &tor_dirclient::SourceInfo,
+) {
     use tor_circmgr::ExternalActivity;
 
     trace!("Marking {:?} as successful", source);
+    stats.note_success(source.cache_id());
     circmgr.note_external_success(source.cache_id(), ExternalActivity::DirCache);
 }
 
+/// Record that `source` is too busy to answer our requests.
+///
+/// Unlike [`note_cache_error`], we don't retire the circuit: the cache isn't
+/// misbehaving, but we'd rather ask somebody else for a while.
+fn note_cache_overloaded<R: Runtime>(
+    circmgr: &CircMgr<R>,
+    stats: &CacheStatsTracker,
+    source: &tor_dirclient::SourceInfo,
+) {
+    use tor_circmgr::ExternalActivity;
+
+    debug!("Marking {:?} as overloaded", source);
+    stats.note_failure(source.cache_id());
+    circmgr.note_external_failure(source.cache_id(), ExternalActivity::DirCache);
+}
+
+/// How we interpret the HTTP status code on a response from a directory cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ResponseStatus {
+    /// 200: The cache answered our request.
+    ///
+    /// (206 counts as this too: the cache sent us the rest of a consensus
+    /// whose download we are resuming.)
+    Ok,
+    /// 304: The cache has nothing newer than what we already have.
+    NotModified,
+    /// 404: The cache doesn't have the documents we asked for.
+    NotFound,
+    /// 503: The cache is too busy to answer us.
+    Overloaded,
+    /// Some other status code.
+    Other(u16),
+}
+
+impl ResponseStatus {
+    /// Classify an HTTP status code.
+    fn from_code(code: u16) -> Self {
+        match code {
+            200 | 206 => ResponseStatus::Ok,
+            304 => ResponseStatus::NotModified,
+            404 => ResponseStatus::NotFound,
+            503 => ResponseStatus::Overloaded,
+            other => ResponseStatus::Other(other),
+        }
+    }
+}
+
 /// Load every document in `missing` and try to apply it to `state`.
 fn load_and_apply_documents<R: Runtime>(
     missing: &[DocId],
@@ -232,6 +306,18 @@ pub(crate) fn make_consensus_request(
         config.tolerance.pre_valid_tolerance,
     );
 
+    // If an earlier download got cut off, ask only for the part we're missing.
+    if config.schedule.resume_consensus() {
+        request.set_resumable(true);
+        match store.partial_consensus(flavor) {
+            Ok(Some(partial)) if partial.as_ref().len() >= MIN_PARTIAL_CONSENSUS_LEN => {
+                request.set_resume_offset(partial.as_ref().len());
+            }
+            Ok(_) => {}
+            Err(e) => warn_report!(e, "Error loading partially downloaded consensus"),
+        }
+    }
+
     Ok(ClientRequest::Consensus(request))
 }
 
@@ -272,20 +358,40 @@ pub(crate) fn make_requests_for_documents<R: Runtime>(
 }
 
 /// Launch a single client request and get an associated response.
+///
+/// If `user_agent` is provided, we use it to identify ourselves to the cache.
+/// `ipv6` says whether we prefer or require a cache that we can reach over IPv6.
 async fn fetch_single<R: Runtime>(
     rt: &R,
     request: ClientRequest,
     current_netdir: Option<&NetDir>,
     circmgr: Arc<CircMgr<R>>,
+    stats: &CacheStatsTracker,
+    user_agent: Option<&str>,
+    ipv6: DirIpv6Preference,
 ) -> Result<(ClientRequest, DirResponse)> {
     let dirinfo: DirInfo = match current_netdir {
         Some(netdir) => netdir.into(),
         None => tor_circmgr::DirInfo::Nothing,
     };
-    let outcome =
-        tor_dirclient::get_resource(request.as_requestable(), dirinfo, rt, circmgr.clone()).await;
+    let outcome = match user_agent {
+        Some(user_agent) => {
+            let requestable = WithUserAgent::new(request.as_requestable(), user_agent);
+            tor_dirclient::get_resource(&requestable, dirinfo, ipv6, rt, circmgr.clone()).await
+        }
+        None => {
+            tor_dirclient::get_resource(
+                request.as_requestable(),
+                dirinfo,
+                ipv6,
+                rt,
+                circmgr.clone(),
+            )
+            .await
+        }
+    };
 
-    note_request_outcome(&circmgr, &outcome);
+    note_request_outcome(&circmgr, stats, &outcome);
 
     let resource = outcome?;
     Ok((request, resource))
@@ -299,20 +405,103 @@ async fn fetch_single<R: Runtime>(
 #[cfg(test)]
 static CANNED_RESPONSE: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(vec![]));
 
+/// Controller for how many download requests we launch at once.
+///
+/// In the default (fixed) mode, this always allows the `parallelism` from our
+/// [`DownloadSchedule`](crate::DownloadSchedule).
+///
+/// In adaptive mode, we start with a single request at a time, and use that
+/// `parallelism` as an upper bound. Every time we see as many consecutive
+/// successes as our current limit, we raise the limit by one; whenever a
+/// request fails, we halve the limit.
+#[derive(Clone, Debug)]
+struct ParallelismController {
+    /// The number of requests we currently allow at once.
+    current: usize,
+    /// The largest number of requests we will ever allow at once.
+    max: usize,
+    /// If true, we adjust `current` in response to successes and failures.
+    adaptive: bool,
+    /// The number of successful requests we have seen since the last failure
+    /// or adjustment.
+    successes: usize,
+}
+
+impl ParallelismController {
+    /// Create a new ParallelismController for a given download schedule.
+    fn new(schedule: &crate::DownloadSchedule) -> Self {
+        let max = usize::from(schedule.parallelism());
+        let adaptive = schedule.adaptive_parallelism();
+        ParallelismController {
+            current: if adaptive { 1 } else { max },
+            max,
+            adaptive,
+            successes: 0,
+        }
+    }
+
+    /// Switch to the limits from `schedule`, keeping what we have learned so far.
+    ///
+    /// We call this when we move to a new state, which may have its own schedule.
+    fn set_schedule(&mut self, schedule: &crate::DownloadSchedule) {
+        let max = usize::from(schedule.parallelism());
+        let adaptive = schedule.adaptive_parallelism();
+        if adaptive && !self.adaptive {
+            // We haven't been learning anything, so start over.
+            *self = Self::new(schedule);
+            return;
+        }
+        self.max = max;
+        self.adaptive = adaptive;
+        self.current = if adaptive { self.current.min(max) } else { max };
+    }
+
+    /// Return the number of requests that we should currently launch at once.
+    fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Note that a request has succeeded.
+    fn note_success(&mut self) {
+        if !self.adaptive {
+            return;
+        }
+        self.successes += 1;
+        if self.successes >= self.current {
+            self.current = (self.current + 1).min(self.max);
+            self.successes = 0;
+        }
+    }
+
+    /// Note that a request has failed.
+    fn note_failure(&mut self) {
+        if !self.adaptive {
+            return;
+        }
+        self.current = (self.current / 2).max(1);
+        self.successes = 0;
+    }
+}
+
 /// Launch a set of download requests for a set of missing objects in
 /// `missing`, and return each request along with the response it received.
 ///
-/// Don't launch more than `parallelism` requests at once.
+/// Don't launch more than `parallelism.current()` requests at once; report
+/// the outcome of each request to `parallelism` as soon as it arrives,
+/// so that the adjusted limit applies to the requests that we launch after it.
 async fn fetch_multiple<R: Runtime>(
     dirmgr: Arc<DirMgr<R>>,
     attempt_id: AttemptId,
     missing: &[DocId],
-    parallelism: usize,
+    parallelism: &mut ParallelismController,
 ) -> Result<Vec<(ClientRequest, DirResponse)>> {
+    let config = dirmgr.config.get();
     let requests = {
         let store = dirmgr.store.lock().expect("store lock poisoned");
-        make_requests_for_documents(&dirmgr.runtime, missing, &**store, &dirmgr.config.get())?
+        make_requests_for_documents(&dirmgr.runtime, missing, &**store, &config)?
     };
+    let user_agent = config.schedule.user_agent();
+    let ipv6 = config.schedule.ipv6_caches();
 
     trace!(attempt=%attempt_id, "Launching {} requests for {} documents",
            requests.len(), missing.len());
@@ -334,32 +523,106 @@ async fn fetch_multiple<R: Runtime>(
 
     // TODO: instead of waiting for all the queries to finish, we
     // could stream the responses back or something.
-    let responses: Vec<Result<(ClientRequest, DirResponse)>> = futures::stream::iter(requests)
-        .map(|query| fetch_single(&dirmgr.runtime, query, netdir.as_deref(), circmgr.clone()))
-        .buffer_unordered(parallelism)
-        .collect()
-        .await;
+    let mut requests = requests.into_iter();
+    let mut in_flight = futures::stream::FuturesUnordered::new();
+    let mut useful_responses = Vec::new();
+    loop {
+        while in_flight.len() < parallelism.current() {
+            let Some(query) = requests.next() else {
+                break;
+            };
+            in_flight.push(fetch_single(
+                &dirmgr.runtime,
+                query,
+                netdir.as_deref(),
+                circmgr.clone(),
+                &dirmgr.cache_stats,
+                user_agent,
+                ipv6,
+            ));
+        }
+        let Some(response) = in_flight.next().await else {
+            break;
+        };
+        useful_responses.extend(sort_responses(&dirmgr, vec![response], parallelism)?);
+    }
+
+    trace!(attempt=%attempt_id, "received {} useful responses from our requests.", useful_responses.len());
+
+    Ok(useful_responses)
+}
 
+/// Look at the status of every response in `responses`, and report the outcome of
+/// each request to `parallelism` and to the caches that answered it.
+///
+/// Return the responses that our state needs to see: those with a body to
+/// apply, and those telling us that the documents we have are still current.
+fn sort_responses<R: Runtime>(
+    dirmgr: &DirMgr<R>,
+    responses: Vec<Result<(ClientRequest, DirResponse)>>,
+    parallelism: &mut ParallelismController,
+) -> Result<Vec<(ClientRequest, DirResponse)>> {
+    let stats = &dirmgr.cache_stats;
     let mut useful_responses = Vec::new();
     for r in responses {
         // TODO: on some error cases we might want to stop using this source.
         match r {
             Ok((request, response)) => {
-                if response.status_code() == 200 {
-                    useful_responses.push((request, response));
-                } else {
-                    trace!(
-                        "cache declined request; reported status {:?}",
-                        response.status_code()
-                    );
+                if let Some(source) = response.source() {
+                    stats.note_bytes(source.cache_id(), response.output_unchecked().len());
+                }
+                match ResponseStatus::from_code(response.status_code()) {
+                    ResponseStatus::Ok => {
+                        // A response that got cut off part way through is
+                        // still worth passing on, since we can keep the part
+                        // we got.  But the request failed, and the cache has
+                        // already been blamed for it in fetch_single.
+                        if response.is_partial() {
+                            parallelism.note_failure();
+                        } else {
+                            parallelism.note_success();
+                        }
+                        useful_responses.push((request, response));
+                    }
+                    ResponseStatus::NotModified => {
+                        // The cache is telling us that what we have is up to date.
+                        // That's a legitimate answer, not a failure on its part.
+                        parallelism.note_success();
+                        if let Some(source) = response.source() {
+                            note_cache_success(dirmgr.circmgr()?.deref(), stats, source);
+                        }
+                        debug!("cache reports that the requested documents are unchanged");
+                        useful_responses.push((request, response));
+                    }
+                    ResponseStatus::NotFound => {
+                        // A cache that doesn't have what we asked for isn't
+                        // broken or overloaded: it may just be behind.  We'll
+                        // ask somebody else, but we don't hold it against this
+                        // cache or slow down our other requests.
+                        debug!("cache does not have the requested documents");
+                    }
+                    ResponseStatus::Overloaded => {
+                        parallelism.note_failure();
+                        if let Some(source) = response.source() {
+                            note_cache_overloaded(dirmgr.circmgr()?.deref(), stats, source);
+                        }
+                    }
+                    ResponseStatus::Other(status) => {
+                        parallelism.note_failure();
+                        if let Some(source) = response.source() {
+                            stats.note_failure(source.cache_id());
+                        }
+                        trace!("cache declined request; reported status {:?}", status);
+                    }
                 }
             }
-            Err(e) => warn_report!(e, "error while downloading"),
+            Err(e) => {
+                parallelism.note_failure();
+                warn_report!(e, "error while downloading");
+            }
         }
     }
 
-    trace!(attempt=%attempt_id, "received {} useful responses from our requests.", useful_responses.len());
-
     Ok(useful_responses)
 }
 
@@ -449,15 +712,119 @@ pub(crate) async fn load<R: Runtime>(
     Ok(state)
 }
 
+/// The smallest interrupted consensus download that we'll keep and try to resume.
+///
+/// A resumed request can't use compression or ask for a diff, so for anything
+/// shorter than this we're better off starting over.
+const MIN_PARTIAL_CONSENSUS_LEN: usize = 64 * 1024;
+
+/// Return true if a response holding `range` of a consensus, with a body of
+/// `body_len` bytes, continues a partial consensus of `partial_len` bytes.
+///
+/// The range has to start where the partial consensus ends, and run to the end
+/// of the consensus.  If the response is `complete`, its body has to fill the
+/// range; otherwise it can fall short.
+fn range_continues(
+    range: &tor_dirclient::ContentRange,
+    partial_len: usize,
+    body_len: usize,
+    complete: bool,
+) -> bool {
+    let range_len = range.end() - range.start() + 1;
+    let body_fits = if complete {
+        body_len == range_len
+    } else {
+        body_len <= range_len
+    };
+    range.start() == partial_len && range.end() + 1 == range.total() && body_fits
+}
+
+/// Combine `response` to a request for a consensus of `flavor` with whatever
+/// part of that consensus we had already downloaded, and update the partial
+/// consensus in `store`.
+///
+/// Return the complete response body, and a flag saying whether it was pieced
+/// together from more than one download.  Return None if we still don't have a
+/// complete response.
+fn combine_consensus_response(
+    flavor: ConsensusFlavor,
+    response: DirResponse,
+    store: &mut dyn Store,
+) -> Option<(Vec<u8>, bool)> {
+    let complete = response.error().is_none();
+    let content_range = response.content_range();
+    let body = response.into_output_unchecked();
+
+    let (body, resumed) = match content_range {
+        None => (body, false),
+        Some(range) => {
+            let partial = match store.partial_consensus(flavor) {
+                Ok(partial) => partial,
+                Err(e) => {
+                    warn_report!(e, "Error loading partially downloaded consensus");
+                    None
+                }
+            };
+            match partial {
+                Some(partial)
+                    if range_continues(&range, partial.as_ref().len(), body.len(), complete) =>
+                {
+                    let mut whole = partial.as_ref().to_vec();
+                    whole.extend_from_slice(&body);
+                    (whole, true)
+                }
+                _ => {
+                    warn!("Resumed consensus download doesn't match what we have; starting over.");
+                    if let Err(e) = store.delete_partial_consensus(flavor) {
+                        warn_report!(e, "Error removing partially downloaded consensus");
+                    }
+                    return None;
+                }
+            }
+        }
+    };
+
+    if !complete {
+        if body.len() < MIN_PARTIAL_CONSENSUS_LEN {
+            // Resuming this little would cost us compression and diffs on our
+            // next request, for almost no savings.
+            debug!(
+                "Discarding {} bytes of interrupted consensus download.",
+                body.len()
+            );
+            if let Err(e) = store.delete_partial_consensus(flavor) {
+                warn_report!(e, "Error removing partially downloaded consensus");
+            }
+        } else if !body.starts_with(b"network-status-diff-version") {
+            // There is no point in resuming a diff: we never ask for the rest of one.
+            debug!(
+                "Saving {} bytes of interrupted consensus download.",
+                body.len()
+            );
+            if let Err(e) = store.store_partial_consensus(flavor, &body) {
+                warn_report!(e, "Error saving partially downloaded consensus");
+            }
+        }
+        return None;
+    }
+
+    // Whether or not this consensus turns out to be any good, we're done
+    // with the partial one.
+    if let Err(e) = store.delete_partial_consensus(flavor) {
+        warn_report!(e, "Error removing partially downloaded consensus");
+    }
+    Some((body, resumed))
+}
+
 /// Helper: Make a set of download attempts for the current directory state,
 /// and on success feed their results into the state object.
 ///
 /// This can launch one or more download requests, but will not launch more
-/// than `parallelism` requests at a time.
+/// than `parallelism.current()` requests at a time.
 async fn download_attempt<R: Runtime>(
     dirmgr: &Arc<DirMgr<R>>,
     state: &mut Box<dyn DirState>,
-    parallelism: usize,
+    parallelism: &mut ParallelismController,
     attempt_id: AttemptId,
 ) -> Result<()> {
     let missing = state.missing_docs();
@@ -465,14 +832,40 @@ async fn download_attempt<R: Runtime>(
     let mut n_errors = 0;
     for (client_req, dir_response) in fetched {
         let source = dir_response.source().cloned();
-        let text = match String::from_utf8(dir_response.into_output_unchecked())
-            .map_err(Error::BadUtf8FromDirectory)
-        {
+        if ResponseStatus::from_code(dir_response.status_code()) == ResponseStatus::NotModified {
+            let mut changed = false;
+            let outcome = state.note_not_modified(&client_req, Some(&dirmgr.store), &mut changed);
+            if let Err(e) = &outcome {
+                dirmgr.note_errors(attempt_id, 1);
+                warn_report!(e, "error while reusing unchanged directory info");
+            }
+            propagate_fatal_errors!(outcome);
+            continue;
+        }
+        let (body, resumed) = match &client_req {
+            ClientRequest::Consensus(req) => {
+                let mut store = dirmgr.store.lock().expect("store lock poisoned");
+                match combine_consensus_response(req.flavor(), dir_response, &mut **store) {
+                    Some(combined) => combined,
+                    None => {
+                        // We don't have a whole consensus yet.  Any blame
+                        // for that has already gone to the cache.
+                        n_errors += 1;
+                        continue;
+                    }
+                }
+            }
+            _ => (dir_response.into_output_unchecked(), false),
+        };
+        // If we glued together parts of a consensus from different caches,
+        // we can't tell which of them to blame if the result is no good.
+        let source = if resumed { None } else { source };
+        let text = match String::from_utf8(body).map_err(Error::BadUtf8FromDirectory) {
             Ok(t) => t,
             Err(e) => {
                 if let Some(source) = source {
                     n_errors += 1;
-                    note_cache_error(dirmgr.circmgr()?.deref(), &source, &e);
+                    note_cache_error(dirmgr.circmgr()?.deref(), &dirmgr.cache_stats, &source, &e);
                 }
                 continue;
             }
@@ -498,15 +891,23 @@ async fn download_attempt<R: Runtime>(
                 if let Some(source) = source {
                     if let Err(e) = &outcome {
                         n_errors += 1;
-                        note_cache_error(dirmgr.circmgr()?.deref(), &source, e);
+                        note_cache_error(
+                            dirmgr.circmgr()?.deref(),
+                            &dirmgr.cache_stats,
+                            &source,
+                            e,
+                        );
                     } else {
-                        note_cache_success(dirmgr.circmgr()?.deref(), &source);
+                        note_cache_success(dirmgr.circmgr()?.deref(), &dirmgr.cache_stats, &source);
                     }
                 }
 
                 if let Err(e) = &outcome {
                     dirmgr.note_errors(attempt_id, 1);
                     warn_report!(e, "error while adding directory info");
+                    if resumed {
+                        info!("Resumed consensus download was no good; starting over.");
+                    }
                 }
                 propagate_fatal_errors!(outcome);
             }
@@ -514,7 +915,7 @@ async fn download_attempt<R: Runtime>(
                 warn_report!(e, "Error when expanding directory text");
                 if let Some(source) = source {
                     n_errors += 1;
-                    note_cache_error(dirmgr.circmgr()?.deref(), &source, &e);
+                    note_cache_error(dirmgr.circmgr()?.deref(), &dirmgr.cache_stats, &source, &e);
                 }
                 propagate_fatal_errors!(Err(e));
             }
@@ -548,9 +949,13 @@ pub(crate) async fn download<R: Runtime>(
 
     trace!(attempt=%attempt_id, state=%state.describe(), "Trying to download directory material.");
 
+    // We keep one controller across all our states and attempts,
+    // so that what we learn about the caches isn't forgotten when we advance.
+    let mut parallelism = ParallelismController::new(&state.dl_config());
+
     'next_state: loop {
         let retry_config = state.dl_config();
-        let parallelism = retry_config.parallelism();
+        parallelism.set_schedule(&retry_config);
 
         // In theory this could be inside the loop below maybe?  If we
         // want to drop the restriction that the missing() members of a
@@ -566,7 +971,7 @@ pub(crate) async fn download<R: Runtime>(
                 // cache, do so.
                 if let Some(source) = e.responsible_cache() {
                     dirmgr.note_errors(attempt_id, 1);
-                    note_cache_error(dirmgr.circmgr()?.deref(), source, e);
+                    note_cache_error(dirmgr.circmgr()?.deref(), &dirmgr.cache_stats, source, e);
                 }
             }
             propagate_fatal_errors!(load_result);
@@ -621,6 +1026,11 @@ pub(crate) async fn download<R: Runtime>(
                     reset(state);
                     continue 'next_state;
                 }
+
+                // Our last attempt didn't get us what we needed.  (We don't
+                // check before the first attempt: a routine refresh often
+                // starts with less than the staleness threshold left.)
+                upgrade_weak_ref(&dirmgr)?.check_staleness(now);
             }
 
             info!(attempt=%attempt_id, "{}: {}", attempt + 1, state.describe());
@@ -629,7 +1039,7 @@ pub(crate) async fn download<R: Runtime>(
             now = {
                 let dirmgr = upgrade_weak_ref(&dirmgr)?;
                 futures::select_biased! {
-                    outcome = download_attempt(&dirmgr, state, parallelism.into(), attempt_id).fuse() => {
+                    outcome = download_attempt(&dirmgr, state, &mut parallelism, attempt_id).fuse() => {
                         if let Err(e) = outcome {
                             // TODO: get warn_report! to support `attempt=%attempt_id`?
                             warn_report!(e, "Error while downloading (attempt {})", attempt_id);
@@ -685,6 +1095,7 @@ pub(crate) async fn download<R: Runtime>(
         }
 
         // We didn't advance the state, after all the retries.
+        upgrade_weak_ref(&dirmgr)?.check_staleness(now);
         warn!(n_attempts=retry_config.n_attempts(),
               state=%state.describe(),
               "Unable to advance downloading state");
@@ -734,11 +1145,14 @@ mod test {
     #![allow(clippy::needless_pass_by_value)]
     //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
     use super::*;
+    use crate::docid::CacheUsage;
+    use crate::docmeta::ConsensusMeta;
     use crate::storage::DynStore;
-    use crate::test::new_mgr;
+    use crate::test::{fake_source, n_failures, new_mgr};
     use crate::DownloadSchedule;
     use std::sync::Mutex;
     use tor_netdoc::doc::microdesc::MdDigest;
+    use tor_netdoc::doc::netstatus::Lifetime;
     use tor_rtcompat::SleepProvider;
 
     #[test]
@@ -883,6 +1297,219 @@ mod test {
         }
     }
 
+    #[test]
+    fn parallelism_fixed() {
+        let sched = DownloadSchedule::builder().parallelism(4).build().unwrap();
+        let mut p = ParallelismController::new(&sched);
+        assert_eq!(p.current(), 4);
+        p.note_failure();
+        assert_eq!(p.current(), 4);
+        p.note_success();
+        assert_eq!(p.current(), 4);
+    }
+
+    #[test]
+    fn parallelism_adaptive() {
+        let sched = DownloadSchedule::builder()
+            .parallelism(4)
+            .adaptive_parallelism(true)
+            .build()
+            .unwrap();
+        let mut p = ParallelismController::new(&sched);
+        assert_eq!(p.current(), 1);
+        p.note_success();
+        assert_eq!(p.current(), 2);
+        p.note_success();
+        assert_eq!(p.current(), 2);
+        p.note_success();
+        assert_eq!(p.current(), 3);
+        for _ in 0..20 {
+            p.note_success();
+        }
+        assert_eq!(p.current(), 4);
+        p.note_failure();
+        assert_eq!(p.current(), 2);
+        p.note_failure();
+        assert_eq!(p.current(), 1);
+        p.note_failure();
+        assert_eq!(p.current(), 1);
+    }
+
+    #[test]
+    fn parallelism_set_schedule() {
+        let adaptive = |n| {
+            DownloadSchedule::builder()
+                .parallelism(n)
+                .adaptive_parallelism(true)
+                .build()
+                .unwrap()
+        };
+        let mut p = ParallelismController::new(&adaptive(4));
+        for _ in 0..20 {
+            p.note_success();
+        }
+        assert_eq!(p.current(), 4);
+
+        // What we learned carries over to a new schedule, within its limit.
+        p.set_schedule(&adaptive(8));
+        assert_eq!(p.current(), 4);
+        p.set_schedule(&adaptive(2));
+        assert_eq!(p.current(), 2);
+
+        // A fixed schedule always allows its full parallelism.
+        let fixed = DownloadSchedule::builder().parallelism(3).build().unwrap();
+        p.set_schedule(&fixed);
+        assert_eq!(p.current(), 3);
+        p.note_failure();
+        assert_eq!(p.current(), 3);
+
+        // Going back to adaptive starts over.
+        p.set_schedule(&adaptive(4));
+        assert_eq!(p.current(), 1);
+    }
+
+    #[test]
+    fn response_status() {
+        use ResponseStatus as RS;
+        assert_eq!(RS::from_code(200), RS::Ok);
+        assert_eq!(RS::from_code(206), RS::Ok);
+        assert_eq!(RS::from_code(304), RS::NotModified);
+        assert_eq!(RS::from_code(404), RS::NotFound);
+        assert_eq!(RS::from_code(503), RS::Overloaded);
+        assert_eq!(RS::from_code(400), RS::Other(400));
+    }
+
+    #[test]
+    fn sort_responses_by_status() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let (_tempdir, mgr) = new_mgr(rt);
+            let sched = DownloadSchedule::builder()
+                .parallelism(4)
+                .adaptive_parallelism(true)
+                .build()
+                .unwrap();
+            let mut parallelism = ParallelismController::new(&sched);
+            let request = || {
+                ClientRequest::Consensus(tor_dirclient::request::ConsensusRequest::new(
+                    ConsensusFlavor::Microdesc,
+                ))
+            };
+            let responses = |codes: &[u16]| {
+                codes
+                    .iter()
+                    .map(|code| Ok((request(), DirResponse::new_for_testing(*code, ""))))
+                    .collect()
+            };
+
+            // We pass on the responses with something to apply, and the ones
+            // telling us that what we have is current.  Both count as successes.
+            let useful = sort_responses(&mgr, responses(&[200, 304, 404]), &mut parallelism)
+                .unwrap()
+                .into_iter()
+                .map(|(_, r)| r.status_code())
+                .collect::<Vec<_>>();
+            assert_eq!(useful, vec![200, 304]);
+            assert_eq!(parallelism.current(), 2);
+
+            // A cache that doesn't have what we asked for doesn't slow us down,
+            // but one that's overloaded (or that fails some other way) does.
+            let useful = sort_responses(&mgr, responses(&[404]), &mut parallelism).unwrap();
+            assert!(useful.is_empty());
+            assert_eq!(parallelism.current(), 2);
+            let useful = sort_responses(&mgr, responses(&[503]), &mut parallelism).unwrap();
+            assert!(useful.is_empty());
+            assert_eq!(parallelism.current(), 1);
+            let useful = sort_responses(&mgr, responses(&[400]), &mut parallelism).unwrap();
+            assert!(useful.is_empty());
+            assert_eq!(parallelism.current(), 1);
+
+            // A download that got cut off is passed on, so that we can keep
+            // what we got, but it still counts as a failure.
+            let useful = sort_responses(&mgr, responses(&[200, 200]), &mut parallelism).unwrap();
+            assert_eq!(useful.len(), 2);
+            assert_eq!(parallelism.current(), 3);
+            let partial = DirResponse::new_for_testing(200, "network-status-version 3")
+                .with_error_for_testing(tor_dirclient::RequestError::DirTimeout);
+            let useful =
+                sort_responses(&mgr, vec![Ok((request(), partial))], &mut parallelism).unwrap();
+            assert_eq!(useful.len(), 1);
+            assert_eq!(parallelism.current(), 1);
+        });
+    }
+
+    #[test]
+    fn partial_consensus_saved() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let (_tempdir, mgr) = new_mgr(rt);
+            let flavor = ConsensusFlavor::Microdesc;
+            let mut store = mgr.store.lock().unwrap();
+            let interrupted = |body: Vec<u8>| {
+                DirResponse::new_for_testing(200, body)
+                    .with_error_for_testing(tor_dirclient::RequestError::DirTimeout)
+            };
+            let partial_len = |store: &dyn Store| {
+                store
+                    .partial_consensus(flavor)
+                    .unwrap()
+                    .map(|p| p.as_ref().len())
+            };
+
+            // A large enough piece of a consensus is kept for resuming.
+            let mut big = b"network-status-version 3\n".to_vec();
+            big.resize(MIN_PARTIAL_CONSENSUS_LEN, b'x');
+            assert!(combine_consensus_response(flavor, interrupted(big), &mut **store).is_none());
+            assert_eq!(partial_len(&**store), Some(MIN_PARTIAL_CONSENSUS_LEN));
+
+            // A tiny one isn't worth resuming, and replaces whatever we had.
+            let small = b"network-status-version 3\n".to_vec();
+            assert!(combine_consensus_response(flavor, interrupted(small), &mut **store).is_none());
+            assert_eq!(partial_len(&**store), None);
+        });
+    }
+
+    #[test]
+    fn resumed_consensus_checked() {
+        use tor_dirclient::ContentRange;
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let (_tempdir, mgr) = new_mgr(rt);
+            let flavor = ConsensusFlavor::Microdesc;
+            let mut store = mgr.store.lock().unwrap();
+            let mut partial = b"network-status-version 3\n".to_vec();
+            partial.resize(MIN_PARTIAL_CONSENSUS_LEN, b'x');
+            let len = partial.len();
+            let rest = b"0123456789";
+            let resumed = |range| {
+                DirResponse::new_for_testing(206, rest).with_content_range_for_testing(
+                    ContentRange::new(range.0, range.1, range.2).unwrap(),
+                )
+            };
+
+            // A range that doesn't pick up where we left off, or that stops short of
+            // the end of the consensus, or that our body doesn't fill, is refused;
+            // and we start over.
+            for range in [
+                (len - 1, len + 9, len + 10),
+                (len + 1, len + 9, len + 10),
+                (len, len + 9, len + 20),
+                (len, len + 19, len + 20),
+            ] {
+                store.store_partial_consensus(flavor, &partial).unwrap();
+                assert!(combine_consensus_response(flavor, resumed(range), &mut **store).is_none());
+                assert!(store.partial_consensus(flavor).unwrap().is_none());
+            }
+
+            // The right range is joined to what we have.
+            store.store_partial_consensus(flavor, &partial).unwrap();
+            let (body, was_resumed) =
+                combine_consensus_response(flavor, resumed((len, len + 9, len + 10)), &mut **store)
+                    .unwrap();
+            assert!(was_resumed);
+            assert_eq!(&body[..len], &partial[..]);
+            assert_eq!(&body[len..], rest);
+            assert!(store.partial_consensus(flavor).unwrap().is_none());
+        });
+    }
+
     #[test]
     fn all_in_cache() {
         // Let's try bootstrapping when everything is in the cache.
@@ -948,6 +1575,21 @@ mod test {
                         .to_owned(),
                 ];
             }
+            // We're refreshing a consensus that will stop being valid in half
+            // an hour: that's routine, and shouldn't cause a warning.
+            let hour = Duration::from_secs(3600);
+            let lifetime =
+                Lifetime::new(now - hour * 5 / 2, now - hour * 3 / 2, now + hour / 2).unwrap();
+            let netdir = tor_netdir::testnet::construct_custom_netdir_with_params(
+                tor_netdir::testnet::simple_net_func,
+                std::iter::empty::<(&str, _)>(),
+                Some(lifetime),
+            )
+            .unwrap()
+            .unwrap_if_sufficient()
+            .unwrap();
+            mgr.netdir.replace(netdir);
+
             let mgr = Arc::new(mgr);
             let mut on_usable = None;
             let attempt_id = AttemptId::next();
@@ -963,6 +1605,111 @@ mod test {
             .await
             .unwrap();
             assert!(state.is_ready(Readiness::Complete));
+            assert_eq!(*mgr.staleness_warning_events().inner.borrow(), None);
         });
     }
+
+    #[test]
+    fn expansion_failure_blames_cache() {
+        // When we can't expand a response, download_attempt notes a cache
+        // error for the response's source, and carries on downloading.
+        // Make sure that the errors we get from a failed expansion are ones
+        // that blame the cache, and that aren't fatal to the bootstrap attempt.
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let now = rt.wallclock();
+            let day = Duration::from_secs(86400);
+            let config = DirMgrConfig::default();
+            let (_tempdir, mgr) = new_mgr(rt);
+
+            let latest_id = DocId::LatestConsensus {
+                flavor: ConsensusFlavor::Microdesc,
+                cache_usage: CacheUsage::CacheOkay,
+            };
+            let consensus_request = || {
+                let store = mgr.store.lock().unwrap();
+                make_requests_for_documents(&mgr.runtime, &[latest_id], &**store, &config)
+                    .unwrap()
+                    .remove(0)
+            };
+            let check_blames_cache = |e: Error| {
+                assert!(e.indicates_cache_failure(), "{e:?}");
+                assert!(
+                    matches!(e.bootstrap_action(), BootstrapAction::Nonfatal),
+                    "{e:?}"
+                );
+
+                // The cache that sent us the response is recorded as having failed.
+                let stats = CacheStatsTracker::default();
+                let source = fake_source(1);
+                let blamed = blame_cache(&stats, &source, &e).unwrap();
+                assert_eq!(blamed.cache_id(), source.cache_id());
+                assert_eq!(n_failures(&stats, &source), 1);
+            };
+
+            let diff = |hash: &str| {
+                format!(
+                    "network-status-diff-version 1\nhash {} {hash}\n2c\nreplacement line\n.\n",
+                    "99".repeat(32),
+                )
+            };
+
+            // A diff that we didn't ask for.
+            let req = consensus_request();
+            let err = mgr
+                .expand_response_text(&req, diff(&"99".repeat(32)))
+                .unwrap_err();
+            assert!(matches!(err, Error::Unwanted(_)));
+            check_blames_cache(err);
+
+            // A diff against a consensus we have, whose result has the wrong digest.
+            {
+                let mut store = mgr.store.lock().unwrap();
+                let d_in = [0x99; 32];
+                let cmeta = ConsensusMeta::new(
+                    Lifetime::new(now, now + day, now + 2 * day).unwrap(),
+                    d_in,
+                    d_in,
+                );
+                store
+                    .store_consensus(
+                        &cmeta,
+                        ConsensusFlavor::Microdesc,
+                        false,
+                        "line 1\nline2\nline 3\n",
+                    )
+                    .unwrap();
+            }
+            let req = consensus_request();
+            let err = mgr
+                .expand_response_text(&req, diff(&"99".repeat(32)))
+                .unwrap_err();
+            assert!(matches!(err, Error::ConsensusDiffError(_)));
+            check_blames_cache(err);
+        });
+    }
+
+    #[test]
+    fn blame_cache_records_failures() {
+        let stats = CacheStatsTracker::default();
+        let source = fake_source(1);
+        let real_source = fake_source(2);
+
+        // An error that isn't the cache's fault doesn't count against it.
+        let err = Error::CacheCorruption("the disk is full of bees");
+        assert!(blame_cache(&stats, &source, &err).is_none());
+        assert!(stats.snapshot().is_empty());
+
+        // An error that names the cache that is really at fault counts against that cache.
+        let err = Error::NetDocError {
+            source: DocSource::DirServer {
+                source: Some(real_source.clone()),
+            },
+            cause: tor_netdoc::doc::microdesc::Microdesc::parse("not a microdescriptor")
+                .unwrap_err(),
+        };
+        let blamed = blame_cache(&stats, &source, &err).unwrap();
+        assert_eq!(blamed.cache_id(), real_source.cache_id());
+        assert_eq!(n_failures(&stats, &source), 0);
+        assert_eq!(n_failures(&stats, &real_source), 1);
+    }
 }
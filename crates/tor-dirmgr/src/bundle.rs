@@ -0,0 +1,346 @@
+//! Export and import the set of documents that make up a usable directory.
+//!
+//! A [`DirBundle`] holds a consensus, along with the authority certificates
+//! and microdescriptors that go with it, in a single portable text format.
+//! This lets us move a working directory from one cache to another:
+//! for example, onto a machine with no network access,
+//! where it can be used with `download_schedule.cache_only`.
+
+use crate::docmeta::{AuthCertMeta, ConsensusMeta};
+use crate::storage::Store;
+use crate::{DocSource, Error, Result};
+
+use tor_checkable::{ExternallySigned, SelfSigned, Timebound};
+use tor_netdoc::doc::authcert::AuthCert;
+use tor_netdoc::doc::microdesc::Microdesc;
+use tor_netdoc::doc::netstatus::{ConsensusFlavor, MdConsensus};
+
+/// The first line of every encoded bundle.
+const BUNDLE_HEADER: &str = "@arti-dir-bundle 1";
+/// The line that introduces the consensus in an encoded bundle.
+const CONSENSUS_TAG: &str = "@consensus";
+/// The line that introduces each authority certificate in an encoded bundle.
+const AUTHCERT_TAG: &str = "@authcert";
+/// The line that introduces each microdescriptor in an encoded bundle.
+const MICRODESC_TAG: &str = "@microdesc";
+
+/// A set of directory documents exported from a directory cache,
+/// so that they can be imported into another one.
+///
+/// Use [`DirMgr::export_bundle`](crate::DirMgr::export_bundle) to make one,
+/// and [`DirMgrStore::import_bundle`](crate::DirMgrStore::import_bundle) to load one
+/// into a store.
+///
+/// In its encoded form, each document is preceded by a line naming its type,
+/// in the style of a netdoc annotation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirBundle {
+    /// The text of a microdescriptor consensus.
+    consensus: String,
+    /// The text of each authority certificate that signed the consensus.
+    authcerts: Vec<String>,
+    /// The text of each microdescriptor listed in the consensus.
+    microdescs: Vec<String>,
+}
+
+impl DirBundle {
+    /// Encode this bundle as a string, suitable for writing to a file.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        push_line(&mut out, BUNDLE_HEADER);
+        push_doc(&mut out, CONSENSUS_TAG, &self.consensus);
+        for cert in &self.authcerts {
+            push_doc(&mut out, AUTHCERT_TAG, cert);
+        }
+        for md in &self.microdescs {
+            push_doc(&mut out, MICRODESC_TAG, md);
+        }
+        out
+    }
+
+    /// Parse a bundle that was encoded with [`DirBundle::encode`].
+    ///
+    /// This only splits the bundle into its documents:
+    /// the documents themselves are checked when the bundle is imported.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut lines = s.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some(BUNDLE_HEADER) {
+            return Err(Error::InvalidBundle("missing bundle header".into()));
+        }
+
+        // Each document's tag, and its text.
+        let mut docs: Vec<(&str, String)> = Vec::new();
+        for line in lines {
+            if line.starts_with('@') {
+                docs.push((line.trim_end(), String::new()));
+            } else if let Some((_, text)) = docs.last_mut() {
+                text.push_str(line);
+            } else if !line.trim().is_empty() {
+                return Err(Error::InvalidBundle(
+                    "text found before the first document".into(),
+                ));
+            }
+        }
+
+        let mut consensus = None;
+        let mut authcerts = Vec::new();
+        let mut microdescs = Vec::new();
+        for (tag, text) in docs {
+            match tag {
+                CONSENSUS_TAG if consensus.is_some() => {
+                    return Err(Error::InvalidBundle("more than one consensus".into()));
+                }
+                CONSENSUS_TAG => consensus = Some(text),
+                AUTHCERT_TAG => authcerts.push(text),
+                MICRODESC_TAG => microdescs.push(text),
+                _ => {
+                    return Err(Error::InvalidBundle(format!(
+                        "unrecognized document type {:?}",
+                        tag
+                    )))
+                }
+            }
+        }
+        let consensus = consensus.ok_or_else(|| Error::InvalidBundle("no consensus".into()))?;
+
+        Ok(DirBundle {
+            consensus,
+            authcerts,
+            microdescs,
+        })
+    }
+}
+
+/// Append `line` to `out`, followed by a newline.
+fn push_line(out: &mut String, line: &str) {
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Append the document `text` to `out`, preceded by `tag`.
+fn push_doc(out: &mut String, tag: &str, text: &str) {
+    push_line(out, tag);
+    out.push_str(text);
+    if !text.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Collect the latest usable consensus in `store`, along with the
+/// authority certificates and microdescriptors that go with it.
+///
+/// Documents that the consensus mentions, but that we don't have, are left out.
+pub(crate) fn export(store: &dyn Store) -> Result<DirBundle> {
+    let consensus = store
+        .latest_consensus(ConsensusFlavor::Microdesc, Some(false))?
+        .ok_or(Error::DirectoryNotPresent)?;
+    let consensus = consensus.as_str()?.to_owned();
+
+    let (_, _, parsed) =
+        MdConsensus::parse(&consensus).map_err(|e| Error::from_netdoc(DocSource::LocalCache, e))?;
+    // We only mark a consensus as non-pending once we have validated it,
+    // so we don't check it again here: we only use it to find out
+    // which other documents belong in the bundle.
+    let unvalidated = parsed.dangerously_assume_timely();
+    let cert_ids: Vec<_> = unvalidated.signing_cert_ids().collect();
+    let md_digests: Vec<_> = unvalidated
+        .dangerously_assume_wellsigned()
+        .relays()
+        .iter()
+        .map(|rs| *rs.md_digest())
+        .collect();
+
+    let mut authcerts = store.authcerts(&cert_ids)?;
+    let mut microdescs = store.microdescs(&md_digests)?;
+
+    Ok(DirBundle {
+        consensus,
+        authcerts: cert_ids
+            .iter()
+            .filter_map(|id| authcerts.remove(id))
+            .collect(),
+        microdescs: md_digests
+            .iter()
+            .filter_map(|d| microdescs.remove(d))
+            .collect(),
+    })
+}
+
+/// Store every document in `bundle` into `store`.
+///
+/// The consensus is stored as pending:
+/// we check its signatures, and mark it usable,
+/// the first time that we load enough of the bundle to build circuits.
+pub(crate) fn import(store: &mut dyn Store, bundle: &DirBundle) -> Result<()> {
+    let bad_doc = |what: &str, e: tor_netdoc::Error| {
+        Error::InvalidBundle(format!("unparseable {}: {}", what, e))
+    };
+
+    let (signed, remainder, parsed) =
+        MdConsensus::parse(&bundle.consensus).map_err(|e| bad_doc("consensus", e))?;
+    let consensus_meta =
+        ConsensusMeta::from_unvalidated(signed, remainder, parsed.dangerously_peek());
+
+    let mut authcerts = Vec::new();
+    for text in &bundle.authcerts {
+        let cert = AuthCert::parse(text)
+            .map_err(|e| bad_doc("authority certificate", e))?
+            .check_signature()?
+            // We check whether it is timely when we use it.
+            .dangerously_assume_timely();
+        authcerts.push((AuthCertMeta::from_authcert(&cert), text.as_str()));
+    }
+
+    let mut microdescs = Vec::new();
+    for text in &bundle.microdescs {
+        let md = Microdesc::parse(text).map_err(|e| bad_doc("microdescriptor", e))?;
+        microdescs.push((text.as_str(), *md.digest()));
+    }
+
+    store.store_authcerts(&authcerts)?;
+    store.store_microdescs(
+        &microdescs
+            .iter()
+            .map(|(text, digest)| (*text, digest))
+            .collect::<Vec<_>>(),
+        consensus_meta.lifetime().valid_after(),
+    )?;
+    store.store_consensus(
+        &consensus_meta,
+        ConsensusFlavor::Microdesc,
+        true,
+        &bundle.consensus,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+    use crate::storage::sqlite::test::new_empty;
+    use tor_netdoc::doc::microdesc::{AllowAnnotations, MicrodescReader};
+
+    const CONSENSUS: &str = include_str!("../testdata/mdconsensus1.txt");
+    const AUTHCERTS: &[&str] = &[
+        include_str!("../testdata/cert-5696.txt"),
+        include_str!("../testdata/cert-5A23.txt"),
+        include_str!("../testdata/cert-7C47.txt"),
+    ];
+    const MICRODESCS: &str = include_str!("../testdata/microdescs.txt");
+
+    /// Return the encoded form of a bundle made from our test data.
+    fn encoded_test_bundle() -> String {
+        let mut out = String::new();
+        push_line(&mut out, BUNDLE_HEADER);
+        push_doc(&mut out, CONSENSUS_TAG, CONSENSUS);
+        for cert in AUTHCERTS {
+            push_doc(&mut out, AUTHCERT_TAG, cert);
+        }
+        for md in MicrodescReader::new(MICRODESCS, &AllowAnnotations::AnnotationsNotAllowed) {
+            push_doc(
+                &mut out,
+                MICRODESC_TAG,
+                md.unwrap().within(MICRODESCS).unwrap(),
+            );
+        }
+        out
+    }
+
+    #[test]
+    fn encode_and_parse() {
+        let encoded = encoded_test_bundle();
+        let bundle = DirBundle::parse(&encoded).unwrap();
+        assert_eq!(bundle.consensus, CONSENSUS);
+        assert_eq!(bundle.authcerts.len(), AUTHCERTS.len());
+        assert!(!bundle.microdescs.is_empty());
+
+        assert_eq!(bundle.encode(), encoded);
+        assert_eq!(DirBundle::parse(&bundle.encode()).unwrap(), bundle);
+    }
+
+    #[test]
+    fn parse_bad() {
+        let bad = |s: &str| match DirBundle::parse(s) {
+            Err(Error::InvalidBundle(_)) => {}
+            other => panic!("{:?}", other),
+        };
+        bad("");
+        bad("@consensus\nnetwork-status-version 3 microdesc\n");
+        bad("@arti-dir-bundle 1\n@microdesc\nonion-key\n");
+        bad("@arti-dir-bundle 1\nnetwork-status-version 3 microdesc\n");
+        bad("@arti-dir-bundle 1\n@consensus\nx\n@consensus\ny\n");
+        bad("@arti-dir-bundle 1\n@consensus\nx\n@routerdesc\ny\n");
+    }
+
+    #[test]
+    fn import_and_export() {
+        let (_tempdir, mut store) = new_empty().unwrap();
+        let bundle = DirBundle::parse(&encoded_test_bundle()).unwrap();
+
+        // A store with no usable consensus has nothing to export.
+        assert!(matches!(export(&store), Err(Error::DirectoryNotPresent)));
+
+        import(&mut store, &bundle).unwrap();
+
+        // The imported consensus isn't usable until we have validated it.
+        assert!(store
+            .latest_consensus(ConsensusFlavor::Microdesc, Some(false))
+            .unwrap()
+            .is_none());
+        assert!(store
+            .latest_consensus(ConsensusFlavor::Microdesc, Some(true))
+            .unwrap()
+            .is_some());
+
+        // Once it has been validated, we can export it again.
+        let (signed, remainder, parsed) = MdConsensus::parse(CONSENSUS).unwrap();
+        let meta = ConsensusMeta::from_unvalidated(signed, remainder, parsed.dangerously_peek());
+        store.mark_consensus_usable(&meta).unwrap();
+
+        let exported = export(&store).unwrap();
+        assert_eq!(exported.consensus, bundle.consensus);
+        assert!(!exported.authcerts.is_empty());
+        assert!(exported
+            .authcerts
+            .iter()
+            .all(|c| bundle.authcerts.contains(c)));
+        assert!(!exported.microdescs.is_empty());
+        assert!(exported
+            .microdescs
+            .iter()
+            .all(|md| bundle.microdescs.contains(md)));
+    }
+
+    #[test]
+    fn import_bad() {
+        let (_tempdir, mut store) = new_empty().unwrap();
+        let mut bundle = DirBundle::parse(&encoded_test_bundle()).unwrap();
+        bundle
+            .microdescs
+            .push("this is not a microdescriptor\n".into());
+
+        assert!(matches!(
+            import(&mut store, &bundle),
+            Err(Error::InvalidBundle(_))
+        ));
+        // Nothing was stored.
+        assert!(store
+            .latest_consensus(ConsensusFlavor::Microdesc, None)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -13,6 +13,7 @@ use crate::retry::{DownloadSchedule, DownloadScheduleBuilder};
 use crate::storage::DynStore;
 use crate::Result;
 use tor_checkable::timed::TimerangeBound;
+use tor_circmgr::DirIpv6Preference;
 use tor_config::{define_list_builder_accessors, impl_standard_builder, ConfigBuildError};
 use tor_guardmgr::fallback::FallbackDirBuilder;
 use tor_netdoc::doc::netstatus::{self, Lifetime};
@@ -54,6 +55,12 @@ pub struct NetworkConfig {
     /// (If none are specified, we use a default list of authorities shipped
     /// with Arti.)
     ///
+    /// We only accept a consensus if it is signed by more than half of the
+    /// authorities in this list, and we ignore signatures from any authority
+    /// that is not listed here.  So on a private network, listing only your
+    /// own authorities here pins the consensus to them: a consensus that
+    /// lacks enough signatures from them is rejected, however well-formed it is.
+    ///
     /// This section cannot be changed in a running Arti client.
     ///
     /// The default is to use a set of compiled-in authorities,
@@ -99,7 +106,7 @@ impl NetworkConfigBuilder {
 /// This type is immutable once constructed. To make one, use
 /// [`DownloadScheduleConfigBuilder`], or deserialize it from a string.
 #[derive(Debug, Clone, Builder, Eq, PartialEq)]
-#[builder(build_fn(error = "ConfigBuildError"))]
+#[builder(build_fn(validate = "Self::validate", error = "ConfigBuildError"))]
 #[builder(derive(Debug, Serialize, Deserialize))]
 pub struct DownloadScheduleConfig {
     /// Top-level configuration for how to retry our initial bootstrap attempt.
@@ -127,10 +134,97 @@ pub struct DownloadScheduleConfig {
     )]
     #[builder_field_attr(serde(default))]
     pub(crate) retry_microdescs: DownloadSchedule,
+
+    /// A value to send as the `User-Agent` header on our directory requests.
+    ///
+    /// This header is never sent on anonymized requests,
+    /// such as those for onion service descriptors.
+    ///
+    /// Most users should leave this unset: a client that identifies itself
+    /// is easier to tell apart from other Tor clients.
+    /// It can be useful on private Tor networks.
+    ///
+    /// The default is not to send a `User-Agent` header at all.
+    #[builder(default, setter(into, strip_option))]
+    pub(crate) user_agent: Option<String>,
+
+    /// If true, never download any directory information.
+    ///
+    /// Instead, we bootstrap using only the documents that are already in our cache,
+    /// and fail to bootstrap if the cache doesn't hold enough of them
+    /// to build circuits.
+    /// This can be useful for examining a cache that was captured elsewhere.
+    ///
+    /// Cannot be changed on a running Arti client.
+    #[builder(default)]
+    pub(crate) cache_only: bool,
+
+    /// Whether to prefer or require directory caches that we can reach over IPv6.
+    ///
+    /// One of `"any"`, `"prefer"`, or `"require"`.
+    /// With `"prefer"`, we use a cache with an IPv6 address when we can find one,
+    /// and fall back to any other cache otherwise.
+    /// With `"require"`, we only ever fetch directory information over IPv6,
+    /// and our downloads fail if we can't find a cache with an IPv6 address.
+    ///
+    /// This can be useful on networks where IPv4 is impaired or unavailable.
+    ///
+    /// The default is `"any"`.
+    #[builder(default)]
+    pub(crate) ipv6_caches: DirIpv6Preference,
+
+    /// If true, keep the part of an interrupted consensus download,
+    /// and later ask a cache for only the rest of it.
+    ///
+    /// A resumed download can't be compressed, and can't be a consensus diff.
+    /// Many directory caches don't support resuming at all,
+    /// and send the whole consensus instead.
+    /// So this is only worth enabling on connections that are often cut off.
+    ///
+    /// The default is false.
+    #[builder(default)]
+    pub(crate) resume_consensus: bool,
 }
 
 impl_standard_builder! { DownloadScheduleConfig }
 
+impl DownloadScheduleConfigBuilder {
+    /// Check that this builder will give a usable configuration.
+    fn validate(&self) -> std::result::Result<(), ConfigBuildError> {
+        if let Some(Some(user_agent)) = &self.user_agent {
+            if !user_agent.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+                return Err(ConfigBuildError::Invalid {
+                    field: "user_agent".to_owned(),
+                    problem: "must contain only printable ASCII characters".to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DownloadScheduleConfig {
+    /// Return the value we should send as our `User-Agent` on directory requests, if any.
+    pub(crate) fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// Return true if we should bootstrap only from our cache, and never download.
+    pub(crate) fn cache_only(&self) -> bool {
+        self.cache_only
+    }
+
+    /// Return whether we prefer or require directory caches that we can reach over IPv6.
+    pub(crate) fn ipv6_caches(&self) -> DirIpv6Preference {
+        self.ipv6_caches
+    }
+
+    /// Return true if we should resume interrupted consensus downloads.
+    pub(crate) fn resume_consensus(&self) -> bool {
+        self.resume_consensus
+    }
+}
+
 /// Configuration for how much much to extend the official tolerances of our
 /// directory information.
 ///
@@ -165,6 +259,17 @@ pub struct DirTolerance {
     #[builder(default = "Duration::from_secs(3 * 24 * 60 * 60)")]
     #[builder_field_attr(serde(default, with = "humantime_serde::option"))]
     pub(crate) post_valid_tolerance: Duration,
+
+    /// How long before the consensus we're using stops being valid should we
+    /// start warning that we haven't been able to replace it?
+    ///
+    /// (This is only an early warning: we keep using the consensus for up to
+    /// `post_valid_tolerance` after it stops being valid.)
+    ///
+    /// Defaults to 1 hour.
+    #[builder(default = "Duration::from_secs(60 * 60)")]
+    #[builder_field_attr(serde(default, with = "humantime_serde::option"))]
+    pub(crate) staleness_warning: Duration,
 }
 
 impl_standard_builder! { DirTolerance }
@@ -297,7 +402,10 @@ impl DirMgrConfig {
                 fallback_caches: new_config.network.fallback_caches.clone(),
                 authorities: self.network.authorities.clone(),
             },
-            schedule: new_config.schedule.clone(),
+            schedule: DownloadScheduleConfig {
+                cache_only: self.schedule.cache_only,
+                ..new_config.schedule.clone()
+            },
             tolerance: new_config.tolerance.clone(),
             override_net_params: new_config.override_net_params.clone(),
             extensions: new_config.extensions.clone(),
@@ -430,6 +538,20 @@ mod test {
         assert_eq!(cfg.retry_bootstrap.n_attempts(), 4);
         assert_eq!(cfg.retry_consensus.n_attempts(), 7);
         assert_eq!(cfg.retry_certs.n_attempts(), 5);
+        assert_eq!(cfg.user_agent(), None);
+
+        bld.user_agent("private-net-client/1.0");
+        let cfg = bld.build().unwrap();
+        assert_eq!(cfg.user_agent(), Some("private-net-client/1.0"));
+
+        bld.user_agent("two\nlines");
+        assert!(bld.build().is_err());
+
+        assert_eq!(cfg.ipv6_caches(), DirIpv6Preference::Any);
+        bld.user_agent("private-net-client/1.0");
+        bld.ipv6_caches(DirIpv6Preference::Require);
+        let cfg = bld.build().unwrap();
+        assert_eq!(cfg.ipv6_caches(), DirIpv6Preference::Require);
 
         Ok(())
     }
@@ -115,6 +115,13 @@ pub enum Error {
     /// An attempt was made to bootstrap a `DirMgr` created in offline mode.
     #[error("Tried to bootstrap a DirMgr that was configured as offline-only")]
     OfflineMode,
+    /// We were configured to bootstrap only from our cache, but the cache
+    /// didn't hold enough information for us to build circuits.
+    #[error("Cannot bootstrap from cache only: cache is missing {0}")]
+    CacheInsufficient(String),
+    /// A directory bundle that we were asked to import was malformed.
+    #[error("Invalid directory bundle: {0}")]
+    InvalidBundle(String),
     /// A problem accessing our cache directory (for example, no such directory)
     #[error("Problem accessing cache directory")]
     CacheAccess(#[from] fs_mistrust::Error),
@@ -245,6 +252,8 @@ impl Error {
             | Error::BadUtf8InCache(_)
             | Error::BadHexInCache(_)
             | Error::OfflineMode
+            | Error::CacheInsufficient(_)
+            | Error::InvalidBundle(_)
             | Error::Spawn { .. }
             | Error::NetDirOlder
             | Error::Bug(_) => false,
@@ -304,6 +313,8 @@ impl Error {
 
             Error::NoDownloadSupport
             | Error::OfflineMode
+            | Error::CacheInsufficient(_)
+            | Error::InvalidBundle(_)
             | Error::CacheCorruption(_)
             | Error::SqliteError(_)
             | Error::ReadOnlyStorage(_)
@@ -352,7 +363,7 @@ impl HasKind for Error {
             E::SqliteError(e) => sqlite_error_kind(e),
             E::ReadOnlyStorage(_) => EK::LocalResourceAlreadyInUse,
             E::UnrecognizedSchema { .. } => EK::CacheCorrupted,
-            E::DirectoryNotPresent => EK::DirectoryExpired,
+            E::DirectoryNotPresent | E::CacheInsufficient(_) => EK::DirectoryExpired,
             E::NetDirOlder => EK::TorDirectoryError,
             E::BadUtf8FromDirectory(_) => EK::TorProtocolViolation,
             E::BadUtf8InCache(_) => EK::CacheCorrupted,
@@ -375,6 +386,7 @@ impl HasKind for Error {
             E::DirClientError(e) => e.kind(),
             E::SignatureError(_) => EK::TorProtocolViolation,
             E::OfflineMode => EK::BadApiUsage,
+            E::InvalidBundle(_) => EK::Other,
             E::Spawn { cause, .. } => cause.kind(),
             E::ExternalDirProvider { kind, .. } => *kind,
             E::Bug(e) => e.kind(),
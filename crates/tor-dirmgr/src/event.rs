@@ -13,7 +13,7 @@ use std::{
         Arc,
     },
     task::Poll,
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use educe::Educe;
@@ -815,6 +815,87 @@ impl Stream for DirBootstrapEvents {
     }
 }
 
+/// A stream of [`Lifetime`](netstatus::Lifetime)s, one for each new consensus
+/// that a [`DirMgr`](crate::DirMgr) installs.
+///
+/// Like [`DirBootstrapEvents`], this stream can be lossy: if the caller doesn't
+/// read it promptly, it will only observe the most recent lifetime.
+#[derive(Clone, Educe)]
+#[educe(Debug)]
+pub struct ConsensusLifetimeEvents {
+    /// The `postage::watch::Receiver` that we're wrapping.
+    ///
+    /// Holds `None` until the first consensus is installed.
+    #[educe(Debug(method = "skip_fmt"))]
+    pub(crate) inner: postage::watch::Receiver<Option<netstatus::Lifetime>>,
+}
+
+impl Stream for ConsensusLifetimeEvents {
+    type Item = netstatus::Lifetime;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Some(lifetime))) => return Poll::Ready(Some(lifetime)),
+                // No consensus yet; keep waiting.
+                Poll::Ready(Some(None)) => continue,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A warning that the consensus we're using will soon stop being valid,
+/// and that we haven't yet managed to replace it.
+///
+/// This is only an early warning: see
+/// [`DirMgr::staleness_warning_events`](crate::DirMgr::staleness_warning_events).
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct StalenessWarning {
+    /// The time at which the consensus we're using stops being valid.
+    pub valid_until: SystemTime,
+    /// How much longer the consensus was valid for, when we issued this warning.
+    pub remaining: Duration,
+}
+
+/// A stream of [`StalenessWarning`]s from a [`DirMgr`](crate::DirMgr).
+///
+/// Like [`DirBootstrapEvents`], this stream can be lossy: if the caller doesn't
+/// read it promptly, it will only observe the most recent warning.
+#[derive(Clone, Educe)]
+#[educe(Debug)]
+pub struct StalenessWarningEvents {
+    /// The `postage::watch::Receiver` that we're wrapping.
+    ///
+    /// Holds `None` whenever we have no warning to give.
+    #[educe(Debug(method = "skip_fmt"))]
+    pub(crate) inner: postage::watch::Receiver<Option<StalenessWarning>>,
+}
+
+impl Stream for StalenessWarningEvents {
+    type Item = StalenessWarning;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Some(warning))) => return Poll::Ready(Some(warning)),
+                // Nothing to warn about; keep waiting.
+                Poll::Ready(Some(None)) => continue,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     // @@ begin test lint list maintained by maint/add_warning @@
@@ -955,6 +1036,63 @@ mod test {
         });
     }
 
+    #[test]
+    fn consensus_lifetime_events() {
+        test_with_all_runtimes!(|_rt| async {
+            let (mut send, recv) = postage::watch::channel();
+            let mut events = ConsensusLifetimeEvents { inner: recv };
+
+            let now = SystemTime::now();
+            let hour = Duration::new(3600, 0);
+            let lifetime = |start: SystemTime| {
+                netstatus::Lifetime::new(start, start + hour, start + hour * 3).unwrap()
+            };
+
+            // Nothing until a consensus is installed.
+            assert!(futures::poll!(events.next()).is_pending());
+
+            *send.borrow_mut() = Some(lifetime(now));
+            let got = events.next().await.unwrap();
+            assert_eq!(got.valid_after(), now);
+            assert!(futures::poll!(events.next()).is_pending());
+
+            *send.borrow_mut() = Some(lifetime(now + hour));
+            let got = events.next().await.unwrap();
+            assert_eq!(got.fresh_until(), now + hour * 2);
+
+            drop(send);
+            assert!(events.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn staleness_warning_events() {
+        test_with_all_runtimes!(|_rt| async {
+            let (mut send, recv) = postage::watch::channel();
+            let mut events = StalenessWarningEvents { inner: recv };
+
+            let now = SystemTime::now();
+            let minute = Duration::new(60, 0);
+
+            assert!(futures::poll!(events.next()).is_pending());
+
+            *send.borrow_mut() = Some(StalenessWarning {
+                valid_until: now + minute * 30,
+                remaining: minute * 30,
+            });
+            let got = events.next().await.unwrap();
+            assert_eq!(got.remaining, minute * 30);
+            assert_eq!(got.valid_until, now + minute * 30);
+
+            // Clearing the warning doesn't produce an event.
+            *send.borrow_mut() = None;
+            assert!(futures::poll!(events.next()).is_pending());
+
+            drop(send);
+            assert!(events.next().await.is_none());
+        });
+    }
+
     #[test]
     fn failed_conversion() {
         assert_eq!(DirEvent::from_index(999), None);
@@ -49,6 +49,7 @@
 
 pub mod authority;
 mod bootstrap;
+mod bundle;
 pub mod config;
 mod docid;
 mod docmeta;
@@ -57,6 +58,7 @@ mod event;
 mod retry;
 mod shared_ref;
 mod state;
+mod stats;
 mod storage;
 
 #[cfg(feature = "bridge-client")]
@@ -79,8 +81,11 @@ use scopeguard::ScopeGuard;
 use tor_circmgr::CircMgr;
 use tor_dirclient::SourceInfo;
 use tor_error::{info_report, into_internal, warn_report};
+use tor_linkspec::RelayIds;
 use tor_netdir::params::NetParameters;
 use tor_netdir::{DirEvent, MdReceiver, NetDir, NetDirProvider};
+use tor_netdoc::doc::microdesc::MdDigest;
+use tor_netdoc::doc::netstatus;
 
 use async_trait::async_trait;
 use futures::{stream::BoxStream, task::SpawnExt};
@@ -89,23 +94,29 @@ use tor_rtcompat::scheduler::{TaskHandle, TaskSchedule};
 use tor_rtcompat::Runtime;
 use tracing::{debug, info, trace, warn};
 
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Weak;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use std::{collections::HashMap, sync::Weak};
 use std::{fmt::Debug, time::SystemTime};
 
 use crate::state::{DirState, NetDirChange};
 pub use authority::{Authority, AuthorityBuilder};
+pub use bundle::DirBundle;
 pub use config::{
     DirMgrConfig, DirTolerance, DirToleranceBuilder, DownloadScheduleConfig,
     DownloadScheduleConfigBuilder, NetworkConfig, NetworkConfigBuilder,
 };
 pub use docid::DocId;
 pub use err::Error;
-pub use event::{DirBlockage, DirBootstrapEvents, DirBootstrapStatus};
-pub use storage::DocumentText;
+pub use event::{
+    ConsensusLifetimeEvents, DirBlockage, DirBootstrapEvents, DirBootstrapStatus, StalenessWarning,
+    StalenessWarningEvents,
+};
+pub use stats::DirCacheStats;
+pub use storage::{DocumentText, PrunedDocuments};
 pub use tor_guardmgr::fallback::{FallbackDir, FallbackDirBuilder};
 pub use tor_netdir::Timeliness;
 
@@ -138,6 +149,14 @@ impl<R: Runtime> DirMgrStore<R> {
         let runtime = PhantomData;
         Ok(DirMgrStore { store, runtime })
     }
+
+    /// Store every document in `bundle`, so that we can bootstrap from them later.
+    ///
+    /// The documents are checked when we load them, just as if we had downloaded them.
+    pub fn import_bundle(&self, bundle: &DirBundle) -> Result<()> {
+        let mut store = self.store.lock().expect("store lock poisoned");
+        bundle::import(&mut **store, bundle)
+    }
 }
 
 /// Trait for DirMgr implementations
@@ -292,9 +311,27 @@ pub struct DirMgr<R: Runtime> {
     /// to discard unread events.
     receive_status: DirBootstrapEvents,
 
+    /// A publisher handle that we notify with the lifetime of every
+    /// consensus we install.
+    send_consensus_lifetime: Mutex<watch::Sender<Option<netstatus::Lifetime>>>,
+
+    /// A receiver handle that gets notified whenever we install a new consensus.
+    receive_consensus_lifetime: ConsensusLifetimeEvents,
+
+    /// A publisher handle that we notify when the consensus we're using is
+    /// about to stop being valid.
+    send_staleness_warning: Mutex<watch::Sender<Option<StalenessWarning>>>,
+
+    /// A receiver handle that gets notified when the consensus we're using is
+    /// about to stop being valid.
+    receive_staleness_warning: StalenessWarningEvents,
+
     /// A circuit manager, if this DirMgr supports downloading.
     circmgr: Option<Arc<CircMgr<R>>>,
 
+    /// Statistics about how well each directory cache has been serving us.
+    cache_stats: stats::CacheStatsTracker,
+
     /// Our asynchronous runtime.
     runtime: R,
 
@@ -423,11 +460,17 @@ impl<R: Runtime> DirMgr<R> {
     ///
     /// If bootstrapping has already successfully taken place, returns early with success.
     ///
+    /// If `download_schedule.cache_only` is set in our configuration, we never download anything:
+    /// this function only loads what it can from the cache, and launches no background task.
+    ///
     /// # Errors
     ///
     /// Returns an error if bootstrapping fails. If the error is [`Error::CantAdvanceState`],
     /// it may be possible to successfully bootstrap later on by calling this function again.
     ///
+    /// If we are configured not to download, and the cache doesn't hold enough information to
+    /// build circuits, returns [`Error::CacheInsufficient`], saying what is missing.
+    ///
     /// # Panics
     ///
     /// Panics if the `DirMgr` passed to this function was not created in online mode, such as
@@ -458,6 +501,13 @@ impl<R: Runtime> DirMgr<R> {
             v.store(false, Ordering::SeqCst);
         });
 
+        if self.config.get().schedule.cache_only() {
+            self.bootstrap_from_cache_only().await?;
+            // Disarm the RAII guard, since we succeeded.
+            let _ = ScopeGuard::into_inner(reset_bootstrap_started);
+            return Ok(());
+        }
+
         let schedule = {
             let sched = self.task_schedule.lock().expect("poisoned lock").take();
             match sched {
@@ -537,6 +587,38 @@ impl<R: Runtime> DirMgr<R> {
         Ok(())
     }
 
+    /// Bootstrap using only the information in our cache, without downloading anything.
+    ///
+    /// Returns [`Error::CacheInsufficient`] if the cache doesn't let us build circuits.
+    async fn bootstrap_from_cache_only(self: &Arc<Self>) -> Result<()> {
+        let attempt_id = AttemptId::next();
+        trace!(attempt=%attempt_id, "Bootstrapping directory from cache only");
+        // We also look at pending consensuses here, since we will never download a
+        // replacement: one of them may have been imported with `import_bundle`,
+        // and not yet validated.
+        let state = self
+            .load_from_cache(attempt_id, CacheUsage::CacheOkay)
+            .await?;
+
+        if self.netdir.get().is_some() {
+            info!("Loaded a good directory from cache; not downloading, since we are configured to use the cache only.");
+            Ok(())
+        } else {
+            let missing =
+                describe_missing_docs(&state.missing_docs()).unwrap_or_else(|| state.describe());
+            Err(Error::CacheInsufficient(missing))
+        }
+    }
+
+    /// Return statistics about how well each directory cache has served our
+    /// requests, keyed by the identity of the cache.
+    ///
+    /// We only keep statistics for a limited number of caches: when we hear from a
+    /// new one, we forget about the one we heard from least recently.
+    pub fn cache_stats(&self) -> HashMap<RelayIds, DirCacheStats> {
+        self.cache_stats.snapshot()
+    }
+
     /// Returns `true` if a bootstrap attempt is in progress, or successfully completed.
     pub fn bootstrap_started(&self) -> bool {
         self.bootstrap_started.load(Ordering::SeqCst)
@@ -772,6 +854,9 @@ impl<R: Runtime> DirMgr<R> {
         if new_config.authorities() != config.authorities() {
             how.cannot_change("network.authorities")?;
         }
+        if new_config.schedule.cache_only() != config.schedule.cache_only() {
+            how.cannot_change("download_schedule.cache_only")?;
+        }
 
         if how == tor_config::Reconfigure::CheckAllOrNothing {
             return Ok(());
@@ -809,6 +894,81 @@ impl<R: Runtime> DirMgr<R> {
         self.receive_status.clone()
     }
 
+    /// Return a stream of consensus [`Lifetime`](netstatus::Lifetime)s,
+    /// yielding one each time we install a new consensus.
+    ///
+    /// This lets callers anticipate when the next consensus is expected
+    /// (around its `fresh_until` time).
+    ///
+    /// Note that this stream can be lossy: the caller will not necessarily
+    /// observe every event on the stream.
+    pub fn consensus_lifetime_events(&self) -> ConsensusLifetimeEvents {
+        self.receive_consensus_lifetime.clone()
+    }
+
+    /// Return a stream of [`StalenessWarning`]s, yielding one whenever an
+    /// attempt to refresh our directory fails while the consensus we're using
+    /// has less than
+    /// [`staleness_warning`](DirToleranceBuilder::staleness_warning) left
+    /// before it stops being valid.
+    ///
+    /// This is an early warning, not a failure: we can keep using the
+    /// consensus for a while after it stops being valid.
+    ///
+    /// Note that this stream can be lossy: the caller will not necessarily
+    /// observe every event on the stream.
+    pub fn staleness_warning_events(&self) -> StalenessWarningEvents {
+        self.receive_staleness_warning.clone()
+    }
+
+    /// Check whether the consensus we're using will stop being valid soon, as
+    /// of `now`, and if so, warn about it.
+    pub(crate) fn check_staleness(&self, now: SystemTime) {
+        let valid_until = match self.netdir.get() {
+            Some(netdir) => netdir.lifetime().valid_until(),
+            None => return,
+        };
+        let threshold = self.config.get().tolerance.staleness_warning;
+        let remaining = valid_until.duration_since(now).unwrap_or_default();
+        if remaining >= threshold {
+            return;
+        }
+        warn!(
+            "Our consensus stops being valid in {}, and we haven't been able to replace it yet.",
+            humantime::format_duration(remaining)
+        );
+        *self
+            .send_staleness_warning
+            .lock()
+            .expect("poisoned lock")
+            .borrow_mut() = Some(StalenessWarning {
+            valid_until,
+            remaining,
+        });
+    }
+
+    /// Start using `netdir` as our current network directory, and tell
+    /// everybody who is watching.
+    fn replace_netdir(&self, mut netdir: NetDir) {
+        let cfg = self.config.get();
+        netdir.replace_overridden_parameters(&cfg.override_net_params);
+        let lifetime = netdir.lifetime().clone();
+        self.netdir.replace(netdir);
+        self.events.publish(DirEvent::NewConsensus);
+        self.events.publish(DirEvent::NewDescriptors);
+        *self
+            .send_consensus_lifetime
+            .lock()
+            .expect("poisoned lock")
+            .borrow_mut() = Some(lifetime);
+        // Whatever we were warning about, it's not true any more.
+        *self
+            .send_staleness_warning
+            .lock()
+            .expect("poisoned lock")
+            .borrow_mut() = None;
+    }
+
     /// Replace the latest status with `progress` and broadcast to anybody
     /// watching via a [`DirBootstrapEvents`] stream.
     fn update_progress(&self, attempt_id: AttemptId, progress: DirProgress) {
@@ -852,6 +1012,42 @@ impl<R: Runtime> DirMgr<R> {
             .upgrade_to_readwrite()
     }
 
+    /// Remove old documents from our directory cache.
+    ///
+    /// This removes consensuses, microdescriptors, authority certificates and
+    /// router descriptors that have been expired (or unlisted) for long enough
+    /// that we will not need them again, and returns how many of each were removed.
+    ///
+    /// If we have a directory, this also removes every microdescriptor that
+    /// its consensus does not reference, unless it was listed
+    /// by a consensus at least as recent (for example, one we are still bootstrapping).
+    ///
+    /// Documents used by the current directory are never removed, so it is safe
+    /// to call this on a running `DirMgr`.
+    /// We already expire old documents automatically whenever a new consensus becomes usable;
+    /// this method is for callers who want to reclaim space at other times.
+    ///
+    /// If our storage is read-only (because another process is managing it),
+    /// this does nothing.
+    pub fn prune_store(&self) -> Result<PrunedDocuments> {
+        let mut store = self.store.lock().expect("Directory storage lock poisoned");
+        if store.is_readonly() {
+            return Ok(PrunedDocuments::default());
+        }
+        let mut pruned = store.expire_all(&crate::storage::EXPIRATION_DEFAULTS)?;
+
+        if let Some(netdir) = self.netdir.get() {
+            let referenced: HashSet<MdDigest> = netdir
+                .all_relays()
+                .map(|relay| *relay.low_level_details().md_digest())
+                .collect();
+            pruned.microdescs +=
+                store.drop_unreferenced_microdescs(&referenced, netdir.lifetime().valid_after())?;
+        }
+
+        Ok(pruned)
+    }
+
     /// Return a reference to the store, if it is currently read-write.
     #[cfg(test)]
     fn store_if_rw(&self) -> Option<&Mutex<DynStore>> {
@@ -890,6 +1086,16 @@ impl<R: Runtime> DirMgr<R> {
         let receive_status = DirBootstrapEvents {
             inner: receive_status,
         };
+        let (send_consensus_lifetime, receive_consensus_lifetime) = postage::watch::channel();
+        let send_consensus_lifetime = Mutex::new(send_consensus_lifetime);
+        let receive_consensus_lifetime = ConsensusLifetimeEvents {
+            inner: receive_consensus_lifetime,
+        };
+        let (send_staleness_warning, receive_staleness_warning) = postage::watch::channel();
+        let send_staleness_warning = Mutex::new(send_staleness_warning);
+        let receive_staleness_warning = StalenessWarningEvents {
+            inner: receive_staleness_warning,
+        };
         #[cfg(feature = "dirfilter")]
         let filter = config.extensions.filter.clone();
 
@@ -905,7 +1111,12 @@ impl<R: Runtime> DirMgr<R> {
             events,
             send_status,
             receive_status,
+            send_consensus_lifetime,
+            receive_consensus_lifetime,
+            send_staleness_warning,
+            receive_staleness_warning,
             circmgr,
+            cache_stats: Default::default(),
             runtime,
             offline,
             bootstrap_started: AtomicBool::new(false),
@@ -921,19 +1132,33 @@ impl<R: Runtime> DirMgr<R> {
     ///
     /// Return false if there is no such consensus.
     async fn load_directory(self: &Arc<Self>, attempt_id: AttemptId) -> Result<bool> {
+        let _ = self
+            .load_from_cache(attempt_id, CacheUsage::CacheOnly)
+            .await?;
+
+        Ok(self.netdir.get().is_some())
+    }
+
+    /// Load as much of the latest non-expired directory as we can from the
+    /// cache, and return the state we reached.
+    ///
+    /// `cache_usage` says whether we may use a pending consensus.
+    async fn load_from_cache(
+        self: &Arc<Self>,
+        attempt_id: AttemptId,
+        cache_usage: CacheUsage,
+    ) -> Result<Box<dyn DirState>> {
         let state = state::GetConsensusState::new(
             self.runtime.clone(),
             self.config.get(),
-            CacheUsage::CacheOnly,
+            cache_usage,
             None,
             #[cfg(feature = "dirfilter")]
             self.filter
                 .clone()
                 .unwrap_or_else(|| Arc::new(crate::filter::NilFilter)),
         );
-        let _ = bootstrap::load(Arc::clone(self), Box::new(state), attempt_id).await?;
-
-        Ok(self.netdir.get().is_some())
+        bootstrap::load(Arc::clone(self), Box::new(state), attempt_id).await
     }
 
     /// Return a new asynchronous stream that will receive notification
@@ -946,6 +1171,16 @@ impl<R: Runtime> DirMgr<R> {
         self.events.subscribe()
     }
 
+    /// Export the latest usable consensus from our cache, along with the
+    /// authority certificates and microdescriptors that go with it.
+    ///
+    /// The resulting bundle can be imported into another store with
+    /// [`DirMgrStore::import_bundle`].
+    pub fn export_bundle(&self) -> Result<DirBundle> {
+        let store = self.store.lock().expect("store lock poisoned");
+        bundle::export(&**store)
+    }
+
     /// Try to load the text of a single document described by `doc` from
     /// storage.
     pub fn text(&self, doc: &DocId) -> Result<Option<DocumentText>> {
@@ -1061,12 +1296,7 @@ impl<R: Runtime> DirMgr<R> {
                         warn!("Got a new NetDir, but it's older than the one we currently have!");
                         return Err(Error::NetDirOlder);
                     }
-                    let cfg = self.config.get();
-                    let mut netdir = netdir.take().expect("AttemptReplace had None");
-                    netdir.replace_overridden_parameters(&cfg.override_net_params);
-                    self.netdir.replace(netdir);
-                    self.events.publish(DirEvent::NewConsensus);
-                    self.events.publish(DirEvent::NewDescriptors);
+                    self.replace_netdir(netdir.take().expect("AttemptReplace had None"));
 
                     info!("Marked consensus usable.");
                     if !store.is_readonly() {
@@ -1109,6 +1339,46 @@ fn upgrade_weak_ref<T>(weak: &Weak<T>) -> Result<Arc<T>> {
     Weak::upgrade(weak).ok_or(Error::ManagerDropped)
 }
 
+/// Return a human-readable summary of the documents in `missing`,
+/// or None if there are none.
+fn describe_missing_docs(missing: &[DocId]) -> Option<String> {
+    let mut consensus = false;
+    let mut certs = 0_usize;
+    let mut microdescs = 0_usize;
+    #[cfg(feature = "routerdesc")]
+    let mut routerdescs = 0_usize;
+    for doc in missing {
+        match doc {
+            DocId::LatestConsensus { .. } => consensus = true,
+            DocId::AuthCert(_) => certs += 1,
+            DocId::Microdesc(_) => microdescs += 1,
+            #[cfg(feature = "routerdesc")]
+            DocId::RouterDesc(_) => routerdescs += 1,
+        }
+    }
+
+    let mut parts = Vec::new();
+    if consensus {
+        parts.push("a usable consensus".to_string());
+    }
+    if certs > 0 {
+        parts.push(format!("{} authority certificate(s)", certs));
+    }
+    if microdescs > 0 {
+        parts.push(format!("{} microdescriptor(s)", microdescs));
+    }
+    #[cfg(feature = "routerdesc")]
+    if routerdescs > 0 {
+        parts.push(format!("{} router descriptor(s)", routerdescs));
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(", "))
+    }
+}
+
 /// Given a time `now`, and an amount of tolerated clock skew `tolerance`,
 /// return the age of the oldest consensus that we should request at that time.
 pub(crate) fn default_consensus_cutoff(
@@ -1172,6 +1442,24 @@ mod test {
         (dir, dirmgr)
     }
 
+    /// Return a `SourceInfo` for an answer from the cache whose RSA identity is made of `id`.
+    pub(crate) fn fake_source(id: u8) -> SourceInfo {
+        let cache = tor_linkspec::OwnedChanTarget::builder()
+            .rsa_identity([id; 20].into())
+            .build()
+            .unwrap();
+        let circuit = tor_proto::circuit::UniqId::new_for_testing(id.into(), 0);
+        SourceInfo::new_for_testing(circuit, cache)
+    }
+
+    /// Return the number of failures that `stats` has recorded for the cache of `source`.
+    pub(crate) fn n_failures(stats: &crate::stats::CacheStatsTracker, source: &SourceInfo) -> u64 {
+        stats
+            .snapshot()
+            .get(&RelayIds::from_relay_ids(source.cache_id()))
+            .map_or(0, |stats| stats.failures)
+    }
+
     #[test]
     fn failing_accessors() {
         tor_rtcompat::test_with_one_runtime!(|rt| async {
@@ -1182,6 +1470,200 @@ mod test {
         });
     }
 
+    #[test]
+    fn cache_only_bootstrap_empty_cache() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let dir = TempDir::new().unwrap();
+            let config = DirMgrConfig {
+                cache_dir: dir.path().into(),
+                schedule: DownloadScheduleConfig::builder()
+                    .cache_only(true)
+                    .build()
+                    .unwrap(),
+                ..Default::default()
+            };
+            let store = DirMgrStore::new(&config, rt.clone(), false).unwrap();
+            let mgr = Arc::new(DirMgr::from_config(config, rt, store, None, false).unwrap());
+
+            let err = mgr.bootstrap().await.unwrap_err();
+            assert!(matches!(err, Error::CacheInsufficient(_)));
+            assert_eq!(
+                err.to_string(),
+                "Cannot bootstrap from cache only: cache is missing a usable consensus"
+            );
+            assert!(!mgr.bootstrap_started());
+            assert!(mgr.netdir(Timeliness::Unchecked).is_err());
+        });
+    }
+
+    #[test]
+    fn missing_docs_description() {
+        let certid = AuthCertKeyIds {
+            id_fingerprint: [3; 20].into(),
+            sk_fingerprint: [4; 20].into(),
+        };
+        assert_eq!(describe_missing_docs(&[]), None);
+        assert_eq!(
+            describe_missing_docs(&[
+                DocId::Microdesc([1; 32]),
+                DocId::AuthCert(certid),
+                DocId::Microdesc([2; 32]),
+            ])
+            .unwrap(),
+            "1 authority certificate(s), 2 microdescriptor(s)"
+        );
+    }
+
+    #[test]
+    fn prune_store() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let now = rt.wallclock();
+            let day = Duration::from_secs(86400);
+            let long_ago = now - day * 100;
+
+            let (_tempdir, mgr) = new_mgr(rt);
+
+            let d1 = [5_u8; 32];
+            let d2 = [7; 32];
+            let d3 = [99; 20];
+            let d4 = [12; 20];
+            let old_cert = AuthCertKeyIds {
+                id_fingerprint: d3.into(),
+                sk_fingerprint: d4.into(),
+            };
+            let current_cert = AuthCertKeyIds {
+                id_fingerprint: d4.into(),
+                sk_fingerprint: d3.into(),
+            };
+
+            {
+                let mut store = mgr.store.lock().unwrap();
+                store
+                    .store_microdescs(&[("Fake micro 1", &d1), ("Fake micro 2", &d2)], long_ago)
+                    .unwrap();
+                // d2 is still listed; d1 has been unlisted for a long time.
+                store.update_microdescs_listed(&[d2], now).unwrap();
+                store
+                    .store_authcerts(&[
+                        (
+                            AuthCertMeta::new(old_cert, long_ago, long_ago + day),
+                            "Old certificate",
+                        ),
+                        (
+                            AuthCertMeta::new(current_cert, now, now + day),
+                            "Current certificate",
+                        ),
+                    ])
+                    .unwrap();
+            }
+
+            let pruned = mgr.prune_store().unwrap();
+            assert_eq!(pruned.microdescs, 1);
+            assert_eq!(pruned.authcerts, 1);
+            assert_eq!(pruned.consensuses, 0);
+
+            let store = mgr.store.lock().unwrap();
+            let mds = store.microdescs(&[d1, d2]).unwrap();
+            assert_eq!(mds.keys().collect::<Vec<_>>(), vec![&d2]);
+            let certs = store.authcerts(&[old_cert, current_cert]).unwrap();
+            assert_eq!(certs.keys().collect::<Vec<_>>(), vec![&current_cert]);
+            drop(store);
+
+            // Pruning again removes nothing more.
+            assert_eq!(mgr.prune_store().unwrap(), PrunedDocuments::default());
+        });
+    }
+
+    #[test]
+    fn prune_store_unreferenced() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let (_tempdir, mgr) = new_mgr(rt);
+
+            let netdir = tor_netdir::testnet::construct_netdir()
+                .unwrap_if_sufficient()
+                .unwrap();
+            let valid_after = netdir.lifetime().valid_after();
+            let hour = Duration::from_secs(3600);
+            let referenced = *netdir.relays().next().unwrap().md().digest();
+            let unreferenced = [5_u8; 32];
+            let pending = [7_u8; 32];
+            mgr.netdir.replace(netdir);
+
+            {
+                let mut store = mgr.store.lock().unwrap();
+                store
+                    .store_microdescs(
+                        &[
+                            ("Referenced micro", &referenced),
+                            ("Unreferenced micro", &unreferenced),
+                        ],
+                        valid_after - hour,
+                    )
+                    .unwrap();
+                // Listed by a newer consensus than our current one.
+                store
+                    .store_microdescs(&[("Pending micro", &pending)], valid_after + hour)
+                    .unwrap();
+            }
+
+            let pruned = mgr.prune_store().unwrap();
+            assert_eq!(pruned.microdescs, 1);
+
+            let store = mgr.store.lock().unwrap();
+            let mds = store
+                .microdescs(&[referenced, unreferenced, pending])
+                .unwrap();
+            assert!(mds.contains_key(&referenced));
+            assert!(!mds.contains_key(&unreferenced));
+            assert!(mds.contains_key(&pending));
+        });
+    }
+
+    #[test]
+    fn staleness_warning() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async {
+            let now = rt.wallclock();
+            let (_tempdir, mgr) = new_mgr(rt);
+            let warnings = mgr.staleness_warning_events();
+            let hour = Duration::from_secs(3600);
+            let netdir_until = |valid_until: SystemTime| {
+                let lifetime =
+                    Lifetime::new(valid_until - hour * 3, valid_until - hour * 2, valid_until)
+                        .unwrap();
+                tor_netdir::testnet::construct_custom_netdir_with_params(
+                    tor_netdir::testnet::simple_net_func,
+                    std::iter::empty::<(&str, _)>(),
+                    Some(lifetime),
+                )
+                .unwrap()
+                .unwrap_if_sufficient()
+                .unwrap()
+            };
+
+            // Nothing to warn about until we have a consensus.
+            mgr.check_staleness(now);
+            assert_eq!(*warnings.inner.borrow(), None);
+
+            // Plenty of time left.
+            mgr.replace_netdir(netdir_until(now + hour * 2));
+            mgr.check_staleness(now);
+            assert_eq!(*warnings.inner.borrow(), None);
+
+            // Less than the default hour left.
+            mgr.replace_netdir(netdir_until(now + hour / 2));
+            mgr.check_staleness(now);
+            let warning = warnings.inner.borrow().clone().unwrap();
+            let valid_until = mgr.netdir.get().unwrap().lifetime().valid_until();
+            assert_eq!(warning.valid_until, valid_until);
+            assert_eq!(warning.remaining, valid_until.duration_since(now).unwrap());
+            assert!(warning.remaining <= hour / 2);
+
+            // Getting a new consensus clears the warning.
+            mgr.replace_netdir(netdir_until(now + hour * 3));
+            assert_eq!(*warnings.inner.borrow(), None);
+        });
+    }
+
     #[test]
     fn load_and_store_internals() {
         tor_rtcompat::test_with_one_runtime!(|rt| async {
@@ -1361,9 +1843,39 @@ mod test {
                     assert_eq!(ds.len(), 1);
                     assert_eq!(ds[0], &d_prev);
                     assert_eq!(r.last_consensus_date(), Some(now));
+                    assert_eq!(r.resume_offset(), None);
                 }
                 _ => panic!("Wrong request type"),
             }
+
+            // With part of a consensus on disk, we only ask for the rest of it
+            // if we're configured to resume downloads.
+            let partial = vec![b'x'; 100 * 1024];
+            mgr.store
+                .lock()
+                .unwrap()
+                .store_partial_consensus(ConsensusFlavor::Microdesc, &partial)
+                .unwrap();
+            let mut resume_config = config.clone();
+            resume_config.schedule.resume_consensus = true;
+            for (config, expected) in [(&config, None), (&resume_config, Some(partial.len()))] {
+                let req = {
+                    let store = mgr.store.lock().unwrap();
+                    bootstrap::make_consensus_request(
+                        now,
+                        ConsensusFlavor::Microdesc,
+                        &**store,
+                        config,
+                    )
+                    .unwrap()
+                };
+                match req {
+                    ClientRequest::Consensus(r) => {
+                        assert_eq!(r.resume_offset(), expected);
+                    }
+                    _ => panic!("Wrong request type"),
+                }
+            }
         });
     }
 
@@ -43,6 +43,13 @@ pub struct DownloadSchedule {
         )
     )]
     parallelism: NonZeroU8,
+
+    /// If true, treat `parallelism` as an upper bound rather than a fixed
+    /// value: start by launching a single request at a time, and adjust
+    /// the number of simultaneous requests based on how our recent
+    /// requests have fared.
+    #[builder(default)]
+    adaptive_parallelism: bool,
 }
 
 impl_standard_builder! { DownloadSchedule }
@@ -103,6 +110,16 @@ impl DownloadSchedule {
         self.parallelism.into()
     }
 
+    /// Return true if we're supposed to adjust the number of parallel
+    /// attempts in response to successes and failures, according to this
+    /// DownloadSchedule.
+    ///
+    /// When this is true, [`parallelism`](Self::parallelism) is the upper
+    /// bound for the number of parallel attempts.
+    pub fn adaptive_parallelism(&self) -> bool {
+        self.adaptive_parallelism
+    }
+
     /// Return a RetryDelay object for this configuration.
     ///
     /// If the initial delay is longer than 32
@@ -143,6 +160,7 @@ mod test {
         assert_eq!(cfg.initial_delay, one_sec);
         let mut sched = cfg.schedule();
         assert_eq!(sched.next_delay(&mut rng), one_sec);
+        assert!(!cfg.adaptive_parallelism());
 
         // Try schedules with zeroes and show that they fail
         DownloadSchedule::builder()
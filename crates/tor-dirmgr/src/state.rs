@@ -140,6 +140,20 @@ pub(crate) trait DirState: Send {
         storage: Option<&Mutex<DynStore>>,
         changed: &mut bool,
     ) -> Result<()>;
+    /// Note that a directory cache answered `request` with "304 Not
+    /// Modified": it has nothing newer than the documents we told it we had.
+    ///
+    /// If `storage` is provided, we may load those documents from it.
+    ///
+    /// Set `changed` to true if any semantic changes in this state were made.
+    fn note_not_modified(
+        &mut self,
+        _request: &ClientRequest,
+        _storage: Option<&Mutex<DynStore>>,
+        _changed: &mut bool,
+    ) -> Result<()> {
+        Ok(())
+    }
     /// Return a summary of this state as a [`DirProgress`].
     fn bootstrap_progress(&self) -> event::DirProgress;
     /// Return a configuration for attempting downloads.
@@ -324,6 +338,36 @@ impl<R: Runtime> DirState for GetConsensusState<R> {
         }
         Ok(())
     }
+    fn note_not_modified(
+        &mut self,
+        request: &ClientRequest,
+        storage: Option<&Mutex<DynStore>>,
+        changed: &mut bool,
+    ) -> Result<()> {
+        let ClientRequest::Consensus(r) = request else {
+            return Ok(());
+        };
+        let Some(store) = storage else {
+            return Ok(());
+        };
+        // The cache has nothing newer than the consensus we based our request
+        // on, so that consensus is as current as any we could download.
+        let text = {
+            let store = store.lock().expect("Directory storage lock poisoned");
+            store.latest_consensus(CONSENSUS_FLAVOR, Some(false))?
+        };
+        let Some(text) = text else {
+            debug!("Cache says our consensus is unchanged, but we have none stored.");
+            return Ok(());
+        };
+        self.add_consensus_text(
+            DocSource::LocalCache,
+            text.as_str().map_err(Error::BadUtf8InCache)?,
+            r.last_consensus_date(),
+            changed,
+        )?;
+        Ok(())
+    }
     fn advance(self: Box<Self>) -> Box<dyn DirState> {
         match self.next {
             Some(next) => Box::new(next),
@@ -1067,6 +1111,10 @@ impl<R: Runtime> DirState for GetMicrodescsState<R> {
                 .within(text)
                 .expect("microdesc not from within text as expected");
             let md = anno.into_microdesc();
+            // The digest is computed from the text we received, so a
+            // microdesc that was corrupted or tampered with ends up here too.
+            // We reject it by itself, and keep the rest of the batch; the
+            // error we return makes the caller blame the cache that sent it.
             if !requested.contains(md.digest()) {
                 warn!(
                     "Received microdescriptor from {} we did not ask for: {:?}",
@@ -1263,6 +1311,9 @@ mod test {
     //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
     #![allow(clippy::cognitive_complexity)]
     use super::*;
+    use crate::bootstrap::blame_cache;
+    use crate::stats::CacheStatsTracker;
+    use crate::test::{fake_source, n_failures};
     use crate::{Authority, AuthorityBuilder, DownloadScheduleConfig};
     use std::convert::TryInto;
     use std::sync::Arc;
@@ -1533,6 +1584,54 @@ mod test {
         });
     }
 
+    #[test]
+    fn get_consensus_state_not_modified() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let rt = make_time_shifted_runtime(test_time(), rt);
+            let cfg = make_dirmgr_config(Some(test_authorities()));
+            let (_tempdir, store) = temp_store();
+            let new_state = || {
+                GetConsensusState::new(
+                    rt.clone(),
+                    cfg.clone(),
+                    CacheUsage::MustDownload,
+                    None,
+                    #[cfg(feature = "dirfilter")]
+                    Arc::new(crate::filter::NilFilter),
+                )
+            };
+            let req = tor_dirclient::request::ConsensusRequest::new(ConsensusFlavor::Microdesc);
+            let req = crate::docid::ClientRequest::Consensus(req);
+
+            // If we have no consensus stored, being told that ours is
+            // unchanged doesn't help.
+            let mut state = new_state();
+            let mut changed = false;
+            let outcome = state.note_not_modified(&req, Some(&store), &mut changed);
+            assert!(outcome.is_ok());
+            assert!(!changed);
+            assert!(!state.can_advance());
+
+            // Once we have one, we use it.
+            {
+                let mut other_state = new_state();
+                let meta = other_state
+                    .add_consensus_text(DocSource::LocalCache, CONSENSUS, None, &mut changed)
+                    .unwrap();
+                store
+                    .lock()
+                    .unwrap()
+                    .store_consensus(meta, ConsensusFlavor::Microdesc, false, CONSENSUS)
+                    .unwrap();
+            }
+            let mut changed = false;
+            let outcome = state.note_not_modified(&req, Some(&store), &mut changed);
+            assert!(outcome.is_ok());
+            assert!(changed);
+            assert!(state.can_advance());
+        });
+    }
+
     #[test]
     fn get_certs_state() {
         tor_rtcompat::test_with_one_runtime!(|rt| async move {
@@ -1669,34 +1768,35 @@ mod test {
         });
     }
 
+    /// Construct a GetMicrodescsState with our test data
+    fn new_getmicrodescs_state(rt: impl Runtime) -> GetMicrodescsState<impl Runtime> {
+        let rt = make_time_shifted_runtime(test_time(), rt);
+        let cfg = make_dirmgr_config(Some(test_authorities()));
+        let (signed, rest, consensus) = MdConsensus::parse(CONSENSUS2).unwrap();
+        let consensus = consensus
+            .dangerously_assume_timely()
+            .dangerously_assume_wellsigned();
+        let meta = ConsensusMeta::from_consensus(signed, rest, &consensus);
+        GetMicrodescsState::new(
+            CacheUsage::CacheOkay,
+            consensus,
+            meta,
+            rt,
+            cfg,
+            None,
+            #[cfg(feature = "dirfilter")]
+            Arc::new(crate::filter::NilFilter),
+        )
+    }
+    /// Decode a base64-encoded microdescriptor digest.
+    fn d64(s: &str) -> MdDigest {
+        use base64ct::{Base64Unpadded, Encoding as _};
+        Base64Unpadded::decode_vec(s).unwrap().try_into().unwrap()
+    }
+
     #[test]
     fn get_microdescs_state() {
         tor_rtcompat::test_with_one_runtime!(|rt| async move {
-            /// Construct a GetCertsState with our test data
-            fn new_getmicrodescs_state(rt: impl Runtime) -> GetMicrodescsState<impl Runtime> {
-                let rt = make_time_shifted_runtime(test_time(), rt);
-                let cfg = make_dirmgr_config(Some(test_authorities()));
-                let (signed, rest, consensus) = MdConsensus::parse(CONSENSUS2).unwrap();
-                let consensus = consensus
-                    .dangerously_assume_timely()
-                    .dangerously_assume_wellsigned();
-                let meta = ConsensusMeta::from_consensus(signed, rest, &consensus);
-                GetMicrodescsState::new(
-                    CacheUsage::CacheOkay,
-                    consensus,
-                    meta,
-                    rt,
-                    cfg,
-                    None,
-                    #[cfg(feature = "dirfilter")]
-                    Arc::new(crate::filter::NilFilter),
-                )
-            }
-            fn d64(s: &str) -> MdDigest {
-                use base64ct::{Base64Unpadded, Encoding as _};
-                Base64Unpadded::decode_vec(s).unwrap().try_into().unwrap()
-            }
-
             // If we start from scratch and reset, we're back in GetConsensus.
             let state = new_getmicrodescs_state(rt.clone());
             let state = Box::new(state).reset();
@@ -1803,4 +1903,59 @@ mod test {
             assert!(missing.is_empty());
         });
     }
+
+    #[test]
+    fn get_microdescs_corrupted() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let mut state = new_getmicrodescs_state(rt.clone());
+            let md_text = microdescs();
+            let md2 = d64("iOhVp33NyZxMRDMHsVNq575rkpRViIJ9LN9yn++nPG0");
+            let md3 = d64("/Cd07b3Bl0K0jX2/1cAvsYXJJMi5d8UBU+oWKaLxoGo");
+
+            // Corrupt md3 by changing its ntor key, so that it no longer
+            // matches the digest that the consensus lists for it.
+            let corrupted = {
+                let text = md_text.get(&md3).unwrap();
+                let pos = text.find("ntor-onion-key ").unwrap() + "ntor-onion-key ".len();
+                let replacement = if &text[pos..=pos] == "A" { "B" } else { "A" };
+                format!("{}{}{}", &text[..pos], replacement, &text[pos + 1..])
+            };
+
+            let mut req = tor_dirclient::request::MicrodescRequest::new();
+            req.push(md2);
+            req.push(md3);
+            let req = ClientRequest::Microdescs(req);
+            let response = format!("{}{}", md_text.get(&md2).unwrap(), corrupted);
+
+            let (_tempdir, store) = temp_store();
+            let cache = fake_source(1);
+            let source = DocSource::DirServer {
+                source: Some(cache.clone()),
+            };
+            let mut changed = false;
+            let outcome = state.add_from_download(
+                response.as_str(),
+                &req,
+                source,
+                Some(&store),
+                &mut changed,
+            );
+
+            // The corrupted microdesc is rejected, in a way that blames the cache...
+            let err = outcome.unwrap_err();
+            assert!(matches!(err, Error::Unwanted(_)));
+            let stats = CacheStatsTracker::default();
+            let blamed = blame_cache(&stats, &cache, &err).unwrap();
+            assert_eq!(blamed.cache_id(), cache.cache_id());
+            assert_eq!(n_failures(&stats, &cache), 1);
+            // ...but the good one in the same batch is still accepted.
+            assert!(changed);
+            let missing = state.missing_docs();
+            assert!(!missing.contains(&DocId::Microdesc(md2)));
+            assert!(missing.contains(&DocId::Microdesc(md3)));
+            let stored = store.lock().unwrap().microdescs(&[md2, md3]).unwrap();
+            assert_eq!(stored.len(), 1);
+            assert!(stored.contains_key(&md2));
+        });
+    }
 }
@@ -0,0 +1,189 @@
+//! Keep track of how well each directory cache has been serving us.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tor_linkspec::{OwnedChanTarget, RelayIds};
+
+/// The largest number of directory caches that we keep statistics for.
+///
+/// When we hear from a new cache and we're already at this limit, we forget
+/// the cache that we heard from least recently.
+const MAX_TRACKED_CACHES: usize = 256;
+
+/// Statistics about the directory requests we have made to a single cache.
+///
+/// Returned by [`DirMgr::cache_stats`](crate::DirMgr::cache_stats).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct DirCacheStats {
+    /// Number of answers from this cache that we were able to use.
+    pub successes: u64,
+    /// Number of requests to this cache that failed, or whose answers we rejected.
+    pub failures: u64,
+    /// Total number of bytes that this cache has sent us in response bodies.
+    pub bytes: u64,
+}
+
+/// A bounded set of [`DirCacheStats`], keyed by the identity of each cache.
+#[derive(Default)]
+pub(crate) struct CacheStatsTracker {
+    /// The statistics themselves, and a clock to tell which is least recently used.
+    inner: Mutex<Inner>,
+}
+
+/// The mutable state of a [`CacheStatsTracker`].
+#[derive(Default)]
+struct Inner {
+    /// Statistics for each cache, along with the value of `clock` when we last
+    /// updated them.
+    caches: HashMap<RelayIds, (DirCacheStats, u64)>,
+    /// A counter that increases every time we update any entry.
+    clock: u64,
+}
+
+impl CacheStatsTracker {
+    /// Record that `cache` has given us an answer we could use.
+    pub(crate) fn note_success(&self, cache: &OwnedChanTarget) {
+        self.update(cache, |stats| stats.successes += 1);
+    }
+
+    /// Record that a request to `cache` failed, or that we rejected its answer.
+    pub(crate) fn note_failure(&self, cache: &OwnedChanTarget) {
+        self.update(cache, |stats| stats.failures += 1);
+    }
+
+    /// Record that `cache` has sent us `n` bytes.
+    pub(crate) fn note_bytes(&self, cache: &OwnedChanTarget, n: usize) {
+        self.update(cache, |stats| {
+            stats.bytes = stats.bytes.saturating_add(n as u64);
+        });
+    }
+
+    /// Return a copy of the statistics for every cache we're tracking.
+    pub(crate) fn snapshot(&self) -> HashMap<RelayIds, DirCacheStats> {
+        let inner = self.inner.lock().expect("lock poisoned");
+        inner
+            .caches
+            .iter()
+            .map(|(ids, (stats, _))| (ids.clone(), stats.clone()))
+            .collect()
+    }
+
+    /// Apply `f` to the statistics for `cache`, creating them if necessary.
+    fn update<F>(&self, cache: &OwnedChanTarget, f: F)
+    where
+        F: FnOnce(&mut DirCacheStats),
+    {
+        let ids = RelayIds::from_relay_ids(cache);
+        let mut inner = self.inner.lock().expect("lock poisoned");
+        inner.clock += 1;
+        let now = inner.clock;
+
+        if !inner.caches.contains_key(&ids) && inner.caches.len() >= MAX_TRACKED_CACHES {
+            let oldest = inner
+                .caches
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(ids, _)| ids.clone());
+            if let Some(oldest) = oldest {
+                inner.caches.remove(&oldest);
+            }
+        }
+
+        let (stats, last_used) = inner.caches.entry(ids).or_default();
+        f(stats);
+        *last_used = now;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+    use tor_llcrypto::pk::rsa::RsaIdentity;
+
+    /// Return a cache whose RSA identity is made of `id`.
+    fn source(id: u8) -> OwnedChanTarget {
+        OwnedChanTarget::builder()
+            .rsa_identity(RsaIdentity::from([id; 20]))
+            .build()
+            .unwrap()
+    }
+
+    /// Return the statistics that `tracker` has for the cache made by `source(id)`.
+    fn stats_for(tracker: &CacheStatsTracker, id: u8) -> Option<DirCacheStats> {
+        let ids = RelayIds::from_relay_ids(&source(id));
+        tracker.snapshot().remove(&ids)
+    }
+
+    #[test]
+    fn counters() {
+        let tracker = CacheStatsTracker::default();
+        assert!(tracker.snapshot().is_empty());
+
+        tracker.note_success(&source(1));
+        tracker.note_bytes(&source(1), 1000);
+        tracker.note_success(&source(1));
+        tracker.note_bytes(&source(1), 24);
+        tracker.note_failure(&source(2));
+        tracker.note_failure(&source(2));
+        tracker.note_success(&source(2));
+
+        assert_eq!(tracker.snapshot().len(), 2);
+        assert_eq!(
+            stats_for(&tracker, 1).unwrap(),
+            DirCacheStats {
+                successes: 2,
+                failures: 0,
+                bytes: 1024,
+            }
+        );
+        assert_eq!(
+            stats_for(&tracker, 2).unwrap(),
+            DirCacheStats {
+                successes: 1,
+                failures: 2,
+                bytes: 0,
+            }
+        );
+        assert!(stats_for(&tracker, 3).is_none());
+    }
+
+    #[test]
+    fn bounded() {
+        let tracker = CacheStatsTracker::default();
+        for id in 0..=255 {
+            tracker.note_success(&source(id));
+        }
+        assert_eq!(tracker.snapshot().len(), MAX_TRACKED_CACHES);
+
+        // Touch cache 0, so that cache 1 becomes the least recently used.
+        tracker.note_failure(&source(0));
+
+        // A new cache pushes out the least recently used one.
+        let newcomer = OwnedChanTarget::builder()
+            .rsa_identity(RsaIdentity::from([7; 20]))
+            .ed_identity([7; 32].into())
+            .build()
+            .unwrap();
+        tracker.note_success(&newcomer);
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.len(), MAX_TRACKED_CACHES);
+        assert!(snapshot.contains_key(&RelayIds::from_relay_ids(&newcomer)));
+        assert!(stats_for(&tracker, 0).is_some());
+        assert!(stats_for(&tracker, 1).is_none());
+    }
+}
@@ -19,7 +19,7 @@ pub(crate) use tor_guardmgr::bridge::BridgeConfig;
 use crate::docmeta::{AuthCertMeta, ConsensusMeta};
 use crate::{Error, Result};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Result as IoResult;
 use std::str::Utf8Error;
@@ -183,6 +183,23 @@ impl From<Vec<u8>> for InputString {
     }
 }
 
+/// The number of documents of each kind that were removed from a [`Store`]
+/// when expiring old documents.
+///
+/// Returned by [`DirMgr::prune_store`](crate::DirMgr::prune_store).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct PrunedDocuments {
+    /// Number of consensus documents removed.
+    pub consensuses: usize,
+    /// Number of microdescriptors removed.
+    pub microdescs: usize,
+    /// Number of authority certificates removed.
+    pub authcerts: usize,
+    /// Number of router descriptors removed.
+    pub router_descs: usize,
+}
+
 /// Configuration of expiration of each element of a [`Store`].
 pub(crate) struct ExpirationConfig {
     /// How long to keep router descriptors.
@@ -246,7 +263,9 @@ pub(crate) trait Store: Send + 'static {
     ///
     /// This is pretty conservative, and only removes things that are
     /// definitely past their good-by date.
-    fn expire_all(&mut self, expiration: &ExpirationConfig) -> Result<()>;
+    ///
+    /// Returns the number of documents of each kind that were removed.
+    fn expire_all(&mut self, expiration: &ExpirationConfig) -> Result<PrunedDocuments>;
 
     /// Load the latest consensus from disk.
     ///
@@ -287,6 +306,17 @@ pub(crate) trait Store: Send + 'static {
     #[allow(dead_code)] // see also allow on REMOVE_CONSENSUS
     fn delete_consensus(&mut self, cmeta: &ConsensusMeta) -> Result<()>;
 
+    /// Return the partially downloaded consensus of the given flavor, if we
+    /// have one.
+    fn partial_consensus(&self, flavor: ConsensusFlavor) -> Result<Option<InputString>>;
+    /// Remember the first part of a consensus of the given flavor, so that we
+    /// can resume downloading it later.
+    ///
+    /// Replaces any partial consensus of the same flavor that we had before.
+    fn store_partial_consensus(&mut self, flavor: ConsensusFlavor, contents: &[u8]) -> Result<()>;
+    /// Forget any partially downloaded consensus of the given flavor.
+    fn delete_partial_consensus(&mut self, flavor: ConsensusFlavor) -> Result<()>;
+
     /// Read all of the specified authority certs from the cache.
     fn authcerts(&self, certs: &[AuthCertKeyIds]) -> Result<HashMap<AuthCertKeyIds, String>>;
     /// Save a list of authority certificates to the cache.
@@ -300,6 +330,15 @@ pub(crate) trait Store: Send + 'static {
     /// Update the `last-listed` time of every microdescriptor in
     /// `input` to `when` or later.
     fn update_microdescs_listed(&mut self, digests: &[MdDigest], when: SystemTime) -> Result<()>;
+    /// Remove every microdescriptor that is not in `referenced`, and that
+    /// was last listed before `listed_before`.
+    ///
+    /// Returns the number of microdescriptors removed.
+    fn drop_unreferenced_microdescs(
+        &mut self,
+        referenced: &HashSet<MdDigest>,
+        listed_before: SystemTime,
+    ) -> Result<usize>;
 
     /// Read all the microdescriptors listed in `input` from the cache.
     ///
@@ -3,7 +3,7 @@
 //! We store most objects in sqlite tables, except for very large ones,
 //! which we store as "blob" files in a separate directory.
 
-use super::ExpirationConfig;
+use super::{ExpirationConfig, PrunedDocuments};
 use crate::docmeta::{AuthCertMeta, ConsensusMeta};
 use crate::err::ReadOnlyStorageError;
 use crate::storage::{InputString, Store};
@@ -21,7 +21,7 @@ use tor_netdoc::doc::routerdesc::RdDigest;
 #[cfg(feature = "bridge-client")]
 pub(crate) use {crate::storage::CachedBridgeDescriptor, tor_guardmgr::bridge::BridgeConfig};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::OpenOptions;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -369,6 +369,13 @@ impl SqliteStore {
                     continue;
                 }
             };
+            if filename.starts_with(PARTIAL_CONSENSUS_PREFIX) {
+                // An old partial download is of no use to anybody: the
+                // consensus it belonged to is long gone.
+                trace!("Removing stale partial consensus '{}'", &filename);
+                self.remove_blob_or_warn(ent.file_name());
+                continue;
+            }
             let found: (u32,) =
                 self.conn
                     .query_row(COUNT_EXTDOC_BY_PATH, params![&filename], |row| {
@@ -415,7 +422,7 @@ impl Store for SqliteStore {
         }
         Ok(true)
     }
-    fn expire_all(&mut self, expiration: &ExpirationConfig) -> Result<()> {
+    fn expire_all(&mut self, expiration: &ExpirationConfig) -> Result<PrunedDocuments> {
         let tx = self.conn.transaction()?;
         // This works around a false positive; see
         //   https://github.com/rust-lang/rust-clippy/issues/8114
@@ -435,10 +442,12 @@ impl Store for SqliteStore {
         // In theory bad system clocks might generate table rows with times far in the future.
         // However, for data which is cached here which comes from the network consensus,
         // we rely on the fact that no consensus from the future exists, so this can't happen.
-        tx.execute(DROP_OLD_MICRODESCS, [now - expiration.microdescs])?;
-        tx.execute(DROP_OLD_AUTHCERTS, [now - expiration.authcerts])?;
-        tx.execute(DROP_OLD_CONSENSUSES, [now - expiration.consensuses])?;
-        tx.execute(DROP_OLD_ROUTERDESCS, [now - expiration.router_descs])?;
+        let pruned = PrunedDocuments {
+            microdescs: tx.execute(DROP_OLD_MICRODESCS, [now - expiration.microdescs])?,
+            authcerts: tx.execute(DROP_OLD_AUTHCERTS, [now - expiration.authcerts])?,
+            consensuses: tx.execute(DROP_OLD_CONSENSUSES, [now - expiration.consensuses])?,
+            router_descs: tx.execute(DROP_OLD_ROUTERDESCS, [now - expiration.router_descs])?,
+        };
 
         // Bridge descriptors come from bridges and bridges might send crazy times,
         // so we need to discard any that look like they are from the future,
@@ -456,7 +465,7 @@ impl Store for SqliteStore {
 
         self.remove_unreferenced_blobs(now, expiration)?;
 
-        Ok(())
+        Ok(pruned)
     }
 
     fn latest_consensus(
@@ -596,6 +605,47 @@ impl Store for SqliteStore {
 
         Ok(())
     }
+    fn partial_consensus(&self, flavor: ConsensusFlavor) -> Result<Option<InputString>> {
+        let fname = partial_consensus_fname(flavor);
+        let file = match self.blob_dir.open(&fname, OpenOptions::new().read(true)) {
+            Ok(file) => file,
+            Err(fs_mistrust::Error::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        InputString::load(file)
+            .map_err(|err| Error::CacheFile {
+                action: "loading",
+                fname: PathBuf::from(fname),
+                error: Arc::new(err),
+            })
+            .map(Some)
+    }
+    fn store_partial_consensus(&mut self, flavor: ConsensusFlavor, contents: &[u8]) -> Result<()> {
+        if self.is_readonly() {
+            // Whoever has the lock will have to do their own downloading.
+            return Ok(());
+        }
+        let fname = partial_consensus_fname(flavor);
+        self.blob_dir
+            .write_and_replace(&fname, contents)
+            .map_err(|e| match e {
+                fs_mistrust::Error::Io { err, .. } => Error::CacheFile {
+                    action: "saving",
+                    fname: PathBuf::from(fname),
+                    error: err,
+                },
+                err => err.into(),
+            })
+    }
+    fn delete_partial_consensus(&mut self, flavor: ConsensusFlavor) -> Result<()> {
+        if self.is_readonly() {
+            return Ok(());
+        }
+        match self.blob_dir.remove_file(partial_consensus_fname(flavor)) {
+            Ok(()) | Err(fs_mistrust::Error::NotFound(_)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
 
     fn authcerts(&self, certs: &[AuthCertKeyIds]) -> Result<HashMap<AuthCertKeyIds, String>> {
         let mut result = HashMap::new();
@@ -677,6 +727,36 @@ impl Store for SqliteStore {
         tx.commit()?;
         Ok(())
     }
+    fn drop_unreferenced_microdescs(
+        &mut self,
+        referenced: &HashSet<MdDigest>,
+        listed_before: SystemTime,
+    ) -> Result<usize> {
+        let listed_before: OffsetDateTime = listed_before.into();
+        let tx = self.conn.transaction()?;
+
+        let candidates = {
+            let mut stmt = tx.prepare(FIND_MDS_LISTED_BEFORE)?;
+            let rows = stmt.query_map(params![listed_before], |row| row.get::<_, String>(0))?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut n_dropped = 0;
+        let mut stmt = tx.prepare(DROP_MD)?;
+        for h_digest in candidates {
+            let digest: Option<MdDigest> = hex::decode(&h_digest)
+                .ok()
+                .and_then(|digest| digest.try_into().ok());
+            if digest.is_some_and(|digest| referenced.contains(&digest)) {
+                continue;
+            }
+            n_dropped += stmt.execute(params![h_digest])?;
+        }
+
+        stmt.finalize()?;
+        tx.commit()?;
+        Ok(n_dropped)
+    }
 
     #[cfg(feature = "routerdesc")]
     fn routerdescs(&self, digests: &[RdDigest]) -> Result<HashMap<RdDigest, String>> {
@@ -763,6 +843,17 @@ impl Store for SqliteStore {
     }
 }
 
+/// Prefix for the names of files holding partially downloaded consensuses.
+///
+/// These files live in the blob directory, but are not listed in the ExtDocs
+/// table.
+const PARTIAL_CONSENSUS_PREFIX: &str = "partial_con_";
+
+/// Return the name of the file holding a partial consensus of `flavor`.
+fn partial_consensus_fname(flavor: ConsensusFlavor) -> String {
+    format!("{}{}", PARTIAL_CONSENSUS_PREFIX, flavor.name())
+}
+
 /// Handle to a blob that we have saved to disk but not yet committed to
 /// the database.
 struct SavedBlobHandle<'a> {
@@ -1048,6 +1139,17 @@ const INSERT_RD: &str = "
   VALUES ( ?, ?, ? );
 ";
 
+/// Query: find the hex-encoded sha256 digests of the microdescriptors
+/// that were last listed before a given time.
+const FIND_MDS_LISTED_BEFORE: &str = "
+  SELECT sha256_digest
+  FROM Microdescs
+  WHERE last_listed < ?
+";
+
+/// Query: Discard the microdescriptor with a given hex-encoded sha256 digest.
+const DROP_MD: &str = "DELETE FROM Microdescs WHERE sha256_digest = ?;";
+
 /// Query: Change the time when a given microdescriptor was last listed.
 const UPDATE_MD_LISTED: &str = "
   UPDATE Microdescs
@@ -1331,6 +1433,40 @@ pub(crate) mod test {
         Ok(())
     }
 
+    #[test]
+    fn partial_consensus() -> Result<()> {
+        let (_tmp_dir, mut store) = new_empty()?;
+
+        assert!(store
+            .partial_consensus(ConsensusFlavor::Microdesc)?
+            .is_none());
+        // Deleting something that isn't there is fine.
+        store.delete_partial_consensus(ConsensusFlavor::Microdesc)?;
+
+        store.store_partial_consensus(ConsensusFlavor::Microdesc, b"network-status-ver")?;
+        store.store_partial_consensus(ConsensusFlavor::Ns, b"network-status")?;
+        let partial = store
+            .partial_consensus(ConsensusFlavor::Microdesc)?
+            .unwrap();
+        assert_eq!(partial.as_ref(), b"network-status-ver");
+
+        // Storing again replaces the old contents.
+        store.store_partial_consensus(ConsensusFlavor::Microdesc, b"network-status-version 3")?;
+        let partial = store
+            .partial_consensus(ConsensusFlavor::Microdesc)?
+            .unwrap();
+        assert_eq!(partial.as_ref(), b"network-status-version 3");
+
+        store.delete_partial_consensus(ConsensusFlavor::Microdesc)?;
+        assert!(store
+            .partial_consensus(ConsensusFlavor::Microdesc)?
+            .is_none());
+        let partial = store.partial_consensus(ConsensusFlavor::Ns)?.unwrap();
+        assert_eq!(partial.as_ref(), b"network-status");
+
+        Ok(())
+    }
+
     #[test]
     fn authcerts() -> Result<()> {
         let (_tmp_dir, mut store) = new_empty()?;
@@ -1389,7 +1525,9 @@ pub(crate) mod test {
         assert_eq!(mds.get(&d4), None);
 
         // Now we'll expire.  that should drop everything but d2.
-        store.expire_all(&EXPIRATION_DEFAULTS)?;
+        let pruned = store.expire_all(&EXPIRATION_DEFAULTS)?;
+        assert_eq!(pruned.microdescs, 2);
+        assert_eq!(pruned.consensuses, 0);
         let mds = store.microdescs(&[d2, d3, d4])?;
         assert_eq!(mds.len(), 1);
         assert_eq!(mds.get(&d2).unwrap(), "Fake micro 2");
@@ -1426,7 +1564,8 @@ pub(crate) mod test {
         assert_eq!(rds.get(&d4), None);
 
         // Now we'll expire.  that should drop everything but d2.
-        store.expire_all(&EXPIRATION_DEFAULTS)?;
+        let pruned = store.expire_all(&EXPIRATION_DEFAULTS)?;
+        assert_eq!(pruned.router_descs, 2);
         let rds = store.routerdescs(&[d2, d3, d4])?;
         assert_eq!(rds.len(), 1);
         assert_eq!(rds.get(&d2).unwrap(), "Fake routerdesc 2");
@@ -1500,10 +1639,21 @@ pub(crate) mod test {
         )
         .expect("Can't adjust mtime");
 
-        assert_eq!(store.blob_dir.read_directory(".")?.count(), 3);
+        // An old partial consensus gets removed too.
+        store.store_partial_consensus(ConsensusFlavor::Microdesc, b"network-status")?;
+        filetime::set_file_mtime(
+            store.blob_dir.join("partial_con_microdesc")?,
+            SystemTime::from(now - one_week).into(),
+        )
+        .expect("Can't adjust mtime");
+
+        assert_eq!(store.blob_dir.read_directory(".")?.count(), 4);
 
         store.remove_unreferenced_blobs(now, &EXPIRATION_DEFAULTS)?;
         assert_eq!(store.blob_dir.read_directory(".")?.count(), 2);
+        assert!(store
+            .partial_consensus(ConsensusFlavor::Microdesc)?
+            .is_none());
 
         Ok(())
     }
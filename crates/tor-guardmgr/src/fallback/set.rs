@@ -119,12 +119,14 @@ impl From<&FallbackList> for FallbackState {
 }
 
 impl FallbackState {
-    /// Return a random member of this FallbackSet that's usable at `now`.
+    /// Return a random member of this FallbackSet that's usable at `now`,
+    /// and permitted by `filter` and `usage`.
     pub(crate) fn choose<R: rand::Rng>(
         &self,
         rng: &mut R,
         now: Instant,
         filter: &crate::GuardFilter,
+        usage: &crate::GuardUsage,
     ) -> Result<&FallbackDir, PickGuardError> {
         if self.fallbacks.is_empty() {
             return Err(PickGuardError::NoCandidatesAvailable);
@@ -136,7 +138,9 @@ impl FallbackState {
         self.fallbacks
             .iter()
             .filter_cnt(&mut running, |ent| ent.status.usable_at(now))
-            .filter_cnt(&mut filtered, |ent| filter.permits(&ent.fallback))
+            .filter_cnt(&mut filtered, |ent| {
+                filter.permits(&ent.fallback) && usage.permits(&ent.fallback)
+            })
             .choose(rng)
             .map(|ent| &ent.fallback)
             .ok_or_else(|| PickGuardError::AllFallbacksDown {
@@ -338,6 +342,7 @@ mod test {
         let list: FallbackList = fbs.into();
         let mut set: FallbackState = (&list).into();
         let filter = crate::GuardFilter::unfiltered();
+        let usage = crate::GuardUsage::default();
 
         let mut counts = [0_usize; 4];
         let now = Instant::now();
@@ -349,13 +354,24 @@ mod test {
         }
         // Basic case: everybody is up.
         for _ in 0..100 {
-            let fb = set.choose(&mut rng, now, &filter).unwrap();
+            let fb = set.choose(&mut rng, now, &filter, &usage).unwrap();
             let idx = lookup_idx(&set, fb).unwrap();
             counts[idx] += 1;
         }
         dbg!("B");
         assert!(counts.iter().all(|v| *v > 0));
 
+        // None of our fallbacks has an IPv6 address.
+        let mut ipv6_usage = crate::GuardUsageBuilder::new();
+        ipv6_usage
+            .restrictions()
+            .push(crate::GuardRestriction::RequireIpv6);
+        let ipv6_usage = ipv6_usage.build().unwrap();
+        assert!(matches!(
+            set.choose(&mut rng, now, &filter, &ipv6_usage),
+            Err(PickGuardError::AllFallbacksDown { .. })
+        ));
+
         // Mark somebody down and make sure they don't get chosen.
         let ids: Vec<_> = set
             .fallbacks
@@ -365,7 +381,7 @@ mod test {
         set.note_failure(&ids[2], now);
         counts = [0; 4];
         for _ in 0..100 {
-            let fb = set.choose(&mut rng, now, &filter).unwrap();
+            let fb = set.choose(&mut rng, now, &filter, &usage).unwrap();
             let idx = lookup_idx(&set, fb).unwrap();
             counts[idx] += 1;
         }
@@ -377,18 +393,18 @@ mod test {
             set.note_failure(id, now);
         }
         assert!(matches!(
-            set.choose(&mut rng, now, &filter),
+            set.choose(&mut rng, now, &filter, &usage),
             Err(PickGuardError::AllFallbacksDown { .. })
         ));
 
         // Construct an empty set; make sure we get the right error.
         let empty_set = FallbackState::from(&FallbackList::from(vec![]));
         assert!(matches!(
-            empty_set.choose(&mut rng, now, &filter),
+            empty_set.choose(&mut rng, now, &filter, &usage),
             Err(PickGuardError::NoCandidatesAvailable)
         ));
 
-        // TODO: test restrictions and filters once they're implemented.
+        // TODO: test filters once they're implemented.
     }
 
     #[test]
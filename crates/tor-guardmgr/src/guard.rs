@@ -509,12 +509,7 @@ impl Guard {
 
     /// Return true if this guard obeys a single restriction.
     fn obeys_restriction(&self, r: &GuardRestriction) -> bool {
-        match r {
-            GuardRestriction::AvoidId(avoid_id) => !self.id.0.has_identity(avoid_id.as_ref()),
-            GuardRestriction::AvoidAllIds(avoid_ids) => {
-                self.id.0.identities().all(|id| !avoid_ids.contains(id))
-            }
-        }
+        r.permits(self)
     }
 
     /// Return true if this guard is suitable to use for the provided `usage`.
@@ -1013,6 +1008,9 @@ mod test {
             vec![ed([99; 32]), ed([100; 32])].into_iter().collect(),
         ));
         let usage6 = usage6.build().unwrap();
+        let mut usage7 = GuardUsageBuilder::new();
+        usage7.restrictions().push(GuardRestriction::RequireIpv6);
+        let usage7 = usage7.build().unwrap();
 
         assert!(g.conforms_to_usage(&usage1));
         assert!(!g.conforms_to_usage(&usage2));
@@ -1020,6 +1018,7 @@ mod test {
         assert!(!g.conforms_to_usage(&usage4));
         assert!(!g.conforms_to_usage(&usage5));
         assert!(g.conforms_to_usage(&usage6));
+        assert!(!g.conforms_to_usage(&usage7));
     }
 
     #[allow(clippy::redundant_clone)]
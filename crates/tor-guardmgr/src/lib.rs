@@ -507,6 +507,13 @@ impl<R: Runtime> GuardMgr<R> {
             == 0
     }
 
+    /// Return true if we are currently configured to use bridges.
+    #[cfg(feature = "bridge-client")]
+    pub fn bridges_enabled(&self) -> bool {
+        let inner = self.inner.lock().expect("Poisoned lock");
+        inner.configured_bridges.is_some()
+    }
+
     /// Mark every guard as potentially retriable, regardless of how recently we
     /// failed to connect to it.
     pub fn mark_all_guards_retriable(&self) {
@@ -598,6 +605,7 @@ impl<R: Runtime> GuardMgr<R> {
         inner.guards.active_guards_mut().consider_all_retries(now);
 
         let (origin, guard) = inner.select_guard_with_expand(&usage, now, wallclock)?;
+        let guard = usage.modify_hop(guard)?;
         trace!(?guard, ?usage, "Guard selected");
 
         let (usable, usable_sender) = if origin.usable_immediately() {
@@ -1485,7 +1493,7 @@ impl GuardMgrInner {
         if usage.kind == GuardUsageKind::OneHopDirectory
             && self.guards.active_set.universe_type() == UniverseType::NetDir
         {
-            return self.select_fallback(now);
+            return self.select_fallback(usage, now);
         }
 
         // Couldn't extend the sample or use a fallback; return the original error.
@@ -1529,13 +1537,14 @@ impl GuardMgrInner {
     /// for [`GuardMgr::select_guard()`]
     fn select_fallback(
         &self,
+        usage: &GuardUsage,
         now: Instant,
     ) -> Result<(sample::ListKind, FirstHop), PickGuardError> {
         let filt = self.guards.active_guards().filter();
 
         let fallback = self
             .fallbacks
-            .choose(&mut rand::thread_rng(), now, filt)?
+            .choose(&mut rand::thread_rng(), now, filt, usage)?
             .as_guard();
         let fallback = filt.modify_hop(fallback)?;
         Ok((sample::ListKind::Fallback, fallback))
@@ -1847,6 +1856,60 @@ pub enum GuardRestriction {
     AvoidId(RelayId),
     /// Don't pick a guard with any of the provided Ed25519 identities.
     AvoidAllIds(RelayIdSet),
+    /// Only pick a guard that has an IPv6 address, and only connect to it over IPv6.
+    ///
+    /// (No bridge that we reach through a pluggable transport obeys this restriction.)
+    RequireIpv6,
+}
+
+impl GuardRestriction {
+    /// Return true if `target` obeys this restriction.
+    pub(crate) fn permits<T>(&self, target: &T) -> bool
+    where
+        T: tor_linkspec::HasRelayIds + tor_linkspec::HasAddrs + ?Sized,
+    {
+        match self {
+            GuardRestriction::AvoidId(avoid_id) => !target.has_identity(avoid_id.as_ref()),
+            GuardRestriction::AvoidAllIds(avoid_ids) => {
+                target.identities().all(|id| !avoid_ids.contains(id))
+            }
+            GuardRestriction::RequireIpv6 => target.addrs().iter().any(SocketAddr::is_ipv6),
+        }
+    }
+}
+
+impl GuardUsage {
+    /// Return true if `target` obeys all of the restrictions in this usage.
+    pub(crate) fn permits<T>(&self, target: &T) -> bool
+    where
+        T: tor_linkspec::HasRelayIds + tor_linkspec::HasAddrs + ?Sized,
+    {
+        self.restrictions.iter().all(|r| r.permits(target))
+    }
+
+    /// Modify `first_hop` so that it contains no addresses that the restrictions
+    /// in this usage forbid us from connecting to.
+    ///
+    /// It is an internal error to call this function on a first hop
+    /// that doesn't obey those restrictions.
+    fn modify_hop(&self, mut first_hop: FirstHop) -> Result<FirstHop, PickGuardError> {
+        if self
+            .restrictions
+            .iter()
+            .any(|r| matches!(r, GuardRestriction::RequireIpv6))
+        {
+            let r = first_hop
+                .chan_target_mut()
+                .chan_method_mut()
+                .retain_addrs(|addr| addr.is_ipv6());
+            if r.is_err() {
+                return Err(
+                    internal!("Tried to require IPv6 for a guard with no IPv6 address").into(),
+                );
+            }
+        }
+        Ok(first_hop)
+    }
 }
 
 /// The kind of vanguards to use.
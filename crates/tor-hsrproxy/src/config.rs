@@ -3,7 +3,11 @@
 use derive_builder::Builder;
 use derive_deftly::Deftly;
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, ops::RangeInclusive, str::FromStr};
+use std::{
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    str::FromStr,
+};
 use tracing::warn;
 //use tor_config::derive_deftly_template_Flattenable;
 use tor_config::{define_list_builder_accessors, define_list_builder_helper, ConfigBuildError};
@@ -18,6 +22,12 @@ pub struct ProxyConfig {
     /// matches, we take the DestroyCircuit action.
     #[builder(sub_builder, setter(custom))]
     pub(crate) proxy_ports: ProxyRuleList,
+    /// A local address to bind to when we forward a stream to an address target.
+    ///
+    /// This is useful if the target can only be reached through a particular
+    /// interface.  If it is not set, the operating system picks the source address.
+    #[builder(default, setter(strip_option))]
+    pub(crate) forward_source_address: Option<IpAddr>,
     //
     // TODO: Someday we may want to allow udp, resolve, etc.  If we do, it will
     // be via another option, rather than adding another subtype to ProxySource.
@@ -39,6 +49,23 @@ impl ProxyConfigBuilder {
             covered.insert(range.clone());
         }
 
+        // Make sure that we can actually reach every target from our source address.
+        if let Some(Some(source)) = self.forward_source_address {
+            for rule in self.proxy_ports.access_opt().iter().flatten() {
+                if let ProxyAction::Forward(_, target @ TargetAddr::Inet(addr)) = &rule.target {
+                    if source.is_ipv4() != addr.is_ipv4() {
+                        return Err(ConfigBuildError::Invalid {
+                            field: "forward_source_address".into(),
+                            problem: format!(
+                                "Source address {} cannot reach onion service target {}",
+                                source, target
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+
         // Warn about proxy setups that are likely to be surprising.
         let mut any_forward = false;
         for rule in self.proxy_ports.access_opt().iter().flatten() {
@@ -87,6 +114,16 @@ define_list_builder_helper! {
 }
 
 impl ProxyConfig {
+    /// Return the list of rules in this configuration, in the order in which they are applied.
+    pub fn proxy_ports(&self) -> &[ProxyRule] {
+        &self.proxy_ports
+    }
+
+    /// Return the local address we bind to when forwarding a stream, if one is configured.
+    pub fn forward_source_address(&self) -> Option<IpAddr> {
+        self.forward_source_address
+    }
+
     /// Find the configured action to use when receiving a request for a
     /// connection on a given port.
     pub(crate) fn resolve_port_for_begin(&self, port: u16) -> Option<&ProxyAction> {
@@ -598,6 +635,51 @@ mod test {
         assert!(bld.build().is_ok());
     }
 
+    #[test]
+    fn source_address() {
+        let ex = r#"{
+            "proxy_ports": [
+                [ "443", "127.0.0.1:11443" ],
+                [ "*", "destroy" ]
+            ],
+            "forward_source_address": "127.0.0.2"
+        }"#;
+        let bld: ProxyConfigBuilder = serde_json::from_str(ex).unwrap();
+        let cfg = bld.build().unwrap();
+        assert_eq!(
+            cfg.forward_source_address(),
+            Some("127.0.0.2".parse().unwrap())
+        );
+
+        // The default is to let the OS choose.
+        let ex = r#"{
+            "proxy_ports": [
+                [ "443", "127.0.0.1:11443" ]
+            ]
+        }"#;
+        let bld: ProxyConfigBuilder = serde_json::from_str(ex).unwrap();
+        assert_eq!(bld.build().unwrap().forward_source_address(), None);
+
+        // An IPv6 source can't reach an IPv4 target.
+        let ex = r#"{
+            "proxy_ports": [
+                [ "443", "127.0.0.1:11443" ]
+            ],
+            "forward_source_address": "::1"
+        }"#;
+        let bld: ProxyConfigBuilder = serde_json::from_str(ex).unwrap();
+        match bld.build() {
+            Err(ConfigBuildError::Invalid { field, problem }) => {
+                assert_eq!(field, "forward_source_address");
+                assert_eq!(
+                    problem,
+                    "Source address ::1 cannot reach onion service target inet:127.0.0.1:11443"
+                );
+            }
+            other => panic!("Expected an Invalid error; got {other:?}"),
+        }
+    }
+
     #[test]
     fn demo() {
         let b: ProxyConfigBuilder = toml::de::from_str(
@@ -9,6 +9,7 @@ use futures::{
 use oneshot_fused_workaround as oneshot;
 use safelog::sensitive as sv;
 use std::io::{Error as IoError, Result as IoResult};
+use std::net::{IpAddr, SocketAddr};
 use tor_cell::relaycell::msg as relaymsg;
 use tor_error::{debug_report, ErrorKind, HasKind};
 use tor_hsservice::{HsNickname, RendRequest, StreamRequest};
@@ -131,6 +132,7 @@ impl OnionServiceReverseProxy {
             };
 
             let action = self.choose_action(stream_request.request());
+            let source = self.forward_source_address();
             let a_clone = action.clone();
             let rt_clone = runtime.clone();
             let nn_clone = Arc::clone(&nickname);
@@ -139,7 +141,8 @@ impl OnionServiceReverseProxy {
             runtime
                 .spawn(async move {
                     let outcome =
-                        run_action(rt_clone, nn_clone.as_ref(), action, stream_request).await;
+                        run_action(rt_clone, nn_clone.as_ref(), action, source, stream_request)
+                            .await;
 
                     log_ratelim!(
                         "Performing action on {}", nn_clone;
@@ -178,13 +181,26 @@ impl OnionServiceReverseProxy {
             // The default action is "destroy the circuit."
             .unwrap_or(ProxyAction::DestroyCircuit)
     }
+
+    /// Return the local address that we should bind to when forwarding a stream,
+    /// based on our current configuration.
+    fn forward_source_address(&self) -> Option<IpAddr> {
+        self.state
+            .lock()
+            .expect("poisoned lock")
+            .config
+            .forward_source_address()
+    }
 }
 
 /// Take the configured action from `action` on the incoming request `request`.
+///
+/// If we forward the request and `source` is set, we make our outgoing connection from `source`.
 async fn run_action<R: Runtime>(
     runtime: R,
     nickname: &HsNickname,
     action: ProxyAction,
+    source: Option<IpAddr>,
     request: StreamRequest,
 ) -> Result<(), RequestFailed> {
     match action {
@@ -196,7 +212,12 @@ async fn run_action<R: Runtime>(
         ProxyAction::Forward(encap, target) => match (encap, target) {
             (Encapsulation::Simple, ref addr @ TargetAddr::Inet(a)) => {
                 let rt_clone = runtime.clone();
-                forward_connection(rt_clone, request, runtime.connect(&a), nickname, addr).await?;
+                let local = source.map(|ip| SocketAddr::new(ip, 0));
+                let connect = match &local {
+                    Some(local) => runtime.connect_from(local, &a),
+                    None => runtime.connect(&a),
+                };
+                forward_connection(rt_clone, request, connect, nickname, addr).await?;
             } /* TODO (#1246)
                 (Encapsulation::Simple, TargetAddr::Unix(_)) => {
                     // TODO: We need to implement unix connections.
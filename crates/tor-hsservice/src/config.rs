@@ -54,6 +54,16 @@ pub struct OnionServiceConfig {
     #[builder(default = "65535")]
     max_concurrent_streams_per_circuit: u32,
 
+    /// How many streams will we allow to be open at once for a single
+    /// rendezvous circuit, before we start rejecting new ones?
+    ///
+    /// Unlike `max_concurrent_streams_per_circuit`, which closes the whole circuit,
+    /// exceeding this limit only rejects the new stream, with an `END` message
+    /// whose reason is `RESOURCELIMIT`.
+    /// It therefore only has an effect if it is lower than `max_concurrent_streams_per_circuit`.
+    #[builder(default = "65535")]
+    max_streams_per_rend_circuit: u32,
+
     /// If true, we will require proof-of-work when we're under heavy load.
     // TODO POW: If this is set to true but the pow feature is disabled we should error.
     #[builder(default = "false")]
@@ -69,6 +79,11 @@ pub struct OnionServiceConfig {
     #[deftly(publisher_view)]
     #[getter(as_mut)]
     pub(crate) restricted_discovery: RestrictedDiscoveryConfig,
+
+    /// Configure how we choose relays to use as introduction points.
+    #[builder(sub_builder)]
+    #[builder_field_attr(serde(default))]
+    pub(crate) intro_point_selection: IptSelectionConfig,
     // TODO(#727): add support for single onion services
     //
     // TODO: Perhaps this belongs at a higher level.  Perhaps we don't need it
@@ -223,12 +238,17 @@ impl OnionServiceConfig {
 
             // We extract this on every introduction request.
             max_concurrent_streams_per_circuit: simply_update,
+            max_streams_per_rend_circuit: simply_update,
 
             // The descriptor publisher responds by generating and publishing a new descriptor.
             restricted_discovery: simply_update,
 
             // TODO POW: Verify that simply_update has correct behaviour here.
             enable_pow: simply_update,
+
+            // IPT manager consults this whenever it chooses a new IPT relay.
+            // Existing IPTs are kept.
+            intro_point_selection: simply_update,
         }
 
         Ok(other)
@@ -247,9 +267,16 @@ impl OnionServiceConfig {
     }
 
     /// Return a RequestFilter based on this configuration.
-    pub(crate) fn filter_settings(&self) -> crate::rend_handshake::RequestFilter {
+    ///
+    /// Any streams rejected by the filter are counted in `rend_circuits`.
+    pub(crate) fn filter_settings(
+        &self,
+        rend_circuits: Arc<RendCircuitTracker>,
+    ) -> crate::rend_handshake::RequestFilter {
         crate::rend_handshake::RequestFilter {
             max_concurrent_streams: self.max_concurrent_streams_per_circuit as usize,
+            max_streams_before_reject: self.max_streams_per_rend_circuit as usize,
+            rend_circuits,
         }
     }
 }
@@ -294,6 +321,49 @@ impl OnionServiceConfigBuilder {
     }
 }
 
+/// Configuration for how an onion service chooses its introduction points.
+///
+/// Whatever is configured here, we never choose a relay that would be unsuitable
+/// as an introduction point, or that is already one of our introduction points.
+#[derive(Debug, Clone, Builder, Eq, PartialEq)]
+#[builder(build_fn(error = "ConfigBuildError", validate = "Self::validate"))]
+#[builder(derive(Serialize, Deserialize, Debug))]
+pub struct IptSelectionConfig {
+    /// If set, prefer relays that are among the fastest `N` percent
+    /// of the relays we could use, as measured by their consensus weight.
+    ///
+    /// Among those relays, we still choose according to consensus weight.
+    /// If none of them are usable, we fall back to choosing among all relays.
+    ///
+    /// If this is not set, we choose among all usable relays,
+    /// according to consensus weight.
+    #[builder(default)]
+    prefer_fastest_percent: Option<u8>,
+}
+tor_config::impl_standard_builder! { IptSelectionConfig }
+
+impl IptSelectionConfig {
+    /// Return the percentage of the fastest relays that we should prefer, if any.
+    pub(crate) fn prefer_fastest_percent(&self) -> Option<u8> {
+        self.prefer_fastest_percent
+    }
+}
+
+impl IptSelectionConfigBuilder {
+    /// Builder helper: check whether the options in this builder are consistent.
+    fn validate(&self) -> Result<(), ConfigBuildError> {
+        if let Some(Some(percent)) = self.prefer_fastest_percent {
+            if !(1..=100).contains(&percent) {
+                return Err(ConfigBuildError::Invalid {
+                    field: "prefer_fastest_percent".into(),
+                    problem: "out of range 1-100".into(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Configure a token-bucket style limit on some process.
 //
 // TODO: Someday we may wish to lower this; it will be used in far more places.
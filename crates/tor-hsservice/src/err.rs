@@ -328,3 +328,34 @@ impl HasKind for StateExpiryError {
         }
     }
 }
+
+/// An error which occurs while waiting for an onion service's descriptor to be published.
+///
+/// This is returned by
+/// [`RunningOnionService::wait_until_published`](crate::RunningOnionService::wait_until_published).
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum PublishWaitError {
+    /// The descriptor publisher has failed, and is not going to recover by itself.
+    ///
+    /// Contains the problem the publisher reported, if any.
+    #[error("Failed to publish the onion service descriptor")]
+    Failed(Option<crate::status::Problem>),
+
+    /// The onion service was shut down before its descriptor was published.
+    #[error("Onion service shut down before its descriptor was published")]
+    Shutdown,
+}
+
+impl HasKind for PublishWaitError {
+    fn kind(&self) -> ErrorKind {
+        use crate::status::Problem;
+        use tor_error::ErrorKind as EK;
+        use PublishWaitError as E;
+        match self {
+            E::Failed(Some(Problem::Runtime(e))) => e.kind(),
+            E::Failed(_) => EK::TorDirectoryError,
+            E::Shutdown => EK::OnionServiceNotRunning,
+        }
+    }
+}
@@ -2,6 +2,10 @@
 
 use crate::internal_prelude::*;
 
+use std::net::SocketAddr;
+
+use tor_cell::relaycell::msg::{End, EndReason};
+
 /// Consume a stream of [`RendRequest`], accepting them all, and produce a
 /// stream of [`StreamRequest`].
 ///
@@ -24,3 +28,146 @@ where
             .flatten_stream()
     })
 }
+
+/// Consume a stream of [`StreamRequest`], and route each one to a local address
+/// according to the virtual port it asks for.
+///
+/// `ports` maps each onion service virtual port to the local address
+/// that streams for that port should be connected to,
+/// much like C Tor's `HiddenServicePort` option.
+///
+/// Each request for a port in `ports` is yielded along with its target address;
+/// it is up to the caller to connect to that address and
+/// [`accept`](StreamRequest::accept) the request.
+/// Requests for any other port, and requests that are not `BEGIN` requests,
+/// are rejected with an `END` message with reason `DONE`.
+pub fn route_stream_requests_by_port<S>(
+    stream_requests: S,
+    ports: HashMap<u16, SocketAddr>,
+) -> impl Stream<Item = (StreamRequest, SocketAddr)>
+where
+    S: Stream<Item = StreamRequest>,
+{
+    route_by_port(stream_requests, ports)
+}
+
+/// The parts of a [`StreamRequest`] that [`route_by_port`] needs.
+trait PortRoutable {
+    /// Return the virtual port this request is for, if it is a `BEGIN` request.
+    fn port(&self) -> Option<u16>;
+
+    /// Reject this request with `end_message`.
+    async fn reject(self, end_message: End) -> Result<(), ClientError>;
+}
+
+impl PortRoutable for StreamRequest {
+    fn port(&self) -> Option<u16> {
+        StreamRequest::port(self)
+    }
+
+    async fn reject(self, end_message: End) -> Result<(), ClientError> {
+        StreamRequest::reject(self, end_message).await
+    }
+}
+
+/// Implementation of [`route_stream_requests_by_port`], generic over the request type.
+fn route_by_port<S, R>(
+    requests: S,
+    ports: HashMap<u16, SocketAddr>,
+) -> impl Stream<Item = (R, SocketAddr)>
+where
+    S: Stream<Item = R>,
+    R: PortRoutable,
+{
+    let ports = Arc::new(ports);
+    requests.filter_map(move |request| {
+        let ports = Arc::clone(&ports);
+        async move {
+            let port = request.port();
+            if let Some(addr) = port.and_then(|port| ports.get(&port).copied()) {
+                return Some((request, addr));
+            }
+
+            debug!("Rejecting stream request for unmapped port {:?}", port);
+            let end = End::new_with_reason(EndReason::DONE);
+            if let Err(e) = request.reject(end).await {
+                debug_report!(e, "Problem while rejecting stream request");
+            }
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    use tor_rtmock::MockRuntime;
+
+    /// A stand-in for a [`StreamRequest`], which records whether it was rejected.
+    #[derive(Debug)]
+    struct FakeRequest {
+        /// The port requested, if this is a `BEGIN` request.
+        port: Option<u16>,
+        /// The ports of requests that were rejected.
+        rejected: Arc<Mutex<Vec<Option<u16>>>>,
+    }
+
+    impl PortRoutable for FakeRequest {
+        fn port(&self) -> Option<u16> {
+            self.port
+        }
+
+        async fn reject(self, end_message: End) -> Result<(), ClientError> {
+            assert_eq!(end_message.reason(), EndReason::DONE);
+            self.rejected.lock().unwrap().push(self.port);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn route_two_ports() {
+        MockRuntime::test_with_various(|_runtime| async move {
+            let web_addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+            let ssh_addr: SocketAddr = "127.0.0.1:2222".parse().unwrap();
+
+            let ports = [(80, web_addr), (22, ssh_addr)].into_iter().collect();
+
+            let rejected = Arc::new(Mutex::new(vec![]));
+            let requests = [Some(80), Some(443), Some(22), None, Some(80)]
+                .into_iter()
+                .map(|port| FakeRequest {
+                    port,
+                    rejected: Arc::clone(&rejected),
+                });
+
+            let routed: Vec<_> = route_by_port(futures::stream::iter(requests), ports)
+                .map(|(req, addr)| (req.port, addr))
+                .collect()
+                .await;
+
+            assert_eq!(
+                routed,
+                vec![
+                    (Some(80), web_addr),
+                    (Some(22), ssh_addr),
+                    (Some(80), web_addr),
+                ]
+            );
+            assert_eq!(*rejected.lock().unwrap(), vec![Some(443), None]);
+        });
+    }
+}
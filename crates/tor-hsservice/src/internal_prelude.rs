@@ -130,6 +130,7 @@ pub(crate) use {
     crate::keys::expire_publisher_keys,
     crate::keys::{IptKeyRole, IptKeySpecifier, IptKeySpecifierPattern},
     crate::publish::Publisher,
+    crate::rend_tracker::RendCircuitTracker,
     crate::replay::ReplayError,
     crate::replay::ReplayLog,
     crate::status::PublisherStatusSender,
@@ -153,6 +154,6 @@ pub(crate) use {
         HsIdPublicKeySpecifier,
     },
     crate::{DescUploadError, IptError},
-    crate::{FatalError, RendRequest},
+    crate::{FatalError, PublishWaitError, RendRequest},
     ipt_establish::{IptEstablisher, IptParameters, IptStatus, IptStatusStatus, IptWantsToRetire},
 };
@@ -255,6 +255,12 @@ pub(crate) struct IptParameters {
     /// form a shared key set of keys with the client, and decrypt information
     /// about the client's chosen rendezvous point and extensions.
     pub(crate) k_ntor: Arc<HsSvcNtorKeypair>,
+    /// The tracker for the rendezvous circuits of this onion service.
+    ///
+    /// We record every rendezvous circuit we open in response to a request
+    /// from this introduction point.
+    #[educe(Debug(ignore))]
+    pub(crate) rend_circuits: Arc<RendCircuitTracker>,
 }
 
 impl IptEstablisher {
@@ -291,6 +297,7 @@ impl IptEstablisher {
             k_ntor,
             accepting_requests,
             replay_log,
+            rend_circuits,
         } = params;
         let config = Arc::clone(&config_rx.borrow());
         let nickname = config.nickname().clone();
@@ -309,9 +316,10 @@ impl IptEstablisher {
             keymgr: Arc::clone(keymgr),
             kp_hss_ntor: Arc::clone(&k_ntor),
             kp_hs_ipt_sid: k_sid.as_ref().as_ref().verifying_key().into(),
-            filter: config.filter_settings(),
+            filter: config.filter_settings(Arc::clone(&rend_circuits)),
             netdir_provider: netdir_provider.clone(),
             circ_pool: pool.clone(),
+            rend_circuits,
         });
 
         let reactor = Reactor {
@@ -991,3 +999,70 @@ impl tor_proto::circuit::MsgHandler for IptMsgHandler {
 #[derive(Clone, Debug, thiserror::Error)]
 #[error("Could not send request; stream was full.")]
 struct StreamWasFull {}
+
+/// Test-only fault injection for introduction point establishment.
+///
+/// This lets a test script what happens to an (otherwise mocked) IPT establisher,
+/// such as "fails to establish twice, then succeeds".
+/// Each step updates an [`IptStatus`] using the same transitions as the real
+/// establisher's reactor, so that tests of the IPT manager see realistic statuses.
+#[cfg(test)]
+pub(crate) mod fault_injection {
+    use super::*;
+
+    /// Something that can happen to an IPT establisher.
+    #[derive(Clone, Debug)]
+    pub(crate) enum IptFault {
+        /// An attempt to establish the IPT fails with this error.
+        FailEstablish(IptEstablisherError),
+        /// An attempt to establish the IPT succeeds.
+        Establish(GoodIptDetails),
+        /// The circuit to the established IPT closes, and we start trying again.
+        Drop,
+    }
+
+    /// A script of [`IptFault`]s to apply, in order, to a single IPT establisher.
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct IptFaultPolicy {
+        /// The steps that have not yet been applied.
+        steps: VecDeque<IptFault>,
+    }
+
+    impl IptFaultPolicy {
+        /// Add `n` attempts to establish the IPT, each failing with `error`.
+        pub(crate) fn fail_establish(mut self, n: usize, error: IptEstablisherError) -> Self {
+            self.steps
+                .extend(std::iter::repeat(IptFault::FailEstablish(error)).take(n));
+            self
+        }
+
+        /// Add a successful attempt to establish the IPT.
+        pub(crate) fn establish(mut self, details: GoodIptDetails) -> Self {
+            self.steps.push_back(IptFault::Establish(details));
+            self
+        }
+
+        /// Add the loss of an established IPT.
+        pub(crate) fn drop_established(mut self) -> Self {
+            self.steps.push_back(IptFault::Drop);
+            self
+        }
+
+        /// Apply the next step of this policy to `status`, as of `now`.
+        ///
+        /// Returns `false` if there were no steps left.
+        pub(crate) fn apply_next(&mut self, status: &mut IptStatus, now: Instant) -> bool {
+            let Some(step) = self.steps.pop_front() else {
+                return false;
+            };
+            status.note_attempt();
+            match step {
+                IptFault::FailEstablish(error) => status.note_error(&error, now),
+                IptFault::Establish(details) => status.note_open(details),
+                // The reactor notes a new attempt as soon as the session closes.
+                IptFault::Drop => {}
+            }
+            true
+        }
+    }
+}
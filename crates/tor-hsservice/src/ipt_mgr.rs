@@ -7,12 +7,13 @@
 
 use crate::internal_prelude::*;
 
+use tor_netdir::{NetDir, RelayWeight, WeightRole};
 use tor_relay_selection::{RelayExclusion, RelaySelector, RelayUsage};
 use IptStatusStatus as ISS;
 use TrackedStatus as TS;
 
 mod persist;
-pub(crate) use persist::IptStorageHandle;
+pub(crate) use persist::{load_discarding_corrupt, IptStorageHandle};
 
 pub use crate::ipt_establish::IptError;
 
@@ -81,6 +82,12 @@ pub(crate) struct Immutable<R> {
     /// A sender for updating the status of the onion service.
     #[educe(Debug(ignore))]
     status_tx: IptMgrStatusSender,
+
+    /// Tracker for the rendezvous circuits of this onion service.
+    ///
+    /// Passed to IPT Establishers we create
+    #[educe(Debug(ignore))]
+    rend_circuits: Arc<RendCircuitTracker>,
 }
 
 /// State of an IPT Manager
@@ -477,6 +484,7 @@ impl Ipt {
             k_sid: k_sid.clone(),
             k_ntor: Arc::clone(&k_hss_ntor),
             accepting_requests: ipt_establish::RequestDisposition::NotAdvertised,
+            rend_circuits: Arc::clone(&imm.rend_circuits),
         };
         let (establisher, mut watch_rx) = mockable.make_new_ipt(imm, params)?;
 
@@ -617,6 +625,7 @@ impl<R: Runtime, M: Mockable<R>> IptManager<R, M> {
         mockable: M,
         keymgr: Arc<KeyMgr>,
         status_tx: IptMgrStatusSender,
+        rend_circuits: Arc<RendCircuitTracker>,
     ) -> Result<Self, StartupError> {
         let irelays = vec![]; // See TODO near persist::load call, in launch_background_tasks
 
@@ -643,6 +652,7 @@ impl<R: Runtime, M: Mockable<R>> IptManager<R, M> {
             keymgr,
             replay_log_dir,
             status_tx,
+            rend_circuits,
         };
         let current_config = config.borrow().clone();
 
@@ -1590,6 +1600,25 @@ impl<R: Runtime, M: Mockable<R>> IptManager<R, M> {
     }
 }
 
+/// Return the smallest weight that a relay usable according to `selector` can have,
+/// and still be among the fastest `percent` percent of such relays.
+///
+/// Returns `None` if there are no such relays.
+fn fastest_relays_weight_threshold(
+    netdir: &NetDir,
+    selector: &RelaySelector<'_>,
+    percent: u8,
+) -> Option<RelayWeight> {
+    let mut weights = netdir
+        .relays()
+        .filter(|r| selector.permits_relay(r))
+        .map(|r| netdir.relay_weight(&r, WeightRole::HsIntro))
+        .collect_vec();
+    weights.sort_unstable_by(|a, b| b.cmp(a));
+    let n_preferred = (weights.len() * usize::from(percent)).div_ceil(100).max(1);
+    weights.get(n_preferred - 1).copied()
+}
+
 impl<R: Runtime, M: Mockable<R>> State<R, M> {
     /// Find the `Ipt` with persistent local id `lid`
     fn ipt_by_lid_mut(&mut self, needle: IptLocalId) -> Option<&mut Ipt> {
@@ -1619,10 +1648,24 @@ impl<R: Runtime, M: Mockable<R>> State<R, M> {
                 RelayUsage::new_intro_point(),
                 RelayExclusion::exclude_identities(exclude_ids),
             );
-            selector
-                .select_relay(&mut rng, &netdir)
-                .0 // TODO: Someday we might want to report why we rejected everything on failure.
-                .ok_or(ChooseIptError::TooFewUsableRelays)?
+            let preferred = self
+                .current_config
+                .intro_point_selection
+                .prefer_fastest_percent()
+                .and_then(|percent| {
+                    let threshold = fastest_relays_weight_threshold(&netdir, &selector, percent)?;
+                    netdir.pick_relay(&mut rng, WeightRole::HsIntro, |r| {
+                        selector.permits_relay(r)
+                            && netdir.relay_weight(r, WeightRole::HsIntro) >= threshold
+                    })
+                });
+            match preferred {
+                Some(relay) => relay,
+                None => selector
+                    .select_relay(&mut rng, &netdir)
+                    .0 // TODO: Someday we might want to report why we rejected everything on failure.
+                    .ok_or(ChooseIptError::TooFewUsableRelays)?,
+            }
         };
 
         let lifetime_low = netdir
@@ -1796,12 +1839,13 @@ mod test {
     use super::*;
 
     use crate::config::OnionServiceConfigBuilder;
-    use crate::ipt_establish::GoodIptDetails;
+    use crate::ipt_establish::fault_injection::IptFaultPolicy;
+    use crate::ipt_establish::{GoodIptDetails, IptEstablisherError};
     use crate::status::{OnionServiceStatus, StatusSender};
     use crate::test::{create_keymgr, create_storage_handles_from_state_dir};
     use rand::SeedableRng as _;
     use slotmap_careful::DenseSlotMap;
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, BTreeSet};
     use std::sync::Mutex;
     use test_temp_dir::{test_temp_dir, TestTempDir};
     use tor_basic_utils::test_rng::TestingRng;
@@ -1937,7 +1981,8 @@ mod test {
 
             let keymgr = create_keymgr(temp_dir);
             let keymgr = keymgr.into_untracked(); // OK because our return value captures 'd
-            let status_tx = StatusSender::new(OnionServiceStatus::new_shutdown()).into();
+            let status_tx = StatusSender::new(OnionServiceStatus::new_shutdown());
+            let rend_circuits = Arc::new(RendCircuitTracker::new(status_tx.clone()));
             let mgr = IptManager::new(
                 runtime.clone(),
                 Arc::new(dir),
@@ -1948,7 +1993,8 @@ mod test {
                 &state_handle,
                 mocks,
                 keymgr,
-                status_tx,
+                status_tx.into(),
+                rend_circuits,
             )
             .unwrap();
 
@@ -1970,6 +2016,14 @@ mod test {
             assert_eq!(runtime.mock_task().n_tasks(), 1); // just us
         }
 
+        /// Apply the next step of `policy` to the `nth` IPT establisher.
+        fn apply_fault(&self, nth: usize, policy: &mut IptFaultPolicy, now: Instant) -> bool {
+            let mut estabs = self.estabs.lock().unwrap();
+            let estab = estabs.values_mut().nth(nth).unwrap();
+            let mut status = estab.st_tx.borrow_mut();
+            policy.apply_next(&mut status, now)
+        }
+
         fn estabs_inventory(&self) -> impl Eq + Debug + 'static {
             let estabs = self.estabs.lock().unwrap();
             let estabs = estabs
@@ -1994,6 +2048,55 @@ mod test {
         }
     }
 
+    #[test]
+    #[traced_test]
+    fn test_mgr_ipt_faults() {
+        MockRuntime::test_with_various(|runtime| async move {
+            let temp_dir = test_temp_dir!();
+
+            let m = MockedIptManager::startup(runtime.clone(), &temp_dir, 0, usize::MAX);
+            runtime.progress_until_stalled().await;
+
+            let lids = || {
+                m.estabs
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|e| e.params.lid)
+                    .collect::<BTreeSet<_>>()
+            };
+            let initial_lids = lids();
+            assert_eq!(initial_lids.len(), 3);
+
+            let good = GoodIptDetails {
+                link_specifiers: vec![],
+                ipt_kp_ntor: [0x55; 32].into(),
+            };
+
+            // IPT #2 fails to establish twice, then succeeds, then drops.
+            // None of this is the IPT's fault, so it shouldn't be replaced.
+            let mut policy = IptFaultPolicy::default()
+                .fail_establish(2, IptEstablisherError::EstablishTimeout)
+                .establish(good.clone())
+                .drop_established();
+            while m.apply_fault(1, &mut policy, runtime.now()) {
+                runtime.advance_by(ms(500)).await;
+                runtime.progress_until_stalled().await;
+                assert_eq!(lids(), initial_lids);
+            }
+
+            // IPT #1 turns out to be faulty, so we should get a new one.
+            let mut policy =
+                IptFaultPolicy::default().fail_establish(1, IptError::IntroPointNotListed.into());
+            assert!(m.apply_fault(0, &mut policy, runtime.now()));
+            runtime.advance_by(ms(500)).await;
+            runtime.progress_until_stalled().await;
+            assert!(!lids().is_subset(&initial_lids));
+
+            m.shutdown_check_no_tasks(&runtime).await;
+        });
+    }
+
     #[test]
     #[traced_test]
     fn test_mgr_lifecycle() {
@@ -2127,4 +2230,76 @@ mod test {
             m.shutdown_check_no_tasks(&runtime).await;
         });
     }
+
+    #[test]
+    #[traced_test]
+    fn test_mgr_corrupt_state() {
+        MockRuntime::test_with_various(|runtime| async move {
+            let temp_dir = test_temp_dir!();
+
+            let lids = |m: &MockedIptManager| {
+                m.estabs
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|ess| ess.params.lid)
+                    .collect::<BTreeSet<_>>()
+            };
+
+            let m = MockedIptManager::startup(runtime.clone(), &temp_dir, 0, 1);
+            runtime.progress_until_stalled().await;
+            let old_lids = lids(&m);
+            assert_eq!(old_lids.len(), 3);
+            m.shutdown_check_no_tasks(&runtime).await;
+
+            // Scribble over the persistent records of our IPTs and of what we published
+            let mut n_corrupted = 0;
+            for ent in WalkDir::new(temp_dir.as_path_untracked()) {
+                let path = ent.unwrap().into_path();
+                let name = path.file_name().unwrap().to_str().unwrap();
+                if ["ipts.json", "iptpub.json"].contains(&name) {
+                    std::fs::write(&path, "this is not json").unwrap();
+                    n_corrupted += 1;
+                }
+            }
+            assert_eq!(n_corrupted, 2);
+
+            // ---------- restart! ----------
+            info!("*** Restarting with corrupt state ***");
+
+            // We start afresh, with entirely new IPTs
+            let m = MockedIptManager::startup(runtime.clone(), &temp_dir, 1, 1);
+            runtime.progress_until_stalled().await;
+            let new_lids = lids(&m);
+            assert_eq!(new_lids.len(), 3);
+            assert!(new_lids.is_disjoint(&old_lids));
+            assert!(logs_contain("Discarding corrupt persistent state"));
+
+            m.shutdown_check_no_tasks(&runtime).await;
+        });
+    }
+
+    #[test]
+    fn fastest_relays_threshold() {
+        let netdir = tor_netdir::testnet::construct_netdir()
+            .unwrap_if_sufficient()
+            .unwrap();
+        let selector = RelaySelector::new(
+            RelayUsage::new_intro_point(),
+            RelayExclusion::no_relays_excluded(),
+        );
+        let weights = netdir
+            .relays()
+            .filter(|r| selector.permits_relay(r))
+            .map(|r| netdir.relay_weight(&r, WeightRole::HsIntro))
+            .collect_vec();
+        let threshold = |percent| fastest_relays_weight_threshold(&netdir, &selector, percent);
+
+        assert_eq!(threshold(100), weights.iter().min().copied());
+        assert_eq!(threshold(1), weights.iter().max().copied());
+
+        let half = threshold(50).unwrap();
+        let n_half = weights.iter().filter(|w| **w >= half).count();
+        assert!(n_half * 2 >= weights.len());
+    }
 }
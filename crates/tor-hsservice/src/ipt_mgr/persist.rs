@@ -87,6 +87,30 @@ pub(super) fn store<R: Runtime, M: Mockable<R>>(
 
 //---------- Loading ----------
 
+/// Load a record from `storage`, treating a corrupt record as if it were absent
+///
+/// If the stored data can't be deserialized, we log a warning and return `Ok(None)`,
+/// so that the caller starts afresh; the bad record will be overwritten next time we store.
+/// Other errors (eg, being unable to read the file) are still fatal.
+///
+/// `what` describes the record, for the log message.
+pub(crate) fn load_discarding_corrupt<T>(
+    storage: &tor_persist::state_dir::StorageHandle<T>,
+    what: &str,
+) -> Result<Option<T>, StartupError>
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    match storage.load() {
+        Ok(on_disk) => Ok(on_disk),
+        Err(e) if matches!(e.source(), tor_persist::ErrorSource::Serde(_)) => {
+            warn_report!(e, "Discarding corrupt persistent state for {}", what);
+            Ok(None)
+        }
+        Err(e) => Err(StartupError::LoadState(e)),
+    }
+}
+
 /// Load the IPTs from the persistent state
 ///
 /// `publish_set` should already have been loaded from its persistent state.
@@ -97,7 +121,8 @@ pub(super) fn load<R: Runtime, M: Mockable<R>>(
     mockable: &mut M,
     publish_set: &PublishIptSet,
 ) -> Result<Vec<IptRelay>, StartupError> {
-    let on_disk = storage.load().map_err(StartupError::LoadState)?;
+    // If our record is corrupt, we select new IPTs, as if we had never run before.
+    let on_disk = load_discarding_corrupt(storage, "introduction points")?;
 
     let Some(on_disk) = on_disk else {
         return Ok(vec![]);
@@ -2,6 +2,8 @@
 
 use crate::internal_prelude::*;
 
+use crate::ipt_mgr::load_discarding_corrupt;
+
 /// Handle for a suitable persistent storage manager
 pub(crate) type IptSetStorageHandle = tor_persist::state_dir::StorageHandle<StateRecord>;
 
@@ -491,7 +493,7 @@ impl PublishIptSet {
         storage: IptSetStorageHandle,
         runtime: &impl SleepProvider,
     ) -> Result<PublishIptSet, StartupError> {
-        let on_disk = storage.load().map_err(StartupError::LoadState)?;
+        let on_disk = load_discarding_corrupt(&storage, "published introduction points")?;
         let last_descriptor_expiry_including_slop = on_disk
             .map(|record| {
                 // Throughout, we use exhaustive struct patterns on the data we got from disk,
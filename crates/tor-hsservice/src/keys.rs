@@ -9,7 +9,9 @@
 //! For TP-based keys, that involves deriving [`HsTimePeriodKeySpecifier`]
 //! and adding a call to `remove_if_expired!` in [`expire_publisher_keys`].
 
+use tor_hscrypto::pk::HsId;
 use tor_keymgr::{CTorPath, CTorServicePath};
+use tor_llcrypto::pk::keymanip::BlindingError;
 
 use crate::internal_prelude::*;
 
@@ -221,6 +223,19 @@ pub(crate) fn expire_publisher_keys(
     Ok(())
 }
 
+/// Compute the blinded identity that clients use to find the descriptor of the
+/// service whose identity is `hsid`, during the time period `period`.
+///
+/// Unlike the blinded keys we store in the keystore, this works for any time period,
+/// not just the ones we are currently publishing descriptors for.
+/// It can be used to check which HsDirs are responsible for our descriptor
+/// in a given time period.
+pub fn blinded_id_for_period(hsid: HsId, period: TimePeriod) -> Result<HsBlindId, BlindingError> {
+    let hsid_key = HsIdKey::try_from(hsid).map_err(|_| BlindingError::BadPubkey)?;
+    let (blinded_key, _subcredential) = hsid_key.compute_blinded_key(period)?;
+    Ok(blinded_key.id())
+}
+
 #[cfg(test)]
 mod test {
     // @@ begin test lint list maintained by maint/add_warning @@
@@ -294,4 +309,25 @@ mod test {
             &format!("hss/shallot/ipts/k_sid+{lid_s}"),
         );
     }
+
+    #[test]
+    fn blinded_id_for_any_period() {
+        use tor_basic_utils::test_rng::testing_rng;
+        use tor_llcrypto::pk::ed25519;
+
+        let keypair = ed25519::Keypair::generate(&mut testing_rng());
+        let hsid_key = HsIdKey::from(keypair.verifying_key());
+        let hsid = hsid_key.id();
+
+        let period = TimePeriod::from_parts(1440, 19000, 43200);
+        let next = period.next().unwrap();
+
+        let (expected, _) = hsid_key.compute_blinded_key(period).unwrap();
+        assert_eq!(blinded_id_for_period(hsid, period).unwrap(), expected.id());
+
+        let (expected_next, _) = hsid_key.compute_blinded_key(next).unwrap();
+        let blinded_next = blinded_id_for_period(hsid, next).unwrap();
+        assert_eq!(blinded_next, expected_next.id());
+        assert_ne!(blinded_next, expected.id());
+    }
 }
@@ -64,10 +64,12 @@ mod ipt_set;
 mod keys;
 mod publish;
 mod rend_handshake;
+mod rend_tracker;
 mod replay;
 mod req;
 pub mod status;
 mod timeout_track;
+mod vanity;
 
 // rustdoc doctests can't use crate-public APIs, so are broken if provided for private items.
 // So we export the whole module again under this name.
@@ -93,18 +95,23 @@ use internal_prelude::*;
 
 pub use anon_level::Anonymity;
 pub use config::OnionServiceConfig;
-pub use err::{ClientError, EstablishSessionError, FatalError, IntroRequestError, StartupError};
+pub use err::{
+    ClientError, EstablishSessionError, FatalError, IntroRequestError, PublishWaitError,
+    StartupError,
+};
 pub use ipt_mgr::IptError;
 pub use keys::{
-    BlindIdKeypairSpecifier, BlindIdPublicKeySpecifier, DescSigningKeypairSpecifier,
-    HsIdKeypairSpecifier, HsIdPublicKeySpecifier,
+    blinded_id_for_period, BlindIdKeypairSpecifier, BlindIdPublicKeySpecifier,
+    DescSigningKeypairSpecifier, HsIdKeypairSpecifier, HsIdPublicKeySpecifier,
 };
 pub use publish::UploadError as DescUploadError;
 pub use req::{RendRequest, StreamRequest};
 pub use tor_hscrypto::pk::HsId;
+pub use tor_keymgr::{KeystoreId, KeystoreSelector};
 pub use tor_persist::hsnickname::{HsNickname, InvalidNickname};
+pub use vanity::{find_vanity_hsid, VanityPrefix, VanityPrefixError};
 
-pub use helpers::handle_rend_requests;
+pub use helpers::{handle_rend_requests, route_stream_requests_by_port};
 
 //---------- top-level service implementation (types and methods) ----------
 
@@ -131,6 +138,8 @@ pub struct RunningOnionService {
     nickname: HsNickname,
     /// The key manager, used for accessing the underlying key stores.
     keymgr: Arc<KeyMgr>,
+    /// The rendezvous circuits this service is currently serving.
+    rend_circuits: Arc<RendCircuitTracker>,
 }
 
 /// Implementation details for an onion service.
@@ -303,6 +312,8 @@ impl OnionService {
 
         let status_tx = StatusSender::new(OnionServiceStatus::new_shutdown());
 
+        let rend_circuits = Arc::new(RendCircuitTracker::new(status_tx.clone()));
+
         let ipt_mgr = IptManager::new(
             runtime.clone(),
             netdir_provider.clone(),
@@ -316,6 +327,7 @@ impl OnionService {
             },
             keymgr.clone(),
             status_tx.clone().into(),
+            Arc::clone(&rend_circuits),
         )?;
 
         let publisher: Publisher<R, publish::Real<R>> = Publisher::new(
@@ -333,6 +345,7 @@ impl OnionService {
         let svc = Arc::new(RunningOnionService {
             nickname,
             keymgr,
+            rend_circuits,
             inner: Mutex::new(SvcInner {
                 config_tx,
                 _shutdown_tx: shutdown_tx,
@@ -359,10 +372,56 @@ impl OnionService {
     ///
     /// Returns `None` if the HsId of the service could not be found in any of the configured
     /// keystores.
+    /// To tell whether that's because the key is missing or because it couldn't be read,
+    /// use [`try_onion_name`](OnionService::try_onion_name).
     pub fn onion_name(&self) -> Option<HsId> {
         onion_name(&self.keymgr, &self.config.nickname)
     }
 
+    /// Return the onion address of this service,
+    /// reporting any errors encountered while reading its identity key.
+    ///
+    /// Unlike [`onion_name`](OnionService::onion_name),
+    /// this distinguishes an identity key that doesn't exist yet,
+    /// for which it returns `Ok(None)`,
+    /// from one that exists but couldn't be read,
+    /// for which it returns an error.
+    ///
+    /// Like `onion_name`, this searches all of the configured keystores.
+    /// To search only one of them,
+    /// use [`onion_name_from_keystore`](OnionService::onion_name_from_keystore).
+    pub fn try_onion_name(&self) -> Result<Option<HsId>, StartupError> {
+        try_onion_name(&self.keymgr, &self.config.nickname)
+    }
+
+    /// Return the onion address of this service,
+    /// as derived from the identity key stored in the keystore specified by `selector`.
+    ///
+    /// Unlike [`onion_name`](OnionService::onion_name),
+    /// this only searches the selected keystore,
+    /// and reports any errors encountered while reading the key.
+    ///
+    /// Returns `Ok(None)` if the selected keystore does not contain the identity key
+    /// of this service.
+    ///
+    /// Returns an error if the selected keystore is not one of the configured keystores.
+    pub fn onion_name_from_keystore(
+        &self,
+        selector: KeystoreSelector,
+    ) -> Result<Option<HsId>, StartupError> {
+        let hsid_spec = HsIdPublicKeySpecifier::new(self.config.nickname.clone());
+
+        let hsid = self
+            .keymgr
+            .get_from::<HsIdKey>(&hsid_spec, selector)
+            .map_err(|cause| StartupError::Keystore {
+                action: "read",
+                cause,
+            })?;
+
+        Ok(hsid.map(|hsid| hsid.id()))
+    }
+
     /// Generate an identity key (KP_hs_id) for this service.
     ///
     /// If the keystore specified by `selector` contains an entry for the identity key
@@ -389,6 +448,115 @@ impl OnionService {
 
         maybe_generate_hsid(&self.keymgr, &self.config.nickname, offline_hsid, selector)
     }
+
+    /// Generate a new identity key (KP_hs_id) for this service,
+    /// replacing any identity key that the keystore specified by `selector` already contains.
+    ///
+    /// **Warning:** this changes the onion address of the service,
+    /// so clients that only know its old address won't be able to reach it.
+    /// Unless you are sure that you want a new address,
+    /// use [`generate_identity_key`](OnionService::generate_identity_key) instead.
+    ///
+    /// Don't call this function while the service is running.
+    pub fn regenerate_identity_key(
+        &self,
+        selector: KeystoreSelector,
+    ) -> Result<HsId, StartupError> {
+        let nickname = &self.config.nickname;
+        self.remove_hsid_public_key(selector)?;
+
+        let hsid_spec = HsIdKeypairSpecifier::new(nickname.clone());
+        let kp = self
+            .keymgr
+            .generate::<HsIdKeypair>(
+                &hsid_spec,
+                selector,
+                &mut rand::thread_rng(),
+                true, /* overwrite */
+            )
+            .map_err(|cause| StartupError::Keystore {
+                action: "generate",
+                cause,
+            })?;
+
+        let hsid = HsIdKey::from(&kp).id();
+        info!(
+            "Generated a new identity for service {nickname}: {}",
+            sensitive(hsid)
+        );
+
+        Ok(hsid)
+    }
+
+    /// Search for an identity key (KP_hs_id) whose onion address starts with `prefix`,
+    /// and store it in the keystore specified by `selector`.
+    ///
+    /// We give up after generating `max_attempts` keys;
+    /// in that case, we return `Ok(None)`, and don't change the keystore.
+    /// See [`find_vanity_hsid`] for details,
+    /// and for a warning about how long this can take.
+    ///
+    /// If the keystore already contains an identity key for this service,
+    /// we replace it if `overwrite` is true, and return an error otherwise.
+    /// Replacing the identity key changes the onion address of the service,
+    /// so don't do it unless you are sure that you want a new address.
+    ///
+    /// Don't call this function while the service is running.
+    pub fn generate_vanity_identity_key(
+        &self,
+        prefix: &VanityPrefix,
+        max_attempts: u64,
+        selector: KeystoreSelector,
+        overwrite: bool,
+    ) -> Result<Option<HsId>, StartupError> {
+        let nickname = &self.config.nickname;
+        // Check this before the search, which can take a long time.
+        if !overwrite && self.onion_name_from_keystore(selector)?.is_some() {
+            return Err(StartupError::Keystore {
+                action: "write",
+                cause: tor_keymgr::Error::KeyAlreadyExists,
+            });
+        }
+
+        let Some((keypair, hsid)) = find_vanity_hsid(prefix, max_attempts, &mut rand::thread_rng())
+        else {
+            return Ok(None);
+        };
+
+        let hsid_spec = HsIdKeypairSpecifier::new(nickname.clone());
+        self.keymgr
+            .insert(keypair, &hsid_spec, selector, overwrite)
+            .map_err(|cause| StartupError::Keystore {
+                action: "write",
+                cause,
+            })?;
+        // Only remove the old public key once the new keypair is stored,
+        // so that a failed insert doesn't leave the service without an identity.
+        if overwrite {
+            self.remove_hsid_public_key(selector)?;
+        }
+
+        info!(
+            "Generated a new identity for service {nickname}: {}",
+            sensitive(hsid)
+        );
+
+        Ok(Some(hsid))
+    }
+
+    /// Remove any identity public key that is stored separately from the identity keypair
+    /// in the keystore specified by `selector`,
+    /// so that it can't shadow a replacement keypair.
+    fn remove_hsid_public_key(&self, selector: KeystoreSelector) -> Result<(), StartupError> {
+        let pub_hsid_spec = HsIdPublicKeySpecifier::new(self.config.nickname.clone());
+        self.keymgr
+            .remove::<HsIdKey>(&pub_hsid_spec, selector)
+            .map_err(|cause| StartupError::Keystore {
+                action: "remove",
+                cause,
+            })?;
+        Ok(())
+    }
 }
 
 impl OnionServiceBuilder {
@@ -448,6 +616,41 @@ impl RunningOnionService {
             .subscribe()
     }
 
+    /// Wait until this onion service has published its descriptor.
+    ///
+    /// Resolves successfully once the descriptor has been uploaded to
+    /// HsDirs in both the current and the next time period's rings
+    /// (that is, once the service is believed to be reachable),
+    /// even if some of the other uploads failed.
+    ///
+    /// Returns an error if the descriptor publisher fails in a way
+    /// it won't recover from by itself, or if it shuts down.
+    /// Transient upload failures are retried, and do not cause an error;
+    /// if you don't want to wait indefinitely, wrap this in a timeout.
+    pub async fn wait_until_published(&self) -> Result<(), PublishWaitError> {
+        status::wait_until_published(self.status_events()).await
+    }
+
+    /// Return the number of rendezvous circuits this onion service is currently serving.
+    ///
+    /// A rendezvous circuit is counted from the moment a [`RendRequest`] is accepted,
+    /// until the circuit closes, or the [`StreamRequest`]s and streams using it are all dropped.
+    ///
+    /// This is cheap enough to call periodically, for example to monitor load.
+    pub fn n_rend_circuits(&self) -> usize {
+        self.rend_circuits.n_active()
+    }
+
+    /// Return the number of stream requests this onion service has rejected
+    /// because their rendezvous circuit already had
+    /// [`max_streams_per_rend_circuit`](OnionServiceConfigBuilder::max_streams_per_rend_circuit)
+    /// open streams.
+    ///
+    /// This is also reported in [`OnionServiceStatus::n_streams_rejected`].
+    pub fn n_streams_rejected(&self) -> u64 {
+        self.status().n_streams_rejected()
+    }
+
     /// Tell this onion service to begin running, and return a
     /// stream of rendezvous requests on the service.
     ///
@@ -571,12 +774,24 @@ fn maybe_generate_hsid(
 // method on an ArtiHss type, and make both OnionService and RunningOnionService deref to
 // ArtiHss.
 fn onion_name(keymgr: &KeyMgr, nickname: &HsNickname) -> Option<HsId> {
+    try_onion_name(keymgr, nickname).ok().flatten()
+}
+
+/// Return the onion address of the service `nickname`,
+/// or an error if we couldn't read its identity key.
+///
+/// Returns `Ok(None)` if none of the keystores in `keymgr` contain the identity key.
+fn try_onion_name(keymgr: &KeyMgr, nickname: &HsNickname) -> Result<Option<HsId>, StartupError> {
     let hsid_spec = HsIdPublicKeySpecifier::new(nickname.clone());
 
-    keymgr
+    let hsid = keymgr
         .get::<HsIdKey>(&hsid_spec)
-        .ok()?
-        .map(|hsid| hsid.id())
+        .map_err(|cause| StartupError::Keystore {
+            action: "read",
+            cause,
+        })?;
+
+    Ok(hsid.map(|hsid| hsid.id()))
 }
 
 #[cfg(test)]
@@ -791,11 +1006,6 @@ pub(crate) mod test {
 
         let (hsid_keypair, hsid_public) = create_hsid();
 
-        // Insert the hsid into the keystore
-        keymgr
-            .insert(hsid_keypair, &hsid_spec, KeystoreSelector::Primary, true)
-            .unwrap();
-
         let config = OnionServiceConfigBuilder::default()
             .nickname(nickname)
             .build()
@@ -814,8 +1024,43 @@ pub(crate) mod test {
             .build()
             .unwrap();
 
+        // There is no hsid yet, but that isn't an error.
+        assert!(service.onion_name().is_none());
+        assert!(service.try_onion_name().unwrap().is_none());
+
+        // Insert the hsid into the keystore
+        keymgr
+            .insert(hsid_keypair, &hsid_spec, KeystoreSelector::Primary, true)
+            .unwrap();
+
         let hsid = HsId::from(hsid_public);
         assert_eq!(service.onion_name().unwrap(), hsid);
+        assert_eq!(service.try_onion_name().unwrap().unwrap(), hsid);
+
+        // Replacing the hsid changes the onion name.
+        let new_hsid = service
+            .regenerate_identity_key(KeystoreSelector::Primary)
+            .unwrap();
+        assert_ne!(new_hsid, hsid);
+        assert_eq!(service.onion_name().unwrap(), new_hsid);
+
+        // We can only store a vanity hsid over the old one if we ask to overwrite it.
+        let prefix: VanityPrefix = "a".parse().unwrap();
+        let selector = KeystoreSelector::Primary;
+        assert!(service
+            .generate_vanity_identity_key(&prefix, 10_000, selector, false)
+            .is_err());
+        // We find that out before searching for a key.
+        assert!(service
+            .generate_vanity_identity_key(&prefix, 0, selector, false)
+            .is_err());
+        assert_eq!(service.onion_name().unwrap(), new_hsid);
+        let vanity_hsid = service
+            .generate_vanity_identity_key(&prefix, 10_000, selector, true)
+            .unwrap()
+            .unwrap();
+        assert!(vanity_hsid.to_string().starts_with('a'));
+        assert_eq!(service.onion_name().unwrap(), vanity_hsid);
 
         drop(temp_dir); // prove that this is still live
     }
@@ -5,7 +5,7 @@ use super::*;
 // These imports just here, because they have names unsuitable for importing widely.
 use tor_cell::relaycell::{
     hs::intro_payload::{IntroduceHandshakePayload, OnionKey},
-    msg::{Introduce2, Rendezvous1},
+    msg::{End, EndReason, Introduce2, Rendezvous1},
 };
 use tor_linkspec::{decode::Strictness, verbatim::VerbatimLinkSpecCircTarget};
 use tor_proto::{
@@ -183,7 +183,8 @@ impl<R: Runtime> RendCircConnector for HsCircPool<R> {
 }
 
 /// Filter callback used to enforce early requirements on streams.
-#[derive(Clone, Debug)]
+#[derive(Clone, Educe)]
+#[educe(Debug)]
 pub(crate) struct RequestFilter {
     /// Largest number of streams we will accept on a circuit at a time.
     //
@@ -192,6 +193,12 @@ pub(crate) struct RequestFilter {
     // value of the setting every time.  Instead, we currently only copy this
     // setting when an intro request is accepted.
     pub(crate) max_concurrent_streams: usize,
+    /// Number of open streams on a circuit at which we start rejecting new ones
+    /// with `RESOURCELIMIT`, without closing the circuit.
+    pub(crate) max_streams_before_reject: usize,
+    /// Tracker in which we count the stream requests we reject.
+    #[educe(Debug(ignore))]
+    pub(crate) rend_circuits: Arc<RendCircuitTracker>,
 }
 impl IncomingStreamRequestFilter for RequestFilter {
     fn disposition(
@@ -199,10 +206,15 @@ impl IncomingStreamRequestFilter for RequestFilter {
         _ctx: &tor_proto::stream::IncomingStreamRequestContext<'_>,
         circ: &tor_proto::circuit::ClientCircSyncView<'_>,
     ) -> tor_proto::Result<tor_proto::stream::IncomingStreamRequestDisposition> {
-        if circ.n_open_streams() >= self.max_concurrent_streams {
+        let n_open_streams = circ.n_open_streams();
+        if n_open_streams >= self.max_concurrent_streams {
             // TODO: We may want to have a way to send back an END message as
             // well and not tear down the circuit.
             Ok(tor_proto::stream::IncomingStreamRequestDisposition::CloseCircuit)
+        } else if n_open_streams >= self.max_streams_before_reject {
+            self.rend_circuits.note_stream_rejected();
+            let end = End::new_with_reason(EndReason::RESOURCELIMIT);
+            Ok(tor_proto::stream::IncomingStreamRequestDisposition::RejectRequest(end))
         } else {
             Ok(tor_proto::stream::IncomingStreamRequestDisposition::Accept)
         }
@@ -0,0 +1,177 @@
+//! Keep track of the rendezvous circuits that an onion service is serving.
+
+use crate::internal_prelude::*;
+
+use std::sync::Weak;
+
+/// The only part of a rendezvous circuit that [`RendCircuitTracker`] looks at.
+pub(crate) trait TrackedCirc: Send + Sync {
+    /// Return true if this circuit is closed, or is in the process of closing.
+    fn is_closing(&self) -> bool;
+}
+
+impl TrackedCirc for ClientCirc {
+    fn is_closing(&self) -> bool {
+        ClientCirc::is_closing(self)
+    }
+}
+
+/// The set of rendezvous circuits that an onion service is currently serving.
+///
+/// We only hold weak references to the circuits,
+/// so tracking a circuit doesn't keep it alive.
+/// A circuit stops being counted as soon as it is closing,
+/// or once every handle to it has been dropped.
+///
+/// We also count, in the service's [`OnionServiceStatus`], the stream requests
+/// we have rejected on these circuits because they exceeded `max_streams_per_rend_circuit`.
+pub(crate) struct RendCircuitTracker<C = ClientCirc> {
+    /// The circuits we have been told about.
+    ///
+    /// May contain entries for circuits that have since closed;
+    /// these are pruned whenever we are asked to count, or to track a new circuit.
+    circuits: Mutex<Vec<Weak<C>>>,
+
+    /// The status of the service, in which we count rejected stream requests.
+    status_tx: StatusSender,
+}
+
+impl<C> RendCircuitTracker<C> {
+    /// Create a new tracker, which reports rejected stream requests to `status_tx`.
+    pub(crate) fn new(status_tx: StatusSender) -> Self {
+        Self {
+            circuits: Mutex::new(vec![]),
+            status_tx,
+        }
+    }
+
+    /// Record that we rejected a stream request for exceeding the per-circuit limit.
+    ///
+    /// The count is reported in the service's status.
+    pub(crate) fn note_stream_rejected(&self) {
+        self.status_tx.note_stream_rejected();
+    }
+}
+
+impl<C: TrackedCirc> RendCircuitTracker<C> {
+    /// Start tracking `circ`, which is a newly established rendezvous circuit.
+    pub(crate) fn track(&self, circ: &Arc<C>) {
+        let mut circuits = self.circuits.lock().expect("lock poisoned");
+        Self::prune(&mut circuits);
+        circuits.push(Arc::downgrade(circ));
+    }
+
+    /// Return the number of tracked circuits that are still open.
+    pub(crate) fn n_active(&self) -> usize {
+        let mut circuits = self.circuits.lock().expect("lock poisoned");
+        Self::prune(&mut circuits);
+        circuits.len()
+    }
+
+    /// Forget about every circuit in `circuits` that is closing, or has been dropped.
+    fn prune(circuits: &mut Vec<Weak<C>>) {
+        circuits.retain(|circ| circ.upgrade().is_some_and(|circ| !circ.is_closing()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use futures::poll;
+    use std::task::Poll;
+    use tor_rtmock::MockRuntime;
+
+    /// A stand-in for a circuit, which can be told to close.
+    #[derive(Default)]
+    struct FakeCirc {
+        /// Whether this circuit has been closed.
+        closed: AtomicBool,
+    }
+
+    impl TrackedCirc for FakeCirc {
+        fn is_closing(&self) -> bool {
+            self.closed.load(Ordering::SeqCst)
+        }
+    }
+
+    /// Make a tracker for [`FakeCirc`]s, and the `StatusSender` it reports to.
+    fn tracker() -> (RendCircuitTracker<FakeCirc>, StatusSender) {
+        let status_tx = StatusSender::new(OnionServiceStatus::new_shutdown());
+        (RendCircuitTracker::new(status_tx.clone()), status_tx)
+    }
+
+    #[test]
+    fn count_drops_when_circuits_close() {
+        let (tracker, _status_tx) = tracker();
+        assert_eq!(tracker.n_active(), 0);
+
+        let circs: Vec<_> = (0..3).map(|_| Arc::new(FakeCirc::default())).collect();
+        for circ in &circs {
+            tracker.track(circ);
+        }
+        assert_eq!(tracker.n_active(), 3);
+
+        // A circuit that closes stops being counted, even if we still have a handle to it.
+        circs[0].closed.store(true, Ordering::SeqCst);
+        assert_eq!(tracker.n_active(), 2);
+
+        // So does a circuit whose last handle has been dropped.
+        let mut circs = circs;
+        circs.pop();
+        assert_eq!(tracker.n_active(), 1);
+
+        let circ = Arc::new(FakeCirc::default());
+        tracker.track(&circ);
+        assert_eq!(tracker.n_active(), 2);
+
+        drop(circs);
+        drop(circ);
+        assert_eq!(tracker.n_active(), 0);
+    }
+
+    #[test]
+    fn count_rejected_streams() {
+        MockRuntime::test_with_various(|runtime| async move {
+            let (tracker, status_tx) = tracker();
+            assert_eq!(status_tx.get().n_streams_rejected(), 0);
+
+            let mut events = status_tx.subscribe();
+            // The initial status.
+            assert!(matches!(poll!(events.next()), Poll::Ready(Some(_))));
+            assert!(poll!(events.next()).is_pending());
+
+            tracker.note_stream_rejected();
+            tracker.note_stream_rejected();
+            runtime.progress_until_stalled().await;
+
+            // Rejections alone don't wake up the watchers...
+            assert!(poll!(events.next()).is_pending());
+            assert_eq!(status_tx.get().n_streams_rejected(), 2);
+
+            // ...but the next status they are sent includes the count.
+            let publisher_tx = PublisherStatusSender::from(status_tx.clone());
+            publisher_tx.send(State::Bootstrapping, None);
+            let status = match poll!(events.next()) {
+                Poll::Ready(Some(status)) => status,
+                other => panic!("expected a status event, got {other:?}"),
+            };
+            assert_eq!(status.n_streams_rejected(), 2);
+        });
+    }
+}
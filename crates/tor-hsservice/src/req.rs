@@ -5,7 +5,7 @@
 
 use crate::internal_prelude::*;
 
-use tor_cell::relaycell::msg::{Connected, End, Introduce2};
+use tor_cell::relaycell::msg::{Connected, End, EndReason, Introduce2};
 use tor_hscrypto::Subcredential;
 use tor_keymgr::ArtiPath;
 use tor_proto::stream::{IncomingStream, IncomingStreamRequest};
@@ -84,6 +84,9 @@ pub(crate) struct RendRequestContext {
 
     /// Circuit pool we'll use to build a rendezvous circuit.
     pub(crate) circ_pool: Arc<dyn RendCircConnector + Send + Sync>,
+
+    /// Tracker to which we report every rendezvous circuit we establish.
+    pub(crate) rend_circuits: Arc<RendCircuitTracker>,
 }
 
 impl RendRequestContext {
@@ -221,6 +224,8 @@ impl RendRequest {
             .await
             .map_err(ClientError::EstablishSession)?;
 
+        self.context.rend_circuits.track(&circuit);
+
         // Note that we move circuit (which is an Arc<ClientCirc>) into this
         // closure, which lives for as long as the stream of StreamRequest, and
         // for as long as each individual StreamRequest.  This is how we keep
@@ -252,6 +257,28 @@ impl StreamRequest {
         self.stream.request()
     }
 
+    /// Return the port that the client asked to connect to,
+    /// if this is a request for a data stream (a `BEGIN` message).
+    ///
+    /// This is the virtual port on the onion service, as matched by
+    /// the `proxy_ports` rules in the configuration.
+    pub fn port(&self) -> Option<u16> {
+        match self.request() {
+            IncomingStreamRequest::Begin(begin) => Some(begin.port()),
+            _ => None,
+        }
+    }
+
+    /// Return the number of hops in the rendezvous circuit on which this
+    /// request arrived.
+    ///
+    /// This counts the hops that we built to the rendezvous point,
+    /// plus the virtual hop that joins our circuit to the client's.
+    /// It does not include any hops built by the client.
+    pub fn circuit_n_hops(&self) -> usize {
+        self.on_circuit.n_hops()
+    }
+
     /// Accept this request and send the client a `CONNECTED` message.
     pub async fn accept(self, connected_message: Connected) -> Result<DataStream, ClientError> {
         self.stream
@@ -273,6 +300,19 @@ impl StreamRequest {
             .map_err(ClientError::RejectStream)
     }
 
+    /// Reject this request, and send the client an `END` message with the given `reason`.
+    ///
+    /// This is a convenience wrapper around [`reject`](StreamRequest::reject),
+    /// for when the only thing you want to choose is the reason code:
+    /// for example, [`EndReason::CONNECTREFUSED`] if nothing is listening on the port,
+    /// or [`EndReason::RESOURCELIMIT`] if the service is overloaded.
+    ///
+    /// The same distinguishability caveat applies as for `reject`:
+    /// other implementations typically only ever send [`EndReason::DONE`].
+    pub async fn reject_with_reason(self, reason: EndReason) -> Result<(), ClientError> {
+        self.reject(End::new_with_reason(reason)).await
+    }
+
     /// Reject this request and close the rendezvous circuit entirely,
     /// along with all other streams attached to the circuit.
     pub fn shutdown_circuit(self) -> Result<(), Bug> {
@@ -280,5 +320,5 @@ impl StreamRequest {
         Ok(())
     }
 
-    // TODO various accessors, including for circuit.
+    // TODO various other accessors, including for circuit.
 }
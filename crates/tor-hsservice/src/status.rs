@@ -2,6 +2,8 @@
 
 use crate::internal_prelude::*;
 
+use std::sync::atomic::{self, AtomicU64};
+
 /// The current reported status of an onion service.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct OnionServiceStatus {
@@ -10,6 +12,13 @@ pub struct OnionServiceStatus {
 
     /// The current high-level state for the descriptor publisher.
     publisher: ComponentStatus,
+
+    /// The number of stream requests we have rejected because their rendezvous circuit
+    /// already had `max_streams_per_rend_circuit` open streams.
+    //
+    // This is here, despite the NOTE below, because it records our reaction to
+    // (possible) abuse, which is something the operator will want to be told about.
+    streams_rejected: u64,
     // TODO (#1194): Add key expiration
     //
     // NOTE: Do _not_ add general metrics (like failure/success rates , number
@@ -190,6 +199,7 @@ impl OnionServiceStatus {
         Self {
             ipt_mgr: ComponentStatus::new_shutdown(),
             publisher: ComponentStatus::new_shutdown(),
+            streams_rejected: 0,
         }
     }
 
@@ -226,6 +236,18 @@ impl OnionServiceStatus {
         }
     }
 
+    /// Return the number of stream requests this onion service has rejected
+    /// because their rendezvous circuit already had
+    /// [`max_streams_per_rend_circuit`](crate::OnionServiceConfigBuilder::max_streams_per_rend_circuit)
+    /// open streams.
+    ///
+    /// Rejecting a stream request doesn't cause a new status to be published,
+    /// so in a status received from a [`OnionServiceStatusStream`],
+    /// this is the number of requests rejected as of when that status was published.
+    pub fn n_streams_rejected(&self) -> u64 {
+        self.streams_rejected
+    }
+
     /// Return a time before which the user must re-provision this onion service
     /// with new keys.
     ///
@@ -259,14 +281,59 @@ impl futures::Stream for OnionServiceStatusStream {
     }
 }
 
+/// Wait until the publisher reported in `events` has published a descriptor.
+///
+/// See [`RunningOnionService::wait_until_published`](crate::RunningOnionService::wait_until_published).
+pub(crate) async fn wait_until_published(
+    mut events: OnionServiceStatusStream,
+) -> Result<(), PublishWaitError> {
+    // Whether we have seen the publisher leave its initial `Shutdown` state.
+    //
+    // The publisher only reports `Bootstrapping` once it has a netdir,
+    // so until then, `Shutdown` just means it hasn't started yet.
+    let mut started = false;
+
+    while let Some(status) = events.next().await {
+        let publisher = status.publisher_status();
+        match publisher.state() {
+            State::Running | State::DegradedReachable => return Ok(()),
+            State::Broken => {
+                return Err(PublishWaitError::Failed(publisher.latest_error));
+            }
+            State::Shutdown if started => return Err(PublishWaitError::Shutdown),
+            // The publisher hasn't reported anything yet.
+            State::Shutdown => {}
+            // The publisher is still trying.
+            State::Bootstrapping | State::DegradedUnreachable | State::Recovering => {
+                started = true;
+            }
+        }
+    }
+
+    // All the senders are gone, so the service has been shut down.
+    Err(PublishWaitError::Shutdown)
+}
+
 /// A shared handle to a postage::watch::Sender that we can use to update an OnionServiceStatus.
+///
+/// Rejected stream requests are counted separately, without taking the lock:
+/// the count is only copied into the status when we are asked for it,
+/// or when we publish a new status.
 //
 // TODO: Possibly, we don't need this to be Clone: as we implement the code
 // that adjusts the status, we might find that only a single location needs to
 // hold the Sender.  If that turns out to be the case, we should remove the
 // `Arc<Mutex<.>>` here.  If not, we should remove this comment.
 #[derive(Clone)]
-pub(crate) struct StatusSender(Arc<Mutex<postage::watch::Sender<OnionServiceStatus>>>);
+pub(crate) struct StatusSender {
+    /// The sender for the status itself.
+    tx: Arc<Mutex<postage::watch::Sender<OnionServiceStatus>>>,
+
+    /// The number of stream requests we have rejected so far.
+    ///
+    /// See [`OnionServiceStatus::n_streams_rejected`].
+    streams_rejected: Arc<AtomicU64>,
+}
 
 /// A handle that can be used by the [`IptManager`]
 /// to update the [`OnionServiceStatus`].
@@ -316,10 +383,11 @@ macro_rules! impl_status_sender {
             /// and notifies all listeners.
             pub(crate) fn send(&self, state: State, err: Option<Problem>) {
                 let sender = &self.0;
-                let mut tx = sender.0.lock().expect("Poisoned lock");
+                let mut tx = sender.tx.lock().expect("Poisoned lock");
                 let mut svc_status = tx.borrow().clone();
                 svc_status.$field.state = state;
                 svc_status.$field.latest_error = err;
+                svc_status.streams_rejected = sender.n_streams_rejected();
                 tx.maybe_send(|_| svc_status);
             }
         }
@@ -333,17 +401,36 @@ impl StatusSender {
     /// Create a new StatusSender with a given initial status.
     pub(crate) fn new(initial_status: OnionServiceStatus) -> Self {
         let (tx, _) = postage::watch::channel_with(initial_status);
-        StatusSender(Arc::new(Mutex::new(tx)))
+        StatusSender {
+            tx: Arc::new(Mutex::new(tx)),
+            streams_rejected: Default::default(),
+        }
     }
 
     /// Return a copy of the current status.
     pub(crate) fn get(&self) -> OnionServiceStatus {
-        self.0.lock().expect("Poisoned lock").borrow().clone()
+        let mut svc_status = self.tx.lock().expect("Poisoned lock").borrow().clone();
+        svc_status.streams_rejected = self.n_streams_rejected();
+        svc_status
     }
 
     /// Return a new OnionServiceStatusStream to return events from this StatusSender.
     pub(crate) fn subscribe(&self) -> OnionServiceStatusStream {
-        OnionServiceStatusStream(self.0.lock().expect("Poisoned lock").subscribe())
+        OnionServiceStatusStream(self.tx.lock().expect("Poisoned lock").subscribe())
+    }
+
+    /// Count a stream request that we rejected for exceeding the per-circuit limit.
+    ///
+    /// This doesn't notify the listeners:
+    /// the new count is reported with the next status we publish.
+    pub(crate) fn note_stream_rejected(&self) {
+        self.streams_rejected
+            .fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    /// Return the number of stream requests we have rejected so far.
+    fn n_streams_rejected(&self) -> u64 {
+        self.streams_rejected.load(atomic::Ordering::Relaxed)
     }
 }
 
@@ -355,7 +442,6 @@ impl PublisherStatusSender {
     }
 }
 
-#[cfg(test)]
 impl OnionServiceStatus {
     /// Return the current high-level state of the publisher`.
     pub(crate) fn publisher_status(&self) -> ComponentStatus {
@@ -363,15 +449,110 @@ impl OnionServiceStatus {
     }
 }
 
-#[cfg(test)]
 impl ComponentStatus {
     /// The current `State` of this component.
     pub(crate) fn state(&self) -> State {
         self.state
     }
+}
 
+#[cfg(test)]
+impl ComponentStatus {
     /// The current error of this component.
     pub(crate) fn current_problem(&self) -> Option<&Problem> {
         self.latest_error.as_ref()
     }
 }
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    use futures::{pin_mut, poll};
+    use std::task::Poll;
+    use tor_rtmock::MockRuntime;
+
+    /// Make a `StatusSender`, and a `PublisherStatusSender` that updates it.
+    fn senders() -> (StatusSender, PublisherStatusSender) {
+        let status_tx = StatusSender::new(OnionServiceStatus::new_shutdown());
+        let publisher_tx = status_tx.clone().into();
+        (status_tx, publisher_tx)
+    }
+
+    #[test]
+    fn wait_published() {
+        MockRuntime::test_with_various(|runtime| async move {
+            let (status_tx, publisher_tx) = senders();
+            let wait = wait_until_published(status_tx.subscribe());
+            pin_mut!(wait);
+
+            // Not launched yet
+            assert!(poll!(&mut wait).is_pending());
+
+            publisher_tx.send(State::Bootstrapping, None);
+            runtime.progress_until_stalled().await;
+            assert!(poll!(&mut wait).is_pending());
+
+            publisher_tx.send(State::DegradedUnreachable, None);
+            runtime.progress_until_stalled().await;
+            assert!(poll!(&mut wait).is_pending());
+
+            publisher_tx.send(State::Running, None);
+            assert!(matches!(poll!(&mut wait), Poll::Ready(Ok(()))));
+        });
+    }
+
+    #[test]
+    fn wait_published_failure() {
+        MockRuntime::test_with_various(|_runtime| async move {
+            let (status_tx, publisher_tx) = senders();
+            publisher_tx.send_broken(FatalError::from(internal!("publisher crashed")));
+            let res = wait_until_published(status_tx.subscribe()).await;
+            assert!(matches!(
+                res,
+                Err(PublishWaitError::Failed(Some(Problem::Runtime(_))))
+            ));
+
+            let (status_tx, publisher_tx) = senders();
+            let events = status_tx.subscribe();
+            drop((status_tx, publisher_tx));
+            let res = wait_until_published(events).await;
+            assert!(matches!(res, Err(PublishWaitError::Shutdown)));
+        });
+    }
+
+    #[test]
+    fn wait_published_shutdown() {
+        MockRuntime::test_with_various(|runtime| async move {
+            let (status_tx, publisher_tx) = senders();
+            let wait = wait_until_published(status_tx.subscribe());
+            pin_mut!(wait);
+
+            publisher_tx.send(State::Bootstrapping, None);
+            runtime.progress_until_stalled().await;
+            assert!(poll!(&mut wait).is_pending());
+
+            // The publisher shuts down while we are still waiting,
+            // even though the senders are still alive.
+            publisher_tx.send_shutdown();
+            assert!(matches!(
+                poll!(&mut wait),
+                Poll::Ready(Err(PublishWaitError::Shutdown))
+            ));
+            drop((status_tx, publisher_tx));
+        });
+    }
+}
@@ -0,0 +1,158 @@
+//! Searching for an identity key whose onion address begins with a chosen prefix.
+//!
+//! This is sometimes called "vanity" address generation.
+//!
+//! There is no shortcut here: we just generate one random identity key after another,
+//! until we find one whose onion address has the prefix we want.
+//! Every onion address character is one of 32 possibilities,
+//! so each extra character of the prefix makes the search about 32 times longer.
+//! A prefix of `n` characters takes about `32^n` attempts on average:
+//! a few characters are cheap,
+//! but a prefix of 8 or more characters will take days or years.
+
+use std::str::FromStr;
+
+use crate::internal_prelude::*;
+
+/// The characters that can appear in the base32 part of an onion address.
+const ONION_ADDRESS_CHARS: &str = "abcdefghijklmnopqrstuvwxyz234567";
+
+/// The number of characters at the start of an onion address
+/// that are derived only from the identity key.
+///
+/// (The 32-byte key is encoded in the first 52 characters,
+/// but the last of those also depends on the address checksum.)
+const MAX_VANITY_PREFIX_LEN: usize = 51;
+
+/// A prefix that we want an onion address to start with.
+///
+/// This can only contain characters that appear in onion addresses
+/// (the lowercase letters `a` through `z`, and the digits `2` through `7`).
+/// Uppercase letters are accepted, and converted to lowercase.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VanityPrefix(String);
+
+/// An error from parsing a [`VanityPrefix`].
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum VanityPrefixError {
+    /// The prefix contained a character that can't appear in an onion address.
+    #[error("{0:?} can't appear in an onion address")]
+    InvalidChar(char),
+    /// The prefix was too long.
+    #[error("Prefix is longer than {MAX_VANITY_PREFIX_LEN} characters")]
+    TooLong,
+}
+
+impl FromStr for VanityPrefix {
+    type Err = VanityPrefixError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let prefix = s.to_ascii_lowercase();
+        if let Some(c) = prefix.chars().find(|c| !ONION_ADDRESS_CHARS.contains(*c)) {
+            return Err(VanityPrefixError::InvalidChar(c));
+        }
+        if prefix.len() > MAX_VANITY_PREFIX_LEN {
+            return Err(VanityPrefixError::TooLong);
+        }
+        Ok(VanityPrefix(prefix))
+    }
+}
+
+impl Display for VanityPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl VanityPrefix {
+    /// Return the expected number of attempts needed to find an address with this prefix.
+    ///
+    /// Returns `None` if that number doesn't fit in a `u64`.
+    pub fn expected_attempts(&self) -> Option<u64> {
+        let len = u32::try_from(self.0.len()).ok()?;
+        32_u64.checked_pow(len)
+    }
+
+    /// Return true if `hsid` has an onion address that starts with this prefix.
+    fn matches(&self, hsid: &HsId) -> bool {
+        hsid.to_string().starts_with(&self.0)
+    }
+}
+
+/// Generate up to `max_attempts` random identity keypairs,
+/// and return the first one whose onion address starts with `prefix`,
+/// along with its [`HsId`].
+///
+/// Returns `None` if none of the keypairs that we tried had a matching address.
+///
+/// Finding a prefix of `n` characters takes about `32^n` attempts on average
+/// (see [`VanityPrefix::expected_attempts`]),
+/// so `max_attempts` should be chosen with care.
+pub fn find_vanity_hsid<R: RngCore + CryptoRng>(
+    prefix: &VanityPrefix,
+    max_attempts: u64,
+    rng: &mut R,
+) -> Option<(HsIdKeypair, HsId)> {
+    for _ in 0..max_attempts {
+        let keypair = ed25519::Keypair::generate(rng);
+        let hsid = HsIdKey::from(keypair.verifying_key()).id();
+        if prefix.matches(&hsid) {
+            let keypair = HsIdKeypair::from(ed25519::ExpandedKeypair::from(&keypair));
+            return Some((keypair, hsid));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use super::*;
+    use tor_basic_utils::test_rng::testing_rng;
+
+    #[test]
+    fn parse_prefix() {
+        let prefix: VanityPrefix = "Arti7".parse().unwrap();
+        assert_eq!(prefix.0, "arti7");
+        assert_eq!(prefix.expected_attempts(), Some(32_u64.pow(5)));
+
+        assert!(matches!(
+            "arti1".parse::<VanityPrefix>(),
+            Err(VanityPrefixError::InvalidChar('1'))
+        ));
+        assert!(matches!(
+            "a".repeat(52).parse::<VanityPrefix>(),
+            Err(VanityPrefixError::TooLong)
+        ));
+        let longest: VanityPrefix = "a".repeat(51).parse().unwrap();
+        assert_eq!(longest.expected_attempts(), None);
+    }
+
+    #[test]
+    fn find_prefix() {
+        let mut rng = testing_rng();
+        let prefix: VanityPrefix = "a".parse().unwrap();
+
+        let (keypair, hsid) = find_vanity_hsid(&prefix, 10_000, &mut rng).unwrap();
+        assert!(hsid.to_string().starts_with('a'));
+        assert_eq!(HsIdKey::from(&keypair).id(), hsid);
+
+        // We give up once we've made enough attempts.
+        assert!(find_vanity_hsid(&prefix, 0, &mut rng).is_none());
+    }
+}
@@ -118,6 +118,23 @@ macro_rules! declare_item_type {
                     )*
                 }
             }
+
+            /// Return the `KeyType` whose [`arti_extension`](KeyType::arti_extension)
+            /// is `extension`, if there is one.
+            ///
+            /// This is the inverse of [`arti_extension`](KeyType::arti_extension).
+            /// Unlike the `From<&str>` impl of [`KeystoreItemType`],
+            /// this returns `None` for certificate extensions and unrecognized extensions.
+            pub fn from_arti_extension(extension: &str) -> Option<KeyType> {
+                use KeyType::*;
+
+                match extension {
+                    $(
+                        $str_repr => Some($variant),
+                    )*
+                    _ => None,
+                }
+            }
         }
 
         impl KeystoreItemType {
@@ -241,6 +258,26 @@ macro_rules! declare_item_type {
                     assert_eq!(cert_ty.arti_extension(), $cert_str_repr);
                 )*
             }
+
+            #[test]
+            fn key_type_extension_round_trip() {
+                let mut extensions = std::collections::HashSet::new();
+                $(
+                    let key_ty = KeyType::$variant;
+                    let ext = key_ty.arti_extension();
+                    assert_eq!(KeyType::from_arti_extension(&ext), Some(key_ty));
+                    // No two item types may share an extension.
+                    assert!(extensions.insert(ext));
+                )*
+                $(
+                    let ext = KeystoreItemType::from(CertType::$cert_variant).arti_extension();
+                    assert_eq!(KeyType::from_arti_extension(&ext), None);
+                    assert!(extensions.insert(ext));
+                )*
+
+                assert_eq!(KeyType::from_arti_extension("rsa"), None);
+                assert_eq!(KeyType::from_arti_extension(""), None);
+            }
         }
     }
 }
@@ -65,6 +65,61 @@ impl From<Algorithm> for SshKeyAlgorithm {
     }
 }
 
+impl SshKeyAlgorithm {
+    /// The algorithms we can convert to and from our own key types.
+    const SUPPORTED: &'static [SshKeyAlgorithm] = &[
+        SshKeyAlgorithm::Ed25519,
+        SshKeyAlgorithm::Ed25519Expanded,
+        SshKeyAlgorithm::X25519,
+    ];
+
+    /// Return the list of SSH key algorithms that this crate supports.
+    ///
+    /// Note that [`Ed25519Expanded`](SshKeyAlgorithm::Ed25519Expanded)
+    /// is only supported for keypairs, not for public keys.
+    pub fn supported() -> &'static [SshKeyAlgorithm] {
+        Self::SUPPORTED
+    }
+
+    /// Return true if this algorithm is one of the [`supported`](SshKeyAlgorithm::supported) ones.
+    pub fn is_supported(&self) -> bool {
+        Self::SUPPORTED.contains(self)
+    }
+
+    /// Return the supported algorithm that is the closest match for this one, if there is one.
+    ///
+    /// Supported algorithms are their own closest match.
+    /// Unsupported variants of a supported algorithm (such as `sk-ssh-ed25519@openssh.com`,
+    /// or an unrecognized algorithm whose name mentions ed25519 or x25519)
+    /// map to that algorithm.
+    /// Anything else (such as `ssh-rsa`) has no close match, and gives `None`.
+    ///
+    /// This is meant for making error messages more helpful:
+    /// it does not mean that a key of this algorithm can be converted.
+    pub fn closest_supported(&self) -> Option<SshKeyAlgorithm> {
+        match self {
+            SshKeyAlgorithm::Ed25519
+            | SshKeyAlgorithm::Ed25519Expanded
+            | SshKeyAlgorithm::X25519 => Some(self.clone()),
+            SshKeyAlgorithm::SkEd25519 => Some(SshKeyAlgorithm::Ed25519),
+            SshKeyAlgorithm::Unknown(algo) => {
+                let name = algo.as_str().to_ascii_lowercase();
+                if name.contains("ed25519") {
+                    Some(SshKeyAlgorithm::Ed25519)
+                } else if name.contains("x25519") || name.contains("curve25519") {
+                    Some(SshKeyAlgorithm::X25519)
+                } else {
+                    None
+                }
+            }
+            SshKeyAlgorithm::Dsa
+            | SshKeyAlgorithm::Ecdsa
+            | SshKeyAlgorithm::Rsa
+            | SshKeyAlgorithm::SkEcdsaSha2NistP256 => None,
+        }
+    }
+}
+
 /// Convert ssh_key KeyData or KeypairData to one of our key types.
 macro_rules! ssh_to_internal_erased {
     (PRIVATE $key:expr, $algo:expr) => {{
@@ -298,3 +353,59 @@ impl SshKeyData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    use ssh_key::AlgorithmName;
+
+    /// Return the `SshKeyAlgorithm` for an unrecognized algorithm called `name`.
+    fn unknown(name: &str) -> SshKeyAlgorithm {
+        let algo = SshKeyAlgorithm::from(Algorithm::Other(AlgorithmName::new(name).unwrap()));
+        assert!(matches!(algo, SshKeyAlgorithm::Unknown(_)));
+        algo
+    }
+
+    #[test]
+    fn supported_algorithms() {
+        for algo in SshKeyAlgorithm::supported() {
+            assert!(algo.is_supported());
+            assert_eq!(algo.closest_supported().as_ref(), Some(algo));
+        }
+        assert!(!SshKeyAlgorithm::Rsa.is_supported());
+        assert!(!SshKeyAlgorithm::SkEd25519.is_supported());
+    }
+
+    #[test]
+    fn closest_supported() {
+        assert_eq!(
+            SshKeyAlgorithm::SkEd25519.closest_supported(),
+            Some(SshKeyAlgorithm::Ed25519)
+        );
+        assert_eq!(SshKeyAlgorithm::Rsa.closest_supported(), None);
+        assert_eq!(SshKeyAlgorithm::Dsa.closest_supported(), None);
+        assert_eq!(
+            unknown("ed25519-other@example.com").closest_supported(),
+            Some(SshKeyAlgorithm::Ed25519)
+        );
+        assert_eq!(
+            unknown("x25519-other@example.com").closest_supported(),
+            Some(SshKeyAlgorithm::X25519)
+        );
+        assert_eq!(unknown("rot13@example.com").closest_supported(), None);
+    }
+}
@@ -10,8 +10,33 @@ pub(crate) mod ephemeral;
 
 use tor_key_forge::{EncodableItem, ErasedKey, KeystoreItemType};
 
+use tor_error::bad_api_usage;
+
 use crate::{KeyPath, KeySpecifier, KeystoreId, Result};
 
+/// A description of what [`Keystore::insert`] would do.
+///
+/// Returned from [`Keystore::would_insert`].
+#[derive(Clone, Debug, PartialEq, amplify::Getters)]
+#[non_exhaustive]
+pub struct InsertPlan {
+    /// The [`KeyPath`] the item would be written to.
+    key_path: KeyPath,
+    /// Whether there is already an item at that path, which would be replaced.
+    #[getter(as_copy)]
+    replaces_existing: bool,
+}
+
+impl InsertPlan {
+    /// Create a new `InsertPlan`.
+    pub(crate) fn new(key_path: KeyPath, replaces_existing: bool) -> Self {
+        Self {
+            key_path,
+            replaces_existing,
+        }
+    }
+}
+
 /// A generic key store.
 pub trait Keystore: Send + Sync + 'static {
     /// An identifier for this key store instance.
@@ -57,6 +82,29 @@ pub trait Keystore: Send + Sync + 'static {
         item_type: &KeystoreItemType,
     ) -> Result<()>;
 
+    /// Check whether [`insert`](Keystore::insert)ing an item of type `item_type`
+    /// for `key_spec` would succeed, without writing anything.
+    ///
+    /// This performs the checks `insert` would do before actually writing the item
+    /// (for example, that the key has a path in this keystore,
+    /// and that the location it would be written to is accessible),
+    /// and describes where the item would be written.
+    ///
+    /// A successful result is not a guarantee that a subsequent `insert` will succeed:
+    /// the keystore might change in the meantime,
+    /// and `insert` can still fail while encoding or writing the item.
+    ///
+    /// The default implementation returns an error;
+    /// keystores that support `insert` should override it.
+    fn would_insert(
+        &self,
+        key_spec: &dyn KeySpecifier,
+        item_type: &KeystoreItemType,
+    ) -> Result<InsertPlan> {
+        let _ = (key_spec, item_type);
+        Err(bad_api_usage!("keystore {} does not support dry-run inserts", self.id()).into())
+    }
+
     /// Remove the specified key.
     ///
     /// A return value of `Ok(None)` indicates the key doesn't exist in this key store, whereas
@@ -12,7 +12,7 @@ use std::result::Result as StdResult;
 use std::str::FromStr;
 
 use crate::keystore::fs_utils::{checked_op, FilesystemAction, FilesystemError, RelKeyPath};
-use crate::keystore::{EncodableItem, ErasedKey, KeySpecifier, Keystore};
+use crate::keystore::{EncodableItem, ErasedKey, InsertPlan, KeySpecifier, Keystore};
 use crate::{
     arti_path, ArtiPath, ArtiPathUnavailableError, KeyPath, KeystoreId, Result, UnknownKeyTypeError,
 };
@@ -23,6 +23,7 @@ use ssh::UnparsedOpenSshKey;
 use fs_mistrust::{CheckedDir, Mistrust};
 use itertools::Itertools;
 use tor_error::internal;
+use tracing::warn;
 use walkdir::WalkDir;
 
 use tor_basic_utils::PathExt as _;
@@ -246,6 +247,52 @@ impl Keystore for ArtiNativeKeystore {
             .map_err(ArtiNativeKeystoreError::Filesystem)?)
     }
 
+    fn would_insert(
+        &self,
+        key_spec: &dyn KeySpecifier,
+        item_type: &KeystoreItemType,
+    ) -> Result<InsertPlan> {
+        if let KeystoreItemType::Unknown { arti_extension } = item_type {
+            return Err(
+                ArtiNativeKeystoreError::UnknownKeyType(UnknownKeyTypeError {
+                    arti_extension: arti_extension.clone(),
+                })
+                .into(),
+            );
+        }
+
+        let arti_path = key_spec
+            .arti_path()
+            .map_err(|e| tor_error::internal!("{e}"))?;
+        let path = self
+            .rel_path(key_spec, item_type)
+            .map_err(|e| tor_error::internal!("{e}"))?;
+
+        // If the parent directory already exists, make sure its permissions are acceptable.
+        // (If it doesn't, insert would create it, which we can't check without doing it.)
+        if let Some(parent) = path.rel_path_unchecked().parent() {
+            match self.keystore_dir.read_directory(parent) {
+                Ok(_) | Err(fs_mistrust::Error::NotFound(_)) => {}
+                Err(err) => {
+                    return Err(
+                        ArtiNativeKeystoreError::Filesystem(FilesystemError::FsMistrust {
+                            action: FilesystemAction::Write,
+                            path: parent.to_path_buf(),
+                            err: err.into(),
+                        })
+                        .into(),
+                    )
+                }
+            }
+        }
+
+        // This fails if there is something other than a regular file at the path,
+        // in which case insert would fail too.
+        let replaces_existing = self.contains(key_spec, item_type)?;
+
+        Ok(InsertPlan::new(arti_path.into(), replaces_existing))
+    }
+
     fn remove(
         &self,
         key_spec: &dyn KeySpecifier,
@@ -333,6 +380,16 @@ impl Keystore for ArtiNativeKeystore {
                     .ok_or_else(|| malformed_err(path, err::MalformedPathError::Utf8))?;
 
                 let item_type = KeystoreItemType::from(extension);
+                if let KeystoreItemType::Unknown { arti_extension } = &item_type {
+                    // Don't fail the whole listing because of a file we don't understand:
+                    // it might belong to a newer version of Arti.
+                    warn!(
+                        "Ignoring file with unrecognized extension {:?} in keystore: {}",
+                        arti_extension,
+                        path.display_lossy()
+                    );
+                    return Ok(None);
+                }
                 // Strip away the file extension
                 let path = path.with_extension("");
                 // Construct slugs in platform-independent way
@@ -675,6 +732,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn would_insert() {
+        let (key_store, _keystore_dir) = init_keystore(false);
+        let key_spec = TestSpecifier::default();
+        let key_type = KeyType::Ed25519Keypair.into();
+        let expected_path = KeyPath::Arti(key_spec.arti_path().unwrap());
+
+        let plan = key_store.would_insert(&key_spec, &key_type).unwrap();
+        assert_eq!(plan.key_path(), &expected_path);
+        assert!(!plan.replaces_existing());
+        // Nothing was written.
+        assert!(key_store.list().unwrap().is_empty());
+        assert!(!key_path(&key_store, &KeyType::Ed25519Keypair)
+            .try_exists()
+            .unwrap());
+
+        let (key_store, _keystore_dir) = init_keystore(true);
+        let plan = key_store.would_insert(&key_spec, &key_type).unwrap();
+        assert_eq!(plan.key_path(), &expected_path);
+        assert!(plan.replaces_existing());
+
+        let unknown = KeystoreItemType::Unknown {
+            arti_extension: "rsa".into(),
+        };
+        assert!(key_store.would_insert(&key_spec, &unknown).is_err());
+    }
+
+    #[test]
+    fn list_skips_unrecognized() {
+        let (key_store, _keystore_dir) = init_keystore(true);
+
+        // A file whose extension doesn't correspond to any item type we know about.
+        let unrecognized = key_path(&key_store, &KeyType::Ed25519Keypair).with_extension("rsa");
+        fs::write(unrecognized, "not a key").unwrap();
+
+        let list = key_store.list().unwrap();
+        assert_contains_arti_paths!([TestSpecifier::path_prefix(),], list);
+        assert_eq!(list[0].1, KeyType::Ed25519Keypair.into());
+    }
+
     #[test]
     fn key_path_not_regular_file() {
         let (key_store, _keystore_dir) = init_keystore(false);
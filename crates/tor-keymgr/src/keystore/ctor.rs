@@ -2,6 +2,7 @@
 
 pub(crate) mod client;
 pub(crate) mod err;
+pub(crate) mod router;
 pub(crate) mod service;
 
 use crate::keystore::fs_utils::{FilesystemAction, FilesystemError, RelKeyPath};
@@ -13,6 +14,7 @@ use std::path::{Path, PathBuf};
 use err::CTorKeystoreError;
 
 pub use client::CTorClientKeystore;
+pub use router::CTorServiceKeystoreRouter;
 pub use service::CTorServiceKeystore;
 
 /// Common fields for C Tor keystores.
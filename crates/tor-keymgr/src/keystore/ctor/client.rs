@@ -10,7 +10,7 @@ use std::str::FromStr as _;
 use crate::keystore::ctor::err::{CTorKeystoreError, MalformedClientKeyError};
 use crate::keystore::ctor::CTorKeystore;
 use crate::keystore::fs_utils::{checked_op, FilesystemAction, FilesystemError, RelKeyPath};
-use crate::keystore::{EncodableItem, ErasedKey, KeySpecifier, Keystore};
+use crate::keystore::{EncodableItem, ErasedKey, InsertPlan, KeySpecifier, Keystore};
 use crate::{CTorPath, KeyPath, KeystoreId, Result};
 
 use fs_mistrust::Mistrust;
@@ -260,6 +260,14 @@ impl Keystore for CTorClientKeystore {
         Err(CTorKeystoreError::NotSupported { action: "insert" }.into())
     }
 
+    fn would_insert(
+        &self,
+        _key_spec: &dyn KeySpecifier,
+        _item_type: &KeystoreItemType,
+    ) -> Result<InsertPlan> {
+        Err(CTorKeystoreError::NotSupported { action: "insert" }.into())
+    }
+
     fn remove(
         &self,
         _key_spec: &dyn KeySpecifier,
@@ -5,6 +5,7 @@ use crate::KeystoreError;
 use tor_error::{ErrorKind, HasKind};
 use tor_hscrypto::pk::HsIdParseError;
 use tor_key_forge::KeystoreItemType;
+use tor_persist::hsnickname::HsNickname;
 
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -43,6 +44,34 @@ pub(crate) enum CTorKeystoreError {
         item: String,
     },
 
+    /// More than one keystore was registered for the same service.
+    #[error("More than one keystore for service {nickname}")]
+    DuplicateNickname {
+        /// The nickname of the service.
+        nickname: HsNickname,
+    },
+
+    /// A keystore was registered for a service whose keys it does not hold.
+    #[error("Keystore registered for service {nickname} holds no keys for it")]
+    UnknownService {
+        /// The nickname of the service.
+        nickname: HsNickname,
+    },
+
+    /// One of the keys of a service is missing.
+    #[error("Key {path} not found")]
+    MissingKey {
+        /// The path of the key.
+        path: PathBuf,
+    },
+
+    /// The public key of a service does not match its keypair.
+    #[error("Public key of service {nickname} does not match its private key")]
+    KeyMismatch {
+        /// The nickname of the service.
+        nickname: HsNickname,
+    },
+
     /// An internal error.
     #[error("Internal error")]
     Bug(#[from] tor_error::Bug),
@@ -138,6 +167,10 @@ impl HasKind for CTorKeystoreError {
             KE::MalformedKey { .. } => ErrorKind::KeystoreCorrupted,
             KE::NotSupported { .. } => ErrorKind::BadApiUsage,
             KE::InvalidKeystoreItemType { .. } => ErrorKind::BadApiUsage,
+            KE::DuplicateNickname { .. } => ErrorKind::BadApiUsage,
+            KE::UnknownService { .. } => ErrorKind::BadApiUsage,
+            KE::MissingKey { .. } => ErrorKind::KeystoreCorrupted,
+            KE::KeyMismatch { .. } => ErrorKind::KeystoreCorrupted,
             KE::Bug(e) => e.kind(),
         }
     }
@@ -0,0 +1,345 @@
+//! A read-only keystore that routes C Tor service keys to per-service keystores.
+//!
+//! See [`CTorServiceKeystoreRouter`] for more details.
+
+use crate::keystore::ctor::err::CTorKeystoreError;
+use crate::keystore::{EncodableItem, ErasedKey, InsertPlan, KeySpecifier, Keystore, KeystoreId};
+use crate::{CTorPath, KeyPath, Result};
+
+use tor_key_forge::KeystoreItemType;
+use tor_persist::hsnickname::HsNickname;
+
+use std::collections::BTreeMap;
+
+/// A read-only keystore that serves the keys of several onion services,
+/// each from its own underlying keystore.
+///
+/// Each underlying keystore (typically a [`CTorServiceKeystore`](crate::CTorServiceKeystore))
+/// is registered under the [`HsNickname`] of the service whose keys it holds.
+/// Requests for a key are dispatched using the nickname in the [`CTorPath`]
+/// of the requested [`KeySpecifier`]:
+/// if the key has no `CTorPath`, if its `CTorPath` is not [`Service`](CTorPath::Service),
+/// or if no keystore is registered for its nickname,
+/// the key will be declared not found.
+///
+/// [`list`](Keystore::list) returns the keys of every underlying keystore.
+///
+/// Like the C Tor keystores, this keystore is read-only:
+/// the only supported [`Keystore`] operations are [`contains`](Keystore::contains),
+/// [`get`](Keystore::get), and [`list`](Keystore::list).
+/// All other keystore operations will return an error.
+pub struct CTorServiceKeystoreRouter {
+    /// The unique identifier of this instance.
+    id: KeystoreId,
+    /// The keystore for each service, by nickname.
+    keystores: BTreeMap<HsNickname, Box<dyn Keystore>>,
+}
+
+impl CTorServiceKeystoreRouter {
+    /// Create a new `CTorServiceKeystoreRouter`
+    /// serving the keys of each service in `keystores` from the corresponding keystore.
+    ///
+    /// Returns an error if the same nickname appears more than once in `keystores`,
+    /// or if any keystore holds no keys for the service it is registered for
+    /// (because it belongs to a different service, or because the service doesn't exist).
+    pub fn new(
+        id: KeystoreId,
+        keystores: impl IntoIterator<Item = (HsNickname, Box<dyn Keystore>)>,
+    ) -> Result<Self> {
+        let mut by_nickname = BTreeMap::new();
+        for (nickname, keystore) in keystores {
+            if by_nickname.contains_key(&nickname) {
+                return Err(CTorKeystoreError::DuplicateNickname { nickname }.into());
+            }
+            let has_service_keys = keystore.list()?.iter().any(|(path, _)| {
+                matches!(
+                    path,
+                    KeyPath::CTor(CTorPath::Service { nickname: n, .. }) if *n == nickname
+                )
+            });
+            if !has_service_keys {
+                return Err(CTorKeystoreError::UnknownService { nickname }.into());
+            }
+            by_nickname.insert(nickname, keystore);
+        }
+
+        Ok(Self {
+            id,
+            keystores: by_nickname,
+        })
+    }
+
+    /// Return the keystore responsible for the key identified by `key_spec`, if there is one.
+    fn keystore_for(&self, key_spec: &dyn KeySpecifier) -> Option<&dyn Keystore> {
+        let CTorPath::Service { nickname, .. } = key_spec.ctor_path()? else {
+            return None;
+        };
+
+        self.keystores.get(&nickname).map(|keystore| &**keystore)
+    }
+}
+
+impl Keystore for CTorServiceKeystoreRouter {
+    fn id(&self) -> &KeystoreId {
+        &self.id
+    }
+
+    fn contains(&self, key_spec: &dyn KeySpecifier, item_type: &KeystoreItemType) -> Result<bool> {
+        match self.keystore_for(key_spec) {
+            Some(keystore) => keystore.contains(key_spec, item_type),
+            None => Ok(false),
+        }
+    }
+
+    fn get(
+        &self,
+        key_spec: &dyn KeySpecifier,
+        item_type: &KeystoreItemType,
+    ) -> Result<Option<ErasedKey>> {
+        match self.keystore_for(key_spec) {
+            Some(keystore) => keystore.get(key_spec, item_type),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(
+        &self,
+        _key: &dyn EncodableItem,
+        _key_spec: &dyn KeySpecifier,
+        _item_type: &KeystoreItemType,
+    ) -> Result<()> {
+        Err(CTorKeystoreError::NotSupported { action: "insert" }.into())
+    }
+
+    fn would_insert(
+        &self,
+        _key_spec: &dyn KeySpecifier,
+        _item_type: &KeystoreItemType,
+    ) -> Result<InsertPlan> {
+        Err(CTorKeystoreError::NotSupported { action: "insert" }.into())
+    }
+
+    fn remove(
+        &self,
+        _key_spec: &dyn KeySpecifier,
+        _item_type: &KeystoreItemType,
+    ) -> Result<Option<()>> {
+        Err(CTorKeystoreError::NotSupported { action: "remove" }.into())
+    }
+
+    fn list(&self) -> Result<Vec<(KeyPath, KeystoreItemType)>> {
+        let mut keys = Vec::new();
+        for keystore in self.keystores.values() {
+            keys.extend(keystore.list()?);
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use super::*;
+    use std::fs;
+    use std::str::FromStr as _;
+    use tempfile::{tempdir, TempDir};
+
+    use crate::test_utils::{assert_found, DummyKey, TestCTorSpecifier};
+    use crate::{CTorServiceKeystore, CTorServicePath};
+    use fs_mistrust::Mistrust;
+    use tor_key_forge::KeyType;
+
+    const PUBKEY: &[u8] = include_bytes!("../../../testdata/tor-service/hs_ed25519_public_key");
+    const PRIVKEY: &[u8] = include_bytes!("../../../testdata/tor-service/hs_ed25519_secret_key");
+
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Create a `CTorServiceKeystore` for `nickname`, containing the keys in `keys`.
+    fn init_service_keystore(
+        nickname: &str,
+        keys: &[(&str, &[u8])],
+    ) -> (HsNickname, Box<dyn Keystore>, TempDir) {
+        let keystore_dir = tempdir().unwrap();
+
+        #[cfg(unix)]
+        fs::set_permissions(&keystore_dir, fs::Permissions::from_mode(0o700)).unwrap();
+
+        let nickname = HsNickname::from_str(nickname).unwrap();
+        let keystore = CTorServiceKeystore::from_path_and_mistrust(
+            &keystore_dir,
+            &Mistrust::default(),
+            KeystoreId::from_str(&format!("ctor-{nickname}")).unwrap(),
+            nickname.clone(),
+        )
+        .unwrap();
+
+        for (name, key) in keys {
+            fs::write(keystore_dir.path().join(name), key).unwrap();
+        }
+
+        (nickname, Box::new(keystore), keystore_dir)
+    }
+
+    /// Create a router for two services:
+    /// "allium-cepa", which has both of its keys,
+    /// and "allium-sativum", which only has its public key.
+    fn init_router() -> (CTorServiceKeystoreRouter, Vec<TempDir>) {
+        let (nick1, store1, dir1) = init_service_keystore(
+            "allium-cepa",
+            &[
+                ("hs_ed25519_public_key", PUBKEY),
+                ("hs_ed25519_secret_key", PRIVKEY),
+            ],
+        );
+        let (nick2, store2, dir2) =
+            init_service_keystore("allium-sativum", &[("hs_ed25519_public_key", PUBKEY)]);
+
+        let router = CTorServiceKeystoreRouter::new(
+            KeystoreId::from_str("router").unwrap(),
+            [(nick1, store1), (nick2, store2)],
+        )
+        .unwrap();
+
+        (router, vec![dir1, dir2])
+    }
+
+    /// Return a specifier for the service key at `path`, for the service called `nickname`.
+    fn spec(nickname: &str, path: CTorServicePath) -> TestCTorSpecifier {
+        TestCTorSpecifier(CTorPath::Service {
+            nickname: HsNickname::from_str(nickname).unwrap(),
+            path,
+        })
+    }
+
+    #[test]
+    fn get() {
+        let (router, _dirs) = init_router();
+
+        assert_found!(
+            router,
+            &spec("allium-cepa", CTorServicePath::PublicKey),
+            &KeyType::Ed25519PublicKey,
+            true
+        );
+        assert_found!(
+            router,
+            &spec("allium-cepa", CTorServicePath::PrivateKey),
+            &KeyType::Ed25519ExpandedKeypair,
+            true
+        );
+        assert_found!(
+            router,
+            &spec("allium-sativum", CTorServicePath::PublicKey),
+            &KeyType::Ed25519PublicKey,
+            true
+        );
+
+        // This service's keystore doesn't have its private key...
+        assert_found!(
+            router,
+            &spec("allium-sativum", CTorServicePath::PrivateKey),
+            &KeyType::Ed25519ExpandedKeypair,
+            false
+        );
+        // ...and we don't have a keystore for this service at all.
+        assert_found!(
+            router,
+            &spec("acutus-cepa", CTorServicePath::PublicKey),
+            &KeyType::Ed25519PublicKey,
+            false
+        );
+    }
+
+    #[test]
+    fn list() {
+        let (router, _dirs) = init_router();
+        let keys = router.list().unwrap();
+
+        assert_eq!(keys.len(), 3);
+        let n_pubkeys = keys
+            .iter()
+            .filter(|(_, key_type)| *key_type == KeyType::Ed25519PublicKey.into())
+            .count();
+        assert_eq!(n_pubkeys, 2);
+    }
+
+    #[test]
+    fn duplicate_nickname() {
+        let keys: &[(&str, &[u8])] = &[("hs_ed25519_public_key", PUBKEY)];
+        let (nick1, store1, _dir1) = init_service_keystore("allium-cepa", keys);
+        let (nick2, store2, _dir2) = init_service_keystore("allium-cepa", keys);
+
+        let err = CTorServiceKeystoreRouter::new(
+            KeystoreId::from_str("router").unwrap(),
+            [(nick1, store1), (nick2, store2)],
+        )
+        .map(|_| ())
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "More than one keystore for service allium-cepa"
+        );
+    }
+
+    #[test]
+    fn unknown_service() {
+        // This service doesn't exist: its keystore is empty.
+        let (nick, store, _dir) = init_service_keystore("allium-cepa", &[]);
+        let err = CTorServiceKeystoreRouter::new(
+            KeystoreId::from_str("router").unwrap(),
+            [(nick, store)],
+        )
+        .map(|_| ())
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Keystore registered for service allium-cepa holds no keys for it"
+        );
+
+        // This keystore holds the keys of a different service.
+        let (_nick, store, _dir) =
+            init_service_keystore("allium-cepa", &[("hs_ed25519_public_key", PUBKEY)]);
+        let err = CTorServiceKeystoreRouter::new(
+            KeystoreId::from_str("router").unwrap(),
+            [(HsNickname::from_str("allium-sativum").unwrap(), store)],
+        )
+        .map(|_| ())
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Keystore registered for service allium-sativum holds no keys for it"
+        );
+    }
+
+    #[test]
+    fn unsupported_operation() {
+        let (router, _dirs) = init_router();
+        let spec = spec("allium-cepa", CTorServicePath::PublicKey);
+
+        let err = router
+            .remove(&spec, &KeyType::Ed25519PublicKey.into())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Operation not supported: remove");
+
+        let err = router
+            .insert(&DummyKey, &spec, &KeyType::Ed25519PublicKey.into())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Operation not supported: insert");
+    }
+}
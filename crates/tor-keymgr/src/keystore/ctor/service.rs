@@ -4,8 +4,8 @@
 
 use crate::keystore::ctor::err::{CTorKeystoreError, MalformedServiceKeyError};
 use crate::keystore::ctor::CTorKeystore;
-use crate::keystore::fs_utils::{checked_op, FilesystemAction, FilesystemError};
-use crate::keystore::{EncodableItem, ErasedKey, KeySpecifier, Keystore, KeystoreId};
+use crate::keystore::fs_utils::{checked_op, FilesystemAction, FilesystemError, RelKeyPath};
+use crate::keystore::{EncodableItem, ErasedKey, InsertPlan, KeySpecifier, Keystore, KeystoreId};
 use crate::{CTorPath, CTorServicePath, KeyPath, Result};
 
 use fs_mistrust::Mistrust;
@@ -15,9 +15,12 @@ use tor_key_forge::{KeyType, KeystoreItemType};
 use tor_llcrypto::pk::ed25519;
 use tor_persist::hsnickname::HsNickname;
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::result::Result as StdResult;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use zeroize::Zeroizing;
 
 /// A read-only C Tor service keystore.
 ///
@@ -43,11 +46,55 @@ use std::sync::Arc;
 /// If the requested `CTorPath` is [`Service`](CTorPath::Service),
 /// but the `ItemType` and [`CTorServicePath`] are mismatched,
 /// an error is returned.
+///
+/// By default, every [`get`](Keystore::get) reads the key from disk.
+/// Use [`with_cache`](CTorServiceKeystore::with_cache)
+/// to keep the keys in memory instead.
 pub struct CTorServiceKeystore {
     /// The underlying keystore
     keystore: CTorKeystore,
     /// The nickname of the service this keystore is meant for
     nickname: HsNickname,
+    /// The keys we have parsed from our key files, if caching is enabled.
+    cache: Option<Mutex<HashMap<PathBuf, CachedKey>>>,
+}
+
+/// A key that we have parsed from one of our key files.
+///
+/// The keypair is behind an `Arc` so that it can be shared with the cache.
+#[derive(Clone)]
+enum ServiceKey {
+    /// The keypair of the service.
+    Keypair(Arc<ed25519::ExpandedKeypair>),
+    /// The public key of the service.
+    Public(ed25519::PublicKey),
+}
+
+impl ServiceKey {
+    /// Convert this key into an [`ErasedKey`].
+    ///
+    /// If the keypair is shared with the cache, the returned key is a copy of it.
+    fn into_erased(self) -> Result<ErasedKey> {
+        Ok(match self {
+            ServiceKey::Keypair(key) => {
+                let key = Arc::try_unwrap(key).or_else(|key| {
+                    let bytes = Zeroizing::new(key.to_secret_key_bytes());
+                    ed25519::ExpandedKeypair::from_secret_key_bytes(*bytes)
+                        .ok_or_else(|| internal!("cached keypair could not be copied"))
+                })?;
+                Box::new(key)
+            }
+            ServiceKey::Public(key) => Box::new(key),
+        })
+    }
+}
+
+/// The key from one of our key files, as we last parsed it.
+struct CachedKey {
+    /// The modification time and size the file had when we read it.
+    version: (SystemTime, u64),
+    /// The key we parsed from the file.
+    key: ServiceKey,
 }
 
 impl CTorServiceKeystore {
@@ -64,10 +111,167 @@ impl CTorServiceKeystore {
     ) -> Result<Self> {
         let keystore = CTorKeystore::from_path_and_mistrust(keystore_dir, mistrust, id)?;
 
-        Ok(Self { keystore, nickname })
+        Ok(Self {
+            keystore,
+            nickname,
+            cache: None,
+        })
+    }
+
+    /// Keep the keys we read in memory,
+    /// so that we don't need to read and parse them again on every [`get`](Keystore::get).
+    ///
+    /// We still look at the modification time and size of a key file on every `get`,
+    /// and read it again if either of them has changed.
+    /// The first read of each file is subject to the usual `Mistrust` checks.
+    pub fn with_cache(mut self) -> Self {
+        self.cache = Some(Default::default());
+        self
+    }
+
+    /// Check that the public key of the service matches its keypair.
+    ///
+    /// Reads both keys using [`get`](Keystore::get),
+    /// and returns an error if either of them is missing or malformed,
+    /// or if the public key is not the one derived from the keypair.
+    pub fn verify_keypair(&self) -> Result<()> {
+        let keypair: ed25519::ExpandedKeypair = self.get_service_key(
+            CTorServicePath::PrivateKey,
+            KeyType::Ed25519ExpandedKeypair.into(),
+        )?;
+        let public: ed25519::PublicKey =
+            self.get_service_key(CTorServicePath::PublicKey, KeyType::Ed25519PublicKey.into())?;
+
+        if keypair.public() != &public {
+            return Err(CTorKeystoreError::KeyMismatch {
+                nickname: self.nickname.clone(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Read the service key at `path`, which is expected to be of type `item_type`.
+    ///
+    /// Returns an error if the key is not present.
+    fn get_service_key<K: 'static>(
+        &self,
+        path: CTorServicePath,
+        item_type: KeystoreItemType,
+    ) -> Result<K> {
+        let rel_path = PathBuf::from(path.to_string());
+        let ctor_path = CTorPath::Service {
+            nickname: self.nickname.clone(),
+            path,
+        };
+        let Some(key) = self.get(&ctor_path, &item_type)? else {
+            return Err(CTorKeystoreError::MissingKey { path: rel_path }.into());
+        };
+
+        key.downcast::<K>()
+            .map(|key| *key)
+            .map_err(|_| internal!("item of type {item_type:?} has an unexpected type").into())
+    }
+
+    /// Read the key of type `item_type` from the key file at `path`.
+    ///
+    /// Returns `Ok(None)` if the file does not exist.
+    ///
+    /// If caching is enabled, and the file hasn't changed since we last read it,
+    /// returns the key we parsed then.
+    fn load_key(
+        &self,
+        path: &RelKeyPath,
+        item_type: &KeystoreItemType,
+    ) -> Result<Option<ServiceKey>> {
+        let Some(cache) = &self.cache else {
+            return load_key_uncached(path, item_type);
+        };
+
+        let meta = match checked_op!(metadata, path) {
+            Ok(meta) => meta,
+            Err(fs_mistrust::Error::NotFound(_)) => {
+                cache
+                    .lock()
+                    .expect("lock poisoned")
+                    .remove(path.rel_path_unchecked());
+                return Ok(None);
+            }
+            Err(err) => return Err(read_error(path, err)),
+        };
+        // If we can't tell when the file was modified, we can't tell whether our copy is stale.
+        let Ok(mtime) = meta.modified() else {
+            return load_key_uncached(path, item_type);
+        };
+        let version = (mtime, meta.len());
+
+        let mut cache = cache.lock().expect("lock poisoned");
+        if let Some(cached) = cache.get(path.rel_path_unchecked()) {
+            if cached.version == version {
+                return Ok(Some(cached.key.clone()));
+            }
+        }
+
+        cache.remove(path.rel_path_unchecked());
+        let key = load_key_uncached(path, item_type)?;
+        if let Some(key) = &key {
+            let cached = CachedKey {
+                version,
+                key: key.clone(),
+            };
+            cache.insert(path.rel_path_unchecked().into(), cached);
+        }
+
+        Ok(key)
     }
 }
 
+/// Read the key of type `item_type` from the key file at `path` on disk.
+///
+/// Returns `Ok(None)` if the file does not exist.
+fn load_key_uncached(
+    path: &RelKeyPath,
+    item_type: &KeystoreItemType,
+) -> Result<Option<ServiceKey>> {
+    use KeystoreItemType::*;
+
+    let key = match checked_op!(read, path) {
+        Ok(contents) => Zeroizing::new(contents),
+        Err(fs_mistrust::Error::NotFound(_)) => return Ok(None),
+        Err(err) => return Err(read_error(path, err)),
+    };
+
+    let parse_err = |err: MalformedServiceKeyError| CTorKeystoreError::MalformedKey {
+        path: path.rel_path_unchecked().into(),
+        err: err.into(),
+    };
+
+    let parsed_key = match item_type {
+        Key(KeyType::Ed25519ExpandedKeypair) => parse_ed25519_keypair(&key)
+            .map_err(parse_err)
+            .map(|key| ServiceKey::Keypair(Arc::new(key)))?,
+        Key(KeyType::Ed25519PublicKey) => parse_ed25519_public(&key)
+            .map_err(parse_err)
+            .map(ServiceKey::Public)?,
+        _ => {
+            return Err(internal!("item type was not validated by rel_path_if_supported?!").into());
+        }
+    };
+
+    Ok(Some(parsed_key))
+}
+
+/// Convert `err`, which occurred while reading `path`, into an [`Error`](crate::Error).
+fn read_error(path: &RelKeyPath, err: fs_mistrust::Error) -> crate::Error {
+    CTorKeystoreError::Filesystem(FilesystemError::FsMistrust {
+        action: FilesystemAction::Read,
+        path: path.rel_path_unchecked().into(),
+        err: err.into(),
+    })
+    .into()
+}
+
 /// Extract the key path (relative to the keystore root) from the specified result `res`,
 /// or return an error.
 ///
@@ -146,41 +350,11 @@ impl Keystore for CTorServiceKeystore {
         key_spec: &dyn KeySpecifier,
         item_type: &KeystoreItemType,
     ) -> Result<Option<ErasedKey>> {
-        use KeystoreItemType::*;
-
         let path = rel_path_if_supported!(self, key_spec, Ok(None), item_type);
 
-        let key = match checked_op!(read, path) {
-            Err(fs_mistrust::Error::NotFound(_)) => return Ok(None),
-            res => res
-                .map_err(|err| FilesystemError::FsMistrust {
-                    action: FilesystemAction::Read,
-                    path: path.rel_path_unchecked().into(),
-                    err: err.into(),
-                })
-                .map_err(CTorKeystoreError::Filesystem)?,
-        };
+        let key = self.load_key(&path, item_type)?;
 
-        let parse_err = |err: MalformedServiceKeyError| CTorKeystoreError::MalformedKey {
-            path: path.rel_path_unchecked().into(),
-            err: err.into(),
-        };
-
-        let parsed_key: ErasedKey = match item_type {
-            Key(KeyType::Ed25519ExpandedKeypair) => parse_ed25519_keypair(&key)
-                .map_err(parse_err)
-                .map(Box::new)?,
-            Key(KeyType::Ed25519PublicKey) => parse_ed25519_public(&key)
-                .map_err(parse_err)
-                .map(Box::new)?,
-            _ => {
-                return Err(
-                    internal!("item type was not validated by rel_path_if_supported?!").into(),
-                );
-            }
-        };
-
-        Ok(Some(parsed_key))
+        key.map(ServiceKey::into_erased).transpose()
     }
 
     fn insert(
@@ -192,6 +366,14 @@ impl Keystore for CTorServiceKeystore {
         Err(CTorKeystoreError::NotSupported { action: "insert" }.into())
     }
 
+    fn would_insert(
+        &self,
+        _key_spec: &dyn KeySpecifier,
+        _item_type: &KeystoreItemType,
+    ) -> Result<InsertPlan> {
+        Err(CTorKeystoreError::NotSupported { action: "insert" }.into())
+    }
+
     fn remove(
         &self,
         _key_spec: &dyn KeySpecifier,
@@ -399,6 +581,97 @@ mod tests {
         );
     }
 
+    #[test]
+    fn get_cached() {
+        let (keystore, keystore_dir) = init_keystore("foo", "allium-cepa");
+        let keystore = keystore.with_cache();
+        let key_path = keystore_dir.path().join("hs_ed25519_public_key");
+        let spec = TestCTorSpecifier(CTorPath::Service {
+            nickname: keystore.nickname.clone(),
+            path: CTorServicePath::PublicKey,
+        });
+        let key_type = KeyType::Ed25519PublicKey.into();
+
+        assert!(keystore.get(&spec, &key_type).unwrap().is_some());
+
+        // Corrupt the key, without changing the size or modification time of the file:
+        // we don't notice, because we're still using the contents we read before.
+        let mtime = fs::metadata(&key_path).unwrap().modified().unwrap();
+        let mut garbage = PUBKEY.to_vec();
+        garbage[0] ^= 0xff;
+        fs::write(&key_path, &garbage).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&key_path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert!(keystore.get(&spec, &key_type).unwrap().is_some());
+
+        // Once the modification time changes, we read the file again.
+        fs::File::options()
+            .write(true)
+            .open(&key_path)
+            .unwrap()
+            .set_modified(mtime + std::time::Duration::from_secs(10))
+            .unwrap();
+        assert!(keystore.get(&spec, &key_type).is_err());
+
+        // And if the file goes away, so does the key.
+        fs::remove_file(&key_path).unwrap();
+        assert!(keystore.get(&spec, &key_type).unwrap().is_none());
+    }
+
+    #[test]
+    fn get_cached_keypair() {
+        let (keystore, _keystore_dir) = init_keystore("foo", "allium-cepa");
+        let keystore = keystore.with_cache();
+        let spec = TestCTorSpecifier(CTorPath::Service {
+            nickname: keystore.nickname.clone(),
+            path: CTorServicePath::PrivateKey,
+        });
+        let key_type = KeyType::Ed25519ExpandedKeypair.into();
+        let get_keypair = || {
+            keystore
+                .get(&spec, &key_type)
+                .unwrap()
+                .unwrap()
+                .downcast::<ed25519::ExpandedKeypair>()
+                .unwrap()
+        };
+
+        // The second time around, we get a copy of the key we parsed the first time.
+        let first = get_keypair();
+        let second = get_keypair();
+        assert_eq!(first.public(), second.public());
+        assert_eq!(first.to_secret_key_bytes(), second.to_secret_key_bytes());
+        assert_eq!(keystore.cache.as_ref().unwrap().lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn verify_keypair() {
+        let (keystore, keystore_dir) = init_keystore("foo", "allium-cepa");
+        keystore.verify_keypair().unwrap();
+
+        // Replace the public key with one that doesn't match the private key.
+        let mut rng = tor_basic_utils::test_rng::testing_rng();
+        let other = ed25519::Keypair::generate(&mut rng);
+        let mut pubkey = PUBKEY[..PUBKEY.len() - 32].to_vec();
+        pubkey.extend_from_slice(other.verifying_key().as_bytes());
+        fs::write(keystore_dir.path().join("hs_ed25519_public_key"), pubkey).unwrap();
+
+        let err = keystore.verify_keypair().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Public key of service allium-cepa does not match its private key"
+        );
+
+        // A missing key is an error too.
+        fs::remove_file(keystore_dir.path().join("hs_ed25519_public_key")).unwrap();
+        let err = keystore.verify_keypair().unwrap_err();
+        assert_eq!(err.to_string(), "Key hs_ed25519_public_key not found");
+    }
+
     #[test]
     fn unsupported_operation() {
         let (keystore, _keystore_dir) = init_keystore("foo", "allium-cepa");
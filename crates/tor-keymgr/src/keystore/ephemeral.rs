@@ -9,6 +9,7 @@ use tor_error::internal;
 use tor_key_forge::{EncodableItem, ErasedKey, KeystoreItem, KeystoreItemType};
 
 use crate::keystore::ephemeral::err::ArtiEphemeralKeystoreError;
+use crate::keystore::InsertPlan;
 use crate::Error;
 use crate::{ArtiPath, KeyPath, KeySpecifier, Keystore, KeystoreId};
 
@@ -115,6 +116,20 @@ impl Keystore for ArtiEphemeralKeystore {
         Ok(())
     }
 
+    fn would_insert(
+        &self,
+        key_spec: &dyn KeySpecifier,
+        item_type: &KeystoreItemType,
+    ) -> Result<InsertPlan, Error> {
+        let arti_path = key_spec
+            .arti_path()
+            .map_err(ArtiEphemeralKeystoreError::ArtiPathUnavailableError)?;
+        let key_dictionary = self.key_dictionary.lock().expect("lock poisoned");
+        let replaces_existing =
+            key_dictionary.contains_key(&(arti_path.clone(), item_type.clone()));
+        Ok(InsertPlan::new(arti_path.into(), replaces_existing))
+    }
+
     fn remove(
         &self,
         key_spec: &dyn KeySpecifier,
@@ -267,6 +282,27 @@ mod tests {
         assert_eq!(key_store.list().unwrap().len(), 1);
     }
 
+    #[test]
+    fn would_insert() {
+        let key_store = ArtiEphemeralKeystore::new("test-ephemeral".to_string());
+        let expected_path = KeyPath::Arti(key_spec().arti_path().unwrap());
+
+        let plan = key_store
+            .would_insert(key_spec().as_ref(), &key_type())
+            .unwrap();
+        assert_eq!(plan.key_path(), &expected_path);
+        assert!(!plan.replaces_existing());
+        assert!(key_store.list().unwrap().is_empty());
+
+        key_store
+            .insert(key().as_ref(), key_spec().as_ref(), &key_type())
+            .unwrap();
+        let plan = key_store
+            .would_insert(key_spec().as_ref(), &key_type())
+            .unwrap();
+        assert!(plan.replaces_existing());
+    }
+
     #[test]
     fn remove() {
         let key_store = ArtiEphemeralKeystore::new("test-ephemeral".to_string());
@@ -75,7 +75,7 @@ pub use key_specifier::{
 #[cfg_attr(docsrs, doc(cfg(feature = "keymgr")))]
 pub use {
     keystore::arti::ArtiNativeKeystore,
-    keystore::Keystore,
+    keystore::{InsertPlan, Keystore},
     mgr::{KeyMgr, KeyMgrBuilder, KeyMgrBuilderError, KeystoreEntry},
     ssh_key,
 };
@@ -89,7 +89,7 @@ pub use keystore::ephemeral::ArtiEphemeralKeystore;
 
 #[cfg(all(feature = "keymgr", feature = "ctor-keystore"))]
 #[cfg_attr(docsrs, doc(cfg(all(feature = "keymgr", feature = "ctor-keystore"))))]
-pub use keystore::ctor::{CTorClientKeystore, CTorServiceKeystore};
+pub use keystore::ctor::{CTorClientKeystore, CTorServiceKeystore, CTorServiceKeystoreRouter};
 
 #[doc(hidden)]
 pub use key_specifier::derive as key_specifier_derive;
@@ -11,10 +11,12 @@ use crate::{
 use itertools::Itertools;
 use std::iter;
 use std::result::Result as StdResult;
+use std::sync::Arc;
 use tor_error::{bad_api_usage, internal, into_bad_api_usage};
 use tor_key_forge::{
     EncodableItem, Keygen, KeygenRng, KeystoreItemType, ToEncodableCert, ToEncodableKey,
 };
+use tor_rtcompat::SpawnBlocking;
 
 /// A key manager that acts as a frontend to a primary [`Keystore`](crate::Keystore) and
 /// any number of secondary [`Keystore`](crate::Keystore)s.
@@ -151,6 +153,34 @@ impl KeyMgr {
         Ok(result)
     }
 
+    /// Read a key from the key store specified by `selector`,
+    /// and try to deserialize it as `K::Key`.
+    ///
+    /// Like [`KeyMgr::get`], except only the selected key store is searched.
+    ///
+    /// Returns `Ok(None)` if the selected key store does not have the requested key.
+    ///
+    /// Returns an error if the selected keystore is not the primary keystore or one of the
+    /// configured secondary stores.
+    pub fn get_from<K: ToEncodableKey>(
+        &self,
+        key_spec: &dyn KeySpecifier,
+        selector: KeystoreSelector,
+    ) -> Result<Option<K>> {
+        let store = self.select_keystore(&selector)?;
+        let result = self.get_from_store(key_spec, &K::Key::item_type(), [store].into_iter())?;
+        if result.is_none() {
+            // If the key_spec is the specifier for the public part of a keypair,
+            // try getting the pair and extracting the public portion from it.
+            if let Some(key_pair_spec) = key_spec.keypair_specifier() {
+                return Ok(self
+                    .get_from::<K::KeyPair>(&*key_pair_spec, selector)?
+                    .map(|k| k.into()));
+            }
+        }
+        Ok(result)
+    }
+
     /// Retrieve the specified keystore entry, and try to deserialize it as `K::Key`.
     ///
     /// The key returned is retrieved from the key store specified in the [`KeystoreEntry`].
@@ -330,6 +360,31 @@ impl KeyMgr {
             .collect::<Result<Vec<_>>>()
     }
 
+    /// Run `op` on this `KeyMgr` on `runtime`'s blocking thread pool,
+    /// and return its result.
+    ///
+    /// All `KeyMgr` operations access the underlying key stores synchronously,
+    /// which typically means they do blocking filesystem I/O
+    /// (for example, [`list_matching`](KeyMgr::list_matching)
+    /// walks the entire directory tree of each key store).
+    /// Async code should use this function instead of calling them directly,
+    /// to avoid stalling the executor.
+    ///
+    /// Returns an error if `op` panics.
+    pub async fn run_blocking<R, F, T>(self: &Arc<Self>, runtime: &R, op: F) -> Result<T>
+    where
+        R: SpawnBlocking,
+        F: FnOnce(&KeyMgr) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let keymgr = Arc::clone(self);
+
+        runtime
+            .spawn_blocking(move || op(&keymgr))
+            .await
+            .map_err(|_| internal!("key manager operation panicked").into())
+    }
+
     /// Describe the specified key.
     ///
     /// Returns [`KeyPathError::Unrecognized`] if none of the registered
@@ -944,6 +999,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn run_blocking() {
+        tor_rtcompat::test_with_one_runtime!(|rt| async move {
+            let mgr = KeyMgrBuilder::default()
+                .primary_store(Box::<Keystore1>::default())
+                .build()
+                .unwrap();
+            let mgr = Arc::new(mgr);
+
+            let old_key = mgr
+                .run_blocking(&rt, |mgr| {
+                    mgr.insert(
+                        TestItem::new("coot"),
+                        &TestKeySpecifier1,
+                        KeystoreSelector::Primary,
+                        true,
+                    )
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(old_key.is_none());
+
+            let key = mgr
+                .run_blocking(&rt, |mgr| mgr.get::<TestItem>(&TestKeySpecifier1))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(key.map(|k| k.meta), Some("keystore1_coot".to_string()));
+
+            // A panic in the operation is reported as an error.
+            let res = mgr.run_blocking(&rt, |_| -> u8 { panic!("oops") }).await;
+            assert!(res.is_err());
+        });
+    }
+
     #[test]
     fn insert_and_get() {
         let mut builder = KeyMgrBuilder::default().primary_store(Box::<Keystore1>::default());
@@ -1067,6 +1158,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn get_from() {
+        let mut builder = KeyMgrBuilder::default().primary_store(Box::<Keystore1>::default());
+
+        builder
+            .secondary_stores()
+            .extend([Keystore2::new_boxed(), Keystore3::new_boxed()]);
+
+        let mgr = builder.build().unwrap();
+        let keystore2 = KeystoreId::from_str("keystore2").unwrap();
+        let keystore3 = KeystoreId::from_str("keystore3").unwrap();
+
+        // Insert a key into Keystore2
+        let old_key = mgr
+            .insert(
+                TestItem::new("coot"),
+                &TestKeySpecifier1,
+                KeystoreSelector::Id(&keystore2),
+                true,
+            )
+            .unwrap();
+        assert!(old_key.is_none());
+
+        // The key is found if we look in Keystore2...
+        assert_eq!(
+            mgr.get_from::<TestItem>(&TestKeySpecifier1, KeystoreSelector::Id(&keystore2))
+                .unwrap()
+                .map(|k| k.meta),
+            Some("keystore2_coot".to_string()),
+        );
+
+        // ...but not if we look in the other stores
+        assert!(mgr
+            .get_from::<TestItem>(&TestKeySpecifier1, KeystoreSelector::Primary)
+            .unwrap()
+            .is_none());
+        assert!(mgr
+            .get_from::<TestItem>(&TestKeySpecifier1, KeystoreSelector::Id(&keystore3))
+            .unwrap()
+            .is_none());
+
+        // Looking in a keystore that doesn't exist is an error
+        let nonexistent = KeystoreId::from_str("keystore4").unwrap();
+        assert!(mgr
+            .get_from::<TestItem>(&TestKeySpecifier1, KeystoreSelector::Id(&nonexistent))
+            .is_err());
+    }
+
     #[test]
     fn remove() {
         let mut builder = KeyMgrBuilder::default().primary_store(Box::<Keystore1>::default());
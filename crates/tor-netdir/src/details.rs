@@ -34,6 +34,7 @@
 use std::sync::Arc;
 
 use tor_linkspec::HasRelayIds;
+use tor_netdoc::doc::microdesc::MdDigest;
 use tor_netdoc::{doc::netstatus, types::policy::PortPolicy};
 
 use crate::{Relay, SubnetConfig};
@@ -168,6 +169,12 @@ impl<'a> UncheckedRelayDetails<'a> {
     pub fn is_dir_cache(&self) -> bool {
         rs_is_dir_cache(self.0.rs)
     }
+    /// Return the digest of the microdescriptor that the consensus lists for this relay.
+    ///
+    /// We may or may not have this microdescriptor.
+    pub fn md_digest(&self) -> &'a MdDigest {
+        self.0.rs.md_digest()
+    }
 }
 
 /// Return true if `rs` is usable as a directory cache.
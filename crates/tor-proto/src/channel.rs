@@ -61,18 +61,21 @@ mod handshake;
 pub mod padding;
 pub mod params;
 mod reactor;
+mod traffic;
 mod unique_id;
 
 pub use crate::channel::params::*;
 use crate::channel::reactor::{BoxedChannelSink, BoxedChannelStream, Reactor};
+use crate::channel::traffic::TrafficRecorder;
+pub(crate) use crate::channel::traffic::FIXED_CELL_LEN;
+pub use crate::channel::traffic::{TrafficCounter, TrafficSnapshot};
 pub use crate::channel::unique_id::UniqId;
 use crate::memquota::{ChannelAccount, CircuitAccount, SpecificAccount as _};
 use crate::util::err::ChannelClosed;
 use crate::util::oneshot_broadcast;
 use crate::util::ts::AtomicOptTimestamp;
 use crate::{circuit, ClockSkew};
-use crate::{Error, Result};
-use safelog::sensitive as sv;
+use crate::{Error, IdentityMismatch, Result};
 use std::future::IntoFuture;
 use std::pin::Pin;
 use std::sync::{Mutex, MutexGuard};
@@ -204,6 +207,10 @@ pub struct Channel {
     clock_skew: ClockSkew,
     /// The time when this channel was successfully completed
     opened_at: coarsetime::Instant,
+    /// The number of bytes that we have read and written on this channel.
+    ///
+    /// Updated by the reactor's cell codec.
+    traffic: Arc<TrafficCounter>,
     /// Mutable state used by the `Channel.
     mutable: Mutex<MutableDetails>,
 
@@ -388,6 +395,9 @@ pub struct ChannelBuilder {
     /// TODO: at some point, check this against the addresses in the netinfo
     /// cell too.
     target: Option<tor_linkspec::ChannelMethod>,
+    /// If present, a counter to which every channel we build
+    /// should report its traffic, in addition to its own counter.
+    traffic: Option<Arc<TrafficCounter>>,
 }
 
 impl ChannelBuilder {
@@ -412,6 +422,15 @@ impl ChannelBuilder {
         self.target = Some(target);
     }
 
+    /// Make the channel built by this builder count its traffic in `counter`,
+    /// as well as in its own [`Channel::traffic`].
+    ///
+    /// Use the same counter with several builders
+    /// to keep a total for all of their channels.
+    pub fn set_traffic_counter(&mut self, counter: Arc<TrafficCounter>) {
+        self.traffic = Some(counter);
+    }
+
     /// Launch a new client handshake over a TLS stream.
     ///
     /// After calling this function, you'll need to call `connect()` on
@@ -429,7 +448,13 @@ impl ChannelBuilder {
         T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
         S: CoarseTimeProvider + SleepProvider,
     {
-        handshake::OutboundClientHandshake::new(tls, self.target, sleep_prov, memquota)
+        handshake::OutboundClientHandshake::new(
+            tls,
+            self.target,
+            sleep_prov,
+            memquota,
+            TrafficRecorder::new(self.traffic),
+        )
     }
 }
 
@@ -449,6 +474,7 @@ impl Channel {
         clock_skew: ClockSkew,
         sleep_prov: S,
         memquota: ChannelAccount,
+        traffic: Arc<TrafficCounter>,
     ) -> Result<(Arc<Self>, reactor::Reactor<S>)>
     where
         S: CoarseTimeProvider + SleepProvider,
@@ -480,6 +506,7 @@ impl Channel {
             peer_id,
             clock_skew,
             opened_at: coarsetime::Instant::now(),
+            traffic,
             mutable: Mutex::new(mutable),
             details: Arc::clone(&details),
         });
@@ -534,6 +561,12 @@ impl Channel {
         self.opened_at.elapsed().into()
     }
 
+    /// Return the number of bytes that we have read and written on this channel so far,
+    /// including those of its handshake.
+    pub fn traffic(&self) -> TrafficSnapshot {
+        self.traffic.snapshot()
+    }
+
     /// Return a ClockSkew declaring how much clock skew the other side of this channel
     /// claimed that we had when we negotiated the connection.
     pub fn clock_skew(&self) -> ClockSkew {
@@ -758,6 +791,7 @@ impl Channel {
             peer_id,
             clock_skew: ClockSkew::None,
             opened_at: coarsetime::Instant::now(),
+            traffic: Default::default(),
             mutable: Default::default(),
             details,
         };
@@ -780,17 +814,15 @@ where
         match my_ident.identity(id_type) {
             Some(actual) if actual == desired => {}
             Some(actual) => {
-                return Err(Error::ChanMismatch(format!(
-                    "Identity {} does not match target {}",
-                    sv(actual),
-                    sv(desired)
-                )));
+                return Err(Error::ChanMismatch(IdentityMismatch::Different {
+                    wanted: desired.to_owned(),
+                    actual: actual.to_owned(),
+                }));
             }
             None => {
-                return Err(Error::ChanMismatch(format!(
-                    "Peer does not have {} identity",
-                    id_type
-                )))
+                return Err(Error::ChanMismatch(IdentityMismatch::Missing {
+                    wanted: desired.to_owned(),
+                }))
             }
         }
     }
@@ -837,6 +869,8 @@ pub(crate) mod test {
     use crate::util::fake_mq;
     use tor_cell::chancell::msg::HandshakeType;
     use tor_cell::chancell::{msg, AnyChanCell};
+    use tor_linkspec::RelayId;
+    use tor_llcrypto::pk::rsa::RsaIdentity;
     use tor_rtcompat::PreferredRuntime;
 
     /// Make a new fake reactor-less channel.  For testing only, obviously.
@@ -857,6 +891,7 @@ pub(crate) mod test {
             peer_id,
             clock_skew: ClockSkew::None,
             opened_at: coarsetime::Instant::now(),
+            traffic: Default::default(),
             mutable: Default::default(),
             details,
         }
@@ -925,6 +960,22 @@ pub(crate) mod test {
         assert!(chan.check_match(&t1).is_ok());
         assert!(chan.check_match(&t2).is_err());
         assert!(chan.check_match(&t3).is_err());
+
+        // Only the RSA identity is wrong: the error should say which one we
+        // wanted, and which one we got.
+        let t4 = OwnedChanTarget::builder()
+            .ed_identity([6; 32].into())
+            .rsa_identity([3; 20].into())
+            .build()
+            .unwrap();
+        let Err(Error::ChanMismatch(mismatch)) = chan.check_match(&t4) else {
+            panic!("expected an identity mismatch");
+        };
+        let wanted: RelayId = RsaIdentity::from([3; 20]).into();
+        let actual: RelayId = RsaIdentity::from([10; 20]).into();
+        assert_eq!(mismatch.wanted(), &wanted);
+        assert_eq!(mismatch.actual(), Some(&actual));
+        assert!(matches!(mismatch, IdentityMismatch::Different { .. }));
     }
 
     #[test]
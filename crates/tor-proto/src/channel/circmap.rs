@@ -33,6 +33,18 @@ pub(super) enum CircIdRange {
     // protocol version 4.
 }
 
+#[cfg(feature = "relay")]
+impl CircIdRange {
+    /// Return true if `id` is one that we would allocate from this range.
+    fn contains(&self, id: CircId) -> bool {
+        let is_high = u32::from(id) & 0x8000_0000 != 0;
+        match self {
+            CircIdRange::Low => !is_high,
+            CircIdRange::High => is_high,
+        }
+    }
+}
+
 impl rand::distributions::Distribution<CircId> for CircIdRange {
     /// Return a random circuit ID in the appropriate range.
     fn sample<R: Rng + ?Sized>(&self, mut rng: &mut R) -> CircId {
@@ -157,6 +169,37 @@ impl CircMap {
         Err(Error::IdRangeFull)
     }
 
+    /// Add a new open circuit to this map, under a circuit ID that the other
+    /// side of the channel chose.
+    ///
+    /// This is what a responder needs when it receives a CREATE cell.  The ID
+    /// must be outside the range that we allocate from ourselves, and must not
+    /// already be in use.
+    //
+    // TODO #1467: Nothing calls this yet: a relay-side channel reactor will,
+    // when it accepts a CREATE2 cell.
+    #[cfg(feature = "relay")]
+    #[allow(dead_code)]
+    pub(super) fn add_ent_with_id(&mut self, id: CircId, sink: CircuitRxSender) -> Result<()> {
+        if self.range.contains(id) {
+            return Err(Error::ChanProto(format!(
+                "Peer chose circuit ID {} from our range",
+                id
+            )));
+        }
+        match self.m.entry(id) {
+            Entry::Occupied(_) => Err(Error::ChanProto(format!(
+                "Peer chose circuit ID {} which is already in use",
+                id
+            ))),
+            Entry::Vacant(ent) => {
+                ent.insert(CircEnt::Open(sink));
+                self.open_count += 1;
+                Ok(())
+            }
+        }
+    }
+
     /// Testing only: install an entry in this circuit map without regard
     /// for consistency.
     #[cfg(test)]
@@ -184,18 +227,27 @@ impl CircMap {
         // this. hash_map::Entry seems like it could be better, but
         // there seems to be no way to replace the object in-place as
         // a consuming function of itself.
-        let ok = matches!(self.m.get(&id), Some(CircEnt::Opening(_, _)));
-        if ok {
-            if let Some(CircEnt::Opening(oneshot, sink)) = self.m.remove(&id) {
-                self.m.insert(id, CircEnt::Open(sink));
-                Ok(oneshot)
-            } else {
-                panic!("internal error: inconsistent circuit state");
+        match self.m.get(&id) {
+            Some(CircEnt::Opening(_, _)) => {}
+            // The circuit has already been created: whatever this cell says,
+            // we must not hand it to the circuit, which has already set up
+            // its cryptographic state.
+            Some(CircEnt::Open(_)) => {
+                return Err(Error::ChanProto(
+                    "Duplicate CREATED* cell on already open circuit".into(),
+                ))
+            }
+            _ => {
+                return Err(Error::ChanProto(
+                    "Unexpected CREATED* cell not on opening circuit".into(),
+                ))
             }
+        }
+        if let Some(CircEnt::Opening(oneshot, sink)) = self.m.remove(&id) {
+            self.m.insert(id, CircEnt::Open(sink));
+            Ok(oneshot)
         } else {
-            Err(Error::ChanProto(
-                "Unexpected CREATED* cell not on opening circuit".into(),
-            ))
+            panic!("internal error: inconsistent circuit state");
         }
     }
 
@@ -226,8 +278,20 @@ impl CircMap {
         self.open_count
     }
 
-    // TODO: Eventually if we want relay support, we'll need to support
-    // circuit IDs chosen by somebody else. But for now, we don't need those.
+    /// Return an iterator over every entry in this map, along with its circuit ID.
+    ///
+    /// This is meant for operations that apply to the whole channel, like
+    /// telling every circuit that the channel is closing.
+    ///
+    /// The entries are visited in no particular order.
+    ///
+    /// Callers must not turn an entry into [`CircEnt::DestroySent`] (or back)
+    /// through this iterator, since that would make
+    /// [`open_ent_count`](CircMap::open_ent_count) wrong;
+    /// use [`destroy_sent`](CircMap::destroy_sent) or [`remove`](CircMap::remove) instead.
+    pub(super) fn iter_mut(&mut self) -> impl Iterator<Item = (CircId, &mut CircEnt)> + '_ {
+        self.m.iter_mut().map(|(id, ent)| (*id, ent))
+    }
 }
 
 #[cfg(test)]
@@ -320,4 +384,146 @@ mod test {
         let adv = map_high.advance_from_opening(CircId::new(77).unwrap());
         assert!(adv.is_err());
     }
+
+    #[test]
+    fn advance_errors() {
+        let mut map = CircMap::new(CircIdRange::High);
+        let mut rng = testing_rng();
+        let (csnd, _) = oneshot::channel();
+        let (snd, _) = fake_mpsc(8);
+        let id = map.add_ent(&mut rng, csnd, snd).unwrap();
+        assert!(map.advance_from_opening(id).is_ok());
+
+        // A second CREATED* cell on the same circuit is reported as a duplicate.
+        let e = map.advance_from_opening(id).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Channel protocol violation: Duplicate CREATED* cell on already open circuit"
+        );
+
+        // A CREATED* cell for a circuit we don't know about is reported as unexpected.
+        let e = map
+            .advance_from_opening(CircId::new(77).unwrap())
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Channel protocol violation: Unexpected CREATED* cell not on opening circuit"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "relay")]
+    fn add_with_peer_chosen_id() {
+        // We initiated this channel, so the peer chooses low IDs.
+        let mut map = CircMap::new(CircIdRange::High);
+        let id = CircId::new(77).unwrap();
+
+        let (snd, _) = fake_mpsc(8);
+        map.add_ent_with_id(id, snd).unwrap();
+        assert!(matches!(*map.get_mut(id).unwrap(), CircEnt::Open(_)));
+        assert_eq!(map.open_ent_count(), 1);
+
+        // Can't reuse an ID that is already present.
+        let (snd, _) = fake_mpsc(8);
+        assert!(matches!(
+            map.add_ent_with_id(id, snd),
+            Err(Error::ChanProto(_))
+        ));
+
+        // Not even if we've sent a DESTROY on it.
+        let half_closed = CircId::new(78).unwrap();
+        map.destroy_sent(half_closed, HalfCirc::new(1));
+        let (snd, _) = fake_mpsc(8);
+        assert!(map.add_ent_with_id(half_closed, snd).is_err());
+        assert_eq!(map.open_ent_count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "relay")]
+    fn add_with_peer_chosen_id_wrong_range() {
+        let low = CircId::new(77).unwrap();
+        let high = CircId::new(0x8000_0077).unwrap();
+
+        let mut map_high = CircMap::new(CircIdRange::High);
+        let (snd, _) = fake_mpsc(8);
+        assert!(map_high.add_ent_with_id(high, snd).is_err());
+        let (snd, _) = fake_mpsc(8);
+        assert!(map_high.add_ent_with_id(low, snd).is_ok());
+
+        let mut map_low = CircMap::new(CircIdRange::Low);
+        let (snd, _) = fake_mpsc(8);
+        assert!(map_low.add_ent_with_id(low, snd).is_err());
+        let (snd, _) = fake_mpsc(8);
+        assert!(map_low.add_ent_with_id(high, snd).is_ok());
+        assert_eq!(map_low.open_ent_count(), 1);
+    }
+
+    #[test]
+    fn removed_ids_can_be_reused() {
+        let mut map = CircMap::new(CircIdRange::High);
+        let id = CircId::new(77).unwrap();
+
+        let (snd, _) = fake_mpsc(8);
+        map.add_ent_with_id(id, snd).unwrap();
+        assert!(matches!(map.remove(id), Some(CircEnt::Open(_))));
+        assert!(map.get_mut(id).is_none());
+        assert_eq!(map.open_ent_count(), 0);
+
+        // Once removed, the ID is free again.
+        let (snd, _) = fake_mpsc(8);
+        map.add_ent_with_id(id, snd).unwrap();
+        assert_eq!(map.open_ent_count(), 1);
+
+        // After we send a DESTROY, the ID stays reserved until the other side
+        // answers with a DESTROY of its own, and we remove the entry.
+        map.destroy_sent(id, HalfCirc::new(1));
+        assert_eq!(map.open_ent_count(), 0);
+        let (snd, _) = fake_mpsc(8);
+        assert!(map.add_ent_with_id(id, snd).is_err());
+        assert!(matches!(map.remove(id), Some(CircEnt::DestroySent(_))));
+        assert_eq!(map.open_ent_count(), 0);
+        let (snd, _) = fake_mpsc(8);
+        map.add_ent_with_id(id, snd).unwrap();
+        assert_eq!(map.open_ent_count(), 1);
+
+        // Removing something that isn't there is harmless.
+        assert!(map.remove(CircId::new(78).unwrap()).is_none());
+        assert_eq!(map.open_ent_count(), 1);
+    }
+
+    #[test]
+    fn iterate_entries() {
+        let mut map = CircMap::new(CircIdRange::High);
+        let mut rng = testing_rng();
+        assert_eq!(map.iter_mut().count(), 0);
+
+        let mut receivers = Vec::new();
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let (csnd, _) = oneshot::channel();
+            let (snd, rcv) = fake_mpsc(8);
+            ids.push(map.add_ent(&mut rng, csnd, snd).unwrap());
+            receivers.push(rcv);
+        }
+        let _ = map.advance_from_opening(ids[0]).unwrap();
+        let destroyed = CircId::new(77).unwrap();
+        map.destroy_sent(destroyed, HalfCirc::new(1));
+
+        let mut seen: Vec<u32> = map.iter_mut().map(|(id, _)| id.into()).collect();
+        seen.sort();
+        let mut expected: Vec<u32> = ids.iter().map(|id| (*id).into()).collect();
+        expected.push(destroyed.into());
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        // Every entry we get back is the one stored under that ID.
+        for (id, ent) in map.iter_mut() {
+            match ent {
+                CircEnt::Open(_) => assert_eq!(id, ids[0]),
+                CircEnt::Opening(_, _) => assert!(ids[1..].contains(&id)),
+                CircEnt::DestroySent(_) => assert_eq!(id, destroyed),
+            }
+        }
+        assert_eq!(map.open_ent_count(), 4);
+    }
 }
@@ -8,6 +8,8 @@ use tor_cell::chancell::{codec, ChanCell, ChanMsg};
 use asynchronous_codec as futures_codec;
 use bytes::BytesMut;
 
+use super::traffic::TrafficRecorder;
+
 /// An error from a ChannelCodec.
 ///
 /// This is a separate error type for now because I suspect that we'll want to
@@ -40,6 +42,9 @@ pub(crate) enum CodecError {
 pub(crate) struct ChannelCodec<IN, OUT> {
     /// The cell codec that we'll use to encode and decode our cells.
     inner: codec::ChannelCodec,
+    /// The counters to which we report the bytes of every cell
+    /// that we encode or decode.
+    traffic: TrafficRecorder,
     /// Tells the compiler that we're using IN, and we might
     /// consume values of type IN.
     _phantom_in: PhantomData<fn(IN)>,
@@ -51,18 +56,31 @@ pub(crate) struct ChannelCodec<IN, OUT> {
 impl<IN, OUT> ChannelCodec<IN, OUT> {
     /// Create a new ChannelCodec with a given link protocol.
     pub(crate) fn new(link_proto: u16) -> Self {
+        Self::new_with_traffic(link_proto, TrafficRecorder::default())
+    }
+
+    /// Create a new ChannelCodec with a given link protocol,
+    /// which reports its traffic to `traffic`.
+    pub(crate) fn new_with_traffic(link_proto: u16, traffic: TrafficRecorder) -> Self {
         ChannelCodec {
             inner: codec::ChannelCodec::new(link_proto),
+            traffic,
             _phantom_in: PhantomData,
             _phantom_out: PhantomData,
         }
     }
 
+    /// Return the counters to which this codec reports its traffic.
+    pub(crate) fn traffic(&self) -> &TrafficRecorder {
+        &self.traffic
+    }
+
     /// Consume this codec, and return a new one that sends and receives
     /// different message types.
     pub(crate) fn change_message_types<IN2, OUT2>(self) -> ChannelCodec<IN2, OUT2> {
         ChannelCodec {
             inner: self.inner,
+            traffic: self.traffic,
             _phantom_in: PhantomData,
             _phantom_out: PhantomData,
         }
@@ -77,9 +95,11 @@ where
     type Error = CodecError;
 
     fn encode(&mut self, item: Self::Item<'_>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let len_before = dst.len();
         self.inner
             .write_cell(item, dst)
             .map_err(CodecError::EncCell)?;
+        self.traffic.note_written(dst.len() - len_before);
         Ok(())
     }
 }
@@ -92,7 +112,12 @@ where
     type Error = CodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        self.inner.decode_cell(src).map_err(CodecError::DecCell)
+        let len_before = src.len();
+        let cell = self.inner.decode_cell(src).map_err(CodecError::DecCell)?;
+        if cell.is_some() {
+            self.traffic.note_read(len_before - src.len());
+        }
+        Ok(cell)
     }
 }
 
@@ -209,6 +234,10 @@ pub(crate) mod test {
 
             framed.flush().await.unwrap();
 
+            let traffic = framed.codec().traffic().channel_counter().snapshot();
+            assert_eq!(traffic.read, 0);
+            assert_eq!(traffic.written, 514 + 8);
+
             let data = framed.into_inner().into_response();
 
             assert_eq!(&data[0..10], &hex!("00000007 04 0200000000")[..]);
@@ -235,6 +264,10 @@ pub(crate) mod test {
             assert_eq!(nocerts.circid(), None);
             assert_eq!(nocerts.msg().cmd(), ChanCmd::CERTS);
 
+            let traffic = framed.codec().traffic().channel_counter().snapshot();
+            assert_eq!(traffic.read, 514 + 8);
+            assert_eq!(traffic.written, 0);
+
             assert!(framed.into_inner().all_consumed());
         });
     }
@@ -8,6 +8,7 @@ use tor_cell::restricted_msg;
 use tor_error::internal;
 
 use crate::channel::codec::{self, ChannelCodec, CodecError};
+use crate::channel::traffic::TrafficRecorder;
 use crate::channel::UniqId;
 use crate::memquota::ChannelAccount;
 use crate::util::skew::ClockSkew;
@@ -54,6 +55,9 @@ pub struct OutboundClientHandshake<
     /// Declared target method for this channel, if any.
     target_method: Option<ChannelMethod>,
 
+    /// The counters to which we report this channel's traffic.
+    traffic: TrafficRecorder,
+
     /// Logging identifier for this stream.  (Used for logging only.)
     unique_id: UniqId,
 }
@@ -161,10 +165,12 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
         target_method: Option<ChannelMethod>,
         sleep_prov: S,
         memquota: ChannelAccount,
+        traffic: TrafficRecorder,
     ) -> Self {
         Self {
             tls,
             target_method,
+            traffic,
             unique_id: UniqId::new(),
             sleep_prov,
             memquota,
@@ -196,16 +202,15 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
         // Send versions cell
         {
             let my_versions = msg::Versions::new(LINK_PROTOCOLS)
-                .map_err(|e| Error::from_cell_enc(e, "versions message"))?;
+                .map_err(|e| Error::from_cell_enc(e, "versions message"))?
+                .encode_for_handshake()
+                .map_err(|e| Error::from_cell_enc(e.into(), "versions message"))?;
             self.tls
-                .write_all(
-                    &my_versions
-                        .encode_for_handshake()
-                        .map_err(|e| Error::from_cell_enc(e.into(), "versions message"))?,
-                )
+                .write_all(&my_versions)
                 .await
                 .map_err(io_err_to_handshake)?;
             self.tls.flush().await.map_err(io_err_to_handshake)?;
+            self.traffic.note_written(my_versions.len());
         }
         let versions_flushed_at = coarsetime::Instant::now();
         let versions_flushed_wallclock = now_fn();
@@ -238,6 +243,7 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
                 .read_exact(&mut msg)
                 .await
                 .map_err(io_err_to_handshake)?;
+            self.traffic.note_read(hdr.len() + msg.len());
             let mut reader = Reader::from_slice(&msg);
             reader
                 .extract()
@@ -254,7 +260,10 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
         // Now we can switch to using a "Framed". We can ignore the
         // AsyncRead/AsyncWrite aspects of the tls, and just treat it
         // as a stream and a sink for cells.
-        let codec = ChannelCodec::<HandshakeMsg, HandshakeMsg>::new(link_protocol);
+        let codec = ChannelCodec::<HandshakeMsg, HandshakeMsg>::new_with_traffic(
+            link_protocol,
+            self.traffic.clone(),
+        );
         let mut tls = futures_codec::Framed::new(self.tls, codec);
 
         // Read until we have the netinfo cells.
@@ -554,7 +563,7 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
         // We enforce that the relay proved that it has every ID that we wanted:
         // it may also have additional IDs that we didn't ask for.
         match super::check_id_match_helper(&actual_identity, peer) {
-            Err(Error::ChanMismatch(msg)) => Err(Error::HandshakeProto(msg)),
+            Err(Error::ChanMismatch(e)) => Err(Error::HandshakeProto(e.to_string())),
             other => other,
         }?;
 
@@ -630,6 +639,7 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
             self.unique_id, self.ed25519_id, self.rsa_id
         );
 
+        let traffic = Arc::clone(self.tls.codec().traffic().channel_counter());
         let (tls_sink, tls_stream) = self.tls.split();
 
         let mut peer_builder = OwnedChanTargetBuilder::default();
@@ -654,6 +664,7 @@ impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static, S: CoarseTimeProvider +
             self.clock_skew,
             self.sleep_prov,
             self.memquota,
+            traffic,
         )
     }
 }
@@ -667,6 +678,7 @@ pub(super) mod test {
 
     use super::*;
     use crate::channel::codec::test::MsgBuf;
+    use crate::channel::TrafficCounter;
     use crate::util::fake_mq;
     use crate::Result;
     use tor_cell::chancell::msg;
@@ -718,12 +730,24 @@ pub(super) mod test {
             // netinfo cell -- quite minimal.
             add_padded(&mut buf, NETINFO_PREFIX);
             let mb = MsgBuf::new(&buf[..]);
-            let handshake = OutboundClientHandshake::new(mb, None, rt.clone(), fake_mq());
+            let total = Arc::new(TrafficCounter::new());
+            let traffic = TrafficRecorder::new(Some(Arc::clone(&total)));
+            let handshake = OutboundClientHandshake::new(mb, None, rt.clone(), fake_mq(), traffic);
             let unverified = handshake.connect(|| now).await?;
 
             assert_eq!(unverified.link_protocol, 5);
             // No timestamp in the NETINFO, so no skew.
             assert_eq!(unverified.clock_skew(), ClockSkew::None);
+            // We read every cell, and wrote only our VERSIONS cell.
+            let traffic = unverified
+                .tls
+                .codec()
+                .traffic()
+                .channel_counter()
+                .snapshot();
+            assert_eq!(traffic.read, buf.len() as u64);
+            assert_eq!(traffic.written, 9);
+            assert_eq!(total.snapshot(), traffic);
 
             // Try again with an authchallenge cell and some padding.
             let mut buf = Vec::new();
@@ -734,7 +758,8 @@ pub(super) mod test {
             buf.extend_from_slice(VPADDING);
             add_padded(&mut buf, NETINFO_PREFIX_WITH_TIME);
             let mb = MsgBuf::new(&buf[..]);
-            let handshake = OutboundClientHandshake::new(mb, None, rt.clone(), fake_mq());
+            let handshake =
+                OutboundClientHandshake::new(mb, None, rt.clone(), fake_mq(), Default::default());
             let unverified = handshake.connect(|| now).await?;
             // Correct timestamp in the NETINFO, so no skew.
             assert_eq!(unverified.clock_skew(), ClockSkew::None);
@@ -742,7 +767,8 @@ pub(super) mod test {
             // Now pretend our clock is fast.
             let now2 = now + Duration::from_secs(3600);
             let mb = MsgBuf::new(&buf[..]);
-            let handshake = OutboundClientHandshake::new(mb, None, rt.clone(), fake_mq());
+            let handshake =
+                OutboundClientHandshake::new(mb, None, rt.clone(), fake_mq(), Default::default());
             let unverified = handshake.connect(|| now2).await?;
             assert_eq!(
                 unverified.clock_skew(),
@@ -758,7 +784,8 @@ pub(super) mod test {
         S: CoarseTimeProvider + SleepProvider,
     {
         let mb = MsgBuf::new(input);
-        let handshake = OutboundClientHandshake::new(mb, None, sleep_prov, fake_mq());
+        let handshake =
+            OutboundClientHandshake::new(mb, None, sleep_prov, fake_mq(), Default::default());
         handshake.connect(SystemTime::now).await.err().unwrap()
     }
 
@@ -13,7 +13,7 @@ use crate::circuit::halfcirc::HalfCirc;
 use crate::util::err::ReactorError;
 use crate::util::oneshot_broadcast;
 use crate::{Error, Result};
-use tor_async_utils::SinkPrepareExt as _;
+use tor_async_utils::{SinkPrepareExt as _, SinkTrySend as _};
 use tor_cell::chancell::msg::{Destroy, DestroyReason, PaddingNegotiate};
 use tor_cell::chancell::ChanMsg;
 use tor_cell::chancell::{msg::AnyChanMsg, AnyChanCell, CircId};
@@ -35,7 +35,10 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use crate::channel::{codec::CodecError, padding, params::*, unique_id, ChannelDetails};
-use crate::circuit::{celltypes::CreateResponse, CircuitRxSender};
+use crate::circuit::{
+    celltypes::{ClientCircChanMsg, CreateResponse},
+    CircuitRxSender,
+};
 use tracing::{debug, trace};
 
 /// A boxed trait object that can provide `ChanCell`s.
@@ -181,6 +184,7 @@ impl<S: SleepProvider> Reactor<S> {
                 Err(ReactorError::Err(e)) => break Err(e),
             }
         };
+        self.notify_circuits_of_close();
         debug!("{}: Reactor stopped: {:?}", &self, result);
         result
     }
@@ -374,6 +378,14 @@ impl<S: SleepProvider> Reactor<S> {
             return Err(Error::ChanProto("'Created' cell without circuit ID".into()));
         };
 
+        if let Some(mut ent) = self.circs.get_mut(circid) {
+            if let CircEnt::DestroySent(hs) = &mut *ent {
+                // We gave up on this circuit (for example, because its CREATED*
+                // cell took too long to arrive), so there's nobody to tell.
+                return hs.receive_cell();
+            }
+        }
+
         let target = self.circs.advance_from_opening(circid)?;
         let created = msg.try_into()?;
         // TODO(nickm) I think that this one actually means the other side
@@ -452,6 +464,32 @@ impl<S: SleepProvider> Reactor<S> {
         Ok(())
     }
 
+    /// Tell every open circuit on this channel that the channel has closed,
+    /// by giving it a DESTROY message.
+    ///
+    /// (The circuits would notice anyway once we drop their senders,
+    /// but this way they learn why.)
+    fn notify_circuits_of_close(&mut self) {
+        // (We can't use `&self` for logging while we're iterating over `self.circs`.)
+        let unique_id = self.unique_id;
+        for (id, ent) in self.circs.iter_mut() {
+            if let CircEnt::Open(sink) = ent {
+                let destroy = Destroy::new(DestroyReason::CHANNEL_CLOSED);
+                // Don't wait for a circuit that isn't reading: the channel is gone anyway.
+                if Pin::new(sink)
+                    .try_send(ClientCircChanMsg::Destroy(destroy))
+                    .is_err()
+                {
+                    trace!(
+                        "{:?}: Couldn't tell circuit {} that we closed",
+                        unique_id,
+                        id
+                    );
+                }
+            }
+        }
+    }
+
     /// Update disused timestamp with current time if this channel is no longer used
     fn update_disused_since(&self) {
         if self.circs.open_ent_count() == 0 {
@@ -510,6 +548,7 @@ pub(crate) mod test {
             crate::ClockSkew::None,
             runtime,
             fake_mq(),
+            Default::default(),
         )
         .expect("channel create failed");
         (chan, reactor, recv1, send2)
@@ -555,6 +594,30 @@ pub(crate) mod test {
         });
     }
 
+    // Open circuits are told when the channel closes.
+    #[test]
+    fn shutdown_notifies_circuits() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            let (chan, mut reactor, _output, _input) = new_reactor(rt);
+            let (snd, mut rcv) = fake_mpsc(64);
+            reactor
+                .circs
+                .put_unchecked(CircId::new(13).unwrap(), CircEnt::Open(snd));
+
+            chan.terminate();
+            reactor.run().await.unwrap();
+
+            match rcv.next().await {
+                Some(ClientCircChanMsg::Destroy(d)) => {
+                    assert_eq!(d.reason(), DestroyReason::CHANNEL_CLOSED);
+                }
+                other => panic!("{:?}", other),
+            }
+            // ... and then the circuit's input is closed.
+            assert!(rcv.next().await.is_none());
+        });
+    }
+
     #[test]
     fn new_circ_closed() {
         tor_rtcompat::test_with_all_runtimes!(|rt| async move {
@@ -682,6 +745,46 @@ pub(crate) mod test {
         });
     }
 
+    #[test]
+    fn duplicate_created() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            use oneshot_fused_workaround as oneshot;
+            use tor_cell::chancell::msg;
+
+            let (_chan, mut reactor, _output, mut input) = new_reactor(rt);
+
+            let (created_tx, created_rx) = oneshot::channel();
+            let (snd, _rcv) = fake_mpsc(64);
+            reactor
+                .circs
+                .put_unchecked(CircId::new(7).unwrap(), CircEnt::Opening(created_tx, snd));
+
+            // The first CREATED_FAST gets passed on to the circuit.
+            let created_cell: OpenChanMsgS2C = msg::CreatedFast::new(*b"first").into();
+            input
+                .send(Ok(OpenChanCellS2C::new(CircId::new(7), created_cell)))
+                .await
+                .unwrap();
+            reactor.run_once().await.unwrap();
+            match created_rx.await.unwrap() {
+                CreateResponse::CreatedFast(c) => assert_eq!(c.into_handshake(), b"first"),
+                other => panic!("{:?}", other),
+            }
+
+            // A second one is a protocol violation, and never reaches the circuit.
+            let created_cell: OpenChanMsgS2C = msg::CreatedFast::new(*b"second").into();
+            input
+                .send(Ok(OpenChanCellS2C::new(CircId::new(7), created_cell)))
+                .await
+                .unwrap();
+            let e = reactor.run_once().await.unwrap_err().unwrap_err();
+            assert_eq!(
+                format!("{}", e),
+                "Channel protocol violation: Duplicate CREATED* cell on already open circuit"
+            );
+        });
+    }
+
     #[test]
     fn deliver_relay() {
         tor_rtcompat::test_with_all_runtimes!(|rt| async move {
@@ -771,6 +874,44 @@ pub(crate) mod test {
         });
     }
 
+    // A CREATED* cell on a circuit we gave up on (for example, because the cell
+    // took too long to arrive) counts against the destroyed circuit, like a relay cell.
+    #[test]
+    fn deliver_late_created() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            use tor_cell::chancell::msg;
+
+            let (_chan, mut reactor, _output, mut input) = new_reactor(rt);
+            reactor.circs.put_unchecked(
+                CircId::new(23).unwrap(),
+                CircEnt::DestroySent(HalfCirc::new(2)),
+            );
+
+            let created_cell: OpenChanMsgS2C = msg::CreatedFast::new(*b"x").into();
+            for _ in 0..2 {
+                input
+                    .send(Ok(OpenChanCellS2C::new(
+                        CircId::new(23),
+                        created_cell.clone(),
+                    )))
+                    .await
+                    .unwrap();
+                reactor.run_once().await.unwrap(); // should be fine.
+            }
+
+            // This one will fail.
+            input
+                .send(Ok(OpenChanCellS2C::new(CircId::new(23), created_cell)))
+                .await
+                .unwrap();
+            let e = reactor.run_once().await.unwrap_err().unwrap_err();
+            assert_eq!(
+                format!("{}", e),
+                "Channel protocol violation: Too many cells received on destroyed circuit"
+            );
+        });
+    }
+
     #[test]
     fn deliver_destroy() {
         tor_rtcompat::test_with_all_runtimes!(|rt| async move {
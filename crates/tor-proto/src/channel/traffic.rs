@@ -0,0 +1,145 @@
+//! Counters for the number of bytes that we read and write on channels and circuits.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tor_cell::chancell::CELL_DATA_LEN;
+
+/// The length of a fixed-length cell on the wire, including its header.
+///
+/// (This assumes a 4-byte circuit ID, as in link protocol 4 and later:
+/// those are the only versions we support.)
+pub(crate) const FIXED_CELL_LEN: usize = 4 + 1 + CELL_DATA_LEN;
+
+/// A pair of counters for the number of bytes read and written
+/// on one or more channels, or on a circuit.
+///
+/// Every [`Channel`](super::Channel) has one of these for its own traffic,
+/// and so does every [`ClientCirc`](crate::circuit::ClientCirc).
+/// You can also give one to a [`ChannelBuilder`](super::ChannelBuilder),
+/// to count the traffic of every channel that it builds.
+///
+/// The counts include the whole of every cell, including its header,
+/// but not the overhead of TLS or of the underlying transport.
+#[derive(Debug, Default)]
+pub struct TrafficCounter {
+    /// The number of bytes we have read.
+    read: AtomicU64,
+    /// The number of bytes we have written.
+    written: AtomicU64,
+}
+
+/// The number of bytes read and written, as sampled from a [`TrafficCounter`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct TrafficSnapshot {
+    /// The number of bytes read.
+    pub read: u64,
+    /// The number of bytes written.
+    pub written: u64,
+}
+
+impl TrafficCounter {
+    /// Construct a new `TrafficCounter`, with both of its counts set to zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the current counts of this `TrafficCounter`.
+    pub fn snapshot(&self) -> TrafficSnapshot {
+        // We only need each count to be accurate on its own,
+        // so there's no need to synchronize them with each other.
+        TrafficSnapshot {
+            read: self.read.load(Ordering::Relaxed),
+            written: self.written.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Record that we have read `n` more bytes.
+    pub(crate) fn note_read(&self, n: usize) {
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Record that we have written `n` more bytes.
+    pub(crate) fn note_written(&self, n: usize) {
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// The counters to which a single channel reports its traffic.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrafficRecorder {
+    /// The counter for this channel alone.
+    channel: Arc<TrafficCounter>,
+    /// A counter shared with other channels, if we have one.
+    total: Option<Arc<TrafficCounter>>,
+}
+
+impl TrafficRecorder {
+    /// Construct a new `TrafficRecorder` for a new channel,
+    /// which also reports to `total` if it is present.
+    pub(crate) fn new(total: Option<Arc<TrafficCounter>>) -> Self {
+        Self {
+            channel: Default::default(),
+            total,
+        }
+    }
+
+    /// Return the counter for this channel alone.
+    pub(crate) fn channel_counter(&self) -> &Arc<TrafficCounter> {
+        &self.channel
+    }
+
+    /// Record that we have read `n` more bytes on this channel.
+    pub(crate) fn note_read(&self, n: usize) {
+        self.channel.note_read(n);
+        if let Some(total) = &self.total {
+            total.note_read(n);
+        }
+    }
+
+    /// Record that we have written `n` more bytes on this channel.
+    pub(crate) fn note_written(&self, n: usize) {
+        self.channel.note_written(n);
+        if let Some(total) = &self.total {
+            total.note_written(n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    #[test]
+    fn shared_total() {
+        let total = Arc::new(TrafficCounter::new());
+        let chan1 = TrafficRecorder::new(Some(Arc::clone(&total)));
+        let chan2 = TrafficRecorder::new(Some(Arc::clone(&total)));
+        let chan3 = TrafficRecorder::new(None);
+
+        chan1.note_read(514);
+        chan1.note_written(1028);
+        chan2.note_read(100);
+        chan3.note_written(514);
+
+        let snapshot = |read, written| TrafficSnapshot { read, written };
+        assert_eq!(chan1.channel_counter().snapshot(), snapshot(514, 1028));
+        assert_eq!(chan2.channel_counter().snapshot(), snapshot(100, 0));
+        assert_eq!(chan3.channel_counter().snapshot(), snapshot(0, 514));
+        assert_eq!(total.snapshot(), snapshot(614, 1028));
+    }
+}
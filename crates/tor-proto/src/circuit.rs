@@ -34,6 +34,7 @@
 //! This is client-only.
 
 pub(crate) mod celltypes;
+mod congestion;
 pub(crate) mod halfcirc;
 mod halfstream;
 
@@ -50,11 +51,9 @@ pub(crate) mod sendme;
 mod streammap;
 mod unique_id;
 
-use crate::channel::Channel;
+use crate::channel::{Channel, TrafficCounter, TrafficSnapshot};
 use crate::circuit::celltypes::*;
-use crate::circuit::reactor::{
-    CircuitHandshake, CtrlMsg, Reactor, RECV_WINDOW_INIT, STREAM_READER_BUFFER,
-};
+use crate::circuit::reactor::{stream_reader_buffer, CircuitHandshake, CtrlMsg, Reactor};
 pub use crate::circuit::unique_id::UniqId;
 pub use crate::crypto::binding::CircuitBinding;
 use crate::crypto::cell::HopNum;
@@ -62,6 +61,8 @@ use crate::crypto::cell::HopNum;
 use crate::crypto::handshake::ntor_v3::NtorV3PublicKey;
 pub use crate::memquota::StreamAccount;
 use crate::memquota::{CircuitAccount, SpecificAccount as _};
+#[cfg(feature = "experimental-api")]
+use crate::stream::ReservedStream;
 use crate::stream::{
     AnyCmdChecker, DataCmdChecker, DataStream, ResolveCmdChecker, ResolveStream, StreamParameters,
     StreamReader,
@@ -86,19 +87,21 @@ use {
 use futures::channel::mpsc;
 use oneshot_fused_workaround as oneshot;
 
-use crate::circuit::sendme::StreamRecvWindow;
+use crate::circuit::sendme::{StreamRecvWindow, WindowParams};
 use futures::{FutureExt as _, SinkExt as _};
 use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tor_async_utils::SinkCloseChannel as _;
 use tor_cell::relaycell::StreamId;
 use tor_memquota::mq_queue::{self, ChannelSpec as _, MpscSpec};
-// use std::time::Duration;
 
 use crate::crypto::handshake::ntor::NtorPublicKey;
+pub use congestion::CongestionControlAlgorithm;
 pub use path::{Path, PathEntry};
 pub use reactor::syncview::ClientCircSyncView;
+pub use reactor::{ReactorEvent, ReactorExit};
 
 /// The size of the buffer for communication between `ClientCirc` and its reactor.
 pub const CIRCUIT_BUFFER_SIZE: usize = 128;
@@ -169,6 +172,8 @@ pub(crate) type CircuitRxReceiver = mq_queue::Receiver<ClientCircChanMsg, MpscSp
 pub struct ClientCirc {
     /// Mutable state shared with the `Reactor`.
     mutable: Arc<Mutex<MutableState>>,
+    /// The number of bytes read and written on this circuit, updated by the `Reactor`.
+    traffic: Arc<TrafficCounter>,
     /// A unique identifier for this circuit.
     unique_id: UniqId,
     /// Channel to send control messages to the reactor.
@@ -218,6 +223,9 @@ struct MutableState {
     /// an `Option`.
     #[educe(Debug(ignore))]
     binding: Vec<Option<CircuitBinding>>,
+
+    /// The SENDME parameters to use for streams on each hop in the circuit's path.
+    stream_sendme: Vec<SendmeParams>,
 }
 
 /// A ClientCirc that needs to send a create cell and receive a created* cell.
@@ -230,6 +238,9 @@ pub struct PendingClientCirc {
     recvcreated: oneshot::Receiver<CreateResponse>,
     /// The ClientCirc object that we can expose on success.
     circ: Arc<ClientCirc>,
+    /// A oneshot sender to notify once the first hop has been established,
+    /// if anybody has asked for one.
+    first_hop_ready: Option<oneshot::Sender<()>>,
 }
 
 /// Description of the network's current rules for building circuits.
@@ -240,6 +251,24 @@ pub struct CircParameters {
     /// Whether we should include ed25519 identities when we send
     /// EXTEND2 cells.
     extend_by_ed25519_id: bool,
+    /// How long to wait for the first hop to answer our CREATE* cell
+    /// before giving up.
+    first_hop_create_timeout: Duration,
+    /// Window size and SENDME increment for circuit-level flow control.
+    circ_sendme: SendmeParams,
+    /// Window size and SENDME increment for stream-level flow control.
+    stream_sendme: SendmeParams,
+    /// The largest number of hops that we'll allow the circuit to have.
+    max_hops: u8,
+    /// The largest number of cells that the circuit reactor will send from its streams
+    /// before yielding to the executor.
+    max_cells_per_yield: u16,
+    /// The congestion control algorithm to use for each hop.
+    congestion_control: CongestionControlAlgorithm,
+    /// How long the circuit may stay open, regardless of activity.
+    max_lifetime: Option<Duration>,
+    /// Whether to enforce stream-level receive windows as cells arrive.
+    strict_recv_windows: bool,
 }
 
 impl Default for CircParameters {
@@ -247,22 +276,32 @@ impl Default for CircParameters {
         CircParameters {
             initial_send_window: 1000,
             extend_by_ed25519_id: true,
+            first_hop_create_timeout: Duration::from_secs(60),
+            circ_sendme: sendme::CircParams::default_params(),
+            stream_sendme: sendme::StreamParams::default_params(),
+            max_hops: 8,
+            max_cells_per_yield: 64,
+            congestion_control: CongestionControlAlgorithm::default(),
+            max_lifetime: None,
+            strict_recv_windows: false,
         }
     }
 }
 
 impl CircParameters {
     /// Override the default initial send window for these parameters.
-    /// Gives an error on any value above 1000.
+    /// Gives an error on any value above the circuit-level SENDME window
+    /// (1000 by default).
     ///
     /// You should probably not call this.
     pub fn set_initial_send_window(&mut self, v: u16) -> Result<()> {
-        if v <= 1000 {
+        if v <= self.circ_sendme.window {
             self.initial_send_window = v;
             Ok(())
         } else {
             Err(Error::from(bad_api_usage!(
-                "Tried to set an initial send window over 1000"
+                "Tried to set an initial send window over {}",
+                self.circ_sendme.window
             )))
         }
     }
@@ -285,6 +324,279 @@ impl CircParameters {
     pub fn extend_by_ed25519_id(&self) -> bool {
         self.extend_by_ed25519_id
     }
+
+    /// Override the default amount of time to wait for the first hop of a circuit
+    /// to answer our CREATE* cell.
+    ///
+    /// If no answer arrives in time, `create_firsthop_*` fail with
+    /// [`Error::CreateTimeout`].
+    ///
+    /// The default is deliberately generous: it is meant as a backstop
+    /// against relays that never answer,
+    /// not as a replacement for adaptive circuit build timeouts.
+    pub fn set_first_hop_create_timeout(&mut self, v: Duration) {
+        self.first_hop_create_timeout = v;
+    }
+
+    /// Return how long we'll wait for the first hop of a circuit
+    /// to answer our CREATE* cell.
+    pub fn first_hop_create_timeout(&self) -> Duration {
+        self.first_hop_create_timeout
+    }
+
+    /// Override the default window size and SENDME increment for
+    /// circuit-level flow control.
+    ///
+    /// Gives an error if the new window is smaller than the current
+    /// initial send window; lower that first with
+    /// [`set_initial_send_window`](CircParameters::set_initial_send_window).
+    ///
+    /// Both ends of a circuit need to agree on these values,
+    /// so you should probably not call this unless you control the other end.
+    pub fn set_circ_sendme_params(&mut self, v: SendmeParams) -> Result<()> {
+        if v.window >= self.initial_send_window {
+            self.circ_sendme = v;
+            Ok(())
+        } else {
+            Err(Error::from(bad_api_usage!(
+                "Tried to set a circuit SENDME window smaller than the initial send window"
+            )))
+        }
+    }
+
+    /// Return the window size and SENDME increment for circuit-level flow control.
+    pub fn circ_sendme_params(&self) -> SendmeParams {
+        self.circ_sendme
+    }
+
+    /// Override the default window size and SENDME increment for
+    /// stream-level flow control.
+    ///
+    /// Gives an error if the new window is larger than the circuit window,
+    /// since a stream could never use the difference.
+    ///
+    /// Both ends of a stream need to agree on these values,
+    /// so you should probably not call this unless you control the other end.
+    pub fn set_stream_sendme_params(&mut self, v: SendmeParams) -> Result<()> {
+        if v.window <= self.circ_sendme.window {
+            self.stream_sendme = v;
+            Ok(())
+        } else {
+            Err(Error::from(bad_api_usage!(
+                "Tried to set a stream SENDME window larger than the circuit window"
+            )))
+        }
+    }
+
+    /// Return the window size and SENDME increment for stream-level flow control.
+    pub fn stream_sendme_params(&self) -> SendmeParams {
+        self.stream_sendme
+    }
+
+    /// Override the default maximum number of hops (8) that a circuit may
+    /// be extended to.
+    ///
+    /// Attempts to extend a circuit that already has this many hops will fail.
+    /// This is a safety net against logic bugs that would over-extend a circuit.
+    ///
+    /// Gives an error on 0.
+    pub fn set_max_hops(&mut self, v: u8) -> Result<()> {
+        if v > 0 {
+            self.max_hops = v;
+            Ok(())
+        } else {
+            Err(Error::from(bad_api_usage!(
+                "Tried to set a maximum of 0 hops"
+            )))
+        }
+    }
+
+    /// Return the maximum number of hops that a circuit may be extended to.
+    pub fn max_hops(&self) -> u8 {
+        self.max_hops
+    }
+
+    /// Override the default maximum number of cells (64) that a circuit's reactor
+    /// will send from its streams before yielding to the executor.
+    ///
+    /// A lower value lets other tasks (such as the reactors of other circuits)
+    /// run more often when this circuit is busy, at some cost in throughput.
+    ///
+    /// Only the value used when creating the first hop of a circuit has any effect.
+    ///
+    /// Gives an error on 0.
+    pub fn set_max_cells_per_yield(&mut self, v: u16) -> Result<()> {
+        if v > 0 {
+            self.max_cells_per_yield = v;
+            Ok(())
+        } else {
+            Err(Error::from(bad_api_usage!(
+                "Tried to set a maximum of 0 cells per yield"
+            )))
+        }
+    }
+
+    /// Return the maximum number of cells that a circuit's reactor will send
+    /// from its streams before yielding to the executor.
+    pub fn max_cells_per_yield(&self) -> u16 {
+        self.max_cells_per_yield
+    }
+
+    /// Override the default congestion control algorithm
+    /// ([`FixedWindow`](CongestionControlAlgorithm::FixedWindow)).
+    ///
+    /// This only affects how much we send: every algorithm still obeys
+    /// the circuit-level SENDME window, so the other end of the circuit
+    /// doesn't need to know which one we use.
+    pub fn set_congestion_control(&mut self, v: CongestionControlAlgorithm) {
+        self.congestion_control = v;
+    }
+
+    /// Return the congestion control algorithm to use for each hop.
+    pub fn congestion_control(&self) -> CongestionControlAlgorithm {
+        self.congestion_control
+    }
+
+    /// Set the longest time that a circuit may stay open, however busy it is.
+    ///
+    /// Once a circuit is this old, its reactor closes it,
+    /// and the reactor's `run` method returns [`ReactorExit::MaxLifetimeReached`].
+    /// This limits how long any single circuit can be used to link our activity together.
+    ///
+    /// The age of a circuit is measured from when its first hop was created,
+    /// using the runtime's clock.
+    /// Only the value used when creating the first hop of a circuit has any effect.
+    ///
+    /// The default (`None`) is to let circuits live for as long as they are open.
+    pub fn set_max_lifetime(&mut self, v: Option<Duration>) {
+        self.max_lifetime = v;
+    }
+
+    /// Return the longest time that a circuit may stay open, if there is a limit.
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        self.max_lifetime
+    }
+
+    /// Enable or disable strict accounting of stream-level receive windows.
+    ///
+    /// Normally, a stream's receive window is only checked when its reader
+    /// takes cells from it, and the reactor will queue up to twice the window
+    /// before giving up on the circuit.
+    /// In strict mode, the reactor also counts every cell that counts towards the
+    /// window as it arrives, and closes the circuit with a protocol error
+    /// as soon as the other side sends more than it has been allowed.
+    /// This is meant for catching misbehaving relays and bugs.
+    ///
+    /// The circuit-level receive window is always enforced in this way.
+    ///
+    /// The default is `false`.
+    pub fn set_strict_recv_windows(&mut self, v: bool) {
+        self.strict_recv_windows = v;
+    }
+
+    /// Return true if we enforce stream-level receive windows as cells arrive.
+    pub fn strict_recv_windows(&self) -> bool {
+        self.strict_recv_windows
+    }
+}
+
+/// A change to those [`CircParameters`] that can be changed on a running circuit.
+///
+/// Anything left unset keeps its current value.
+/// See [`ClientCirc::update_params`].
+#[derive(Clone, Debug, Default)]
+pub struct CircParamsUpdate {
+    /// A new value for [`CircParameters::max_cells_per_yield`], if any.
+    max_cells_per_yield: Option<u16>,
+    /// A new value for [`CircParameters::max_lifetime`], if any.
+    max_lifetime: Option<Option<Duration>>,
+}
+
+impl CircParamsUpdate {
+    /// Return a new `CircParamsUpdate` that changes nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change the largest number of cells that the circuit's reactor will send
+    /// from its streams before yielding to the executor.
+    ///
+    /// This takes effect immediately.
+    ///
+    /// Gives an error on 0.
+    pub fn set_max_cells_per_yield(&mut self, v: u16) -> Result<()> {
+        if v > 0 {
+            self.max_cells_per_yield = Some(v);
+            Ok(())
+        } else {
+            Err(Error::from(bad_api_usage!(
+                "Tried to set a maximum of 0 cells per yield"
+            )))
+        }
+    }
+
+    /// Change the longest time that the circuit may stay open,
+    /// or remove the limit if `v` is `None`.
+    ///
+    /// This takes effect immediately,
+    /// and is still measured from when the circuit's first hop was created;
+    /// so lowering it can close the circuit straight away.
+    pub fn set_max_lifetime(&mut self, v: Option<Duration>) {
+        self.max_lifetime = Some(v);
+    }
+
+    /// Apply this update to `params`.
+    pub(crate) fn apply_to(&self, params: &mut CircParameters) {
+        if let Some(v) = self.max_cells_per_yield {
+            params.max_cells_per_yield = v;
+        }
+        if let Some(v) = self.max_lifetime {
+            params.max_lifetime = v;
+        }
+    }
+}
+
+/// The size of a SENDME flow-control window, and the amount by which
+/// each SENDME advances it.
+///
+/// The defaults are 1000 and 100 cells for circuits,
+/// and 500 and 50 cells for streams.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SendmeParams {
+    /// Largest allowable value for the window.
+    window: u16,
+    /// Number of cells acknowledged by each SENDME.
+    increment: u16,
+}
+
+impl SendmeParams {
+    /// Construct a new `SendmeParams`.
+    ///
+    /// Gives an error unless `window` is nonzero,
+    /// and `increment` is nonzero and evenly divides `window`.
+    pub fn new(window: u16, increment: u16) -> Result<Self> {
+        if window == 0 {
+            return Err(Error::from(bad_api_usage!("SENDME window must be nonzero")));
+        }
+        if increment == 0 || window % increment != 0 {
+            return Err(Error::from(bad_api_usage!(
+                "SENDME increment {} does not evenly divide window {}",
+                increment,
+                window
+            )));
+        }
+        Ok(Self { window, increment })
+    }
+
+    /// Return the largest allowable value for the window.
+    pub fn window(&self) -> u16 {
+        self.window
+    }
+
+    /// Return the number of cells acknowledged by each SENDME.
+    pub fn increment(&self) -> u16 {
+        self.increment
+    }
 }
 
 /// Internal handle, used to implement a stream on a particular circuit.
@@ -390,6 +702,14 @@ impl ClientCirc {
         &self.channel
     }
 
+    /// Return the number of bytes that we have read and written on this circuit so far,
+    /// including the CREATE* cell that built it and the reply.
+    ///
+    /// Like [`Channel::traffic`], this counts whole cells, including their headers.
+    pub fn traffic(&self) -> TrafficSnapshot {
+        self.traffic.snapshot()
+    }
+
     /// Return a reference to this circuit's memory quota account
     pub fn mq_account(&self) -> &CircuitAccount {
         &self.memquota
@@ -414,6 +734,22 @@ impl ClientCirc {
         // it very rarely, so it's not _that_ bad IMO.
     }
 
+    /// Return a new receive window for a stream on `hop`.
+    ///
+    /// Uses the default stream parameters if we don't know about the hop.
+    #[cfg(feature = "hs-service")]
+    fn new_stream_recv_window(&self, hop: HopNum) -> StreamRecvWindow {
+        let params = self
+            .mutable
+            .lock()
+            .expect("poisoned lock")
+            .stream_sendme
+            .get::<usize>(hop.into())
+            .copied()
+            .unwrap_or_else(sendme::StreamParams::default_params);
+        StreamRecvWindow::new_with_params(params.window(), params)
+    }
+
     /// Start an ad-hoc protocol exchange to the specified hop on this circuit
     ///
     /// To use this:
@@ -551,6 +887,44 @@ impl ClientCirc {
         receiver.await.map_err(|_| Error::CircuitClosed)?
     }
 
+    /// Install a handler for every message with the relay command `cmd`
+    /// that arrives from `hop_num` with a stream ID of 0.
+    ///
+    /// Unlike the handler given to [`ClientCirc::start_conversation`],
+    /// this handler is consulted only for messages with this command,
+    /// and it stays installed until it returns
+    /// [`ConversationFinished`](MetaCellDisposition::ConversationFinished).
+    /// Together with [`ClientCirc::send_raw_msg`], this is meant for
+    /// experimenting with relay commands that `tor-proto` does not otherwise support.
+    ///
+    /// Messages with this command that arrive from any other hop
+    /// will cause the circuit to close with an error.
+    ///
+    /// Only commands that `tor-proto` never handles on its own can have a handler:
+    /// that is, commands it doesn't recognize,
+    /// and the onion service and padding negotiation commands
+    /// (which it only ever passes to the handler from
+    /// [`ClientCirc::start_conversation`]).
+    ///
+    /// Returns an error if there is already a handler for `cmd` on this circuit,
+    /// if `hop_num` is not a hop on this circuit,
+    /// or if `cmd` is not one of the commands above.
+    #[cfg(feature = "send-control-msg")]
+    pub async fn register_cmd_handler(
+        &self,
+        cmd: tor_cell::relaycell::RelayCmd,
+        handler: impl MsgHandler + Send + 'static,
+        hop_num: HopNum,
+    ) -> Result<()> {
+        let handler = Box::new(msghandler::UserMsgHandler::new(hop_num, handler));
+        let (done, receiver) = oneshot::channel();
+        self.control
+            .unbounded_send(CtrlMsg::RegisterCmdHandler { cmd, handler, done })
+            .map_err(|_| Error::CircuitClosed)?;
+
+        receiver.await.map_err(|_| Error::CircuitClosed)?
+    }
+
     /// Tell this circuit to begin allowing the final hop of the circuit to try
     /// to create new Tor streams, and to return those pending requests in an
     /// asynchronous stream.
@@ -580,7 +954,7 @@ impl ClientCirc {
         use futures::stream::StreamExt;
 
         /// The size of the channel receiving IncomingStreamRequestContexts.
-        const INCOMING_BUFFER: usize = STREAM_READER_BUFFER;
+        const INCOMING_BUFFER: usize = reactor::STREAM_READER_BUFFER;
 
         let time_prov = self.channel().time_provider().clone();
         let cmd_checker = IncomingCmdChecker::new_any(allow_commands);
@@ -620,6 +994,7 @@ impl ClientCirc {
             // security-critical.
             assert_eq!(allowed_hop_num, hop_num);
 
+            let recv_window = circ.new_stream_recv_window(hop_num);
             let target = StreamTarget {
                 circ: Arc::clone(&circ),
                 tx: msg_tx,
@@ -630,7 +1005,7 @@ impl ClientCirc {
             let reader = StreamReader {
                 target: target.clone(),
                 receiver,
-                recv_window: StreamRecvWindow::new(RECV_WINDOW_INIT),
+                recv_window,
                 ended: false,
             };
 
@@ -779,36 +1154,81 @@ impl ClientCirc {
         self: &Arc<ClientCirc>,
         begin_msg: AnyRelayMsg,
         cmd_checker: AnyCmdChecker,
+    ) -> Result<(StreamReader, StreamTarget, StreamAccount)> {
+        self.open_stream_impl(Some(begin_msg), cmd_checker).await
+    }
+
+    /// Helper, used to reserve a stream ID without sending anything.
+    ///
+    /// This function allocates a stream ID, and registers the stream with the reactor,
+    /// but doesn't send any message to the other side.
+    /// The caller is responsible for sending the message that initiates the stream,
+    /// using the returned [`StreamTarget`].
+    ///
+    /// This is for protocols that need to know the stream ID
+    /// before they can construct the message that initiates the stream.
+    #[cfg(any(test, feature = "experimental-api"))]
+    pub(crate) async fn reserve_stream_impl(
+        self: &Arc<ClientCirc>,
+        cmd_checker: AnyCmdChecker,
+    ) -> Result<(StreamReader, StreamTarget, StreamAccount)> {
+        self.open_stream_impl(None, cmd_checker).await
+    }
+
+    /// Helper for [`begin_stream_impl`](Self::begin_stream_impl)
+    /// and [`reserve_stream_impl`](Self::reserve_stream_impl).
+    ///
+    /// Allocates a stream ID and, if `begin_msg` is provided, sends it on the new stream.
+    async fn open_stream_impl(
+        self: &Arc<ClientCirc>,
+        begin_msg: Option<AnyRelayMsg>,
+        cmd_checker: AnyCmdChecker,
     ) -> Result<(StreamReader, StreamTarget, StreamAccount)> {
         // TODO: Possibly this should take a hop, rather than just
         // assuming it's the last hop.
 
         let time_prov = self.channel().time_provider().clone();
 
-        let hop_num = self
-            .mutable
-            .lock()
-            .expect("poisoned lock")
-            .path
-            .last_hop_num()
-            .ok_or_else(|| Error::from(internal!("Can't begin a stream at the 0th hop")))?;
+        let (hop_num, stream_sendme) = {
+            let mutable = self.mutable.lock().expect("poisoned lock");
+            let hop_num = mutable
+                .path
+                .last_hop_num()
+                .ok_or_else(|| Error::from(internal!("Can't begin a stream at the 0th hop")))?;
+            let stream_sendme = mutable
+                .stream_sendme
+                .get::<usize>(hop_num.into())
+                .copied()
+                .ok_or_else(|| Error::from(internal!("No SENDME parameters for last hop")))?;
+            (hop_num, stream_sendme)
+        };
 
         let memquota = StreamAccount::new(self.mq_account())?;
-        let (sender, receiver) = MpscSpec::new(STREAM_READER_BUFFER)
+        let (sender, receiver) = MpscSpec::new(stream_reader_buffer(stream_sendme))
             .new_mq(time_prov.clone(), memquota.as_raw_account())?;
         let (tx, rx) = oneshot::channel();
         let (msg_tx, msg_rx) =
             MpscSpec::new(CIRCUIT_BUFFER_SIZE).new_mq(time_prov, memquota.as_raw_account())?;
 
-        self.control
-            .unbounded_send(CtrlMsg::BeginStream {
+        let ctrl = match begin_msg {
+            Some(message) => CtrlMsg::BeginStream {
                 hop_num,
-                message: begin_msg,
+                message,
                 sender,
                 rx: msg_rx,
                 done: tx,
                 cmd_checker,
-            })
+            },
+            None => CtrlMsg::ReserveStream {
+                hop_num,
+                sender,
+                rx: msg_rx,
+                done: tx,
+                cmd_checker,
+            },
+        };
+        self.control
+            .unbounded_send(ctrl)
             .map_err(|_| Error::CircuitClosed)?;
 
         let stream_id = rx.await.map_err(|_| Error::CircuitClosed)??;
@@ -823,7 +1243,7 @@ impl ClientCirc {
         let reader = StreamReader {
             target: target.clone(),
             receiver,
-            recv_window: StreamRecvWindow::new(RECV_WINDOW_INIT),
+            recv_window: StreamRecvWindow::new_with_params(stream_sendme.window(), stream_sendme),
             ended: false,
         };
 
@@ -871,6 +1291,21 @@ impl ClientCirc {
         self.begin_data_stream(beginmsg.into(), optimistic).await
     }
 
+    /// Reserve a stream ID on the last hop of this circuit, without sending anything.
+    ///
+    /// This is for protocols that need to know the stream ID
+    /// before they can construct the message that initiates the stream.
+    /// Use [`ReservedStream::begin`] to send that message.
+    ///
+    /// This is an experimental API; it is not covered by semver guarantee. It
+    /// is likely to change or disappear in a future release.
+    #[cfg(feature = "experimental-api")]
+    pub async fn reserve_stream(self: &Arc<ClientCirc>) -> Result<ReservedStream> {
+        let (reader, target, memquota) =
+            self.reserve_stream_impl(DataCmdChecker::new_any()).await?;
+        Ok(ReservedStream::new(reader, target, memquota))
+    }
+
     /// Start a new stream to the last relay in the circuit, using
     /// a BEGIN_DIR cell.
     pub async fn begin_dir_stream(self: Arc<ClientCirc>) -> Result<DataStream> {
@@ -951,6 +1386,70 @@ impl ClientCirc {
         let _ = self.control.unbounded_send(CtrlMsg::Shutdown);
     }
 
+    /// Close every open stream on this circuit, without closing the circuit itself.
+    ///
+    /// We send an END message for each stream,
+    /// and the local end of each stream will see it as closed.
+    /// The circuit stays open, and can be used for new streams.
+    ///
+    /// Returns the number of streams that were closed.
+    pub async fn close_all_streams(&self) -> Result<usize> {
+        let (sender, receiver) = oneshot::channel();
+        self.control
+            .unbounded_send(CtrlMsg::CloseAllStreams { done: sender })
+            .map_err(|_| Error::CircuitClosed)?;
+
+        receiver.await.map_err(|_| Error::CircuitClosed)?
+    }
+
+    /// Stop (if `paused` is true) or resume sending data from the streams on this circuit.
+    ///
+    /// While the circuit is paused, nothing is sent from any of its streams:
+    /// whatever they write stays queued until the circuit is resumed,
+    /// or until the stream's own buffer fills up.
+    /// Incoming cells are still handled as usual,
+    /// and the circuit can still be extended, or used to open new streams.
+    ///
+    /// This is meant for throttling many circuits at once.
+    /// Pausing a circuit for too long may cause its streams to time out.
+    pub fn set_paused(&self, paused: bool) -> Result<()> {
+        self.control
+            .unbounded_send(CtrlMsg::SetCircuitPaused(paused))
+            .map_err(|_| Error::CircuitClosed)
+    }
+
+    /// Change some of the parameters of this circuit while it's running.
+    ///
+    /// Only the parameters in [`CircParamsUpdate`] can be changed this way.
+    ///
+    /// The others can't:
+    ///
+    ///  * The initial send window and the SENDME parameters were used to set up
+    ///    the flow-control windows of each hop when it was created,
+    ///    and the other end of the circuit is counting cells against the same windows.
+    ///    If we changed them on our side alone, the two ends would disagree
+    ///    about when a SENDME is due, and the circuit would stall or be closed
+    ///    for a protocol violation.
+    ///  * The congestion control algorithm and strict receive window mode
+    ///    are likewise part of the state of each hop.
+    ///  * The remaining parameters only matter when creating or extending a circuit,
+    ///    and are given anew each time.
+    ///
+    /// To pause or resume the circuit, use [`set_paused`](ClientCirc::set_paused).
+    ///
+    /// Gives an error if the circuit doesn't have any hops yet.
+    pub async fn update_params(&self, update: CircParamsUpdate) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.control
+            .unbounded_send(CtrlMsg::UpdateParams {
+                update,
+                done: sender,
+            })
+            .map_err(|_| Error::CircuitClosed)?;
+
+        receiver.await.map_err(|_| Error::CircuitClosed)?
+    }
+
     /// Called when a circuit-level protocol error has occurred and the
     /// circuit needs to shut down.
     ///
@@ -1055,11 +1554,19 @@ impl PendingClientCirc {
         unique_id: UniqId,
         memquota: CircuitAccount,
     ) -> (PendingClientCirc, reactor::Reactor) {
-        let (reactor, control_tx, reactor_closed_rx, mutable) =
-            Reactor::new(channel.clone(), id, unique_id, input, memquota.clone());
+        let traffic = Arc::new(TrafficCounter::new());
+        let (reactor, control_tx, reactor_closed_rx, mutable) = Reactor::new(
+            channel.clone(),
+            id,
+            unique_id,
+            input,
+            memquota.clone(),
+            Arc::clone(&traffic),
+        );
 
         let circuit = ClientCirc {
             mutable,
+            traffic,
             unique_id,
             control: control_tx,
             reactor_closed_rx: reactor_closed_rx.shared(),
@@ -1072,6 +1579,7 @@ impl PendingClientCirc {
         let pending = PendingClientCirc {
             recvcreated: createdreceiver,
             circ: Arc::new(circuit),
+            first_hop_ready: None,
         };
         (pending, reactor)
     }
@@ -1081,6 +1589,29 @@ impl PendingClientCirc {
         self.circ.unique_id
     }
 
+    /// Return a future that will resolve as soon as the first hop of this
+    /// circuit has been established.
+    ///
+    /// The future resolves from within the circuit's reactor,
+    /// at the moment the first hop's handshake completes,
+    /// so it can be handed to some other task:
+    /// for example, to measure how long it takes to build the first hop
+    /// separately from how long it takes to build the entire circuit.
+    ///
+    /// The future resolves to [`Error::CircuitClosed`] if the first hop
+    /// could not be established.
+    ///
+    /// Only the most recently returned future will be notified:
+    /// if you call this method more than once, the futures returned earlier
+    /// will resolve to an error.
+    pub fn first_hop_ready(
+        &mut self,
+    ) -> impl futures::Future<Output = Result<()>> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        self.first_hop_ready = Some(tx);
+        rx.map(|r| r.map_err(|_| Error::CircuitClosed))
+    }
+
     /// Use the (questionable!) CREATE_FAST handshake to connect to the
     /// first hop of this circuit.
     ///
@@ -1095,6 +1626,7 @@ impl PendingClientCirc {
                 recv_created: self.recvcreated,
                 handshake: CircuitHandshake::CreateFast,
                 params: params.clone(),
+                first_hop_ready: self.first_hop_ready,
                 done: tx,
             })
             .map_err(|_| Error::CircuitClosed)?;
@@ -1134,6 +1666,7 @@ impl PendingClientCirc {
                         .ok_or(Error::MissingId(RelayIdType::Ed25519))?,
                 },
                 params: params.clone(),
+                first_hop_ready: self.first_hop_ready,
                 done: tx,
             })
             .map_err(|_| Error::CircuitClosed)?;
@@ -1175,6 +1708,7 @@ impl PendingClientCirc {
                     },
                 },
                 params: params.clone(),
+                first_hop_ready: self.first_hop_ready,
                 done: tx,
             })
             .map_err(|_| Error::CircuitClosed)?;
@@ -1329,6 +1863,12 @@ impl StreamTarget {
         Ok(())
     }
 
+    /// Return the ID of the stream that owns this `StreamTarget`.
+    #[cfg(feature = "experimental-api")]
+    pub(crate) fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
     /// Return a reference to the circuit that this `StreamTarget` is using.
     #[cfg(any(feature = "experimental-api", feature = "stream-ctrl"))]
     pub(crate) fn circuit(&self) -> &Arc<ClientCirc> {
@@ -1406,7 +1946,10 @@ mod test {
         }
     }
 
-    fn rmsg_to_ccmsg(id: Option<StreamId>, msg: relaymsg::AnyRelayMsg) -> ClientCircChanMsg {
+    pub(crate) fn rmsg_to_ccmsg(
+        id: Option<StreamId>,
+        msg: relaymsg::AnyRelayMsg,
+    ) -> ClientCircChanMsg {
         let body: BoxedCellBody = AnyRelayMsgOuter::new(id, msg)
             .encode(&mut testing_rng())
             .unwrap();
@@ -1459,7 +2002,7 @@ mod test {
         )
     }
 
-    fn working_fake_channel<R: Runtime>(
+    pub(crate) fn working_fake_channel<R: Runtime>(
         rt: &R,
     ) -> (
         Arc<Channel>,
@@ -1612,97 +2155,176 @@ mod test {
         });
     }
 
-    // An encryption layer that doesn't do any crypto.   Can be used
-    // as inbound or outbound, but not both at once.
-    pub(crate) struct DummyCrypto {
-        counter_tag: [u8; 20],
-        counter: u32,
-        lasthop: bool,
-    }
-    impl DummyCrypto {
-        fn next_tag(&mut self) -> &[u8; 20] {
-            #![allow(clippy::identity_op)]
-            self.counter_tag[0] = ((self.counter >> 0) & 255) as u8;
-            self.counter_tag[1] = ((self.counter >> 8) & 255) as u8;
-            self.counter_tag[2] = ((self.counter >> 16) & 255) as u8;
-            self.counter_tag[3] = ((self.counter >> 24) & 255) as u8;
-            self.counter += 1;
-            &self.counter_tag
-        }
-    }
+    // Make sure that a seeded handshake RNG makes our CREATE cells reproducible.
+    #[test]
+    fn test_create_seeded_rng() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            use rand::SeedableRng as _;
+            use tor_basic_utils::test_rng::TestingRng;
+
+            let mut handshakes = vec![];
+            for _ in 0..2 {
+                let (chan, mut rx, _sink) = working_fake_channel(&rt);
+                let (_created_send, created_recv) = oneshot::channel();
+                let (_circmsg_send, circmsg_recv) = fake_mpsc(64);
+                let (pending, mut reactor) = PendingClientCirc::new(
+                    CircId::new(128).unwrap(),
+                    chan,
+                    created_recv,
+                    circmsg_recv,
+                    UniqId::new(23, 17),
+                    CircuitAccount::new_noop(),
+                );
+                reactor.set_handshake_rng(TestingRng::from_seed([7; 32]));
 
-    impl crate::crypto::cell::OutboundClientLayer for DummyCrypto {
-        fn originate_for(&mut self, _cell: &mut RelayCellBody) -> &[u8] {
-            self.next_tag()
-        }
-        fn encrypt_outbound(&mut self, _cell: &mut RelayCellBody) {}
-    }
-    impl crate::crypto::cell::InboundClientLayer for DummyCrypto {
-        fn decrypt_inbound(&mut self, _cell: &mut RelayCellBody) -> Option<&[u8]> {
-            if self.lasthop {
-                Some(self.next_tag())
-            } else {
-                None
-            }
-        }
-    }
-    impl DummyCrypto {
-        pub(crate) fn new(lasthop: bool) -> Self {
-            DummyCrypto {
-                counter_tag: [0; 20],
-                counter: 0,
-                lasthop,
+                rt.spawn(async {
+                    let _ignore = reactor.run().await;
+                })
+                .unwrap();
+                rt.spawn(async move {
+                    let params = CircParameters::default();
+                    let _ignore = pending.create_firsthop_fast(&params).await;
+                })
+                .unwrap();
+
+                let create_cell = rx.next().await.unwrap();
+                let handshake = match create_cell.msg() {
+                    AnyChanMsg::CreateFast(cf) => cf.handshake().to_vec(),
+                    other => panic!("{:?}", other),
+                };
+                handshakes.push(handshake);
             }
-        }
+
+            assert_eq!(handshakes[0], handshakes[1]);
+        });
     }
 
-    // Helper: set up a 3-hop circuit with no encryption, where the
-    // next inbound message seems to come from hop next_msg_from
-    async fn newcirc_ext<R: Runtime>(
-        rt: &R,
-        chan: Arc<Channel>,
-        next_msg_from: HopNum,
-    ) -> (Arc<ClientCirc>, CircuitRxSender) {
-        let circid = CircId::new(128).unwrap();
-        let (_created_send, created_recv) = oneshot::channel();
-        let (circmsg_send, circmsg_recv) = fake_mpsc(64);
-        let unique_id = UniqId::new(23, 17);
+    // Make sure that we give up on creating the first hop if it never answers.
+    #[test]
+    fn test_create_timeout() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            const TIMEOUT: Duration = Duration::from_secs(10);
 
-        let (pending, reactor) = PendingClientCirc::new(
-            circid,
-            chan,
-            created_recv,
-            circmsg_recv,
-            unique_id,
-            CircuitAccount::new_noop(),
-        );
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (_created_send, created_recv) = oneshot::channel();
+            let (_circmsg_send, circmsg_recv) = fake_mpsc(64);
+            let (pending, reactor) = PendingClientCirc::new(
+                CircId::new(128).unwrap(),
+                chan,
+                created_recv,
+                circmsg_recv,
+                UniqId::new(23, 17),
+                CircuitAccount::new_noop(),
+            );
+            rt.spawn(async {
+                let _ignore = reactor.run().await;
+            })
+            .unwrap();
 
-        rt.spawn(async {
-            let _ignore = reactor.run().await;
-        })
-        .unwrap();
+            let mut params = CircParameters::default();
+            params.set_first_hop_create_timeout(TIMEOUT);
+            let (res_tx, mut res_rx) = oneshot::channel();
+            rt.spawn(async move {
+                let _ = res_tx.send(pending.create_firsthop_fast(&params).await);
+            })
+            .unwrap();
 
-        let PendingClientCirc {
-            circ,
-            recvcreated: _,
-        } = pending;
+            // The relay gets our CREATE_FAST cell, but never answers it.
+            let create_cell = rx.next().await.unwrap();
+            assert!(matches!(create_cell.msg(), AnyChanMsg::CreateFast(_)));
 
-        // TODO #1067: Support other formats
-        let relay_cell_format = RelayCellFormat::V0;
-        for idx in 0_u8..3 {
-            let params = CircParameters::default();
-            let (tx, rx) = oneshot::channel();
-            circ.control
-                .unbounded_send(CtrlMsg::AddFakeHop {
-                    relay_cell_format,
-                    fwd_lasthop: idx == 2,
-                    rev_lasthop: idx == u8::from(next_msg_from),
-                    params,
-                    done: tx,
+            rt.advance_by(TIMEOUT - Duration::from_secs(1)).await;
+            assert!(futures::poll!(&mut res_rx).is_pending());
+
+            rt.advance_by(Duration::from_secs(2)).await;
+            let res = res_rx.await.unwrap();
+            assert!(matches!(res, Err(Error::CreateTimeout)));
+        });
+    }
+
+    // Make sure that we're told about the first hop as soon as it's established,
+    // and only if it is.
+    #[test]
+    fn test_first_hop_ready() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            use crate::crypto::handshake::{fast::CreateFastServer, ServerHandshake};
+
+            for succeed in [true, false] {
+                let (chan, mut rx, _sink) = working_fake_channel(&rt);
+                let (created_send, created_recv) = oneshot::channel();
+                let (_circmsg_send, circmsg_recv) = fake_mpsc(64);
+                let (mut pending, reactor) = PendingClientCirc::new(
+                    CircId::new(128).unwrap(),
+                    chan,
+                    created_recv,
+                    circmsg_recv,
+                    UniqId::new(23, 17),
+                    CircuitAccount::new_noop(),
+                );
+                let first_hop_ready = pending.first_hop_ready();
+                rt.spawn(async {
+                    let _ignore = reactor.run().await;
+                })
+                .unwrap();
+                rt.spawn(async move {
+                    let params = CircParameters::default();
+                    let _ignore = pending.create_firsthop_fast(&params).await;
                 })
                 .unwrap();
-            rx.await.unwrap().unwrap();
-        }
+
+                let create_cell = rx.next().await.unwrap();
+                let cf = match create_cell.msg() {
+                    AnyChanMsg::CreateFast(cf) => cf,
+                    other => panic!("{:?}", other),
+                };
+                if succeed {
+                    let (_, rep) = CreateFastServer::server(
+                        &mut testing_rng(),
+                        &mut |_: &()| Some(()),
+                        &[()],
+                        cf.handshake(),
+                    )
+                    .unwrap();
+                    created_send
+                        .send(CreateResponse::CreatedFast(CreatedFast::new(rep)))
+                        .unwrap();
+                    first_hop_ready.await.unwrap();
+                } else {
+                    // The relay goes away without answering.
+                    drop(created_send);
+                    assert!(matches!(first_hop_ready.await, Err(Error::CircuitClosed)));
+                }
+            }
+        });
+    }
+
+    // Helper: set up a 3-hop circuit with no encryption, where the
+    // next inbound message seems to come from hop next_msg_from
+    async fn newcirc_ext<R: Runtime>(
+        rt: &R,
+        chan: Arc<Channel>,
+        next_msg_from: HopNum,
+    ) -> (Arc<ClientCirc>, CircuitRxSender) {
+        let circid = CircId::new(128).unwrap();
+        newcirc_with_params(rt, chan, circid, next_msg_from, CircParameters::default()).await
+    }
+
+    // Helper: like newcirc_ext, but with the given circuit ID,
+    // and using `params` for every hop.
+    async fn newcirc_with_params<R: Runtime>(
+        rt: &R,
+        chan: Arc<Channel>,
+        circid: CircId,
+        next_msg_from: HopNum,
+        params: CircParameters,
+    ) -> (Arc<ClientCirc>, CircuitRxSender) {
+        let (reactor, circ, circmsg_send) =
+            Reactor::new_fake(chan, circid, 3, next_msg_from, &params);
+
+        rt.spawn(async {
+            let _ignore = reactor.run().await;
+        })
+        .unwrap();
 
         (circ, circmsg_send)
     }
@@ -1713,6 +2335,20 @@ mod test {
         newcirc_ext(rt, chan, 2.into()).await
     }
 
+    // We never negotiate padding, so a DROP message from a relay is a protocol violation.
+    #[test]
+    fn unnegotiated_drop_closes_circuit() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut sink) = newcirc(&rt, chan).await;
+
+            let drop = relaymsg::Drop::default().into();
+            sink.send(rmsg_to_ccmsg(None, drop)).await.unwrap();
+            rt.advance_until_stalled().await;
+            assert!(circ.is_closing());
+        });
+    }
+
     // Try sending a cell via send_relay_cell
     #[test]
     fn send_simple() {
@@ -1728,18 +2364,606 @@ mod test {
                 })
                 .unwrap();
 
-            // Here's what we tried to put on the TLS channel.  Note that
-            // we're using dummy relay crypto for testing convenience.
-            let rcvd = rx.next().await.unwrap();
-            assert_eq!(rcvd.circid(), Some(circ.peek_circid()));
-            let m = match rcvd.into_circid_and_msg().1 {
-                AnyChanMsg::Relay(r) => {
-                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
-                        .unwrap()
+            // Here's what we tried to put on the TLS channel.  Note that
+            // we're using dummy relay crypto for testing convenience.
+            let rcvd = rx.next().await.unwrap();
+            assert_eq!(rcvd.circid(), Some(circ.peek_circid()));
+            let m = match rcvd.into_circid_and_msg().1 {
+                AnyChanMsg::Relay(r) => {
+                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                        .unwrap()
+                }
+                other => panic!("{:?}", other),
+            };
+            assert!(matches!(m.msg(), AnyRelayMsg::BeginDir(_)));
+        });
+    }
+
+    // Make sure that a reactor run with an idle timeout gives up once nothing
+    // has happened for long enough, and that activity resets the timer.
+    #[test]
+    fn reactor_idle_timeout() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (_created_send, created_recv) = oneshot::channel();
+            let (_circmsg_send, circmsg_recv) = fake_mpsc(64);
+            let (pending, reactor) = PendingClientCirc::new(
+                CircId::new(128).unwrap(),
+                chan,
+                created_recv,
+                circmsg_recv,
+                UniqId::new(23, 17),
+                CircuitAccount::new_noop(),
+            );
+            let (exit_tx, mut exit_rx) = oneshot::channel();
+            rt.spawn(async move {
+                let _ = exit_tx.send(reactor.run_until_idle(IDLE_TIMEOUT).await);
+            })
+            .unwrap();
+            let circ = pending.circ;
+
+            // The timer doesn't run until we have a hop.
+            rt.advance_by(IDLE_TIMEOUT * 2).await;
+            assert!(!circ.is_closing());
+
+            let (tx, rx) = oneshot::channel();
+            circ.control
+                .unbounded_send(CtrlMsg::AddFakeHop {
+                    relay_cell_format: RelayCellFormat::V0,
+                    fwd_lasthop: true,
+                    rev_lasthop: true,
+                    params: CircParameters::default(),
+                    done: tx,
+                })
+                .unwrap();
+            rx.await.unwrap().unwrap();
+
+            // A control message shortly before the deadline resets the timer.
+            rt.advance_by(IDLE_TIMEOUT - Duration::from_secs(10)).await;
+            let (tx, rx) = oneshot::channel();
+            circ.control
+                .unbounded_send(CtrlMsg::QuerySendWindow {
+                    hop: 0.into(),
+                    done: tx,
+                })
+                .unwrap();
+            rx.await.unwrap().unwrap();
+            rt.advance_by(IDLE_TIMEOUT - Duration::from_secs(10)).await;
+            assert!(futures::poll!(&mut exit_rx).is_pending());
+            assert!(!circ.is_closing());
+
+            rt.advance_by(Duration::from_secs(20)).await;
+            assert_eq!(exit_rx.await.unwrap().unwrap(), ReactorExit::IdleTimeout);
+            assert!(circ.is_closing());
+        });
+    }
+
+    // Make sure that the reactor reports why it shut down.
+    #[test]
+    fn reactor_exit_reasons() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            for terminate in [true, false] {
+                let (chan, _rx, _sink) = working_fake_channel(&rt);
+                let (_created_send, created_recv) = oneshot::channel();
+                let (_circmsg_send, circmsg_recv) = fake_mpsc(64);
+                let (pending, reactor) = PendingClientCirc::new(
+                    CircId::new(128).unwrap(),
+                    chan,
+                    created_recv,
+                    circmsg_recv,
+                    UniqId::new(23, 17),
+                    CircuitAccount::new_noop(),
+                );
+                let exit = rt.spawn_with_handle(reactor.run()).unwrap();
+
+                let expected = if terminate {
+                    pending.circ.terminate();
+                    ReactorExit::ShutdownRequested
+                } else {
+                    drop(pending);
+                    ReactorExit::HandlesDropped
+                };
+                assert_eq!(exit.await.unwrap(), expected);
+            }
+        });
+    }
+
+    // Helper: like newcirc, but also return a receiver for the reactor's events,
+    // and a handle for its exit status.
+    async fn newcirc_with_events<R: Runtime>(
+        rt: &R,
+        chan: Arc<Channel>,
+    ) -> (
+        Arc<ClientCirc>,
+        CircuitRxSender,
+        mpsc::UnboundedReceiver<ReactorEvent>,
+        futures::future::RemoteHandle<Result<ReactorExit>>,
+    ) {
+        let (_created_send, created_recv) = oneshot::channel();
+        let (circmsg_send, circmsg_recv) = fake_mpsc(64);
+        let (pending, mut reactor) = PendingClientCirc::new(
+            CircId::new(128).unwrap(),
+            chan,
+            created_recv,
+            circmsg_recv,
+            UniqId::new(23, 17),
+            CircuitAccount::new_noop(),
+        );
+        let (event_tx, event_rx) = mpsc::unbounded();
+        reactor.set_event_sink(event_tx);
+        let exit = rt.spawn_with_handle(reactor.run()).unwrap();
+        let circ = pending.circ;
+
+        for idx in 0_u8..3 {
+            let (tx, rx) = oneshot::channel();
+            circ.control
+                .unbounded_send(CtrlMsg::AddFakeHop {
+                    relay_cell_format: RelayCellFormat::V0,
+                    fwd_lasthop: idx == 2,
+                    rev_lasthop: idx == 2,
+                    params: CircParameters::default(),
+                    done: tx,
+                })
+                .unwrap();
+            rx.await.unwrap().unwrap();
+        }
+
+        (circ, circmsg_send, event_rx, exit)
+    }
+
+    // Helper: return the events for adding the three hops of a circuit
+    // from newcirc_with_events.
+    fn hops_added_events(circ: UniqId) -> Vec<ReactorEvent> {
+        (0_u8..3)
+            .map(|hop| ReactorEvent::HopAdded {
+                circ,
+                hop: hop.into(),
+            })
+            .collect()
+    }
+
+    // Make sure that the reactor reports lifecycle events to its sink.
+    #[test]
+    fn reactor_events() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (circ, _send, event_rx, exit) = newcirc_with_events(&rt, chan).await;
+            let unique_id = circ.unique_id();
+
+            // Nobody answers this, so it stays open until we close it.
+            let circ2 = Arc::clone(&circ);
+            rt.spawn(async move {
+                let _ = circ2.begin_stream("www.example.com", 80, None).await;
+            })
+            .unwrap();
+            rt.advance_until_stalled().await;
+            assert_eq!(circ.close_all_streams().await.unwrap(), 1);
+
+            circ.terminate();
+            assert_eq!(exit.await.unwrap(), ReactorExit::ShutdownRequested);
+
+            let events: Vec<ReactorEvent> = event_rx.collect().await;
+            let hop = |n: u8| HopNum::from(n);
+            let stream = match events[3] {
+                ReactorEvent::StreamBegun {
+                    circ,
+                    hop: h,
+                    stream,
+                } => {
+                    assert_eq!(circ, unique_id);
+                    assert_eq!(h, hop(2));
+                    stream
+                }
+                ref other => panic!("{other:?}"),
+            };
+            assert_eq!(
+                events,
+                vec![
+                    ReactorEvent::HopAdded {
+                        circ: unique_id,
+                        hop: hop(0)
+                    },
+                    ReactorEvent::HopAdded {
+                        circ: unique_id,
+                        hop: hop(1)
+                    },
+                    ReactorEvent::HopAdded {
+                        circ: unique_id,
+                        hop: hop(2)
+                    },
+                    ReactorEvent::StreamBegun {
+                        circ: unique_id,
+                        hop: hop(2),
+                        stream
+                    },
+                    ReactorEvent::StreamClosed {
+                        circ: unique_id,
+                        hop: hop(2),
+                        stream
+                    },
+                    ReactorEvent::Shutdown {
+                        circ: unique_id,
+                        exit: Some(ReactorExit::ShutdownRequested)
+                    },
+                ]
+            );
+        });
+    }
+
+    // Make sure that the reactor reports a stream as closed when the other end ends it,
+    // and doesn't report it again when we drop it.
+    #[test]
+    fn reactor_events_remote_end() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut send, event_rx, exit) = newcirc_with_events(&rt, chan).await;
+            let unique_id = circ.unique_id();
+
+            let begin_fut = circ.begin_stream("www.example.com", 80, None);
+            let respond_fut = async {
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                let AnyChanMsg::Relay(r) = msg else {
+                    panic!("Unexpected chanmsg: {msg:?}");
+                };
+                let rmsg =
+                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                        .unwrap();
+                let (streamid, _) = rmsg.into_streamid_and_msg();
+                let connected = relaymsg::Connected::new_empty().into();
+                send.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+                streamid.unwrap()
+            };
+            let (stream, stream_id) = futures::join!(begin_fut, respond_fut);
+            let stream = stream.unwrap();
+
+            send.send(rmsg_to_ccmsg(
+                Some(stream_id),
+                relaymsg::End::new_misc().into(),
+            ))
+            .await
+            .unwrap();
+            rt.advance_until_stalled().await;
+            drop(stream);
+            rt.advance_until_stalled().await;
+
+            circ.terminate();
+            assert_eq!(exit.await.unwrap(), ReactorExit::ShutdownRequested);
+
+            let events: Vec<ReactorEvent> = event_rx.collect().await;
+            let mut expected = hops_added_events(unique_id);
+            expected.extend([
+                ReactorEvent::StreamBegun {
+                    circ: unique_id,
+                    hop: 2.into(),
+                    stream: stream_id,
+                },
+                ReactorEvent::StreamClosed {
+                    circ: unique_id,
+                    hop: 2.into(),
+                    stream: stream_id,
+                },
+                ReactorEvent::Shutdown {
+                    circ: unique_id,
+                    exit: Some(ReactorExit::ShutdownRequested),
+                },
+            ]);
+            assert_eq!(events, expected);
+        });
+    }
+
+    // Make sure that the reactor reports incoming streams on an onion service circuit.
+    #[test]
+    #[cfg(feature = "hs-service")]
+    fn reactor_events_incoming_stream() {
+        use tor_cell::relaycell::msg::BeginFlags;
+
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut send, event_rx, exit) = newcirc_with_events(&rt, chan).await;
+            let unique_id = circ.unique_id();
+            let stream_id = StreamId::new(12).unwrap();
+
+            let mut incoming = circ
+                .allow_stream_requests(
+                    &[tor_cell::relaycell::RelayCmd::BEGIN],
+                    circ.last_hop_num().unwrap(),
+                    AllowAllStreamsFilter,
+                )
+                .await
+                .unwrap();
+
+            let begin = Begin::new("localhost", 80, BeginFlags::IPV6_OKAY).unwrap();
+            send.send(rmsg_to_ccmsg(Some(stream_id), begin.into()))
+                .await
+                .unwrap();
+            let _stream = incoming.next().await.unwrap();
+
+            circ.terminate();
+            assert_eq!(exit.await.unwrap(), ReactorExit::ShutdownRequested);
+
+            let events: Vec<ReactorEvent> = event_rx.collect().await;
+            let mut expected = hops_added_events(unique_id);
+            expected.extend([
+                ReactorEvent::StreamBegun {
+                    circ: unique_id,
+                    hop: 2.into(),
+                    stream: stream_id,
+                },
+                ReactorEvent::Shutdown {
+                    circ: unique_id,
+                    exit: Some(ReactorExit::ShutdownRequested),
+                },
+            ]);
+            assert_eq!(events, expected);
+        });
+    }
+
+    // Make sure we can tell whether the reactor is waiting for a meta cell.
+    #[test]
+    fn query_meta_handler() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (circ, _send) = newcirc(&rt, chan).await;
+
+            let query = |circ: &ClientCirc| {
+                let (tx, rx) = oneshot::channel();
+                circ.control
+                    .unbounded_send(CtrlMsg::QueryMetaHandler { done: tx })
+                    .unwrap();
+                rx
+            };
+
+            assert_eq!(query(&circ).await.unwrap().unwrap(), None);
+
+            // Start extending the circuit, but never answer.
+            let circ2 = circ.clone();
+            rt.spawn(async move {
+                let params = CircParameters::default();
+                let _ = circ2.extend_ntor(&example_target(), &params).await;
+            })
+            .unwrap();
+            rt.progress_until_stalled().await;
+
+            assert_eq!(query(&circ).await.unwrap().unwrap(), Some(2.into()));
+        });
+    }
+
+    // Make sure that a handler registered for a relay command gets those messages
+    // until it says it's finished.
+    #[cfg(feature = "send-control-msg")]
+    #[test]
+    fn cmd_handler() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            /// A handler that forwards every message it gets, and finishes after two.
+            struct Forward(mpsc::UnboundedSender<AnyRelayMsg>, usize);
+            impl MsgHandler for Forward {
+                fn handle_msg(
+                    &mut self,
+                    _conversation: ConversationInHandler<'_, '_, '_>,
+                    msg: AnyRelayMsg,
+                ) -> Result<MetaCellDisposition> {
+                    self.0.unbounded_send(msg).unwrap();
+                    self.1 += 1;
+                    Ok(if self.1 < 2 {
+                        MetaCellDisposition::Consumed
+                    } else {
+                        MetaCellDisposition::ConversationFinished
+                    })
+                }
+            }
+
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut sink) = newcirc(&rt, chan).await;
+            let cmd = RelayCmd::from(200);
+            let custom_msg =
+                || -> AnyRelayMsg { relaymsg::Unrecognized::new(cmd, &b"hello"[..]).into() };
+
+            let (tx, mut rx) = mpsc::unbounded();
+            circ.register_cmd_handler(cmd, Forward(tx.clone(), 0), 2.into())
+                .await
+                .unwrap();
+
+            // We can't have two handlers for the same command.
+            let err = circ
+                .register_cmd_handler(cmd, Forward(tx.clone(), 0), 2.into())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, Error::Bug(_)));
+
+            // Nor can we take over any of the commands that the circuit handles itself.
+            for cmd in [
+                RelayCmd::SENDME,
+                RelayCmd::TRUNCATED,
+                RelayCmd::EXTENDED2,
+                RelayCmd::DROP,
+                RelayCmd::DATA,
+            ] {
+                let err = circ
+                    .register_cmd_handler(cmd, Forward(tx.clone(), 0), 2.into())
+                    .await
+                    .unwrap_err();
+                assert!(matches!(err, Error::Bug(_)));
+            }
+
+            // But onion service commands are fine: we only ever pass those on.
+            circ.register_cmd_handler(RelayCmd::RENDEZVOUS2, Forward(tx, 0), 2.into())
+                .await
+                .unwrap();
+
+            for _ in 0..2 {
+                sink.send(rmsg_to_ccmsg(None, custom_msg())).await.unwrap();
+                rt.progress_until_stalled().await;
+                assert_eq!(rx.next().await.unwrap().cmd(), cmd);
+            }
+            assert!(!circ.is_closing());
+
+            // The handler is finished, so this message is unexpected.
+            sink.send(rmsg_to_ccmsg(None, custom_msg())).await.unwrap();
+            rt.progress_until_stalled().await;
+            assert!(circ.is_closing());
+        });
+    }
+
+    // Make sure that a circuit with a maximum lifetime gets closed once it is
+    // that old, even if it is busy.
+    #[test]
+    fn reactor_max_lifetime() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            const MAX_LIFETIME: Duration = Duration::from_secs(600);
+
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (_created_send, created_recv) = oneshot::channel();
+            let (_circmsg_send, circmsg_recv) = fake_mpsc(64);
+            let (pending, reactor) = PendingClientCirc::new(
+                CircId::new(128).unwrap(),
+                chan,
+                created_recv,
+                circmsg_recv,
+                UniqId::new(23, 17),
+                CircuitAccount::new_noop(),
+            );
+            let (exit_tx, mut exit_rx) = oneshot::channel();
+            rt.spawn(async move {
+                let _ = exit_tx.send(reactor.run().await);
+            })
+            .unwrap();
+            let circ = pending.circ;
+
+            // The clock doesn't start until we have a hop.
+            rt.advance_by(MAX_LIFETIME * 2).await;
+            assert!(!circ.is_closing());
+
+            let mut params = CircParameters::default();
+            params.set_max_lifetime(Some(MAX_LIFETIME));
+            let (tx, rx) = oneshot::channel();
+            circ.control
+                .unbounded_send(CtrlMsg::AddFakeHop {
+                    relay_cell_format: RelayCellFormat::V0,
+                    fwd_lasthop: true,
+                    rev_lasthop: true,
+                    params,
+                    done: tx,
+                })
+                .unwrap();
+            rx.await.unwrap().unwrap();
+
+            // Activity doesn't keep the circuit alive.
+            for _ in 0..9 {
+                rt.advance_by(Duration::from_secs(60)).await;
+                let (tx, rx) = oneshot::channel();
+                circ.control
+                    .unbounded_send(CtrlMsg::QuerySendWindow {
+                        hop: 0.into(),
+                        done: tx,
+                    })
+                    .unwrap();
+                rx.await.unwrap().unwrap();
+            }
+            assert!(futures::poll!(&mut exit_rx).is_pending());
+            assert!(!circ.is_closing());
+
+            rt.advance_by(Duration::from_secs(61)).await;
+            assert_eq!(
+                exit_rx.await.unwrap().unwrap(),
+                ReactorExit::MaxLifetimeReached
+            );
+            assert!(circ.is_closing());
+        });
+    }
+
+    // Make sure that we can change some circuit parameters on a running circuit.
+    #[test]
+    fn update_params() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (circ, _send) = newcirc(&rt, chan).await;
+
+            // Nothing has to change.
+            circ.update_params(CircParamsUpdate::new()).await.unwrap();
+
+            let mut update = CircParamsUpdate::new();
+            assert!(update.set_max_cells_per_yield(0).is_err());
+            update.set_max_cells_per_yield(8).unwrap();
+            update.set_max_lifetime(Some(Duration::from_secs(600)));
+            circ.update_params(update).await.unwrap();
+
+            // Lowering the maximum lifetime below the circuit's age closes it.
+            rt.advance_by(Duration::from_secs(60)).await;
+            assert!(!circ.is_closing());
+            let mut update = CircParamsUpdate::new();
+            update.set_max_lifetime(Some(Duration::from_secs(30)));
+            circ.update_params(update).await.unwrap();
+            rt.advance_until_stalled().await;
+            assert!(circ.is_closing());
+        });
+    }
+
+    #[test]
+    fn update_params_apply() {
+        let mut params = CircParameters::default();
+        params.set_max_lifetime(Some(Duration::from_secs(600)));
+
+        // Leaving things unset keeps them.
+        CircParamsUpdate::new().apply_to(&mut params);
+        assert_eq!(params.max_cells_per_yield(), 64);
+        assert_eq!(params.max_lifetime(), Some(Duration::from_secs(600)));
+
+        let mut update = CircParamsUpdate::new();
+        update.set_max_cells_per_yield(8).unwrap();
+        update.set_max_lifetime(None);
+        update.apply_to(&mut params);
+        assert_eq!(params.max_cells_per_yield(), 8);
+        assert_eq!(params.max_lifetime(), None);
+    }
+
+    // Test: in strict mode, we close the circuit as soon as the other side
+    // sends more cells than a stream's receive window allows.
+    #[test]
+    fn strict_recv_windows() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let mut params = CircParameters::default();
+            params
+                .set_stream_sendme_params(SendmeParams::new(20, 10).unwrap())
+                .unwrap();
+            for strict in [false, true] {
+                params.set_strict_recv_windows(strict);
+                let (chan, mut rx, _sink) = working_fake_channel(&rt);
+                let (circ, mut sink) = newcirc_with_params(
+                    &rt,
+                    chan,
+                    CircId::new(128).unwrap(),
+                    2.into(),
+                    params.clone(),
+                )
+                .await;
+
+                // BEGIN_DIR streams are optimistic, so we needn't wait for an answer.
+                let _stream = Arc::clone(&circ).begin_dir_stream().await.unwrap();
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                let rmsg = match msg {
+                    AnyChanMsg::Relay(r) => {
+                        AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                            .unwrap()
+                    }
+                    other => panic!("{:?}", other),
+                };
+                let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+                assert_eq!(rmsg.cmd(), RelayCmd::BEGIN_DIR);
+
+                let connected = relaymsg::Connected::new_empty().into();
+                sink.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+
+                // Send one more DATA cell than the window allows, without reading any.
+                // This still fits in the stream's buffer.
+                for _ in 0..=20 {
+                    let data = relaymsg::Data::new(b"hello").unwrap().into();
+                    sink.send(rmsg_to_ccmsg(streamid, data)).await.unwrap();
                 }
-                other => panic!("{:?}", other),
-            };
-            assert!(matches!(m.msg(), AnyRelayMsg::BeginDir(_)));
+                rt.advance_until_stalled().await;
+                assert_eq!(circ.is_closing(), strict);
+            }
         });
     }
 
@@ -1837,6 +3061,137 @@ mod test {
         }
     }
 
+    #[test]
+    fn query_outbound_queues() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            // More cells than the channel (and our end of it) can hold.
+            const N_CELLS: usize = 300;
+
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, _send) = newcirc(&rt, chan).await;
+
+            let query = |circ: &ClientCirc| {
+                let (tx, rx) = oneshot::channel();
+                circ.control
+                    .unbounded_send(CtrlMsg::QueryOutboundQueues { done: tx })
+                    .unwrap();
+                rx
+            };
+
+            assert_eq!(query(&circ).await.unwrap().unwrap(), (0, vec![0, 0, 0]));
+
+            // Send lots of cells, without reading any of them from the channel.
+            for _ in 0..N_CELLS {
+                let drop = AnyRelayMsgOuter::new(None, relaymsg::Drop::default().into());
+                circ.control
+                    .unbounded_send(CtrlMsg::SendRelayCell {
+                        hop: 2.into(),
+                        early: false,
+                        cell: drop,
+                    })
+                    .unwrap();
+            }
+            rt.progress_until_stalled().await;
+
+            // The channel is full, so the rest of the cells are queued in the reactor.
+            let (n_queued, hops) = query(&circ).await.unwrap().unwrap();
+            assert!(n_queued > 0);
+            assert!(n_queued < N_CELLS);
+            assert_eq!(hops, vec![0, 0, 0]);
+
+            // Once we read them all, there's nothing left in the queue.
+            for _ in 0..N_CELLS {
+                let _ = rx.next().await.unwrap();
+            }
+            rt.progress_until_stalled().await;
+            assert_eq!(query(&circ).await.unwrap().unwrap(), (0, vec![0, 0, 0]));
+        });
+    }
+
+    #[test]
+    fn circuit_traffic() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut sink) = newcirc(&rt, chan).await;
+            assert_eq!(circ.traffic(), TrafficSnapshot::default());
+
+            // Open a stream: that's one cell in each direction.
+            let begin_fut = circ.begin_stream("www.example.com", 80, None);
+            let respond_fut = async {
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                let AnyChanMsg::Relay(r) = msg else {
+                    panic!("Unexpected chanmsg: {msg:?}");
+                };
+                let rmsg =
+                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                        .unwrap();
+                let (streamid, _) = rmsg.into_streamid_and_msg();
+                let connected = relaymsg::Connected::new_empty().into();
+                sink.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+            };
+            let (stream, ()) = futures::join!(begin_fut, respond_fut);
+            let _stream = stream.unwrap();
+
+            let traffic = circ.traffic();
+            assert_eq!(traffic.read, 514);
+            assert_eq!(traffic.written, 514);
+        });
+    }
+
+    #[test]
+    fn query_outbound_queues_streams() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            // More cells than the channel (and our end of it) can hold, for each stream.
+            const N_CELLS: usize = 300;
+
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut sink) = newcirc(&rt, chan).await;
+
+            // Open two streams on the last hop, and have each of them
+            // try to send more data than the channel can take.
+            for _ in 0..2 {
+                let begin_fut = circ.begin_stream("www.example.com", 80, None);
+                let respond_fut = async {
+                    let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                    let AnyChanMsg::Relay(r) = msg else {
+                        panic!("Unexpected chanmsg: {msg:?}");
+                    };
+                    let rmsg = AnyRelayMsgOuter::decode_singleton(
+                        RelayCellFormat::V0,
+                        r.into_relay_body(),
+                    )
+                    .unwrap();
+                    let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+                    assert_eq!(rmsg.cmd(), RelayCmd::BEGIN);
+                    let connected =
+                        relaymsg::Connected::new_with_addr("10.0.0.1".parse().unwrap(), 1234)
+                            .into();
+                    sink.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+                };
+                let (stream, ()) = futures::join!(begin_fut, respond_fut);
+                let mut stream = stream.unwrap();
+                rt.spawn(async move {
+                    let _ = stream
+                        .write_all(&[0_u8; relaymsg::Data::MAXLEN * N_CELLS])
+                        .await;
+                })
+                .unwrap();
+            }
+            rt.progress_until_stalled().await;
+
+            // The channel filled up while both streams still had data to send,
+            // so the reactor is holding on to the message that one of them has ready.
+            let (tx, query_rx) = oneshot::channel();
+            circ.control
+                .unbounded_send(CtrlMsg::QueryOutboundQueues { done: tx })
+                .unwrap();
+            let (_, hops) = query_rx.await.unwrap().unwrap();
+            assert_eq!(hops.len(), 3);
+            assert_eq!(hops[0..2], [0, 0]);
+            assert!(hops[2] > 0);
+        });
+    }
+
     #[test]
     fn test_extend_ntor() {
         tor_rtcompat::test_with_all_runtimes!(|rt| async move {
@@ -1852,6 +3207,27 @@ mod test {
         });
     }
 
+    #[test]
+    fn extend_past_max_hops() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            // This gives us a circuit with 3 hops.
+            let (circ, _sink) = newcirc(&rt, chan).await;
+            let mut params = CircParameters::default();
+            params.set_max_hops(3).unwrap();
+
+            let target = example_target();
+            let res = circ.extend_ntor(&target, &params).await;
+            assert!(matches!(res, Err(Error::Bug(_))));
+
+            // We didn't send an EXTEND2, and the circuit is still usable.
+            rt.advance_until_stalled().await;
+            assert!(rx.try_next().is_err());
+            assert!(!circ.is_closing());
+            assert_eq!(circ.n_hops(), 3);
+        });
+    }
+
     async fn bad_extend_test_impl<R: Runtime>(
         rt: &R,
         reply_hop: HopNum,
@@ -2081,6 +3457,238 @@ mod test {
         close_stream_helper(false);
     }
 
+    // Test: close every stream on a circuit, without closing the circuit.
+    #[test]
+    fn close_all_streams() {
+        tor_rtcompat::test_with_all_runtimes!(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut sink) = newcirc(&rt, chan).await;
+
+            let stream_fut = async move {
+                let stream = circ
+                    .begin_stream("www.example.com", 80, None)
+                    .await
+                    .unwrap();
+
+                assert_eq!(circ.close_all_streams().await.unwrap(), 1);
+                // There's nothing left to close.
+                assert_eq!(circ.close_all_streams().await.unwrap(), 0);
+                assert!(!circ.is_closing());
+                (stream, circ) // make sure to keep the circuit alive
+            };
+            let handler_fut = async {
+                // Read the BEGIN message.
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                let rmsg = match msg {
+                    AnyChanMsg::Relay(r) => {
+                        AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                            .unwrap()
+                    }
+                    other => panic!("{:?}", other),
+                };
+                let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+                assert_eq!(rmsg.cmd(), RelayCmd::BEGIN);
+
+                // Reply with a CONNECTED.
+                let connected =
+                    relaymsg::Connected::new_with_addr("10.0.0.1".parse().unwrap(), 1234).into();
+                sink.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+
+                // Expect an END for that stream.
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                let rmsg = match msg {
+                    AnyChanMsg::Relay(r) => {
+                        AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                            .unwrap()
+                    }
+                    other => panic!("{:?}", other),
+                };
+                let (end_streamid, rmsg) = rmsg.into_streamid_and_msg();
+                assert_eq!(rmsg.cmd(), RelayCmd::END);
+                assert_eq!(end_streamid, streamid);
+
+                (rx, sink) // keep these alive or the reactor will exit.
+            };
+
+            let ((stream, _circ), (_rx, _sink)) = futures::join!(stream_fut, handler_fut);
+
+            // The local end of the stream sees that it was closed.
+            let (mut r, _w) = stream.split();
+            let mut buf = [0_u8; 16];
+            assert!(!matches!(r.read(&mut buf).await, Ok(n) if n > 0));
+        });
+    }
+
+    // Test: when a TRUNCATED cell removes hops, every stream on those hops is closed.
+    #[test]
+    fn truncated_closes_streams() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            // Our inbound messages come from the middle hop.
+            let (mut reactor, circ, mut sink) = Reactor::new_fake(
+                chan,
+                CircId::new(128).unwrap(),
+                3,
+                1.into(),
+                &CircParameters::default(),
+            );
+            let (event_tx, event_rx) = mpsc::unbounded();
+            reactor.set_event_sink(event_tx);
+            let exit = rt.spawn_with_handle(reactor.run()).unwrap();
+
+            // BEGIN_DIR streams are optimistic, so we needn't answer them.
+            let mut streams = vec![];
+            for _ in 0..2 {
+                streams.push(Arc::clone(&circ).begin_dir_stream().await.unwrap());
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                assert!(matches!(msg, AnyChanMsg::Relay(_)));
+            }
+
+            let truncated = relaymsg::Truncated::new(chanmsg::DestroyReason::FINISHED).into();
+            sink.send(rmsg_to_ccmsg(None, truncated)).await.unwrap();
+            assert_eq!(exit.await.unwrap(), ReactorExit::ClosedByCell);
+
+            // Every reader sees EOF, rather than waiting forever.
+            for stream in &mut streams {
+                let mut buf = [0_u8; 16];
+                assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+            }
+
+            // Both streams were closed on the last hop before the reactor shut down.
+            let events: Vec<ReactorEvent> = event_rx.collect().await;
+            let closed = events
+                .iter()
+                .filter(|e| {
+                    matches!(e, ReactorEvent::StreamClosed { hop, .. } if *hop == HopNum::from(2))
+                })
+                .count();
+            assert_eq!(closed, 2);
+            assert!(matches!(
+                events.last(),
+                Some(ReactorEvent::Shutdown {
+                    exit: Some(ReactorExit::ClosedByCell),
+                    ..
+                })
+            ));
+        });
+    }
+
+    // Test: reserve a stream ID, and only send the BEGIN message afterwards.
+    #[test]
+    fn reserve_stream() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, _sink) = newcirc(&rt, chan).await;
+
+            let (_reader, mut target, _memquota) = circ
+                .reserve_stream_impl(DataCmdChecker::new_any())
+                .await
+                .unwrap();
+            // Reserving the stream didn't send anything.
+            rt.advance_until_stalled().await;
+            assert!(rx.try_next().is_err());
+
+            let begin = relaymsg::Begin::new("www.example.com", 80, 0).unwrap();
+            target.send(begin.into()).await.unwrap();
+
+            let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+            let rmsg = match msg {
+                AnyChanMsg::Relay(r) => {
+                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                        .unwrap()
+                }
+                other => panic!("{:?}", other),
+            };
+            let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+            assert_eq!(rmsg.cmd(), RelayCmd::BEGIN);
+            assert_eq!(streamid, Some(target.stream_id));
+        });
+    }
+
+    // Test: reserve a stream through the public API, and use it to start a data stream.
+    #[test]
+    #[cfg(feature = "experimental-api")]
+    fn reserve_stream_then_begin() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, _sink) = newcirc(&rt, chan).await;
+
+            let reserved = circ.reserve_stream().await.unwrap();
+            let stream_id = reserved.stream_id();
+            rt.advance_until_stalled().await;
+            assert!(rx.try_next().is_err());
+
+            let begin = relaymsg::Begin::new("www.example.com", 80, 0).unwrap();
+            let _stream = reserved.begin(begin.into()).await.unwrap();
+
+            let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+            let rmsg = match msg {
+                AnyChanMsg::Relay(r) => {
+                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                        .unwrap()
+                }
+                other => panic!("{:?}", other),
+            };
+            let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+            assert_eq!(rmsg.cmd(), RelayCmd::BEGIN);
+            assert_eq!(streamid, Some(stream_id));
+        });
+    }
+
+    // Test: pause a circuit, and make sure that its streams' data is held back until it resumes.
+    #[test]
+    fn pause_and_resume() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (circ, mut sink) = newcirc(&rt, chan).await;
+
+            let stream_fut = circ.begin_stream("www.example.com", 80, None);
+            let handler_fut = async {
+                // Read the BEGIN message.
+                let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                let rmsg = match msg {
+                    AnyChanMsg::Relay(r) => {
+                        AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                            .unwrap()
+                    }
+                    other => panic!("{:?}", other),
+                };
+                let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+                assert_eq!(rmsg.cmd(), RelayCmd::BEGIN);
+
+                // Reply with a CONNECTED.
+                let connected =
+                    relaymsg::Connected::new_with_addr("10.0.0.1".parse().unwrap(), 1234).into();
+                sink.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+            };
+            let (stream, ()) = futures::join!(stream_fut, handler_fut);
+            let mut stream = stream.unwrap();
+
+            circ.set_paused(true).unwrap();
+            stream.write_all(b"GET / HTTP/1.0\r\n").await.unwrap();
+            stream.flush().await.unwrap();
+            rt.advance_until_stalled().await;
+            // Nothing was sent while we were paused.
+            assert!(rx.try_next().is_err());
+            assert!(!circ.is_closing());
+
+            circ.set_paused(false).unwrap();
+            rt.advance_until_stalled().await;
+            let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+            let rmsg = match msg {
+                AnyChanMsg::Relay(r) => {
+                    AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                        .unwrap()
+                }
+                other => panic!("{:?}", other),
+            };
+            match rmsg.into_streamid_and_msg() {
+                (_, AnyRelayMsg::Data(d)) => assert_eq!(d.as_ref(), &b"GET / HTTP/1.0\r\n"[..]),
+                (_, other) => panic!("{:?}", other),
+            }
+        });
+    }
+
     // Set up a circuit and stream that expects some incoming SENDMEs.
     async fn setup_incoming_sendme_case<R: Runtime>(
         rt: &R,
@@ -2401,6 +4009,96 @@ mod test {
         });
     }
 
+    #[test]
+    fn busy_circuits_interleave() {
+        use futures::io::AsyncWriteExt as _;
+        use tor_rtcompat::BlockOn as _;
+        use tor_rtmock::task::SchedulingPolicy;
+
+        // Number of cells for each circuit to send.
+        const N_CELLS: usize = 32;
+        // How many cells each circuit may send before yielding.
+        const MAX_CELLS_PER_YIELD: u16 = 4;
+
+        // With the default "stack" scheduling policy, a task that yields is run again
+        // immediately, so we need to use a "queue" to see other tasks get a turn.
+        let rt = tor_rtmock::MockRuntime::builder()
+            .scheduling(SchedulingPolicy::Queue)
+            .build();
+        rt.clone().block_on(async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let mut params = CircParameters::default();
+            params.set_max_cells_per_yield(MAX_CELLS_PER_YIELD).unwrap();
+
+            let mut circs = Vec::new();
+            for circid in [128, 129] {
+                let (circ, mut sink) = newcirc_with_params(
+                    &rt,
+                    chan.clone(),
+                    CircId::new(circid).unwrap(),
+                    2.into(),
+                    params.clone(),
+                )
+                .await;
+
+                let begin_fut = circ.begin_stream("www.example.com", 80, None);
+                let respond_fut = async {
+                    let (_, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                    let AnyChanMsg::Relay(r) = msg else {
+                        panic!("Unexpected chanmsg: {msg:?}");
+                    };
+                    let rmsg = AnyRelayMsgOuter::decode_singleton(
+                        RelayCellFormat::V0,
+                        r.into_relay_body(),
+                    )
+                    .unwrap();
+                    let (streamid, rmsg) = rmsg.into_streamid_and_msg();
+                    assert_eq!(rmsg.cmd(), RelayCmd::BEGIN);
+                    let connected =
+                        relaymsg::Connected::new_with_addr("10.0.0.1".parse().unwrap(), 1234)
+                            .into();
+                    sink.send(rmsg_to_ccmsg(streamid, connected)).await.unwrap();
+                };
+                let (stream, ()) = futures::join!(begin_fut, respond_fut);
+                let mut stream = stream.unwrap();
+
+                // Queue up all our data while the circuit is paused,
+                // so that both circuits are busy at once when we resume them.
+                circ.set_paused(true).unwrap();
+                stream
+                    .write_all(&[0_u8; relaymsg::Data::MAXLEN * N_CELLS])
+                    .await
+                    .unwrap();
+                stream.flush().await.unwrap();
+                circs.push((circ, sink, stream));
+            }
+            rt.progress_until_stalled().await;
+
+            for (circ, _, _) in &circs {
+                circ.set_paused(false).unwrap();
+            }
+
+            // Find out which circuit each of the cells came from.
+            let mut circids = Vec::new();
+            for _ in 0..N_CELLS * 2 {
+                let (circid, msg) = rx.next().await.unwrap().into_circid_and_msg();
+                assert!(matches!(msg, AnyChanMsg::Relay(_)));
+                circids.push(circid.unwrap());
+            }
+
+            // Neither circuit got to send more than its share of cells in a row.
+            let longest_run = circids
+                .chunk_by(|a, b| a == b)
+                .map(|run| run.len())
+                .max()
+                .unwrap();
+            assert!(
+                longest_run <= usize::from(MAX_CELLS_PER_YIELD),
+                "one circuit sent {longest_run} cells in a row: {circids:?}"
+            );
+        });
+    }
+
     #[test]
     fn basic_params() {
         use super::CircParameters;
@@ -2415,6 +4113,59 @@ mod test {
 
         assert!(p.set_initial_send_window(9000).is_err());
         assert_eq!(p.initial_send_window(), 500);
+
+        assert_eq!(p.max_hops(), 8);
+        assert!(p.set_max_hops(0).is_err());
+        assert!(p.set_max_hops(4).is_ok());
+        assert_eq!(p.max_hops(), 4);
+
+        assert_eq!(p.max_cells_per_yield(), 64);
+        assert!(p.set_max_cells_per_yield(0).is_err());
+        assert!(p.set_max_cells_per_yield(8).is_ok());
+        assert_eq!(p.max_cells_per_yield(), 8);
+    }
+
+    #[test]
+    fn sendme_params() {
+        use super::{CircParameters, SendmeParams};
+        let mut p = CircParameters::default();
+        assert_eq!(
+            p.circ_sendme_params(),
+            SendmeParams::new(1000, 100).unwrap()
+        );
+        assert_eq!(
+            p.stream_sendme_params(),
+            SendmeParams::new(500, 50).unwrap()
+        );
+
+        // The window has to be nonzero; the increment has to be nonzero, and divide the window.
+        assert!(SendmeParams::new(0, 100).is_err());
+        assert!(SendmeParams::new(1000, 0).is_err());
+        assert!(SendmeParams::new(1000, 300).is_err());
+        assert!(SendmeParams::new(100, 200).is_err());
+
+        // We can't shrink the circuit window below the initial send window...
+        let small = SendmeParams::new(400, 40).unwrap();
+        assert!(p.set_circ_sendme_params(small).is_err());
+        assert_eq!(p.circ_sendme_params().window(), 1000);
+        // ...unless we lower the initial send window first.
+        assert!(p.set_initial_send_window(400).is_ok());
+        assert!(p.set_circ_sendme_params(small).is_ok());
+        assert_eq!(p.circ_sendme_params().increment(), 40);
+        assert!(p.set_initial_send_window(500).is_err());
+
+        // A larger circuit window permits a larger initial send window.
+        let large = SendmeParams::new(2000, 100).unwrap();
+        assert!(p.set_circ_sendme_params(large).is_ok());
+        assert!(p.set_initial_send_window(2000).is_ok());
+
+        assert!(p.set_stream_sendme_params(small).is_ok());
+        assert_eq!(p.stream_sendme_params(), small);
+
+        // A stream window can't be larger than the circuit window.
+        let huge = SendmeParams::new(4000, 100).unwrap();
+        assert!(p.set_stream_sendme_params(huge).is_err());
+        assert_eq!(p.stream_sendme_params(), small);
     }
 
     #[cfg(feature = "hs-service")]
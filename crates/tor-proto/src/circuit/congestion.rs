@@ -0,0 +1,344 @@
+//! Congestion control for the cells that we send on a circuit.
+//!
+//! Each hop of a circuit has a [`CongestionControl`] object, which decides
+//! whether we may send another cell that counts towards the circuit's
+//! SENDME window to that hop.
+//!
+//! The default algorithm is the classic fixed window from tor-spec:
+//! we may have up to 1000 unacknowledged cells in flight, and each
+//! circuit-level SENDME acknowledges 100 of them.
+//!
+//! We also have an experimental algorithm that follows the ideas of
+//! TCP Vegas, as described in proposal 324.  Unlike the algorithm in that
+//! proposal, it doesn't need any cooperation from the other end of the
+//! circuit: it only ever sends _less_ than the fixed window allows,
+//! so it still works with relays that only know about the fixed window.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::sendme::CircSendWindow;
+#[cfg(test)]
+use super::sendme::CircTag;
+use super::CircParameters;
+use crate::Result;
+
+/// An algorithm for deciding when we may send cells on one hop of a circuit.
+///
+/// Only cells that count towards SENDME windows are subject to congestion control.
+pub(crate) trait CongestionControl: Send {
+    /// Return true if we may send another cell that counts towards SENDME windows.
+    fn can_send(&self) -> bool;
+
+    /// Record that at `now` we sent a cell that counts towards SENDME windows,
+    /// and which was encrypted with the authentication tag `tag`.
+    ///
+    /// Gives an error if we weren't allowed to send it.
+    fn note_data_sent(&mut self, now: Instant, tag: &[u8; 20]) -> Result<()>;
+
+    /// Handle a circuit-level SENDME, with the authentication tag `tag`,
+    /// that we received at `now`.
+    ///
+    /// Gives an error if we weren't expecting a SENDME, or if the tag is wrong.
+    fn note_sendme_received(&mut self, now: Instant, tag: Option<[u8; 20]>) -> Result<()>;
+
+    /// For testing: get a copy of the current send window, and the
+    /// expected incoming tags.
+    #[cfg(test)]
+    fn send_window_and_expected_tags(&self) -> (u16, Vec<CircTag>);
+}
+
+/// A congestion control algorithm for circuits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CongestionControlAlgorithm {
+    /// The classic fixed SENDME window from tor-spec.
+    ///
+    /// This is the default.
+    #[default]
+    FixedWindow,
+    /// An experimental algorithm in the style of TCP Vegas.
+    ///
+    /// This keeps a congestion window that grows while the circuit's round trip
+    /// time stays close to the smallest that we have seen, and shrinks when it
+    /// grows, since that suggests that cells are queueing somewhere.
+    /// It never lets us send more than the fixed window would.
+    Vegas,
+}
+
+/// Return a new [`CongestionControl`] for a hop of a circuit built with `params`.
+pub(crate) fn new_congestion_control(params: &CircParameters) -> Box<dyn CongestionControl> {
+    let window =
+        CircSendWindow::new_with_params(params.initial_send_window(), params.circ_sendme_params());
+    match params.congestion_control() {
+        CongestionControlAlgorithm::FixedWindow => Box::new(FixedWindow { window }),
+        CongestionControlAlgorithm::Vegas => {
+            Box::new(Vegas::new(window, params.circ_sendme_params().increment()))
+        }
+    }
+}
+
+/// The classic fixed-window congestion control from tor-spec.
+struct FixedWindow {
+    /// The SENDME window, which says how many more cells we may send.
+    window: CircSendWindow,
+}
+
+impl CongestionControl for FixedWindow {
+    fn can_send(&self) -> bool {
+        self.window.window() > 0
+    }
+
+    fn note_data_sent(&mut self, _now: Instant, tag: &[u8; 20]) -> Result<()> {
+        self.window.take(tag)?;
+        Ok(())
+    }
+
+    fn note_sendme_received(&mut self, _now: Instant, tag: Option<[u8; 20]>) -> Result<()> {
+        self.window.put(tag)?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn send_window_and_expected_tags(&self) -> (u16, Vec<CircTag>) {
+        self.window.window_and_expected_tags()
+    }
+}
+
+/// The size of our initial congestion window, in SENDME increments.
+///
+/// This is about the same as the `cc_cwnd_init` of proposal 324.
+const VEGAS_INITIAL_INCREMENTS: u16 = 4;
+
+/// When the number of cells that we think are queued is under this many
+/// SENDME increments, we grow the congestion window.
+const VEGAS_ALPHA_INCREMENTS: u16 = 1;
+
+/// When the number of cells that we think are queued is over this many
+/// SENDME increments, we shrink the congestion window.
+const VEGAS_BETA_INCREMENTS: u16 = 2;
+
+/// Congestion control in the style of TCP Vegas.
+///
+/// We estimate how many of our cells are sitting in queues, by comparing
+/// the latest round trip time with the smallest one we have seen.
+/// We grow the congestion window by one SENDME increment per SENDME while we
+/// think that few cells are queued, and shrink it by one increment when we
+/// think that many are.
+///
+/// Until we first see any queueing, we're in "slow start",
+/// where we keep growing the window.
+struct Vegas {
+    /// The SENDME window.
+    ///
+    /// The other end of the circuit enforces this, so we must never
+    /// send more than it allows, whatever our congestion window says.
+    window: CircSendWindow,
+    /// The number of cells acknowledged by each SENDME.
+    increment: u16,
+    /// The largest number of unacknowledged cells that we want in flight.
+    cwnd: u16,
+    /// The number of cells that we have sent, but which haven't been acknowledged.
+    inflight: u16,
+    /// The times at which we sent the cells whose SENDMEs we're waiting for,
+    /// oldest first.
+    sent_at: VecDeque<Instant>,
+    /// The smallest round trip time that we have seen.
+    min_rtt: Option<Duration>,
+    /// True if we're still in slow start.
+    slow_start: bool,
+}
+
+impl Vegas {
+    /// Return a new `Vegas` that obeys `window`, whose SENDMEs acknowledge
+    /// `increment` cells each.
+    fn new(window: CircSendWindow, increment: u16) -> Self {
+        Vegas {
+            window,
+            increment,
+            cwnd: increment.saturating_mul(VEGAS_INITIAL_INCREMENTS),
+            inflight: 0,
+            sent_at: VecDeque::new(),
+            min_rtt: None,
+            slow_start: true,
+        }
+    }
+
+    /// Adjust the congestion window after a SENDME that took `rtt` to arrive.
+    fn update(&mut self, rtt: Duration) {
+        let min_rtt = *self
+            .min_rtt
+            .insert(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        if rtt.is_zero() {
+            // We can't learn anything from this.
+            return;
+        }
+
+        // If there were no queues, we could get `cwnd` cells through in `min_rtt`:
+        // so the cells that took us longer than that to get through were queued.
+        let expected = u128::from(self.cwnd) * min_rtt.as_nanos() / rtt.as_nanos();
+        let queued = u128::from(self.cwnd).saturating_sub(expected);
+        let alpha = u128::from(self.increment) * u128::from(VEGAS_ALPHA_INCREMENTS);
+        let beta = u128::from(self.increment) * u128::from(VEGAS_BETA_INCREMENTS);
+
+        if self.slow_start {
+            if queued > alpha {
+                self.slow_start = false;
+            } else {
+                self.cwnd = self.cwnd.saturating_add(self.increment);
+            }
+        } else if queued < alpha {
+            self.cwnd = self.cwnd.saturating_add(self.increment);
+        } else if queued > beta {
+            self.cwnd = self.cwnd.saturating_sub(self.increment);
+        }
+
+        // We always need to allow at least one increment in flight, or we
+        // would never get another SENDME.
+        self.cwnd = self.cwnd.max(self.increment);
+    }
+}
+
+impl CongestionControl for Vegas {
+    fn can_send(&self) -> bool {
+        self.window.window() > 0 && self.inflight < self.cwnd
+    }
+
+    fn note_data_sent(&mut self, now: Instant, tag: &[u8; 20]) -> Result<()> {
+        let left = self.window.take(tag)?;
+        self.inflight = self.inflight.saturating_add(1);
+        if left % self.increment == 0 {
+            // The window just recorded this cell's tag: the matching SENDME
+            // will tell us how long this cell took to get through.
+            self.sent_at.push_back(now);
+        }
+        Ok(())
+    }
+
+    fn note_sendme_received(&mut self, now: Instant, tag: Option<[u8; 20]>) -> Result<()> {
+        self.window.put(tag)?;
+        self.inflight = self.inflight.saturating_sub(self.increment);
+        if let Some(sent) = self.sent_at.pop_front() {
+            self.update(now.saturating_duration_since(sent));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn send_window_and_expected_tags(&self) -> (u16, Vec<CircTag>) {
+        self.window.window_and_expected_tags()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+    use super::*;
+
+    /// Send cells on `cc` at `now` until it won't let us send any more,
+    /// and return the number of cells we sent and the tags we'll expect in SENDMEs.
+    fn fill(cc: &mut dyn CongestionControl, now: Instant) -> (usize, Vec<[u8; 20]>) {
+        let mut n = 0;
+        while cc.can_send() {
+            cc.note_data_sent(now, &[n as u8; 20]).unwrap();
+            n += 1;
+        }
+        let tags = (0..n)
+            .filter(|i| (i + 1) % 100 == 0)
+            .map(|i| [i as u8; 20])
+            .collect();
+        (n, tags)
+    }
+
+    #[test]
+    fn fixed_window() {
+        let params = CircParameters::default();
+        assert_eq!(
+            params.congestion_control(),
+            CongestionControlAlgorithm::FixedWindow
+        );
+        let mut cc = new_congestion_control(&params);
+        let now = Instant::now();
+
+        let (n, tags) = fill(cc.as_mut(), now);
+        assert_eq!(n, 1000);
+        assert_eq!(cc.send_window_and_expected_tags().0, 0);
+        assert!(cc.note_data_sent(now, &[0; 20]).is_err());
+
+        // A SENDME with the wrong tag is an error.
+        assert!(cc.note_sendme_received(now, Some([0xff; 20])).is_err());
+
+        cc.note_sendme_received(now, Some(tags[0])).unwrap();
+        assert!(cc.can_send());
+        assert_eq!(cc.send_window_and_expected_tags().0, 100);
+    }
+
+    #[test]
+    fn vegas() {
+        let mut params = CircParameters::default();
+        params.set_congestion_control(CongestionControlAlgorithm::Vegas);
+        let mut cc = new_congestion_control(&params);
+        let start = Instant::now();
+        let rtt = Duration::from_millis(100);
+
+        // We start with a small window, well under the SENDME window.
+        let (n, tags) = fill(cc.as_mut(), start);
+        assert_eq!(n, 400);
+        assert_eq!(cc.send_window_and_expected_tags().0, 600);
+
+        // While the RTT stays the same, we're in slow start, and the window grows.
+        cc.note_sendme_received(start + rtt, Some(tags[0])).unwrap();
+        cc.note_sendme_received(start + rtt, Some(tags[1])).unwrap();
+        let (n, _) = fill(cc.as_mut(), start + rtt);
+        assert_eq!(n, 400);
+    }
+
+    #[test]
+    fn vegas_backs_off() {
+        let increment = 100;
+        let window =
+            CircSendWindow::new_with_params(1000, CircParameters::default().circ_sendme_params());
+        let mut vegas = Vegas::new(window, increment);
+        let min_rtt = Duration::from_millis(100);
+
+        // Slow start ends as soon as we see queueing.
+        vegas.update(min_rtt);
+        assert_eq!(vegas.cwnd, 500);
+        vegas.update(min_rtt * 2);
+        assert!(!vegas.slow_start);
+        assert_eq!(vegas.cwnd, 500);
+
+        // Lots of queueing makes the window shrink.
+        for _ in 0..10 {
+            vegas.update(min_rtt * 100);
+        }
+        assert_eq!(vegas.cwnd, 200);
+
+        // No queueing makes it grow again.
+        vegas.update(min_rtt);
+        assert_eq!(vegas.cwnd, 300);
+    }
+
+    #[test]
+    fn vegas_obeys_window() {
+        let window =
+            CircSendWindow::new_with_params(1000, CircParameters::default().circ_sendme_params());
+        let mut vegas = Vegas::new(window, 100);
+        vegas.cwnd = u16::MAX;
+
+        let (n, _) = fill(&mut vegas, Instant::now());
+        assert_eq!(n, 1000);
+    }
+}
@@ -20,6 +20,14 @@ use crate::crypto::cell::Tor1Hsv3RelayCrypto;
 use crate::crypto::cell::{
     ClientLayer, CryptInit, InboundClientLayer, OutboundClientLayer, Tor1RelayCrypto,
 };
+#[cfg(feature = "relay")]
+use {
+    crate::crypto::handshake::ntor::{NtorSecretKey, NtorServer},
+    crate::crypto::handshake::{RelayHandshakeError, RelayHandshakeResult, ServerHandshake},
+    rand_core::{CryptoRng, RngCore},
+    tor_cell::chancell::msg::{Create2, Created2, HandshakeType},
+    tor_error::into_internal,
+};
 
 use crate::Result;
 
@@ -140,3 +148,129 @@ where
         binding: Some(binding),
     })
 }
+
+/// Answer a client's CREATE2 message that asks for an ntor handshake, as a relay.
+///
+/// Given the client's `create2` message and our ntor onion `keys`,
+/// return the CREATED2 message to send in reply,
+/// along with the cryptographic layers for our hop of the new circuit.
+///
+/// This is the relay-side counterpart of
+/// [`create_firsthop_ntor`](crate::circuit::PendingClientCirc::create_firsthop_ntor).
+//
+// TODO #1467: Nothing calls this yet, since we don't have a relay-side circuit reactor.
+#[cfg(feature = "relay")]
+#[allow(dead_code)]
+pub(crate) fn respond_create2_ntor<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    keys: &[NtorSecretKey],
+    create2: &Create2,
+) -> RelayHandshakeResult<(Created2, BoxedClientLayer)> {
+    if create2.handshake_type() != HandshakeType::NTOR {
+        return Err(RelayHandshakeError::BadClientHandshake);
+    }
+    let (keygen, reply) = NtorServer::server(rng, &mut |_: &()| Some(()), keys, create2.body())?;
+    // TODO #1067: Support other formats
+    let layer = RelayCryptLayerProtocol::Tor1(RelayCellFormat::V0)
+        .construct_layers(HandshakeRole::Responder, keygen)
+        .map_err(into_internal!(
+            "Unable to set up cell crypto for a new circuit"
+        ))?;
+    Ok((Created2::new(reply), layer))
+}
+
+#[cfg(all(test, feature = "relay"))]
+mod test {
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    use super::*;
+    use crate::crypto::cell::RelayCellBody;
+    use crate::crypto::handshake::ntor::{NtorClient, NtorPublicKey};
+    use crate::crypto::handshake::ClientHandshake;
+    use tor_basic_utils::test_rng::testing_rng;
+    use tor_cell::relaycell::{msg, AnyRelayMsgOuter};
+    use tor_llcrypto::pk::curve25519::{PublicKey, StaticSecret};
+    use tor_llcrypto::pk::rsa::RsaIdentity;
+
+    /// Make an ntor onion key for a relay, along with the public key that clients would use.
+    fn onion_key<R: RngCore + CryptoRng>(rng: &mut R) -> (NtorSecretKey, NtorPublicKey) {
+        let sk = StaticSecret::random_from_rng(rng);
+        let pk = PublicKey::from(&sk);
+        let id = RsaIdentity::from_bytes(&[12; 20]).unwrap();
+        (NtorSecretKey::new(sk, pk, id), NtorPublicKey { id, pk })
+    }
+
+    #[test]
+    fn respond_ntor() {
+        let mut rng = testing_rng();
+        let (relay_sk, relay_pk) = onion_key(&mut rng);
+        let (state, onionskin) = NtorClient::client1(&mut rng, &relay_pk, &()).unwrap();
+        let create2 = Create2::new(HandshakeType::NTOR, onionskin);
+
+        let (created2, mut relay_layer) =
+            respond_create2_ntor(&mut rng, &[relay_sk], &create2).unwrap();
+
+        let ((), keygen) = NtorClient::client2(state, created2.into_body()).unwrap();
+        let mut client_layer = RelayCryptLayerProtocol::Tor1(RelayCellFormat::V0)
+            .construct_layers(HandshakeRole::Initiator, keygen)
+            .unwrap();
+
+        // A cell that the client sends to this hop is recognized by the relay...
+        let body = AnyRelayMsgOuter::new(None, msg::Drop::default().into())
+            .encode(&mut rng)
+            .unwrap();
+        let mut cell = RelayCellBody::from(body);
+        let tag = client_layer.fwd.originate_for(&mut cell).to_vec();
+        client_layer.fwd.encrypt_outbound(&mut cell);
+        assert_eq!(
+            relay_layer.back.decrypt_inbound(&mut cell).unwrap(),
+            &tag[..]
+        );
+
+        // ...and a cell that the relay sends back is recognized by the client.
+        let body = AnyRelayMsgOuter::new(None, msg::Drop::default().into())
+            .encode(&mut rng)
+            .unwrap();
+        let mut cell = RelayCellBody::from(body);
+        let tag = relay_layer.fwd.originate_for(&mut cell).to_vec();
+        relay_layer.fwd.encrypt_outbound(&mut cell);
+        assert_eq!(
+            client_layer.back.decrypt_inbound(&mut cell).unwrap(),
+            &tag[..]
+        );
+    }
+
+    #[test]
+    fn respond_ntor_errors() {
+        let mut rng = testing_rng();
+        let (relay_sk, relay_pk) = onion_key(&mut rng);
+        let (_state, onionskin) = NtorClient::client1(&mut rng, &relay_pk, &()).unwrap();
+
+        // Wrong handshake type.
+        let create2 = Create2::new(HandshakeType::NTOR_V3, onionskin.clone());
+        assert!(matches!(
+            respond_create2_ntor(&mut rng, &[relay_sk], &create2),
+            Err(RelayHandshakeError::BadClientHandshake)
+        ));
+
+        // A key we don't have.
+        let (other_sk, _) = onion_key(&mut rng);
+        let create2 = Create2::new(HandshakeType::NTOR, onionskin);
+        assert!(matches!(
+            respond_create2_ntor(&mut rng, &[other_sk], &create2),
+            Err(RelayHandshakeError::MissingKey)
+        ));
+    }
+}
@@ -22,6 +22,10 @@ use super::{ConversationInHandler, MetaCellDisposition};
 /// is used to check any incoming message whose stream ID is 0, and which would
 /// otherwise not be accepted on a given circuit.
 ///
+/// A handler can also be supplied to
+/// [`ClientCirc::register_cmd_handler`](super::ClientCirc::register_cmd_handler),
+/// in which case it only sees messages with a single relay command.
+///
 /// (The messages that `tor-proto` will handle on its own, and _not_ deliver, are
 /// are DESTROY, DATA, SENDME, ...)  Ordinarily, any unexpected control
 /// message will cause the circuit to exit with an error.
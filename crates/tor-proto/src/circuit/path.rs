@@ -74,6 +74,28 @@ impl PathEntry {
             HopDetail::Virtual => None,
         }
     }
+
+    /// Return a new `PathEntry` for a hop to the relay `target`.
+    ///
+    /// Suitable for external callers who want to test code that inspects
+    /// a circuit's [`Path`].
+    #[cfg(feature = "testing")]
+    pub fn new_fake_relay(target: OwnedChanTarget) -> Self {
+        PathEntry {
+            inner: HopDetail::Relay(target),
+        }
+    }
+
+    /// Return a new `PathEntry` for a virtual hop.
+    ///
+    /// Suitable for external callers who want to test code that inspects
+    /// a circuit's [`Path`].
+    #[cfg(all(feature = "testing", feature = "hs-common"))]
+    pub fn new_fake_virtual() -> Self {
+        PathEntry {
+            inner: HopDetail::Virtual,
+        }
+    }
 }
 
 /// A circuit's path through the network.
@@ -90,6 +112,15 @@ pub struct Path {
 }
 
 impl Path {
+    /// Return a new `Path` made of `hops`, starting with the first hop.
+    ///
+    /// Suitable for external callers who want to test code that inspects
+    /// a circuit's `Path`.
+    #[cfg(feature = "testing")]
+    pub fn new_fake(hops: Vec<PathEntry>) -> Self {
+        Path { hops }
+    }
+
     /// Return the number of hops in this path
     pub fn n_hops(&self) -> usize {
         self.hops.len()
@@ -18,6 +18,7 @@
 
 pub(super) mod syncview;
 
+use super::congestion::{new_congestion_control, CongestionControl};
 use super::handshake::RelayCryptLayerProtocol;
 use super::streammap::{EndSentStreamEnt, ShouldSendEnd, StreamEntMut};
 use super::MutableState;
@@ -25,8 +26,8 @@ use crate::circuit::celltypes::{ClientCircChanMsg, CreateResponse};
 use crate::circuit::handshake::{BoxedClientLayer, HandshakeRole};
 use crate::circuit::unique_id::UniqId;
 use crate::circuit::{
-    sendme, streammap, CircParameters, CircuitRxReceiver, Create2Wrap, CreateFastWrap,
-    CreateHandshakeWrap,
+    sendme, streammap, CircParameters, CircParamsUpdate, CircuitRxReceiver, Create2Wrap,
+    CreateFastWrap, CreateHandshakeWrap, SendmeParams,
 };
 use crate::crypto::binding::CircuitBinding;
 use crate::crypto::cell::{
@@ -46,13 +47,14 @@ use std::borrow::Borrow;
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::pin::Pin;
+use std::time::{Duration, Instant};
 use tor_cell::chancell::msg::{AnyChanMsg, HandshakeType, Relay};
 use tor_cell::relaycell::msg::{AnyRelayMsg, End, Sendme};
 use tor_cell::relaycell::{
     AnyRelayMsgOuter, RelayCellDecoder, RelayCellFormat, RelayCellFormatTrait, RelayCellFormatV0,
     RelayCmd, StreamId, UnparsedRelayMsg,
 };
-use tor_error::internal;
+use tor_error::{bad_api_usage, internal};
 #[cfg(feature = "hs-service")]
 use {
     crate::stream::{DataCmdChecker, IncomingStreamRequest, IncomingStreamRequestFilter},
@@ -61,17 +63,19 @@ use {
 
 use futures::channel::mpsc;
 use futures::Stream;
-use futures::{Sink, StreamExt};
+use futures::{select_biased, FutureExt as _, Sink, StreamExt};
 use oneshot_fused_workaround as oneshot;
 
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 
-use crate::channel::{Channel, ChannelSender};
+use crate::channel::{Channel, ChannelSender, TrafficCounter, FIXED_CELL_LEN};
 use crate::circuit::path;
 #[cfg(test)]
 use crate::circuit::sendme::CircTag;
 use crate::circuit::sendme::StreamSendWindow;
+#[cfg(test)]
+use crate::circuit::{CircuitRxSender, ClientCirc, PendingClientCirc};
 use crate::circuit::{StreamMpscReceiver, StreamMpscSender};
 use crate::crypto::handshake::ntor::{NtorClient, NtorPublicKey};
 use crate::crypto::handshake::{ClientHandshake, KeyGenerator};
@@ -85,18 +89,49 @@ use tor_linkspec::{EncodedLinkSpec, OwnedChanTarget, RelayIds};
 use tor_llcrypto::pk;
 use tor_memquota::derive_deftly_template_HasMemoryCost;
 use tor_memquota::mq_queue::{self, ChannelSpec as _, MpscSpec};
-use tracing::{debug, trace, warn};
+use tor_rtcompat::SleepProvider as _;
+use tracing::{debug, instrument, trace, warn};
+
+/// Default initial value for inbound flow-control window on streams.
+const RECV_WINDOW_INIT: u16 = 500;
+/// Size of the buffer used between the reactor and a `StreamReader`,
+/// for streams using the default flow-control window.
+pub(super) const STREAM_READER_BUFFER: usize = (2 * RECV_WINDOW_INIT) as usize;
 
-/// Initial value for outbound flow-control window on streams.
-pub(super) const SEND_WINDOW_INIT: u16 = 500;
-/// Initial value for inbound flow-control window on streams.
-pub(super) const RECV_WINDOW_INIT: u16 = 500;
-/// Size of the buffer used between the reactor and a `StreamReader`.
+/// Return the size of the buffer used between the reactor and a `StreamReader`,
+/// for a stream whose flow-control window is described by `params`.
 ///
 /// FIXME(eta): We pick 2× the receive window, which is very conservative (we arguably shouldn't
 ///             get sent more than the receive window anyway!). We might do due to things that
 ///             don't count towards the window though.
-pub(super) const STREAM_READER_BUFFER: usize = (2 * RECV_WINDOW_INIT) as usize;
+pub(super) fn stream_reader_buffer(params: SendmeParams) -> usize {
+    2 * usize::from(params.window())
+}
+
+/// Return true if an embedder may install a handler for incoming meta cells with command `cmd`.
+///
+/// We only allow this for commands that the reactor never handles on its own:
+/// anything else would let the handler take over (say) our flow control,
+/// or the replies that our own extend handshakes are waiting for.
+#[cfg(feature = "send-control-msg")]
+fn accepts_cmd_handler(cmd: RelayCmd) -> bool {
+    match cmd {
+        // Onion service commands only ever go to a conversation handler.
+        RelayCmd::ESTABLISH_INTRO
+        | RelayCmd::ESTABLISH_RENDEZVOUS
+        | RelayCmd::INTRODUCE1
+        | RelayCmd::INTRODUCE2
+        | RelayCmd::RENDEZVOUS1
+        | RelayCmd::RENDEZVOUS2
+        | RelayCmd::INTRO_ESTABLISHED
+        | RelayCmd::RENDEZVOUS_ESTABLISHED
+        | RelayCmd::INTRODUCE_ACK => true,
+        // We don't implement padding negotiation.
+        RelayCmd::PADDING_NEGOTIATE | RelayCmd::PADDING_NEGOTIATED => true,
+        // Everything else that we recognize, we handle ourselves (or reject).
+        cmd => !cmd.is_recognized(),
+    }
+}
 
 /// The type of a oneshot channel used to inform reactor users of the result of an operation.
 pub(super) type ReactorResultChannel<T> = oneshot::Sender<Result<T>>;
@@ -147,6 +182,82 @@ impl Default for CloseStreamBehavior {
     }
 }
 
+/// The reason why [`Reactor::run`] or [`Reactor::run_until_idle`] finished without an error.
+///
+/// (If the reactor stopped because of a protocol violation or some other problem,
+/// those methods return an [`Error`] instead.)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReactorExit {
+    /// We were explicitly asked to shut down the circuit.
+    ShutdownRequested,
+    /// Every handle to the circuit was dropped.
+    HandlesDropped,
+    /// The channel that the circuit was using closed.
+    ChannelClosed,
+    /// We received a cell that closed the circuit.
+    ///
+    /// This can be a DESTROY or TRUNCATED cell from the other side,
+    /// or a message that one of our handlers decided should close the circuit.
+    ClosedByCell,
+    /// No cell or control message arrived within the configured idle timeout.
+    IdleTimeout,
+    /// The circuit reached its maximum lifetime.
+    ///
+    /// See [`CircParameters::set_max_lifetime`].
+    MaxLifetimeReached,
+}
+
+/// Something that happened during the lifetime of a circuit reactor.
+///
+/// You can receive these by giving the reactor a sink with
+/// [`Reactor::set_event_sink`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReactorEvent {
+    /// We added a hop to the circuit.
+    ///
+    /// This is hop 0 when we create the circuit,
+    /// and a later hop each time that we extend it.
+    HopAdded {
+        /// The circuit.
+        circ: UniqId,
+        /// The hop that we added.
+        hop: HopNum,
+    },
+    /// A new stream began.
+    ///
+    /// Either we sent a message to begin it, or
+    /// (on an onion service circuit) we accepted a request from the other end to begin it.
+    StreamBegun {
+        /// The circuit.
+        circ: UniqId,
+        /// The hop that the stream goes to.
+        hop: HopNum,
+        /// The ID of the new stream.
+        stream: StreamId,
+    },
+    /// A stream closed.
+    ///
+    /// Either we closed it, or the other end sent a message to end it:
+    /// whichever happened first.
+    StreamClosed {
+        /// The circuit.
+        circ: UniqId,
+        /// The hop that the stream went to.
+        hop: HopNum,
+        /// The ID of the stream.
+        stream: StreamId,
+    },
+    /// The reactor stopped.
+    Shutdown {
+        /// The circuit.
+        circ: UniqId,
+        /// Why the reactor stopped, or `None` if it stopped because of an error.
+        exit: Option<ReactorExit>,
+    },
+}
+
 /// A message telling the reactor to do something.
 #[derive(educe::Educe)]
 #[educe(Debug)]
@@ -159,6 +270,9 @@ pub(super) enum CtrlMsg {
         handshake: CircuitHandshake,
         /// Other parameters relevant for circuit creation.
         params: CircParameters,
+        /// Oneshot channel to notify as soon as the first hop is established,
+        /// if anybody asked to be told.
+        first_hop_ready: Option<oneshot::Sender<()>>,
         /// Oneshot channel to notify on completion.
         done: ReactorResultChannel<()>,
     },
@@ -236,6 +350,24 @@ pub(super) enum CtrlMsg {
         /// A `CmdChecker` to keep track of which message types are acceptable.
         cmd_checker: AnyCmdChecker,
     },
+    /// Reserve a stream ID on the provided hop in this circuit.
+    ///
+    /// Allocates a stream ID and registers the stream, but doesn't send anything to the hop:
+    /// the message that initiates the stream should be sent later, on the stream itself.
+    ReserveStream {
+        /// The hop number to reserve the stream ID on.
+        hop_num: HopNum,
+        /// A channel to send messages on this stream down.
+        ///
+        /// (See [`CtrlMsg::BeginStream`] for why this shouldn't block.)
+        sender: StreamMpscSender<UnparsedRelayMsg>,
+        /// A channel to receive messages to send on this stream from.
+        rx: StreamMpscReceiver<AnyRelayMsg>,
+        /// Oneshot channel to notify on completion, with the allocated stream ID.
+        done: ReactorResultChannel<StreamId>,
+        /// A `CmdChecker` to keep track of which message types are acceptable.
+        cmd_checker: AnyCmdChecker,
+    },
     /// Close the specified pending incoming stream, sending the provided END message.
     ///
     /// A stream is said to be pending if the message for initiating the stream was received but
@@ -303,6 +435,17 @@ pub(super) enum CtrlMsg {
         /// The hop number the stream is on.
         hop_num: HopNum,
     },
+    /// Install a handler for every incoming meta cell with a given relay command.
+    #[cfg(feature = "send-control-msg")]
+    RegisterCmdHandler {
+        /// The relay command to handle.
+        cmd: RelayCmd,
+        /// The handler to install.
+        #[educe(Debug(ignore))]
+        handler: Box<dyn MetaCellHandler + Send + 'static>,
+        /// Oneshot channel to notify on completion.
+        done: ReactorResultChannel<()>,
+    },
     /// Shut down the reactor.
     Shutdown,
     /// (tests only) Add a hop to the list of hops on this circuit, with dummy cryptography.
@@ -314,12 +457,48 @@ pub(super) enum CtrlMsg {
         params: CircParameters,
         done: ReactorResultChannel<()>,
     },
+    /// Close every open stream on every hop of this circuit, sending an END message for each,
+    /// but leave the circuit itself open.
+    ///
+    /// Reports the number of streams that were closed.
+    CloseAllStreams {
+        /// Oneshot channel to notify on completion.
+        done: ReactorResultChannel<usize>,
+    },
+    /// Stop (if `true`) or resume (if `false`) sending messages from the streams on this circuit.
+    ///
+    /// While the circuit is paused, we still handle incoming cells and control messages,
+    /// but we leave any outgoing stream messages queued in their streams.
+    SetCircuitPaused(bool),
+    /// Change the parameters of this circuit that can be changed while it is running.
+    ///
+    /// See [`ClientCirc::update_params`](super::ClientCirc::update_params)
+    /// for which parameters those are.
+    UpdateParams {
+        /// The parameters to change.
+        update: CircParamsUpdate,
+        /// Oneshot channel to notify on completion.
+        done: ReactorResultChannel<()>,
+    },
     /// (tests only) Get the send window and expected tags for a given hop.
     #[cfg(test)]
     QuerySendWindow {
         hop: HopNum,
         done: ReactorResultChannel<(u16, Vec<CircTag>)>,
     },
+    /// (tests only) Get the number of cells we have queued for the channel
+    /// because it wasn't ready to accept them,
+    /// and, for each hop, the number of streams with a message waiting to be sent.
+    #[cfg(test)]
+    QueryOutboundQueues {
+        done: ReactorResultChannel<(usize, Vec<usize>)>,
+    },
+    /// (tests only) Get the hop from which the installed meta-cell handler
+    /// expects a message, or `None` if there is no such handler.
+    #[cfg(test)]
+    QueryMetaHandler {
+        done: ReactorResultChannel<Option<HopNum>>,
+    },
     /// (tests only) Send a raw relay cell with send_relay_cell().
     #[cfg(test)]
     SendRelayCell {
@@ -328,6 +507,23 @@ pub(super) enum CtrlMsg {
         cell: AnyRelayMsgOuter,
     },
 }
+
+/// Return an END message for the stream `id`, with reason `DONE`,
+/// to deliver to that stream's reader when we close the stream ourselves.
+fn local_end_msg(id: StreamId) -> Result<UnparsedRelayMsg> {
+    use tor_cell::relaycell::msg::EndReason;
+    use tor_error::into_internal;
+
+    let end = End::new_with_reason(EndReason::DONE);
+    let body = AnyRelayMsgOuter::new(Some(id), end.into())
+        .encode(&mut rand::thread_rng())
+        .map_err(|e| Error::from_cell_enc(e, "END message"))?;
+    // TODO #1067: Support other formats
+    let msg = UnparsedRelayMsg::from_singleton_body(RelayCellFormat::V0, body)
+        .map_err(into_internal!("Couldn't decode our own END message"))?;
+    Ok(msg)
+}
+
 /// Represents the reactor's view of a single hop.
 pub(super) struct CircHop {
     /// Map from stream IDs to streams.
@@ -338,10 +534,12 @@ pub(super) struct CircHop {
     map: streammap::StreamMap,
     /// Window used to say how many cells we can receive.
     recvwindow: sendme::CircRecvWindow,
-    /// Window used to say how many cells we can send.
-    sendwindow: sendme::CircSendWindow,
+    /// Congestion control, which says whether we can send more cells.
+    ccontrol: Box<dyn CongestionControl>,
     /// Decodes relay cells received from this hop.
     inbound: RelayCellDecoder,
+    /// Window size and SENDME increment for streams on this hop.
+    stream_sendme: SendmeParams,
 }
 
 /// An indicator on what we should do when we receive a cell for a circuit.
@@ -355,14 +553,22 @@ enum CellStatus {
 
 impl CircHop {
     /// Create a new hop.
-    pub(super) fn new(format: RelayCellFormat, initial_window: u16) -> Self {
+    pub(super) fn new(format: RelayCellFormat, params: &CircParameters) -> Self {
+        let circ_sendme = params.circ_sendme_params();
+        let stream_sendme = params.stream_sendme_params();
         CircHop {
-            map: streammap::StreamMap::new(),
-            recvwindow: sendme::CircRecvWindow::new(1000),
-            sendwindow: sendme::CircSendWindow::new(initial_window),
+            map: streammap::StreamMap::new(stream_sendme, params.strict_recv_windows()),
+            recvwindow: sendme::CircRecvWindow::new_with_params(circ_sendme.window(), circ_sendme),
+            ccontrol: new_congestion_control(params),
             inbound: RelayCellDecoder::new(format),
+            stream_sendme,
         }
     }
+
+    /// Return a new send window for a stream on this hop.
+    fn new_stream_send_window(&self) -> StreamSendWindow {
+        StreamSendWindow::new_with_params(self.stream_sendme.window(), self.stream_sendme)
+    }
 }
 
 /// Handle to use during an ongoing protocol exchange with a circuit's last hop
@@ -479,6 +685,7 @@ where
     /// current last hop which relay to connect to.
     #[allow(clippy::too_many_arguments)]
     #[allow(clippy::blocks_in_conditions)]
+    #[instrument(level = "debug", name = "circ_extend", skip_all)]
     fn begin(
         cx: &mut Context<'_>,
         relay_cell_format: RelayCellFormat,
@@ -492,11 +699,10 @@ where
         done: ReactorResultChannel<()>,
     ) -> Result<Self> {
         match (|| {
-            let mut rng = rand::thread_rng();
             let unique_id = reactor.unique_id;
 
             use tor_cell::relaycell::msg::Extend2;
-            let (state, msg) = H::client1(&mut rng, key, client_aux_data)?;
+            let (state, msg) = reactor.client1::<H, _>(key, client_aux_data)?;
 
             let n_hops = reactor.crypto_out.n_layers();
             let hop = ((n_hops - 1) as u8).into();
@@ -545,6 +751,12 @@ where
     /// Perform the work of extending the circuit another hop.
     ///
     /// This is a separate function to simplify the error-handling work of handle_msg().
+    #[instrument(
+        level = "debug",
+        name = "circ_extend",
+        skip_all,
+        fields(hop = %self.expected_hop.display())
+    )]
     fn extend_circuit(
         &mut self,
         msg: UnparsedRelayMsg,
@@ -724,21 +936,139 @@ pub struct Reactor {
     crypto_out: OutboundClientCrypt,
     /// List of hops state objects used by the reactor
     hops: Vec<CircHop>,
+    /// The index in `hops` of the hop whose streams we should try to drain first
+    /// on the next iteration of `run_once`.
+    ///
+    /// We advance this past each hop that sends a message,
+    /// so that when the channel can only accept a few cells,
+    /// the earlier hops don't always get to send first.
+    /// (Within a hop, the `StreamMap` already schedules its streams round-robin.)
+    next_hop_to_drain: usize,
+    /// The number of cells we may send from our streams before we yield to the executor.
+    ///
+    /// Taken from the [`CircParameters`] of the first hop.
+    max_cells_per_yield: usize,
+    /// The number of cells we have sent from our streams since we last yielded
+    /// or waited for something to do.
+    cells_since_yield: usize,
+    /// The parameters for this circuit as a whole.
+    ///
+    /// Taken from the [`CircParameters`] of the first hop,
+    /// as changed by any [`CtrlMsg::UpdateParams`] since.
+    /// This is `None` until we have a first hop.
+    params: Option<CircParameters>,
+    /// The time at which we added the first hop of this circuit, if we have.
+    ///
+    /// We measure the circuit's age, for its maximum lifetime, from here.
+    created_at: Option<Instant>,
     /// Mutable information about this circuit, shared with
     /// [`ClientCirc`](super::ClientCirc).
     mutable: Arc<Mutex<MutableState>>,
+    /// The number of bytes we have read and written on this circuit,
+    /// shared with [`ClientCirc`](super::ClientCirc).
+    traffic: Arc<TrafficCounter>,
     /// An identifier for logging about this reactor's circuit.
     unique_id: UniqId,
     /// This circuit's identifier on the upstream channel.
     channel_id: CircId,
     /// A handler for a meta cell, together with a result channel to notify on completion.
     meta_handler: Option<Box<dyn MetaCellHandler + Send>>,
+    /// Handlers for meta cells with particular relay commands.
+    ///
+    /// There is at most one handler for each command.
+    /// (We use a `Vec` because `RelayCmd` isn't `Hash`, and we expect very few handlers.)
+    ///
+    /// These take precedence over `meta_handler`, and stay installed
+    /// until they return [`MetaCellDisposition::ConversationFinished`].
+    #[cfg(feature = "send-control-msg")]
+    cmd_handlers: Vec<(RelayCmd, Box<dyn MetaCellHandler + Send>)>,
     /// A handler for incoming stream requests.
     #[cfg(feature = "hs-service")]
     incoming_stream_req_handler: Option<IncomingStreamRequestHandler>,
     /// Memory quota account
     #[allow(dead_code)] // Partly here to keep it alive as long as the circuit
     memquota: CircuitAccount,
+    /// Why we are shutting down, once we have decided to.
+    ///
+    /// Set whenever we return [`ReactorError::Shutdown`].
+    exit_reason: Option<ReactorExit>,
+    /// Whether we have been told to stop sending messages from our streams.
+    ///
+    /// See [`CtrlMsg::SetCircuitPaused`].
+    paused: bool,
+    /// An RNG to use for circuit handshakes instead of `thread_rng()`.
+    ///
+    /// Only tests set this, so that they can reproduce exact handshake messages.
+    #[cfg(any(test, feature = "testing"))]
+    handshake_rng: Option<Box<dyn HandshakeRng>>,
+    /// A sink for the [`ReactorEvent`]s that we report, if anybody wants them.
+    event_sink: Option<mpsc::UnboundedSender<ReactorEvent>>,
+}
+
+/// An RNG that can be used for circuit handshakes.
+///
+/// (This trait exists only so that we can put such an RNG in a `Box`.)
+#[cfg(any(test, feature = "testing"))]
+trait HandshakeRng: rand::RngCore + rand::CryptoRng + Send {}
+#[cfg(any(test, feature = "testing"))]
+impl<R: rand::RngCore + rand::CryptoRng + Send> HandshakeRng for R {}
+
+/// An encryption layer that doesn't do any crypto.  Can be used
+/// as inbound or outbound, but not both at once.
+///
+/// The hops of circuits made with [`Reactor::new_fake`] use this.
+#[cfg(test)]
+pub(crate) struct DummyCrypto {
+    /// The tag we last reported.
+    counter_tag: [u8; 20],
+    /// How many tags we have reported so far.
+    counter: u32,
+    /// Whether inbound cells should be recognized as coming from this hop.
+    lasthop: bool,
+}
+
+#[cfg(test)]
+impl DummyCrypto {
+    /// Create a new `DummyCrypto`.
+    ///
+    /// If `lasthop` is true, it recognizes every inbound cell as its own.
+    pub(crate) fn new(lasthop: bool) -> Self {
+        DummyCrypto {
+            counter_tag: [0; 20],
+            counter: 0,
+            lasthop,
+        }
+    }
+
+    /// Return a new tag, different from every one we have returned before.
+    fn next_tag(&mut self) -> &[u8; 20] {
+        #![allow(clippy::identity_op)]
+        self.counter_tag[0] = ((self.counter >> 0) & 255) as u8;
+        self.counter_tag[1] = ((self.counter >> 8) & 255) as u8;
+        self.counter_tag[2] = ((self.counter >> 16) & 255) as u8;
+        self.counter_tag[3] = ((self.counter >> 24) & 255) as u8;
+        self.counter += 1;
+        &self.counter_tag
+    }
+}
+
+#[cfg(test)]
+impl OutboundClientLayer for DummyCrypto {
+    fn originate_for(&mut self, _cell: &mut RelayCellBody) -> &[u8] {
+        self.next_tag()
+    }
+    fn encrypt_outbound(&mut self, _cell: &mut RelayCellBody) {}
+}
+
+#[cfg(test)]
+impl InboundClientLayer for DummyCrypto {
+    fn decrypt_inbound(&mut self, _cell: &mut RelayCellBody) -> Option<&[u8]> {
+        if self.lasthop {
+            Some(self.next_tag())
+        } else {
+            None
+        }
+    }
 }
 
 /// Information about an incoming stream request.
@@ -800,6 +1130,7 @@ impl Reactor {
         unique_id: UniqId,
         input: CircuitRxReceiver,
         memquota: CircuitAccount,
+        traffic: Arc<TrafficCounter>,
     ) -> (
         Self,
         mpsc::UnboundedSender<CtrlMsg>,
@@ -810,7 +1141,12 @@ impl Reactor {
         let (control_tx, control_rx) = mpsc::unbounded();
         let path = Arc::new(path::Path::default());
         let binding = Vec::new();
-        let mutable = Arc::new(Mutex::new(MutableState { path, binding }));
+        let stream_sendme = Vec::new();
+        let mutable = Arc::new(Mutex::new(MutableState {
+            path,
+            binding,
+            stream_sendme,
+        }));
 
         let (reactor_closed_tx, reactor_closed_rx) = oneshot::channel();
 
@@ -824,34 +1160,186 @@ impl Reactor {
             input,
             crypto_in: InboundClientCrypt::new(),
             hops: vec![],
+            next_hop_to_drain: 0,
+            max_cells_per_yield: CircParameters::default().max_cells_per_yield().into(),
+            cells_since_yield: 0,
+            params: None,
+            created_at: None,
             unique_id,
             channel_id,
             crypto_out,
             meta_handler: None,
+            #[cfg(feature = "send-control-msg")]
+            cmd_handlers: Vec::new(),
             #[cfg(feature = "hs-service")]
             incoming_stream_req_handler: None,
             mutable: mutable.clone(),
+            traffic,
             memquota,
+            exit_reason: None,
+            paused: false,
+            #[cfg(any(test, feature = "testing"))]
+            handshake_rng: None,
+            event_sink: None,
         };
 
         (reactor, control_tx, reactor_closed_rx, mutable)
     }
 
+    /// Use `rng` instead of `thread_rng()` for the handshakes on this circuit.
+    ///
+    /// This lets tests make the CREATE and EXTEND messages we send reproducible,
+    /// by passing a seeded RNG. It must be called before the reactor is run.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn set_handshake_rng<R>(&mut self, rng: R)
+    where
+        R: rand::RngCore + rand::CryptoRng + Send + 'static,
+    {
+        self.handshake_rng = Some(Box::new(rng));
+    }
+
+    /// Create a new circuit reactor for testing, along with its circuit.
+    ///
+    /// The circuit already has `n_hops` hops, all using `params`,
+    /// whose cryptography does nothing at all.
+    /// The returned sender delivers cells to the reactor as if they had arrived on `channel`;
+    /// they appear to come from hop `next_msg_from`.
+    ///
+    /// The reactor isn't running yet:
+    /// the caller should launch it with [`run`](Reactor::run), as usual.
+    #[cfg(test)]
+    pub(crate) fn new_fake(
+        channel: Arc<Channel>,
+        circid: CircId,
+        n_hops: u8,
+        next_msg_from: HopNum,
+        params: &CircParameters,
+    ) -> (Self, Arc<ClientCirc>, CircuitRxSender) {
+        let (_created_send, created_recv) = oneshot::channel();
+        let (circmsg_send, circmsg_recv) = crate::fake_mpsc(64);
+        let (pending, mut reactor) = PendingClientCirc::new(
+            circid,
+            channel,
+            created_recv,
+            circmsg_recv,
+            UniqId::new(23, 17),
+            CircuitAccount::new_noop(),
+        );
+
+        // TODO #1067: Support other formats
+        let relay_cell_format = RelayCellFormat::V0;
+        for idx in 0..n_hops {
+            reactor.add_fake_hop(
+                relay_cell_format,
+                idx + 1 == n_hops,
+                HopNum::from(idx) == next_msg_from,
+                params,
+            );
+        }
+
+        (reactor, pending.circ, circmsg_send)
+    }
+
+    /// Report a [`ReactorEvent`] on `sink` whenever something significant happens
+    /// to this circuit, such as adding a hop or beginning a stream.
+    ///
+    /// This must be called before the reactor is run.
+    /// If the receiver is dropped, we stop reporting events.
+    /// Since `sink` is unbounded, the receiver should keep up with the events.
+    pub fn set_event_sink(&mut self, sink: mpsc::UnboundedSender<ReactorEvent>) {
+        self.event_sink = Some(sink);
+    }
+
+    /// Send `event` to our event sink, if we have one.
+    fn report_event(&mut self, event: ReactorEvent) {
+        if let Some(sink) = &self.event_sink {
+            if sink.unbounded_send(event).is_err() {
+                // Nobody is listening any more.
+                self.event_sink = None;
+            }
+        }
+    }
+
     /// Launch the reactor, and run until the circuit closes or we
     /// encounter an error.
     ///
     /// Once this method returns, the circuit is dead and cannot be
     /// used again.
-    pub async fn run(mut self) -> Result<()> {
+    pub async fn run(mut self) -> Result<ReactorExit> {
+        self.run_inner(None).await
+    }
+
+    /// Launch the reactor, and run until the circuit closes, we encounter an
+    /// error, or the circuit has been idle for `idle_timeout`.
+    ///
+    /// The circuit counts as idle when no cell or control message has arrived,
+    /// and no stream has had anything to send.
+    /// The timer only starts once the first hop of the circuit has been created.
+    ///
+    /// Once this method returns, the circuit is dead and cannot be
+    /// used again.
+    pub async fn run_until_idle(mut self, idle_timeout: Duration) -> Result<ReactorExit> {
+        self.run_inner(Some(idle_timeout)).await
+    }
+
+    /// Helper for `run` and `run_until_idle`: runs the reactor loop,
+    /// giving up after `idle_timeout` of inactivity if one is provided.
+    ///
+    /// Everything the reactor logs happens within a span that records the
+    /// circuit's [`UniqId`], so that logs can be filtered by circuit.
+    #[instrument(level = "debug", name = "circ_reactor", skip_all, fields(circ = %self.unique_id))]
+    async fn run_inner(&mut self, idle_timeout: Option<Duration>) -> Result<ReactorExit> {
         trace!("{}: Running circuit reactor", self.unique_id);
-        let result: Result<()> = loop {
-            match self.run_once().await {
-                Ok(()) => (),
-                Err(ReactorError::Shutdown) => break Ok(()),
-                Err(ReactorError::Err(e)) => break Err(e),
+        let sleep_prov = self.chan_sender.as_inner().time_provider().clone();
+        let result: Result<ReactorExit> = loop {
+            // Creating the first hop can't safely be cancelled,
+            // so we never time out while we have no hops.
+            // (We don't have an `expires_at()` until then either.)
+            let idle = idle_timeout
+                .filter(|_| !self.hops.is_empty())
+                .map(|timeout| (timeout, ReactorExit::IdleTimeout));
+            let lifetime = self.expires_at().map(|when| {
+                (
+                    when.saturating_duration_since(sleep_prov.now()),
+                    ReactorExit::MaxLifetimeReached,
+                )
+            });
+            let timeout = idle.into_iter().chain(lifetime).min_by_key(|(t, _)| *t);
+            let outcome = match timeout {
+                Some((timeout, exit)) => {
+                    select_biased! {
+                        res = self.run_once().fuse() => Ok(res),
+                        () = sleep_prov.sleep(timeout).fuse() => Err(exit),
+                    }
+                }
+                None => Ok(self.run_once().await),
+            };
+            match outcome {
+                Ok(Ok(())) => (),
+                Ok(Err(ReactorError::Shutdown)) => {
+                    break self.exit_reason.take().ok_or_else(|| {
+                        internal!("Circuit reactor shut down without a reason").into()
+                    });
+                }
+                Ok(Err(ReactorError::Err(e))) => break Err(e),
+                Err(ReactorExit::IdleTimeout) => {
+                    debug!("{}: Circuit reactor idle; shutting down", self.unique_id);
+                    break Ok(ReactorExit::IdleTimeout);
+                }
+                Err(exit) => {
+                    debug!(
+                        "{}: Circuit reached its maximum lifetime; shutting down",
+                        self.unique_id
+                    );
+                    break Ok(exit);
+                }
             }
         };
         trace!("{}: Circuit reactor stopped: {:?}", self.unique_id, result);
+        self.report_event(ReactorEvent::Shutdown {
+            circ: self.unique_id,
+            exit: result.as_ref().ok().copied(),
+        });
         result
     }
 
@@ -864,6 +1352,14 @@ impl Reactor {
             return Ok(());
         }
 
+        if self.cells_since_yield >= self.max_cells_per_yield {
+            // We've been busy sending cells for a while without ever returning `Pending`.
+            // Give other tasks (such as the reactors of other circuits on this thread)
+            // a chance to run.
+            self.cells_since_yield = 0;
+            tor_rtcompat::task::yield_now().await;
+        }
+
         #[allow(clippy::cognitive_complexity)]
         let fut = futures::future::poll_fn(|cx| -> Poll<std::result::Result<_, ReactorError>> {
             let mut did_things = false;
@@ -873,7 +1369,7 @@ impl Reactor {
                 match ret {
                     None => {
                         trace!("{}: reactor shutdown due to control drop", self.unique_id);
-                        return Poll::Ready(Err(ReactorError::Shutdown));
+                        return Poll::Ready(Err(self.shutdown(ReactorExit::HandlesDropped)));
                     }
                     Some(CtrlMsg::Shutdown) => return Poll::Ready(self.handle_shutdown()),
                     Some(msg) => {
@@ -888,27 +1384,41 @@ impl Reactor {
                 match ret {
                     None => {
                         trace!("{}: reactor shutdown due to input drop", self.unique_id);
-                        return Poll::Ready(Err(ReactorError::Shutdown));
+                        return Poll::Ready(Err(self.shutdown(ReactorExit::ChannelClosed)));
                     }
                     Some(cell) => {
+                        self.traffic.note_read(FIXED_CELL_LEN);
                         if self.handle_cell(cx, cell)? == CellStatus::CleanShutdown {
                             trace!("{}: reactor shutdown due to handled cell", self.unique_id);
-                            return Poll::Ready(Err(ReactorError::Shutdown));
+                            return Poll::Ready(Err(self.shutdown(ReactorExit::ClosedByCell)));
                         }
                         did_things = true;
                     }
                 }
             }
 
-            // Check each hop for an outbound message pending.
-            for i in 0..self.hops.len() {
+            // Check each hop for an outbound message pending,
+            // starting from where we left off last time.
+            //
+            // We take a copy of the starting point, since we update
+            // `next_hop_to_drain` as we go.
+            let n_hops = self.hops.len();
+            let start = self.next_hop_to_drain;
+            for offset in 0..n_hops {
+                if self.paused {
+                    // We've been told not to send anything from our streams.
+                    // Whatever they have queued stays queued until we're resumed:
+                    // resuming is a control message, which will wake us up.
+                    break;
+                }
+                let i = (start + offset) % n_hops;
                 if !self.chan_sender.poll_ready_unpin_bool(cx)? {
                     // Channel isn't ready to send; we can't act on anything else.
                     // (Even processing an end-of-stream would end up having to buffer
                     // an END message in the channel).
                     break;
                 }
-                if self.hops[i].sendwindow.window() == 0 {
+                if !self.hops[i].ccontrol.can_send() {
                     // We can't send anything on this hop that counts towards SENDME windows.
                     //
                     // In theory we could send messages that don't count towards
@@ -964,6 +1474,8 @@ impl Reactor {
                     "Stream {sid} produced a message it can't send: {msg:?}"
                 );
                 self.send_relay_cell(cx, hop_num, false, AnyRelayMsgOuter::new(Some(sid), msg))?;
+                self.next_hop_to_drain = i + 1;
+                self.cells_since_yield += 1;
                 did_things = true;
             }
 
@@ -974,6 +1486,9 @@ impl Reactor {
             if did_things {
                 Poll::Ready(Ok(()))
             } else {
+                // We're about to park, which gives other tasks their chance to run,
+                // so we don't need to yield again until we've sent another batch.
+                self.cells_since_yield = 0;
                 Poll::Pending
             }
         });
@@ -988,7 +1503,7 @@ impl Reactor {
     async fn wait_for_create(&mut self) -> std::result::Result<(), ReactorError> {
         let Some(msg) = self.control.next().await else {
             trace!("{}: reactor shutdown due to control drop", self.unique_id);
-            return Err(ReactorError::Shutdown);
+            return Err(self.shutdown(ReactorExit::HandlesDropped));
         };
 
         match msg {
@@ -996,9 +1511,10 @@ impl Reactor {
                 recv_created,
                 handshake,
                 params,
+                first_hop_ready,
                 done,
             } => {
-                self.handle_create(recv_created, handshake, &params, done)
+                self.handle_create(recv_created, handshake, &params, first_hop_ready, done)
                     .await
             }
             CtrlMsg::Shutdown => self.handle_shutdown(),
@@ -1027,6 +1543,7 @@ impl Reactor {
         recv_created: oneshot::Receiver<CreateResponse>,
         handshake: CircuitHandshake,
         params: &CircParameters,
+        first_hop_ready: Option<oneshot::Sender<()>>,
         done: ReactorResultChannel<()>,
     ) -> std::result::Result<(), ReactorError> {
         let ret = match handshake {
@@ -1044,6 +1561,9 @@ impl Reactor {
                     .await
             }
         };
+        if let (Ok(()), Some(first_hop_ready)) = (&ret, first_hop_ready) {
+            let _ = first_hop_ready.send(()); // don't care if receiver goes away
+        }
         let _ = done.send(ret); // don't care if sender goes away
 
         futures::future::poll_fn(|cx| -> Poll<Result<()>> {
@@ -1058,13 +1578,20 @@ impl Reactor {
     }
 
     /// Handle a [`CtrlMsg::Shutdown`] message.
-    fn handle_shutdown(&self) -> std::result::Result<(), ReactorError> {
+    fn handle_shutdown(&mut self) -> std::result::Result<(), ReactorError> {
         trace!(
             "{}: reactor shutdown due to explicit request",
             self.unique_id
         );
 
-        Err(ReactorError::Shutdown)
+        Err(self.shutdown(ReactorExit::ShutdownRequested))
+    }
+
+    /// Record `reason` as the reason we're shutting down,
+    /// and return the error that tells `run_inner` to stop.
+    fn shutdown(&mut self, reason: ReactorExit) -> ReactorError {
+        self.exit_reason = Some(reason);
+        ReactorError::Shutdown
     }
 
     /// Handle a [`CtrlMsg::AddFakeHop`] message.
@@ -1077,8 +1604,19 @@ impl Reactor {
         params: &CircParameters,
         done: ReactorResultChannel<()>,
     ) {
-        use crate::circuit::test::DummyCrypto;
+        self.add_fake_hop(format, fwd_lasthop, rev_lasthop, params);
+        let _ = done.send(Ok(()));
+    }
 
+    /// Add a hop to the end of this circuit, with dummy cryptography.
+    #[cfg(test)]
+    fn add_fake_hop(
+        &mut self,
+        format: RelayCellFormat,
+        fwd_lasthop: bool,
+        rev_lasthop: bool,
+        params: &CircParameters,
+    ) {
         let dummy_peer_id = OwnedChanTarget::builder()
             .ed_identity([4; 32].into())
             .rsa_identity([5; 20].into())
@@ -1096,7 +1634,24 @@ impl Reactor {
             binding,
             params,
         );
-        let _ = done.send(Ok(()));
+    }
+
+    /// Helper: perform the client side of the first step of handshake `H`.
+    ///
+    /// Uses the RNG from [`set_handshake_rng`](Reactor::set_handshake_rng), if any,
+    /// and `thread_rng()` otherwise.
+    fn client1<H, M>(&mut self, key: &H::KeyType, msg: &M) -> Result<(H::StateType, Vec<u8>)>
+    where
+        H: ClientHandshake,
+        M: Borrow<H::ClientAuxData>,
+    {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(rng) = &mut self.handshake_rng {
+            return H::client1(rng, key, msg);
+        }
+
+        let mut rng = rand::thread_rng();
+        H::client1(&mut rng, key, msg)
     }
 
     /// Helper: create the first hop of a circuit.
@@ -1104,6 +1659,7 @@ impl Reactor {
     /// This is parameterized not just on the RNG, but a wrapper object to
     /// build the right kind of create cell, and a handshake object to perform
     /// the cryptographic handshake.
+    #[instrument(level = "debug", name = "circ_create", skip_all)]
     async fn create_impl<H, W, M>(
         &mut self,
         cell_protocol: RelayCryptLayerProtocol,
@@ -1123,12 +1679,7 @@ impl Reactor {
         // function consumes the PendingClientCirc and only returns
         // a ClientCirc on success.
 
-        let (state, msg) = {
-            // done like this because holding the RNG across an await boundary makes the future
-            // non-Send
-            let mut rng = rand::thread_rng();
-            H::client1(&mut rng, key, msg)?
-        };
+        let (state, msg) = self.client1::<H, _>(key, msg)?;
         let create_cell = wrap.to_chanmsg(msg);
         trace!(
             "{}: Extending to hop 1 with {}",
@@ -1137,10 +1688,18 @@ impl Reactor {
         );
         self.send_msg(create_cell).await?;
 
-        let reply = recvcreated
-            .await
-            .map_err(|_| Error::CircProto("Circuit closed while waiting".into()))?;
+        let sleep_prov = self.chan_sender.as_inner().time_provider().clone();
+        let reply = select_biased! {
+            reply = recvcreated.fuse() => {
+                reply.map_err(|_| Error::CircProto("Circuit closed while waiting".into()))?
+            }
+            () = sleep_prov.sleep(params.first_hop_create_timeout()).fuse() => {
+                debug!("{}: Timed out waiting for a CREATED* cell", self.unique_id);
+                return Err(Error::CreateTimeout);
+            }
+        };
 
+        self.traffic.note_read(FIXED_CELL_LEN);
         let relay_handshake = wrap.decode_chanmsg(reply)?;
         let (server_msg, keygen) = H::client2(state, relay_handshake)?;
 
@@ -1269,13 +1828,25 @@ impl Reactor {
         binding: Option<CircuitBinding>,
         params: &CircParameters,
     ) {
-        let hop = crate::circuit::reactor::CircHop::new(format, params.initial_send_window());
+        if self.hops.is_empty() {
+            self.max_cells_per_yield = params.max_cells_per_yield().into();
+            self.params = Some(params.clone());
+            self.created_at = Some(self.chan_sender.as_inner().time_provider().now());
+        }
+        let hop = crate::circuit::reactor::CircHop::new(format, params);
         self.hops.push(hop);
+        let hop_num = HopNum::from((self.hops.len() - 1) as u8);
         self.crypto_in.add_layer(rev);
         self.crypto_out.add_layer(fwd);
         let mut mutable = self.mutable.lock().expect("poisoned lock");
         Arc::make_mut(&mut mutable.path).push_hop(peer_id);
         mutable.binding.push(binding);
+        mutable.stream_sendme.push(params.stream_sendme_params());
+        drop(mutable);
+        self.report_event(ReactorEvent::HopAdded {
+            circ: self.unique_id,
+            hop: hop_num,
+        });
     }
 
     /// Handle a RELAY cell on this circuit with stream ID 0.
@@ -1316,11 +1887,49 @@ impl Reactor {
                 reason
             );
 
+            // Every hop after the one that sent this is gone, and so are its streams.
+            // There is nobody left to send END messages to.
+            for i in (usize::from(hopnum) + 1)..self.hops.len() {
+                self.close_hop_streams(
+                    cx,
+                    HopNum::from(i as u8),
+                    &CloseStreamBehavior::SendNothing,
+                )?;
+            }
+
             return Ok(CellStatus::CleanShutdown);
         }
 
         trace!("{}: Received meta-cell {:?}", self.unique_id, msg);
 
+        #[cfg(feature = "send-control-msg")]
+        if let Some(idx) = self.cmd_handlers.iter().position(|(c, _)| *c == msg.cmd()) {
+            let (cmd, mut handler) = self.cmd_handlers.swap_remove(idx);
+            if handler.expected_hop() != hopnum {
+                self.cmd_handlers.push((cmd, handler));
+                return Err(Error::CircProto(format!(
+                    "Unexpected {} cell from hop {} on client circuit",
+                    cmd,
+                    hopnum.display(),
+                )));
+            }
+            let ret = handler.handle_msg(cx, msg, self);
+            trace!(
+                "{}: handler for {} completed with result: {:?}",
+                self.unique_id,
+                cmd,
+                ret
+            );
+            return match ret? {
+                MetaCellDisposition::Consumed => {
+                    self.cmd_handlers.push((cmd, handler));
+                    Ok(CellStatus::Continue)
+                }
+                MetaCellDisposition::ConversationFinished => Ok(CellStatus::Continue),
+                MetaCellDisposition::CloseCirc => Ok(CellStatus::CleanShutdown),
+            };
+        }
+
         // For all other command types, we'll only get them in response
         // to another command, which should have registered a responder.
         //
@@ -1371,6 +1980,7 @@ impl Reactor {
     fn handle_sendme(&mut self, hopnum: HopNum, msg: Sendme) -> Result<CellStatus> {
         // No need to call "shutdown" on errors in this function;
         // it's called from the reactor task and errors will propagate there.
+        let now = self.chan_sender.as_inner().time_provider().now();
         let hop = self
             .hop_mut(hopnum)
             .ok_or_else(|| Error::CircProto(format!("Couldn't find hop {}", hopnum.display())))?;
@@ -1389,7 +1999,7 @@ impl Reactor {
                 return Err(Error::CircProto("missing tag on circuit sendme".into()));
             }
         };
-        hop.sendwindow.put(auth)?;
+        hop.ccontrol.note_sendme_received(now, auth)?;
         Ok(CellStatus::Continue)
     }
 
@@ -1406,6 +2016,8 @@ impl Reactor {
     /// that would send here while you know you're unable to forward the messages on).
     fn send_msg_direct(&mut self, cx: &mut Context<'_>, msg: AnyChanMsg) -> Result<()> {
         let cell = AnyChanCell::new(Some(self.channel_id), msg);
+        // Every message we send on a circuit goes in a fixed-length cell.
+        self.traffic.note_written(FIXED_CELL_LEN);
         Pin::new(&mut self.chan_sender).pollish_send_unbounded(cx, cell)
     }
 
@@ -1441,6 +2053,7 @@ impl Reactor {
     ) -> Result<()> {
         let c_t_w = sendme::cmd_counts_towards_windows(msg.cmd());
         let stream_id = msg.stream_id();
+        let now = self.chan_sender.as_inner().time_provider().now();
         let hop_num = Into::<usize>::into(hop);
         let circhop = &mut self.hops[hop_num];
         // We need to apply stream-level flow control *before* encoding the message.
@@ -1472,10 +2085,10 @@ impl Reactor {
         } else {
             AnyChanMsg::Relay(msg)
         };
-        // If the cell counted towards our sendme window, decrement
-        // that window, and maybe remember the authentication tag.
+        // If the cell counted towards our sendme window, tell congestion control,
+        // which will decrement that window, and maybe remember the authentication tag.
         if c_t_w {
-            circhop.sendwindow.take(tag)?;
+            circhop.ccontrol.note_data_sent(now, tag)?;
         }
         self.send_msg_direct(cx, msg)
     }
@@ -1493,6 +2106,33 @@ impl Reactor {
         }
     }
 
+    /// Try to install `handler` for every incoming meta cell whose command is `cmd`.
+    #[cfg(feature = "send-control-msg")]
+    fn register_cmd_handler(
+        &mut self,
+        cmd: RelayCmd,
+        handler: Box<dyn MetaCellHandler + Send>,
+    ) -> Result<()> {
+        if !accepts_cmd_handler(cmd) {
+            return Err(Error::from(bad_api_usage!(
+                "Tried to install a handler for {cmd} cells, which the circuit handles itself."
+            )));
+        }
+        if self.hop_mut(handler.expected_hop()).is_none() {
+            return Err(Error::from(bad_api_usage!(
+                "Tried to install a handler for {cmd} cells from nonexistent hop {}",
+                handler.expected_hop().display()
+            )));
+        }
+        if self.cmd_handlers.iter().any(|(c, _)| *c == cmd) {
+            return Err(Error::from(bad_api_usage!(
+                "Tried to install a second handler for {cmd} cells."
+            )));
+        }
+        self.cmd_handlers.push((cmd, handler));
+        Ok(())
+    }
+
     /// Try to install a given cell handler on this circuit.
     #[cfg(feature = "hs-service")]
     fn set_incoming_stream_req_handler(
@@ -1509,6 +2149,41 @@ impl Reactor {
         }
     }
 
+    /// Return the time at which this circuit reaches its maximum lifetime,
+    /// if it has one.
+    fn expires_at(&self) -> Option<Instant> {
+        let created_at = self.created_at?;
+        let max_lifetime = self.params.as_ref()?.max_lifetime()?;
+        created_at.checked_add(max_lifetime)
+    }
+
+    /// Apply `update` to the parameters of this circuit.
+    fn update_params(&mut self, update: &CircParamsUpdate) -> Result<()> {
+        let params = self.params.as_mut().ok_or_else(|| {
+            Error::from(bad_api_usage!(
+                "Tried to update the parameters of a circuit with no hops"
+            ))
+        })?;
+        update.apply_to(params);
+        self.max_cells_per_yield = params.max_cells_per_yield().into();
+        debug!("{}: Updated circuit parameters", self.unique_id);
+        Ok(())
+    }
+
+    /// Return an error if this circuit may not be extended by another hop,
+    /// because it already has as many hops as `params` allows.
+    fn check_hop_limit(&self, params: &CircParameters) -> Result<()> {
+        let n_hops = self.hops.len();
+        if n_hops >= usize::from(params.max_hops()) {
+            return Err(Error::from(bad_api_usage!(
+                "Tried to extend a circuit with {} hops past its maximum of {}",
+                n_hops,
+                params.max_hops()
+            )));
+        }
+        Ok(())
+    }
+
     /// Handle a CtrlMsg other than Create and Shutdown.
     fn handle_control(&mut self, cx: &mut Context<'_>, msg: CtrlMsg) -> Result<()> {
         trace!("{}: reactor received {:?}", self.unique_id, msg);
@@ -1524,6 +2199,11 @@ impl Reactor {
                 params,
                 done,
             } => {
+                if let Err(e) = self.check_hop_limit(&params) {
+                    let _ = done.send(Err(e)); // don't care if sender goes away
+                    return Ok(());
+                }
+
                 // ntor handshake only supports V0.
                 /// Local type alias to ensure consistency below.
                 type Rcf = RelayCellFormatV0;
@@ -1550,6 +2230,11 @@ impl Reactor {
                 params,
                 done,
             } => {
+                if let Err(e) = self.check_hop_limit(&params) {
+                    let _ = done.send(Err(e)); // don't care if sender goes away
+                    return Ok(());
+                }
+
                 // TODO #1067: support negotiating other formats.
                 /// Local type alias to ensure consistency below.
                 type Rcf = RelayCellFormatV0;
@@ -1579,6 +2264,11 @@ impl Reactor {
                 params,
                 done,
             } => {
+                if let Err(e) = self.check_hop_limit(&params) {
+                    let _ = done.send(Err(e)); // don't care if sender goes away
+                    return Ok(());
+                }
+
                 let (outbound, inbound, binding) = cell_crypto;
 
                 // TODO HS: Perhaps this should describe the onion service, or
@@ -1599,6 +2289,16 @@ impl Reactor {
                 let ret = self.begin_stream(cx, hop_num, message, sender, rx, cmd_checker);
                 let _ = done.send(ret); // don't care if sender goes away
             }
+            CtrlMsg::ReserveStream {
+                hop_num,
+                sender,
+                rx,
+                done,
+                cmd_checker,
+            } => {
+                let ret = self.reserve_stream(hop_num, sender, rx, cmd_checker);
+                let _ = done.send(ret); // don't care if sender goes away
+            }
             #[cfg(feature = "hs-service")]
             CtrlMsg::ClosePendingStream {
                 hop_num,
@@ -1639,6 +2339,13 @@ impl Reactor {
                 let sendme = Sendme::new_empty();
                 let cell = AnyRelayMsgOuter::new(Some(stream_id), sendme.into());
                 self.send_relay_cell(cx, hop_num, false, cell)?;
+                // The other side may now send us more cells on this stream.
+                if let Some(StreamEntMut::Open(ent)) = self
+                    .hop_mut(hop_num)
+                    .and_then(|hop| hop.map.get_mut(stream_id))
+                {
+                    ent.put_recv_window()?;
+                }
             }
             #[cfg(feature = "send-control-msg")]
             CtrlMsg::SendMsg {
@@ -1673,6 +2380,11 @@ impl Reactor {
                 let _ = sender.send(outcome.clone()); // don't care if receiver goes away.
                 outcome?;
             }
+            #[cfg(feature = "send-control-msg")]
+            CtrlMsg::RegisterCmdHandler { cmd, handler, done } => {
+                let ret = self.register_cmd_handler(cmd, handler);
+                let _ = done.send(ret); // don't care if sender goes away
+            }
             #[cfg(test)]
             CtrlMsg::AddFakeHop {
                 relay_cell_format,
@@ -1689,10 +2401,25 @@ impl Reactor {
                     done,
                 );
             }
+            CtrlMsg::CloseAllStreams { done } => {
+                let ret = self.close_all_streams(cx)?;
+                let _ = done.send(Ok(ret)); // don't care if sender goes away
+            }
+            CtrlMsg::SetCircuitPaused(paused) => {
+                debug!(
+                    "{}: {} sending stream messages",
+                    self.unique_id,
+                    if paused { "Pausing" } else { "Resuming" }
+                );
+                self.paused = paused;
+            }
+            CtrlMsg::UpdateParams { update, done } => {
+                let _ = done.send(self.update_params(&update));
+            }
             #[cfg(test)]
             CtrlMsg::QuerySendWindow { hop, done } => {
                 let _ = done.send(if let Some(hop) = self.hop_mut(hop) {
-                    Ok(hop.sendwindow.window_and_expected_tags())
+                    Ok(hop.ccontrol.send_window_and_expected_tags())
                 } else {
                     Err(Error::from(internal!(
                         "received QuerySendWindow for unknown hop {}",
@@ -1701,6 +2428,22 @@ impl Reactor {
                 });
             }
             #[cfg(test)]
+            CtrlMsg::QueryOutboundQueues { done } => {
+                let hops = self
+                    .hops
+                    .iter()
+                    .map(|hop| hop.map.n_ready_streams())
+                    .collect();
+                let _ = done.send(Ok((self.chan_sender.n_queued(), hops)));
+            }
+            #[cfg(test)]
+            CtrlMsg::QueryMetaHandler { done } => {
+                let _ = done.send(Ok(self
+                    .meta_handler
+                    .as_ref()
+                    .map(|handler| handler.expected_hop())));
+            }
+            #[cfg(test)]
             CtrlMsg::SendRelayCell { hop, early, cell } => {
                 self.send_relay_cell(cx, hop, early, cell)?;
             }
@@ -1719,16 +2462,33 @@ impl Reactor {
         rx: StreamMpscReceiver<AnyRelayMsg>,
         cmd_checker: AnyCmdChecker,
     ) -> Result<StreamId> {
-        let hop = self
-            .hop_mut(hopnum)
-            .ok_or_else(|| Error::from(internal!("No such hop {}", hopnum.display())))?;
-        let send_window = StreamSendWindow::new(SEND_WINDOW_INIT);
-        let r = hop.map.add_ent(sender, rx, send_window, cmd_checker)?;
+        let r = self.reserve_stream(hopnum, sender, rx, cmd_checker)?;
         let cell = AnyRelayMsgOuter::new(Some(r), message);
         self.send_relay_cell(cx, hopnum, false, cell)?;
+        self.report_event(ReactorEvent::StreamBegun {
+            circ: self.unique_id,
+            hop: hopnum,
+            stream: r,
+        });
         Ok(r)
     }
 
+    /// Reserve a stream. Creates an entry in the stream map with the given channels,
+    /// but doesn't send anything to the provided hop.
+    fn reserve_stream(
+        &mut self,
+        hopnum: HopNum,
+        sender: StreamMpscSender<UnparsedRelayMsg>,
+        rx: StreamMpscReceiver<AnyRelayMsg>,
+        cmd_checker: AnyCmdChecker,
+    ) -> Result<StreamId> {
+        let hop = self
+            .hop_mut(hopnum)
+            .ok_or_else(|| Error::from(internal!("No such hop {}", hopnum.display())))?;
+        let send_window = hop.new_stream_send_window();
+        hop.map.add_ent(sender, rx, send_window, cmd_checker)
+    }
+
     /// Close the stream associated with `id` because the stream was
     /// dropped.
     ///
@@ -1766,9 +2526,78 @@ impl Reactor {
             let end_cell = AnyRelayMsgOuter::new(Some(id), end_message.into());
             self.send_relay_cell(cx, hopnum, false, end_cell)?;
         }
+        // If we shouldn't send an END, the other end already ended the stream,
+        // and we reported it then.
+        if should_send_end == ShouldSendEnd::Send {
+            self.report_event(ReactorEvent::StreamClosed {
+                circ: self.unique_id,
+                hop: hopnum,
+                stream: id,
+            });
+        }
         Ok(())
     }
 
+    /// Close every open stream on every hop of this circuit, sending an END for each.
+    ///
+    /// Return the number of streams that we closed.
+    fn close_all_streams(&mut self, cx: &mut Context<'_>) -> Result<usize> {
+        let mut n_closed = 0;
+        for i in 0..self.hops.len() {
+            n_closed +=
+                self.close_hop_streams(cx, HopNum::from(i as u8), &CloseStreamBehavior::default())?;
+        }
+        debug!("{}: Closed {} streams on request", self.unique_id, n_closed);
+        Ok(n_closed)
+    }
+
+    /// Close every open stream on the hop `hopnum`, as [`close_stream`](Self::close_stream) would,
+    /// using `message` for each one.
+    ///
+    /// Every stream's reader sees its stream end cleanly, as if the other side had
+    /// sent an END with reason `DONE`, and anybody waiting for the stream to connect
+    /// gets an error.
+    /// We use this before a hop goes away, so that none of its streams are left waiting.
+    ///
+    /// Return the number of streams that we closed.
+    fn close_hop_streams(
+        &mut self,
+        cx: &mut Context<'_>,
+        hopnum: HopNum,
+        message: &CloseStreamBehavior,
+    ) -> Result<usize> {
+        let hop = self.hop_mut(hopnum).ok_or_else(|| {
+            Error::from(internal!(
+                "Tried to close the streams on a hop {:?} that wasn't there?",
+                hopnum
+            ))
+        })?;
+        let stream_ids: Vec<StreamId> = hop.map.open_stream_ids().collect();
+        // Build every END message before touching any stream, so that a failure here
+        // can't leave the hop with only some of its streams told that they're done.
+        let ends = stream_ids
+            .iter()
+            .map(|&sid| Ok((sid, local_end_msg(sid)?)))
+            .collect::<Result<Vec<_>>>()?;
+        for (sid, end) in ends {
+            if let Some(StreamEntMut::Open(ent)) = hop.map.get_mut(sid) {
+                // If the reader is gone, or has fallen too far behind, it'll see
+                // an error instead once we drop its sink.
+                let _ = Pin::new(&mut ent.sink).try_send(end);
+            }
+        }
+        for &sid in &stream_ids {
+            self.close_stream(
+                cx,
+                hopnum,
+                sid,
+                message.clone(),
+                streammap::TerminateReason::ExplicitEnd,
+            )?;
+        }
+        Ok(stream_ids.len())
+    }
+
     /// Helper: process a cell on a channel.  Most cells get ignored
     /// or rejected; a few get delivered to circuits.
     ///
@@ -1922,6 +2751,13 @@ impl Reactor {
                     return Ok(CellStatus::Continue);
                 }
 
+                if sendme::cell_counts_towards_windows(&msg) && ent.take_recv_window().is_err() {
+                    return Err(Error::CircProto(format!(
+                        "Received more cells than our receive window allows on stream ID {}",
+                        sv(streamid),
+                    )));
+                }
+
                 let message_closes_stream =
                     ent.cmd_checker.check_msg(&msg)? == StreamStatus::Closed;
 
@@ -1944,6 +2780,11 @@ impl Reactor {
                 }
                 if message_closes_stream {
                     hop.map.ending_msg_received(streamid)?;
+                    self.report_event(ReactorEvent::StreamClosed {
+                        circ: self.unique_id,
+                        hop: hopnum,
+                        stream: streamid,
+                    });
                 }
             }
             #[cfg(feature = "hs-service")]
@@ -2070,15 +2911,20 @@ impl Reactor {
         let memquota = StreamAccount::new(&self.memquota)?;
         let time_prov = self.chan_sender.as_inner().time_provider().clone();
 
-        let (sender, receiver) = MpscSpec::new(STREAM_READER_BUFFER)
+        let (sender, receiver) = MpscSpec::new(stream_reader_buffer(hop.stream_sendme))
             .new_mq(time_prov.clone(), memquota.as_raw_account())?;
         let (msg_tx, msg_rx) = MpscSpec::new(super::CIRCUIT_BUFFER_SIZE)
             .new_mq(time_prov, memquota.as_raw_account())?;
 
-        let send_window = StreamSendWindow::new(SEND_WINDOW_INIT);
+        let send_window = hop.new_stream_send_window();
         let cmd_checker = DataCmdChecker::new_connected();
         hop.map
             .add_ent_with_id(sender, msg_rx, send_window, stream_id, cmd_checker)?;
+        self.report_event(ReactorEvent::StreamBegun {
+            circ: self.unique_id,
+            hop: hop_num,
+            stream: stream_id,
+        });
 
         let outcome = Pin::new(&mut handler.incoming_sender).try_send(StreamReqInfo {
             req,
@@ -2174,5 +3020,281 @@ impl Drop for Reactor {
 
 #[cfg(test)]
 mod test {
-    // Tested in [`crate::circuit::test`].
+    // @@ begin test lint list maintained by maint/add_warning @@
+    #![allow(clippy::bool_assert_comparison)]
+    #![allow(clippy::clone_on_copy)]
+    #![allow(clippy::dbg_macro)]
+    #![allow(clippy::mixed_attributes_style)]
+    #![allow(clippy::print_stderr)]
+    #![allow(clippy::print_stdout)]
+    #![allow(clippy::single_char_pattern)]
+    #![allow(clippy::unwrap_used)]
+    #![allow(clippy::unchecked_duration_subtraction)]
+    #![allow(clippy::useless_vec)]
+    #![allow(clippy::needless_pass_by_value)]
+    //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
+
+    // Most of the reactor is tested through its circuit, in [`crate::circuit::test`].
+    // Here we drive the reactor by hand, one step at a time.
+
+    use super::*;
+    use crate::circuit::test::{rmsg_to_ccmsg, working_fake_channel};
+    use crate::stream::DataCmdChecker;
+    use futures::sink::SinkExt as _;
+    use tor_cell::relaycell::msg as relaymsg;
+
+    /// Make a fake 3-hop reactor, whose inbound cells come from the last hop.
+    fn fake_reactor(chan: Arc<Channel>) -> (Reactor, Arc<ClientCirc>, CircuitRxSender) {
+        Reactor::new_fake(
+            chan,
+            CircId::new(128).unwrap(),
+            3,
+            2.into(),
+            &CircParameters::default(),
+        )
+    }
+
+    #[test]
+    fn new_fake() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (reactor, circ, _input) = fake_reactor(chan);
+
+            assert_eq!(reactor.hops.len(), 3);
+            assert_eq!(circ.n_hops(), 3);
+            assert!(reactor.params.is_some());
+            assert!(!circ.is_closing());
+        });
+    }
+
+    #[test]
+    fn run_once_handles_cells() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (mut reactor, circ, mut input) = fake_reactor(chan);
+
+            // Messages for a stream on the last hop get delivered to that stream.
+            let (sink, mut stream_input) = crate::fake_mpsc(16);
+            let (_tx, stream_rx) = crate::fake_mpsc(4);
+            let stream_id = reactor.hops[2]
+                .map
+                .add_ent(
+                    sink,
+                    stream_rx,
+                    StreamSendWindow::new(500),
+                    DataCmdChecker::new_any(),
+                )
+                .unwrap();
+            input
+                .send(rmsg_to_ccmsg(
+                    Some(stream_id),
+                    relaymsg::Connected::new_empty().into(),
+                ))
+                .await
+                .unwrap();
+            assert!(reactor.run_once().await.is_ok());
+            assert!(stream_input.next().now_or_never().flatten().is_some());
+            assert!(!circ.is_closing());
+
+            // We never negotiate padding, so a DROP message is a protocol violation.
+            input
+                .send(rmsg_to_ccmsg(None, relaymsg::Drop::default().into()))
+                .await
+                .unwrap();
+            assert!(matches!(
+                reactor.run_once().await,
+                Err(ReactorError::Err(Error::CircProto(_)))
+            ));
+        });
+    }
+
+    #[test]
+    fn run_once_rotates_hops() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (mut reactor, _circ, _input) = fake_reactor(chan);
+
+            // Give each of the first two hops a stream with several DATA messages queued,
+            // each of which says which hop it is for.
+            let mut senders = Vec::new();
+            for hop in 0..2_u8 {
+                let (sink, _) = crate::fake_mpsc(16);
+                let (mut tx, stream_rx) = crate::fake_mpsc(4);
+                for _ in 0..4 {
+                    let msg = relaymsg::Data::new(&[hop; 8]).unwrap();
+                    tx.send(msg.into()).await.unwrap();
+                }
+                reactor.hops[usize::from(hop)]
+                    .map
+                    .add_ent(
+                        sink,
+                        stream_rx,
+                        StreamSendWindow::new(500),
+                        DataCmdChecker::new_any(),
+                    )
+                    .unwrap();
+                senders.push(tx);
+            }
+
+            // Return the hops that the cells on the channel came from, in order.
+            let mut sent_from_hops = || {
+                std::iter::from_fn(|| rx.next().now_or_never().flatten())
+                    .map(|cell| {
+                        let AnyChanMsg::Relay(r) = cell.into_circid_and_msg().1 else {
+                            panic!("not a relay cell");
+                        };
+                        let msg = AnyRelayMsgOuter::decode_singleton(
+                            RelayCellFormat::V0,
+                            r.into_relay_body(),
+                        )
+                        .unwrap();
+                        let AnyRelayMsg::Data(data) = msg.msg() else {
+                            panic!("not a DATA message: {msg:?}");
+                        };
+                        data.as_ref()[0]
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            // Each call to run_once gives every hop one turn,
+            // rather than letting the first hop send twice.
+            for _ in 0..2 {
+                reactor.run_once().await.unwrap();
+                rt.progress_until_stalled().await;
+                assert_eq!(sent_from_hops(), vec![0, 1]);
+                assert_eq!(reactor.next_hop_to_drain, 2);
+            }
+        });
+    }
+
+    #[test]
+    fn run_once_parking_resets_yield_count() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (mut reactor, _circ, _input) = fake_reactor(chan);
+
+            let (sink, _) = crate::fake_mpsc(16);
+            let (mut tx, stream_rx) = crate::fake_mpsc(4);
+            tx.send(relaymsg::Data::new(&[0; 8]).unwrap().into())
+                .await
+                .unwrap();
+            reactor.hops[0]
+                .map
+                .add_ent(
+                    sink,
+                    stream_rx,
+                    StreamSendWindow::new(500),
+                    DataCmdChecker::new_any(),
+                )
+                .unwrap();
+
+            // Sending a cell counts towards our next yield...
+            reactor.run_once().await.unwrap();
+            assert_eq!(reactor.cells_since_yield, 1);
+
+            // ...but once we've run out of things to do and parked,
+            // the count starts again.
+            assert!(reactor.run_once().now_or_never().is_none());
+            assert_eq!(reactor.cells_since_yield, 0);
+        });
+    }
+
+    #[test]
+    fn close_hop_streams() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, mut rx, _sink) = working_fake_channel(&rt);
+            let (mut reactor, circ, mut input) = fake_reactor(chan);
+
+            // Give each of the last two hops a stream.
+            let mut readers = Vec::new();
+            let mut writers = Vec::new();
+            let mut stream_ids = Vec::new();
+            for hop in &mut reactor.hops[1..] {
+                let (sink, reader) = crate::fake_mpsc(16);
+                let (writer, stream_rx) = crate::fake_mpsc(4);
+                let stream_id = hop
+                    .map
+                    .add_ent(
+                        sink,
+                        stream_rx,
+                        StreamSendWindow::new(500),
+                        DataCmdChecker::new_any(),
+                    )
+                    .unwrap();
+                readers.push(reader);
+                writers.push(writer);
+                stream_ids.push(stream_id);
+            }
+
+            // Close the streams on the middle hop only.
+            let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+            let n_closed = reactor
+                .close_hop_streams(&mut cx, 1.into(), &CloseStreamBehavior::default())
+                .unwrap();
+            assert_eq!(n_closed, 1);
+            assert_eq!(reactor.hops[1].map.n_open_streams(), 0);
+            assert_eq!(reactor.hops[2].map.n_open_streams(), 1);
+
+            // The middle hop's reader gets a clean END, and then nothing more.
+            let end = readers[0].next().await.unwrap();
+            assert_eq!(end.cmd(), RelayCmd::END);
+            assert_eq!(end.stream_id(), Some(stream_ids[0]));
+            assert!(readers[0].next().await.is_none());
+
+            // The other side was sent an END too.
+            rt.progress_until_stalled().await;
+            let cell = rx.next().now_or_never().flatten().unwrap();
+            let AnyChanMsg::Relay(r) = cell.into_circid_and_msg().1 else {
+                panic!("not a relay cell");
+            };
+            let msg = AnyRelayMsgOuter::decode_singleton(RelayCellFormat::V0, r.into_relay_body())
+                .unwrap();
+            assert_eq!(msg.cmd(), RelayCmd::END);
+            assert_eq!(msg.stream_id(), Some(stream_ids[0]));
+
+            // The hop itself stays, and so does the last hop's stream.
+            assert_eq!(reactor.hops.len(), 3);
+            assert!(!circ.is_closing());
+            input
+                .send(rmsg_to_ccmsg(
+                    Some(stream_ids[1]),
+                    relaymsg::Connected::new_empty().into(),
+                ))
+                .await
+                .unwrap();
+            reactor.run_once().await.unwrap();
+            let connected = readers[1].next().now_or_never().flatten().unwrap();
+            assert_eq!(connected.cmd(), RelayCmd::CONNECTED);
+        });
+    }
+
+    #[test]
+    fn run_once_shutdown_requested() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (mut reactor, circ, _input) = fake_reactor(chan);
+
+            circ.terminate();
+            assert!(matches!(
+                reactor.run_once().await,
+                Err(ReactorError::Shutdown)
+            ));
+            assert_eq!(reactor.exit_reason, Some(ReactorExit::ShutdownRequested));
+        });
+    }
+
+    #[test]
+    fn run_once_input_closed() {
+        tor_rtmock::MockRuntime::test_with_various(|rt| async move {
+            let (chan, _rx, _sink) = working_fake_channel(&rt);
+            let (mut reactor, _circ, input) = fake_reactor(chan);
+
+            drop(input);
+            assert!(matches!(
+                reactor.run_once().await,
+                Err(ReactorError::Shutdown)
+            ));
+            assert_eq!(reactor.exit_reason, Some(ReactorExit::ChannelClosed));
+        });
+    }
 }
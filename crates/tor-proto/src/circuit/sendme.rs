@@ -16,6 +16,7 @@ use tor_cell::relaycell::RelayCmd;
 use tor_cell::relaycell::UnparsedRelayMsg;
 use tor_error::internal;
 
+use super::SendmeParams;
 use crate::{Error, Result};
 
 /// Tag type used in regular v1 sendme cells.
@@ -75,6 +76,8 @@ where
     /// Tag values that incoming "SENDME" messages need to match in order
     /// for us to send more data.
     tags: VecDeque<T>,
+    /// The maximum and increment for this window.
+    params: SendmeParams,
     /// Marker type to tell the compiler that the P type is used.
     _dummy: std::marker::PhantomData<P>,
 }
@@ -85,6 +88,14 @@ pub(crate) trait WindowParams {
     fn maximum() -> u16;
     /// Increment for this window.
     fn increment() -> u16;
+
+    /// Return the default [`SendmeParams`] for this kind of window.
+    fn default_params() -> SendmeParams {
+        SendmeParams {
+            window: Self::maximum(),
+            increment: Self::increment(),
+        }
+    }
 }
 
 /// Parameters used for SENDME windows on circuits: limit at 1000 cells,
@@ -117,13 +128,18 @@ where
     P: WindowParams,
     T: PartialEq + Eq + Clone,
 {
-    /// Construct a new SendWindow.
+    /// Construct a new SendWindow, using the default parameters for `P`.
     pub(crate) fn new(window: u16) -> SendWindow<P, T> {
-        let increment = P::increment();
-        let capacity = window.div_ceil(increment);
+        Self::new_with_params(window, P::default_params())
+    }
+
+    /// Construct a new SendWindow, with a maximum and increment taken from `params`.
+    pub(crate) fn new_with_params(window: u16, params: SendmeParams) -> SendWindow<P, T> {
+        let capacity = window.div_ceil(params.increment);
         SendWindow {
             window,
             tags: VecDeque::with_capacity(capacity as usize),
+            params,
             _dummy: std::marker::PhantomData,
         }
     }
@@ -142,7 +158,7 @@ where
     {
         if let Some(val) = self.window.checked_sub(1) {
             self.window = val;
-            if self.window % P::increment() == 0 {
+            if self.window % self.params.increment == 0 {
                 // We record this tag.
                 // TODO: I'm not saying that this cell in particular
                 // matches the spec, but Tor seems to like it.
@@ -188,10 +204,10 @@ where
 
         let v = self
             .window
-            .checked_add(P::increment())
+            .checked_add(self.params.increment)
             .ok_or_else(|| Error::from(internal!("Overflow on SENDME window")))?;
 
-        if v > P::maximum() {
+        if v > self.params.window {
             // This should be unreachable, since we would have found a missing tag earlier.
             return Err(Error::CircProto("SENDME would exceed SENDME window".into()));
         }
@@ -220,15 +236,23 @@ pub(crate) struct RecvWindow<P: WindowParams> {
     /// Number of cells that we'd be willing to receive on this window
     /// before sending a SENDME.
     window: u16,
+    /// The maximum and increment for this window.
+    params: SendmeParams,
     /// Marker type to tell the compiler that the P type is used.
     _dummy: std::marker::PhantomData<P>,
 }
 
 impl<P: WindowParams> RecvWindow<P> {
-    /// Create a new RecvWindow.
+    /// Create a new RecvWindow, using the default parameters for `P`.
     pub(crate) fn new(window: u16) -> RecvWindow<P> {
+        Self::new_with_params(window, P::default_params())
+    }
+
+    /// Create a new RecvWindow, with a maximum and increment taken from `params`.
+    pub(crate) fn new_with_params(window: u16, params: SendmeParams) -> RecvWindow<P> {
         RecvWindow {
             window,
+            params,
             _dummy: std::marker::PhantomData,
         }
     }
@@ -244,7 +268,7 @@ impl<P: WindowParams> RecvWindow<P> {
             self.window = x;
             // TODO: same note as in SendWindow.take(). I don't know if
             // this truly matches the spec, but tor accepts it.
-            Ok(x % P::increment() == 0)
+            Ok(x % self.params.increment == 0)
         } else {
             Err(Error::CircProto(
                 "Received a data cell in violation of a window".into(),
@@ -269,10 +293,10 @@ impl<P: WindowParams> RecvWindow<P> {
     pub(crate) fn put(&mut self) -> Result<()> {
         self.window = self
             .window
-            .checked_add(P::increment())
+            .checked_add(self.params.increment)
             .expect("Overflow detected while attempting to increment window");
 
-        if self.window > P::maximum() {
+        if self.window > self.params.window {
             Err(internal!("SENDME places window value above its maximum").into())
         } else {
             Ok(())
@@ -369,6 +393,41 @@ mod test {
         assert!(w.take().is_err());
     }
 
+    #[test]
+    fn recvwindow_custom_params() {
+        let params = SendmeParams::new(200, 20).unwrap();
+        let mut w: RecvWindow<StreamParams> = RecvWindow::new_with_params(200, params);
+
+        for _ in 0..19 {
+            assert!(!w.take().unwrap());
+        }
+        assert!(w.take().unwrap());
+        assert_eq!(w.window, 180);
+
+        w.put().unwrap();
+        assert_eq!(w.window, 200);
+        // We can't go above the configured maximum.
+        assert!(w.put().is_err());
+    }
+
+    #[test]
+    fn sendwindow_custom_params() -> Result<()> {
+        let params = SendmeParams::new(200, 20).unwrap();
+        let mut w: SendWindow<CircParams, &'static str> = SendWindow::new_with_params(200, params);
+
+        for _ in 0_usize..19 {
+            w.take(&"little")?;
+        }
+        assert_eq!(w.tags.len(), 0);
+        let n = w.take(&"tag")?;
+        assert_eq!(n, 180);
+        assert_eq!(w.tags.len(), 1);
+
+        let n = w.put(Some("tag"));
+        assert_eq!(n?, 200);
+        Ok(())
+    }
+
     fn new_sendwindow() -> SendWindow<CircParams, &'static str> {
         SendWindow::new(1000)
     }
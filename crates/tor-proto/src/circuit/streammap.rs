@@ -21,8 +21,8 @@ use tor_error::{bad_api_usage, internal};
 
 use rand::Rng;
 
-use crate::circuit::reactor::RECV_WINDOW_INIT;
 use crate::circuit::sendme::StreamRecvWindow;
+use crate::circuit::SendmeParams;
 use tracing::debug;
 
 /// Entry for an open stream
@@ -52,6 +52,11 @@ pub(super) struct OpenStreamEnt {
     /// Waker to be woken when more sending capacity becomes available (e.g.
     /// receiving a SENDME).
     flow_ctrl_waker: Option<Waker>,
+    /// The receive window for this stream, as we have advertised it to the other side.
+    ///
+    /// Only present in strict mode:
+    /// see [`CircParameters::set_strict_recv_windows`](crate::circuit::CircParameters::set_strict_recv_windows).
+    recv_window: Option<StreamRecvWindow>,
 }
 
 impl OpenStreamEnt {
@@ -84,6 +89,25 @@ impl OpenStreamEnt {
     pub(crate) fn take_capacity_to_send<M: RelayMsg>(&mut self, msg: &M) -> Result<()> {
         self.flow_ctrl.take_capacity_to_send(msg)
     }
+
+    /// Note that we've received a cell on this stream that counts towards its receive window.
+    ///
+    /// In strict mode, returns an error if the other side wasn't allowed to send it.
+    /// Otherwise, does nothing.
+    pub(crate) fn take_recv_window(&mut self) -> Result<()> {
+        if let Some(recv_window) = &mut self.recv_window {
+            recv_window.take()?;
+        }
+        Ok(())
+    }
+
+    /// Note that we've sent a SENDME on this stream, so the other side may send more cells.
+    pub(crate) fn put_recv_window(&mut self) -> Result<()> {
+        if let Some(recv_window) = &mut self.recv_window {
+            recv_window.put()?;
+        }
+        Ok(())
+    }
 }
 
 /// Private wrapper over `OpenStreamEnt`. We implement `futures::Stream` for
@@ -240,11 +264,18 @@ pub(super) struct StreamMap {
     /// priority whenever an outgoing message is processed from that stream,
     /// putting it last in line.
     next_priority: Priority,
+    /// Window size and SENDME increment for streams in this map.
+    stream_sendme: SendmeParams,
+    /// Whether the streams in this map enforce their receive windows as cells arrive.
+    strict_recv_windows: bool,
 }
 
 impl StreamMap {
-    /// Make a new empty StreamMap.
-    pub(super) fn new() -> Self {
+    /// Make a new empty StreamMap, for streams using the flow-control
+    /// parameters in `stream_sendme`.
+    ///
+    /// If `strict_recv_windows` is true, each stream keeps track of its own receive window.
+    pub(super) fn new(stream_sendme: SendmeParams, strict_recv_windows: bool) -> Self {
         let mut rng = rand::thread_rng();
         let next_stream_id: NonZeroU16 = rng.gen();
         StreamMap {
@@ -252,6 +283,8 @@ impl StreamMap {
             closed_streams: HashMap::new(),
             next_stream_id: next_stream_id.into(),
             next_priority: Priority(0),
+            stream_sendme,
+            strict_recv_windows,
         }
     }
 
@@ -260,6 +293,18 @@ impl StreamMap {
         self.open_streams.len()
     }
 
+    /// Return the IDs of all the open streams in this map.
+    pub(super) fn open_stream_ids(&self) -> impl Iterator<Item = StreamId> + '_ {
+        self.open_streams.keys().copied()
+    }
+
+    /// Return a new receive window for a stream in this map, if we're in strict mode.
+    fn new_strict_recv_window(&self) -> Option<StreamRecvWindow> {
+        self.strict_recv_windows.then(|| {
+            StreamRecvWindow::new_with_params(self.stream_sendme.window(), self.stream_sendme)
+        })
+    }
+
     /// Return the next available priority.
     fn take_next_priority(&mut self) -> Priority {
         let rv = self.next_priority;
@@ -283,6 +328,7 @@ impl StreamMap {
                 cmd_checker,
                 rx: StreamUnobtrusivePeeker::new(rx),
                 flow_ctrl_waker: None,
+                recv_window: self.new_strict_recv_window(),
             },
         };
         let priority = self.take_next_priority();
@@ -324,6 +370,7 @@ impl StreamMap {
                 cmd_checker,
                 rx: StreamUnobtrusivePeeker::new(rx),
                 flow_ctrl_waker: None,
+                recv_window: self.new_strict_recv_window(),
             },
         };
         let priority = self.take_next_priority();
@@ -398,7 +445,8 @@ impl StreamMap {
             // FIXME(eta): we don't copy the receive window, instead just creating a new one,
             //             so a malicious peer can send us slightly more data than they should
             //             be able to; see arti#230.
-            let mut recv_window = StreamRecvWindow::new(RECV_WINDOW_INIT);
+            let mut recv_window =
+                StreamRecvWindow::new_with_params(self.stream_sendme.window(), self.stream_sendme);
             recv_window.decrement_n(dropped)?;
             // TODO: would be nice to avoid new_ref.
             let half_stream = HalfStream::new(flow_ctrl, recv_window, cmd_checker);
@@ -463,6 +511,16 @@ impl StreamMap {
             })
     }
 
+    /// Return the number of open streams that are known to have a message ready to send
+    /// (or whose sender has been dropped), but haven't had it taken yet.
+    ///
+    /// Streams only become known to be ready when polled
+    /// by [`Self::poll_ready_streams_iter`].
+    #[cfg(test)]
+    pub(super) fn n_ready_streams(&self) -> usize {
+        self.open_streams.n_ready()
+    }
+
     /// If the stream `sid` has a message ready, take it, and reprioritize `sid`
     /// to the "back of the line" with respect to
     /// [`Self::poll_ready_streams_iter`].
@@ -487,7 +545,9 @@ pub(super) enum TerminateReason {
     /// corresponding senders were all dropped.
     StreamTargetClosed,
     /// Closing a stream because we were explicitly told to end it via
-    /// [`StreamTarget::close_pending`](crate::circuit::StreamTarget::close_pending).
+    /// [`StreamTarget::close_pending`](crate::circuit::StreamTarget::close_pending),
+    /// or [`ClientCirc::close_all_streams`](crate::circuit::ClientCirc::close_all_streams),
+    /// or because the hop it was on went away.
     ExplicitEnd,
 }
 
@@ -515,8 +575,9 @@ mod test {
     #![allow(clippy::needless_pass_by_value)]
     //! <!-- @@ end test lint list maintained by maint/add_warning @@ -->
     use super::*;
+    use crate::circuit::sendme::{StreamParams, StreamSendWindow, WindowParams};
     use crate::circuit::test::fake_mpsc;
-    use crate::{circuit::sendme::StreamSendWindow, stream::DataCmdChecker};
+    use crate::stream::DataCmdChecker;
 
     #[test]
     fn test_wrapping_next_stream_id() {
@@ -530,7 +591,7 @@ mod test {
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn streammap_basics() -> Result<()> {
-        let mut map = StreamMap::new();
+        let mut map = StreamMap::new(StreamParams::default_params(), false);
         let mut next_id = map.next_stream_id;
         let mut ids = Vec::new();
 
@@ -601,4 +662,50 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn saturated_streams_take_turns() -> Result<()> {
+        use futures::{FutureExt as _, SinkExt as _};
+        use tor_cell::relaycell::msg::Data;
+
+        const N_STREAMS: usize = 4;
+        const N_QUEUED: usize = 8;
+
+        let mut map = StreamMap::new(StreamParams::default_params(), false);
+        let mut ids = Vec::new();
+        let mut senders = Vec::new();
+        for _ in 0..N_STREAMS {
+            let (sink, _) = fake_mpsc(128);
+            let (mut tx, rx) = fake_mpsc(N_QUEUED);
+            for _ in 0..N_QUEUED {
+                let msg: AnyRelayMsg = Data::new(b"hello").unwrap().into();
+                tx.send(msg).now_or_never().unwrap().unwrap();
+            }
+            ids.push(map.add_ent(
+                sink,
+                rx,
+                StreamSendWindow::new(500),
+                DataCmdChecker::new_any(),
+            )?);
+            senders.push(tx);
+        }
+
+        // Every stream always has a message ready,
+        // but each one gets its turn before any stream gets a second one.
+        let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+        let mut counts: HashMap<StreamId, usize> = HashMap::new();
+        for round in 1..=N_QUEUED / 2 {
+            for _ in 0..N_STREAMS {
+                let (sid, msg) = map.poll_ready_streams_iter(&mut cx).next().unwrap();
+                assert!(msg.is_some());
+                assert!(map.take_ready_msg(sid).is_some());
+                *counts.entry(sid).or_default() += 1;
+            }
+            for id in &ids {
+                assert_eq!(counts.get(id), Some(&round));
+            }
+        }
+
+        Ok(())
+    }
 }
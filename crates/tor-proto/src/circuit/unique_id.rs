@@ -22,6 +22,12 @@ impl UniqId {
         UniqId { chan, circ }
     }
 
+    /// Construct a new circuit UniqId from its parts, for testing.
+    #[cfg(feature = "testing")]
+    pub fn new_for_testing(chan: usize, circ: usize) -> Self {
+        Self::new(chan, circ)
+    }
+
     /// A helper for displaying the process-unique identifiers of this circuit.
     ///
     /// Unlike the [`Display`] implementation, this does not display a `Circ` prefix.
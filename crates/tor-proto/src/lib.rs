@@ -57,7 +57,7 @@ pub mod memquota;
 pub mod stream;
 mod util;
 
-pub use util::err::{Error, ResolveError};
+pub use util::err::{Error, IdentityMismatch, ResolveError};
 pub use util::skew::ClockSkew;
 
 pub use channel::params::ChannelPaddingInstructions;
@@ -21,6 +21,9 @@ mod raw;
 mod resolve;
 
 pub(crate) use cmdcheck::{AnyCmdChecker, CmdChecker, StreamStatus};
+#[cfg(feature = "experimental-api")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental-api")))]
+pub use data::ReservedStream;
 pub use data::{DataReader, DataStream, DataWriter};
 #[cfg(feature = "hs-service")]
 #[cfg_attr(docsrs, doc(cfg(feature = "hs-service")))]
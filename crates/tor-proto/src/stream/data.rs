@@ -490,6 +490,55 @@ impl DataStream {
     }
 }
 
+/// A stream whose ID has been reserved on a circuit, but which hasn't been started yet.
+///
+/// This is obtained from [`ClientCirc::reserve_stream`],
+/// for protocols that need to know the stream ID before they can construct
+/// the message that initiates the stream.
+///
+/// If this object is dropped, the stream is closed.
+///
+/// This is an experimental API; it is not covered by semver guarantee. It
+/// is likely to change or disappear in a future release.
+#[cfg(feature = "experimental-api")]
+#[derive(Debug)]
+pub struct ReservedStream {
+    /// The reader for the stream.
+    reader: StreamReader,
+    /// The target for the stream.
+    target: StreamTarget,
+    /// The memory quota account for the stream.
+    memquota: StreamAccount,
+}
+
+#[cfg(feature = "experimental-api")]
+impl ReservedStream {
+    /// Wrap the raw parts of a stream whose ID has been reserved.
+    pub(crate) fn new(reader: StreamReader, target: StreamTarget, memquota: StreamAccount) -> Self {
+        Self {
+            reader,
+            target,
+            memquota,
+        }
+    }
+
+    /// Return the ID that was reserved for this stream.
+    pub fn stream_id(&self) -> tor_cell::relaycell::StreamId {
+        self.target.stream_id()
+    }
+
+    /// Start this stream by sending `msg`, and return it as a [`DataStream`].
+    ///
+    /// `msg` should be a message that opens a data stream, such as BEGIN or BEGIN_DIR.
+    ///
+    /// This does not wait for the stream to be connected:
+    /// use [`DataStream::wait_for_connection`] for that.
+    pub async fn begin(mut self, msg: tor_cell::relaycell::msg::AnyRelayMsg) -> Result<DataStream> {
+        self.target.send(msg).await?;
+        Ok(DataStream::new(self.reader, self.target, self.memquota))
+    }
+}
+
 impl AsyncRead for DataStream {
     fn poll_read(
         mut self: Pin<&mut Self>,
@@ -1,9 +1,10 @@
 //! Define an error type for the tor-proto crate.
+use safelog::sensitive as sv;
 use std::{sync::Arc, time::Duration};
 use thiserror::Error;
 use tor_cell::relaycell::{msg::EndReason, StreamId};
 use tor_error::{ErrorKind, HasKind};
-use tor_linkspec::RelayIdType;
+use tor_linkspec::{RelayId, RelayIdType};
 
 /// An error type for the tor-proto crate.
 ///
@@ -106,6 +107,9 @@ pub enum Error {
     /// operation.
     #[error("Circuit closed")]
     CircuitClosed,
+    /// The first hop of a circuit didn't answer our CREATE* cell in time.
+    #[error("Timed out waiting for a CREATED* cell")]
+    CreateTimeout,
     /// Can't allocate any more circuit or stream IDs on a channel.
     #[error("Too many entries in map: can't allocate ID")]
     IdRangeFull,
@@ -134,7 +138,7 @@ pub enum Error {
 
     /// Channel does not match target
     #[error("Peer identity mismatch: {0}")]
-    ChanMismatch(String),
+    ChanMismatch(IdentityMismatch),
     /// There was a programming error somewhere in our code, or the calling code.
     #[error("Programming error")]
     Bug(#[from] tor_error::Bug),
@@ -150,6 +154,47 @@ pub enum Error {
     Memquota(#[from] tor_memquota::Error),
 }
 
+/// Details of how a channel's peer failed to match the identities we wanted.
+///
+/// Returned inside [`Error::ChanMismatch`].
+#[derive(Error, Debug, Clone)]
+#[non_exhaustive]
+pub enum IdentityMismatch {
+    /// The peer has an identity of the wanted type, but it is a different one.
+    #[error("Identity {} does not match target {}", sv(.actual), sv(.wanted))]
+    Different {
+        /// The identity we wanted.
+        wanted: RelayId,
+        /// The identity the peer actually has.
+        actual: RelayId,
+    },
+    /// The peer has no identity of the wanted type.
+    #[error("Peer does not have {} identity", .wanted.id_type())]
+    Missing {
+        /// The identity we wanted.
+        wanted: RelayId,
+    },
+}
+
+impl IdentityMismatch {
+    /// Return the identity that we wanted the peer to have.
+    pub fn wanted(&self) -> &RelayId {
+        match self {
+            IdentityMismatch::Different { wanted, .. } | IdentityMismatch::Missing { wanted } => {
+                wanted
+            }
+        }
+    }
+
+    /// Return the peer's actual identity of the wanted type, if it has one.
+    pub fn actual(&self) -> Option<&RelayId> {
+        match self {
+            IdentityMismatch::Different { actual, .. } => Some(actual),
+            IdentityMismatch::Missing { .. } => None,
+        }
+    }
+}
+
 /// Error which indicates that the channel was closed.
 #[derive(Error, Debug, Clone)]
 #[error("Channel closed")]
@@ -223,6 +268,8 @@ impl From<Error> for std::io::Error {
 
             CircuitClosed => ErrorKind::ConnectionReset,
 
+            CreateTimeout => ErrorKind::TimedOut,
+
             Memquota { .. } => ErrorKind::OutOfMemory,
 
             BytesErr { .. }
@@ -278,6 +325,7 @@ impl HasKind for Error {
             E::CircProto(_) => EK::TorProtocolViolation,
             E::ChannelClosed(e) => e.kind(),
             E::CircuitClosed => EK::CircuitCollapse,
+            E::CreateTimeout => EK::TorNetworkTimeout,
             E::IdRangeFull => EK::BadApiUsage,
             E::CircRefused(_) => EK::CircuitRefused,
             E::BadStreamAddress => EK::BadApiUsage,
@@ -395,10 +395,22 @@ where
         self.ready_streams.get_mut(&(priority.clone(), key.clone()))
     }
 
+    /// Iterate over the keys of all the streams managed by this object.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.priorities.keys()
+    }
+
     /// Number of streams managed by this object.
     pub fn len(&self) -> usize {
         self.priorities.len()
     }
+
+    /// Number of streams that were found to be ready when last polled,
+    /// and whose values haven't been taken yet.
+    #[cfg(test)]
+    pub fn n_ready(&self) -> usize {
+        self.ready_streams.len()
+    }
 }
 
 /// Error returned by [`StreamPollSet::try_insert`].
@@ -72,6 +72,16 @@ pub struct RelaySigningKeypairSpecifier {
     pub(crate) valid_until: Timestamp,
 }
 
+impl RelaySigningKeypairSpecifier {
+    /// Return the expiration time of the key identified by this specifier.
+    ///
+    /// See the caveats on `valid_until`: this is only meant for choosing
+    /// between several signing keypairs, not for validating them.
+    pub fn valid_until(&self) -> SystemTime {
+        Iso8601TimeSlug::from(self.valid_until).into()
+    }
+}
+
 /// The approximate time when a [`RelaySigningKeypairSpecifier`] was generated.
 ///
 /// Used as a denotator to distinguish between the different signing keypair instances
@@ -143,6 +153,7 @@ mod test {
         );
 
         check_key_specifier(&key_spec, "relay/ks_relaysign_ed+19700101000000");
+        assert_eq!(key_spec.valid_until(), ts);
     }
 
     #[test]
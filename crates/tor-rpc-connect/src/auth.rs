@@ -106,6 +106,81 @@ impl Cookie {
     }
 }
 
+/// Length of a nonce used in the RPC cookie authentication protocol.
+pub const COOKIE_NONCE_LEN: usize = 32;
+
+/// Length of a MAC used in the RPC cookie authentication protocol.
+pub const COOKIE_MAC_LEN: usize = 32;
+
+/// The customization string `S` used for TupleHash in the RPC cookie authentication protocol.
+const COOKIE_MAC_CUSTOMIZATION: &[u8] = b"arti-rpc-cookie-v1";
+
+/// A random nonce, sent by one party in the RPC cookie authentication protocol.
+///
+/// In JSON, this is encoded as a hexadecimal string.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct CookieAuthNonce(#[serde(with = "hex::serde")] [u8; COOKIE_NONCE_LEN]);
+
+impl CookieAuthNonce {
+    /// Generate a new random nonce.
+    ///
+    /// Every nonce must be used for only a single authentication attempt.
+    #[cfg(any(feature = "rpc-client", feature = "rpc-server"))]
+    pub fn new<R: rand::CryptoRng + rand::RngCore>(rng: &mut R) -> Self {
+        let mut nonce = [0_u8; COOKIE_NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        CookieAuthNonce(nonce)
+    }
+}
+
+/// A message authentication code, sent by one party in the RPC cookie authentication protocol.
+///
+/// In JSON, this is encoded as a hexadecimal string.
+///
+/// Comparing two of these values takes constant time.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct CookieAuthMac(#[serde(with = "hex::serde")] [u8; COOKIE_MAC_LEN]);
+
+impl PartialEq for CookieAuthMac {
+    fn eq(&self, other: &Self) -> bool {
+        use subtle::ConstantTimeEq as _;
+        self.0.ct_eq(&other.0).into()
+    }
+}
+impl Eq for CookieAuthMac {}
+
+impl Cookie {
+    /// Compute the MAC that the server sends in the RPC cookie authentication protocol.
+    ///
+    /// This is `MAC(cookie, "Server", socket_canonical, client_nonce)` in the RPC cookie spec.
+    pub fn server_mac(&self, server_addr: &str, client_nonce: &CookieAuthNonce) -> CookieAuthMac {
+        self.mac("Server", server_addr, client_nonce)
+    }
+
+    /// Compute the MAC that the client sends in the RPC cookie authentication protocol.
+    ///
+    /// This is `MAC(cookie, "Client", socket_canonical, server_nonce)` in the RPC cookie spec.
+    pub fn client_mac(&self, server_addr: &str, server_nonce: &CookieAuthNonce) -> CookieAuthMac {
+        self.mac("Client", server_addr, server_nonce)
+    }
+
+    /// Compute `MAC(cookie, role, server_addr, nonce)`.
+    fn mac(&self, role: &str, server_addr: &str, nonce: &CookieAuthNonce) -> CookieAuthMac {
+        use tiny_keccak::{Hasher as _, TupleHash};
+
+        let mut hasher = TupleHash::v128(COOKIE_MAC_CUSTOMIZATION);
+        hasher.update(self.as_ref());
+        hasher.update(role.as_bytes());
+        hasher.update(server_addr.as_bytes());
+        hasher.update(&nonce.0);
+        let mut mac = [0_u8; COOKIE_MAC_LEN];
+        hasher.finalize(&mut mac);
+        CookieAuthMac(mac)
+    }
+}
+
 /// An error that has occurred while trying to load or create a cookie.
 #[derive(Clone, Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -182,4 +257,38 @@ mod test {
         assert_eq!(s_c1.as_ref(), c_c1.as_ref());
         assert_eq!(s_c2.as_ref(), c_c2.as_ref());
     }
+
+    #[test]
+    fn cookie_macs() {
+        let cookie = Cookie {
+            value: Sensitive::new(Zeroizing::new([7; COOKIE_LEN])),
+        };
+        let other_cookie = Cookie {
+            value: Sensitive::new(Zeroizing::new([8; COOKIE_LEN])),
+        };
+        let nonce = CookieAuthNonce([1; COOKIE_NONCE_LEN]);
+        let other_nonce = CookieAuthNonce([2; COOKIE_NONCE_LEN]);
+        let addr = "inet:127.0.0.1:9180";
+
+        let server_mac = cookie.server_mac(addr, &nonce);
+        assert_eq!(server_mac, cookie.server_mac(addr, &nonce));
+
+        // Changing any input changes the MAC.
+        assert_ne!(server_mac, cookie.client_mac(addr, &nonce));
+        assert_ne!(server_mac, cookie.server_mac("inet:127.0.0.1:9181", &nonce));
+        assert_ne!(server_mac, cookie.server_mac(addr, &other_nonce));
+        assert_ne!(server_mac, other_cookie.server_mac(addr, &nonce));
+    }
+
+    #[test]
+    fn cookie_auth_encoding() {
+        let nonce = CookieAuthNonce([0xab; COOKIE_NONCE_LEN]);
+        let json = serde_json::to_string(&nonce).unwrap();
+        assert_eq!(json, format!("\"{}\"", "ab".repeat(COOKIE_NONCE_LEN)));
+        let decoded: CookieAuthNonce = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, nonce);
+
+        // Wrong lengths are rejected.
+        assert!(serde_json::from_str::<CookieAuthMac>("\"abcd\"").is_err());
+    }
 }
@@ -188,6 +188,10 @@ pub enum ConnectError {
     /// so we couldn't bind to it.
     #[error("Could not acquire lock: Another process is listening on this connect point")]
     AlreadyLocked,
+    /// The directory holding a Unix socket would let users reach the socket
+    /// whom the socket's permissions are meant to keep out.
+    #[error("Socket directory is more permissive than the socket's permissions")]
+    SocketDirTooPermissive,
 }
 
 impl From<io::Error> for ConnectError {
@@ -208,6 +212,7 @@ impl crate::HasClientErrorAction for ConnectError {
             E::InvalidUnixAddress => A::Decline,
             E::UnixAddressAccess(err) => err.client_action(),
             E::AlreadyLocked => A::Abort, // (This one can't actually occur for clients.)
+            E::SocketDirTooPermissive => A::Abort, // (Nor can this one.)
         }
     }
 }
@@ -49,12 +49,45 @@ impl Drop for UnlinkOnDrop {
 impl ResolvedConnectPoint {
     /// Try to bind to a location as specified by this connect point.
     pub async fn bind<R>(&self, runtime: &R, mistrust: &Mistrust) -> Result<Listener, ConnectError>
+    where
+        R: NetStreamProvider<general::SocketAddr, Listener = tor_rtcompat::general::Listener>,
+    {
+        self.bind_inner(runtime, mistrust, None).await
+    }
+
+    /// As [`bind`](Self::bind), but if we bind to a socket in the filesystem,
+    /// give it the Unix permissions `socket_mode`.
+    ///
+    /// Before we bind, we make sure that the socket's parent directory
+    /// doesn't let anybody reach the socket whom `socket_mode` wouldn't let connect to it.
+    /// So nobody else can connect in the moment before we set the socket's permissions.
+    ///
+    /// On platforms without Unix file permissions, `socket_mode` is ignored.
+    pub async fn bind_with_socket_mode<R>(
+        &self,
+        runtime: &R,
+        mistrust: &Mistrust,
+        socket_mode: u32,
+    ) -> Result<Listener, ConnectError>
+    where
+        R: NetStreamProvider<general::SocketAddr, Listener = tor_rtcompat::general::Listener>,
+    {
+        self.bind_inner(runtime, mistrust, Some(socket_mode)).await
+    }
+
+    /// Helper: Implement `bind` and `bind_with_socket_mode`.
+    async fn bind_inner<R>(
+        &self,
+        runtime: &R,
+        mistrust: &Mistrust,
+        socket_mode: Option<u32>,
+    ) -> Result<Listener, ConnectError>
     where
         R: NetStreamProvider<general::SocketAddr, Listener = tor_rtcompat::general::Listener>,
     {
         use crate::connpt::ConnectPointEnum as CptE;
         match &self.0 {
-            CptE::Connect(connect) => connect.bind(runtime, mistrust).await,
+            CptE::Connect(connect) => connect.bind(runtime, mistrust, socket_mode).await,
             CptE::Builtin(builtin) => builtin.bind(),
         }
     }
@@ -72,13 +105,24 @@ impl crate::connpt::Builtin {
 
 impl crate::connpt::Connect<crate::connpt::Resolved> {
     /// Try to bind to a "Connect" connect point.
-    async fn bind<R>(&self, runtime: &R, mistrust: &Mistrust) -> Result<Listener, ConnectError>
+    ///
+    /// If `socket_mode` is provided, give a socket in the filesystem those permissions.
+    async fn bind<R>(
+        &self,
+        runtime: &R,
+        mistrust: &Mistrust,
+        socket_mode: Option<u32>,
+    ) -> Result<Listener, ConnectError>
     where
         R: NetStreamProvider<general::SocketAddr, Listener = tor_rtcompat::general::Listener>,
     {
         if let Some(sock_parent_dir) = crate::socket_parent_path(self.socket.as_ref()) {
             // TODO RPC: Revisit this and other uses of make_directory; do we really want to do so?
             mistrust.make_directory(sock_parent_dir)?;
+            #[cfg(unix)]
+            if let Some(mode) = socket_mode {
+                check_socket_dir(sock_parent_dir, mode)?;
+            }
         }
 
         let guard = if let Some(socket_path) = self.socket.as_ref().as_pathname() {
@@ -124,6 +168,14 @@ impl crate::connpt::Connect<crate::connpt::Resolved> {
 
         let listener = runtime.listen(self.socket.as_ref()).await?;
 
+        #[cfg(unix)]
+        if let (Some(mode), Some(socket_path)) = (socket_mode, self.socket.as_ref().as_pathname()) {
+            use std::os::unix::fs::PermissionsExt as _;
+            std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(mode))?;
+        }
+        #[cfg(not(unix))]
+        let _ = socket_mode;
+
         // We try to bind to the listener before we (maybe) create the cookie file,
         // so that if we encounter an `EADDRINUSE` we won't overwrite the old cookie file.
         let auth = match &self.auth {
@@ -142,3 +194,19 @@ impl crate::connpt::Connect<crate::connpt::Resolved> {
         })
     }
 }
+
+/// Return an error if the directory `dir` lets anybody reach a socket inside it
+/// whom the socket permissions `socket_mode` wouldn't let connect.
+#[cfg(unix)]
+fn check_socket_dir(dir: &std::path::Path, socket_mode: u32) -> Result<(), ConnectError> {
+    use std::os::unix::fs::PermissionsExt as _;
+    let dir_mode = std::fs::metadata(dir)?.permissions().mode();
+    // Reaching a socket needs search (execute) permission on its directory;
+    // connecting to it needs write permission on the socket.
+    let group_excluded = dir_mode & 0o010 != 0 && socket_mode & 0o020 == 0;
+    let other_excluded = dir_mode & 0o001 != 0 && socket_mode & 0o002 == 0;
+    if group_excluded || other_excluded {
+        return Err(ConnectError::SocketDirTooPermissive);
+    }
+    Ok(())
+}
@@ -111,6 +111,30 @@ where
     }
 }
 
+impl<SpawnR, SleepR, CoarseTimeR, TcpR, UnixR, TlsR, UdpR> SpawnBlocking
+    for CompoundRuntime<SpawnR, SleepR, CoarseTimeR, TcpR, UnixR, TlsR, UdpR>
+where
+    SpawnR: SpawnBlocking,
+    SleepR: Clone + Send + Sync + 'static,
+    CoarseTimeR: Clone + Send + Sync + 'static,
+    TcpR: Clone + Send + Sync + 'static,
+    UnixR: Clone + Send + Sync + 'static,
+    TlsR: Clone + Send + Sync + 'static,
+    UdpR: Clone + Send + Sync + 'static,
+{
+    #[inline]
+    fn spawn_blocking<F, T>(
+        &self,
+        f: F,
+    ) -> futures::future::BoxFuture<'static, std::thread::Result<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.inner.spawn.spawn_blocking(f)
+    }
+}
+
 impl<SpawnR, SleepR, CoarseTimeR, TcpR, UnixR, TlsR, UdpR> SleepProvider
     for CompoundRuntime<SpawnR, SleepR, CoarseTimeR, TcpR, UnixR, TlsR, UdpR>
 where
@@ -180,6 +204,15 @@ where
         self.inner.tcp.connect(addr).await
     }
 
+    #[inline]
+    async fn connect_from(
+        &self,
+        local: &net::SocketAddr,
+        addr: &net::SocketAddr,
+    ) -> IoResult<Self::Stream> {
+        self.inner.tcp.connect_from(local, addr).await
+    }
+
     #[inline]
     async fn listen(&self, addr: &net::SocketAddr) -> IoResult<Self::Listener> {
         self.inner.tcp.listen(addr).await
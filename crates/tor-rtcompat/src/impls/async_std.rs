@@ -193,6 +193,7 @@ mod net {
 
 // ==============================
 
+use futures::future::BoxFuture;
 use futures::{Future, FutureExt};
 use std::pin::Pin;
 use std::time::Duration;
@@ -216,3 +217,16 @@ impl BlockOn for async_executors::AsyncStd {
         async_executors::AsyncStd::block_on(f)
     }
 }
+
+impl SpawnBlocking for async_executors::AsyncStd {
+    fn spawn_blocking<F, T>(&self, f: F) -> BoxFuture<'static, std::thread::Result<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        // async_std doesn't catch panics in blocking tasks for us.
+        Box::pin(async_std_crate::task::spawn_blocking(move || {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        }))
+    }
+}
@@ -10,7 +10,8 @@ pub(crate) mod net {
     use tor_general_addr::unix;
 
     pub(crate) use tokio_crate::net::{
-        TcpListener as TokioTcpListener, TcpStream as TokioTcpStream, UdpSocket as TokioUdpSocket,
+        TcpListener as TokioTcpListener, TcpSocket as TokioTcpSocket, TcpStream as TokioTcpStream,
+        UdpSocket as TokioUdpSocket,
     };
     #[cfg(unix)]
     pub(crate) use tokio_crate::net::{
@@ -195,6 +196,7 @@ pub(crate) mod net {
 
 use crate::traits::*;
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 use futures::Future;
 use std::io::Result as IoResult;
 use std::time::Duration;
@@ -216,6 +218,19 @@ impl crate::traits::NetStreamProvider for TokioRuntimeHandle {
         let s = net::TokioTcpStream::connect(addr).await?;
         Ok(s.into())
     }
+    async fn connect_from(
+        &self,
+        local: &std::net::SocketAddr,
+        addr: &std::net::SocketAddr,
+    ) -> IoResult<Self::Stream> {
+        let socket = match local {
+            std::net::SocketAddr::V4(_) => net::TokioTcpSocket::new_v4()?,
+            std::net::SocketAddr::V6(_) => net::TokioTcpSocket::new_v6()?,
+        };
+        socket.bind(*local)?;
+        let s = socket.connect(*addr).await?;
+        Ok(s.into())
+    }
     async fn listen(&self, addr: &std::net::SocketAddr) -> IoResult<Self::Listener> {
         let lis = net::TokioTcpListener::bind(*addr).await?;
         Ok(net::TcpListener { lis })
@@ -331,6 +346,23 @@ impl BlockOn for TokioRuntimeHandle {
     }
 }
 
+impl SpawnBlocking for TokioRuntimeHandle {
+    fn spawn_blocking<F, T>(&self, f: F) -> BoxFuture<'static, std::thread::Result<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let join_handle = self.handle.spawn_blocking(f);
+        Box::pin(async move {
+            join_handle.await.map_err(|e| match e.try_into_panic() {
+                Ok(payload) => payload,
+                // The task was cancelled, which only happens if the runtime is shutting down.
+                Err(e) => Box::new(e) as Box<dyn std::any::Any + Send>,
+            })
+        })
+    }
+}
+
 impl futures::task::Spawn for TokioRuntimeHandle {
     #[track_caller]
     fn spawn_obj(
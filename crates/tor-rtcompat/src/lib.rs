@@ -67,7 +67,8 @@ pub mod unix;
 use std::io;
 pub use traits::{
     BlockOn, CertifiedConn, CoarseTimeProvider, NetStreamListener, NetStreamProvider, Runtime,
-    SleepProvider, StreamOps, TlsProvider, UdpProvider, UdpSocket, UnsupportedStreamOp,
+    SleepProvider, SpawnBlocking, StreamOps, TlsProvider, UdpProvider, UdpSocket,
+    UnsupportedStreamOp,
 };
 
 pub use coarse_time::{CoarseDuration, CoarseInstant, RealCoarseTimeProvider};
@@ -471,6 +472,36 @@ mod test {
         })
     }
 
+    // Try connecting to ourself from a chosen local address.
+    //
+    // NOTE: requires Ipv4 localhost.
+    #[cfg(feature = "tokio")]
+    fn connect_from_tcp<R: Runtime>(runtime: &R) -> IoResult<()> {
+        let localhost = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+        let rt1 = runtime.clone();
+
+        let listener = runtime.block_on(rt1.listen(&(SocketAddr::from(localhost))))?;
+        let addr = listener.local_addr()?;
+
+        // Find a local port that is (very likely) free.
+        let source = std::net::TcpListener::bind(localhost)?.local_addr()?;
+
+        runtime.block_on(async {
+            let task1 = async {
+                let (_con, peer) = listener.incoming().next().await.expect("closed?")?;
+                IoResult::Ok(peer)
+            };
+            let task2 = rt1.connect_from(&source, &addr);
+
+            let (peer, con) = futures::join!(task1, task2);
+            let _con = con?;
+
+            assert_eq!(peer?, source);
+
+            Ok(())
+        })
+    }
+
     // Try connecting to ourself and sending a little data.
     //
     // NOTE: requires Ipv4 localhost.
@@ -618,6 +649,20 @@ mod test {
         IoResult::Ok(())
     }
 
+    // Run a closure on the blocking pool, and make sure that both its result
+    // and its panics make it back to us.
+    fn spawn_blocking<R: Runtime + SpawnBlocking>(runtime: &R) -> IoResult<()> {
+        let rt = runtime.clone();
+        runtime.block_on(async {
+            let outcome = rt.spawn_blocking(|| 6_u32 * 7).await;
+            assert_eq!(outcome.unwrap(), 42);
+
+            let outcome = rt.spawn_blocking(|| -> u32 { panic!("oops") }).await;
+            assert!(outcome.is_err());
+        });
+        Ok(())
+    }
+
     macro_rules! tests_with_runtime {
         { $runtime:expr  => $($id:ident),* $(,)? } => {
             $(
@@ -684,4 +729,20 @@ mod test {
     tls_runtime_tests! {
         simple_tls,
     }
+
+    // Not every runtime can choose the local address of a connection.
+    #[cfg(feature = "tokio")]
+    mod tokio_connect_from_tests {
+        tests_with_runtime! { &crate::tokio::PreferredRuntime::create()? => connect_from_tcp }
+    }
+
+    // SpawnBlocking isn't required of every Runtime, so test it separately.
+    #[cfg(feature = "tokio")]
+    mod tokio_spawn_blocking_tests {
+        tests_with_runtime! { &crate::tokio::PreferredRuntime::create()? => spawn_blocking }
+    }
+    #[cfg(feature = "async-std")]
+    mod async_std_spawn_blocking_tests {
+        tests_with_runtime! { &crate::async_std::PreferredRuntime::create()? => spawn_blocking }
+    }
 }
@@ -24,6 +24,17 @@ macro_rules! implement_opaque_runtime {
 
     }
 
+    impl $crate::traits::SpawnBlocking for $t {
+        #[inline]
+        fn spawn_blocking<F, T>(&self, f: F) -> futures::future::BoxFuture<'static, std::thread::Result<T>>
+        where
+            F: FnOnce() -> T + Send + 'static,
+            T: Send + 'static,
+        {
+            self.$member.spawn_blocking(f)
+        }
+    }
+
     impl $crate::traits::SleepProvider for $t {
         type SleepFuture = <$mty as $crate::traits::SleepProvider>::SleepFuture;
         #[inline]
@@ -48,6 +59,10 @@ macro_rules! implement_opaque_runtime {
             self.$member.connect(addr).await
         }
         #[inline]
+        async fn connect_from(&self, local: &std::net::SocketAddr, addr: &std::net::SocketAddr) -> std::io::Result<Self::Stream> {
+            self.$member.connect_from(local, addr).await
+        }
+        #[inline]
         async fn listen(&self, addr: &std::net::SocketAddr) -> std::io::Result<Self::Listener> {
             self.$member.listen(addr).await
         }
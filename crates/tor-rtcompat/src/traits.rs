@@ -1,5 +1,6 @@
 //! Declarations for traits that we need our runtimes to implement.
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 use futures::stream;
 use futures::task::Spawn;
 use futures::{AsyncRead, AsyncWrite, Future};
@@ -152,6 +153,20 @@ pub trait BlockOn: Clone + Send + Sync + 'static {
     fn block_on<F: Future>(&self, future: F) -> F::Output;
 }
 
+/// Trait for a runtime that can run blocking code without stalling its executor.
+///
+/// This is not (yet) required of every [`Runtime`].
+pub trait SpawnBlocking: Clone + Send + Sync + 'static {
+    /// Run `f` on a thread that is set aside for blocking operations,
+    /// and return a future that resolves to its output.
+    ///
+    /// If `f` panics, the future resolves to an error holding the panic payload.
+    fn spawn_blocking<F, T>(&self, f: F) -> BoxFuture<'static, std::thread::Result<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static;
+}
+
 /// Trait providing additional operations on network sockets.
 pub trait StreamOps {
     /// Set the [`TCP_NOTSENT_LOWAT`] socket option, if this `Stream` is a TCP stream.
@@ -221,6 +236,21 @@ pub trait NetStreamProvider<ADDR = net::SocketAddr>: Clone + Send + Sync + 'stat
     /// unnecessary DNS lookups.
     async fn connect(&self, addr: &ADDR) -> IoResult<Self::Stream>;
 
+    /// Launch a connection to a given socket address, from a given local address.
+    ///
+    /// This behaves like [`connect`](Self::connect),
+    /// except that the new socket is bound to `local` before it connects.
+    ///
+    /// Not every provider can do this.
+    /// The default implementation returns an error of kind [`io::ErrorKind::Unsupported`].
+    async fn connect_from(&self, local: &ADDR, addr: &ADDR) -> IoResult<Self::Stream>
+    where
+        ADDR: Sync,
+    {
+        let _ = (local, addr);
+        Err(UnsupportedStreamOp::new("connect_from", "unsupported by this provider").into())
+    }
+
     /// Open a listener on a given socket address.
     async fn listen(&self, addr: &ADDR) -> IoResult<Self::Listener>;
 }